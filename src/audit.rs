@@ -0,0 +1,306 @@
+//! データ変更の履歴（監査ログ）を記録する
+//! 前回保存したときのスナップショットとの差分を取り、フィールド単位で`audit.json`に追記する
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::{fs::File, io::BufReader, io::Write};
+
+use crate::photodata::{GUIGroupData, GUIPhotoData};
+
+/// `audit.json`の大きさの上限（バイト）
+/// これを超えたら古いログを`audit.1.json`に退避する
+pub const AUDIT_LOG_MAX_SIZE: u64 = 1024 * 1024;
+/// 退避したログを何世代まで残すか
+pub const AUDIT_LOG_ROTATE_COUNT: usize = 3;
+
+/// 1件の変更履歴
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+  /// 変更を検知した時刻
+  pub time: DateTime<FixedOffset>,
+  /// 変更された写真またはグループのID
+  pub id: String,
+  /// 変更されたフィールド名
+  /// 追加・削除の場合は`(追加)`・`(削除)`になる
+  pub field: String,
+  /// 変更前の値
+  pub old_value: String,
+  /// 変更後の値
+  pub new_value: String,
+}
+
+/// 差分を取るための前回保存時のデータ
+#[derive(Debug, Clone, Default)]
+pub struct AuditSnapshot {
+  pub gui_photo_data_lst: HashMap<String, GUIPhotoData>,
+  pub gui_group_data_lst: HashMap<String, GUIGroupData>,
+}
+
+impl AuditSnapshot {
+  pub fn new(
+    gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+    gui_group_data_lst: &HashMap<String, GUIGroupData>,
+  ) -> Self {
+    AuditSnapshot {
+      gui_photo_data_lst: gui_photo_data_lst.clone(),
+      gui_group_data_lst: gui_group_data_lst.clone(),
+    }
+  }
+
+  /// 前回保存したときから写真やグループのデータが変わっているかどうか
+  /// 未保存の変更があるかを毎フレーム確かめるため、差分の一覧は作らずに比べる
+  pub fn is_changed(
+    &self,
+    gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+    gui_group_data_lst: &HashMap<String, GUIGroupData>,
+  ) -> bool {
+    self.gui_photo_data_lst != *gui_photo_data_lst || self.gui_group_data_lst != *gui_group_data_lst
+  }
+}
+
+/// 言語ごとの文字列を`en=...; fr=...`のように1つの文字列にまとめる
+fn translation_label(translation_lst: &BTreeMap<String, String>) -> String {
+  translation_lst
+    .iter()
+    .map(|(language, text)| format!("{}={}", language, text))
+    .collect::<Vec<String>>()
+    .join("; ")
+}
+
+/// 写真データのフィールド名と値の組を列挙する
+fn photo_data_fields(data: &GUIPhotoData) -> Vec<(&'static str, String)> {
+  vec![
+    ("file_name", data.file_name.clone()),
+    ("alt", data.alt.clone()),
+    ("title", data.title.clone()),
+    (
+      "alt_translation",
+      translation_label(&data.alt_translation_lst),
+    ),
+    (
+      "title_translation",
+      translation_label(&data.title_translation_lst),
+    ),
+    ("year", data.year.clone()),
+    ("month", data.month.clone()),
+    ("day", data.day.clone()),
+    ("hour", data.hour.clone()),
+    ("minutes", data.minutes.clone()),
+    ("body", data.body.clone()),
+    ("lens", data.lens.clone()),
+    ("time", data.time.clone()),
+    ("focal_length", data.focal_length.clone()),
+    ("F_value", data.f_value.clone()),
+    ("iso", data.iso.clone()),
+    ("exposure_program", data.exposure_program.clone()),
+    ("max_f_value", data.max_f_value.clone()),
+    ("location", data.location.clone()),
+    ("rating", data.rating.to_string()),
+    ("keywords", data.keywords.clone()),
+    ("offset_time", data.offset_time.clone()),
+    ("focal_length_35mm", data.focal_length_35mm.clone()),
+    ("altitude", data.altitude.clone()),
+    ("locked", data.locked.to_string()),
+    ("session", data.session.clone()),
+    ("flag", data.flag.clone().unwrap_or_default()),
+  ]
+}
+
+/// グループデータのフィールド名と値の組を列挙する
+fn group_data_fields(data: &GUIGroupData) -> Vec<(&'static str, String)> {
+  vec![
+    ("photo_id_list", data.photo_id_list.join(",")),
+    ("year", data.year.clone()),
+    ("month", data.month.clone()),
+    ("day", data.day.clone()),
+    ("hour", data.hour.clone()),
+    ("minutes", data.minutes.clone()),
+    ("title", data.title.clone()),
+    ("description", data.description.clone()),
+    ("location", data.location.clone()),
+    (
+      "cover_photo_id",
+      data.cover_photo_id.clone().unwrap_or_default(),
+    ),
+    (
+      "parent_group_id",
+      data.parent_group_id.clone().unwrap_or_default(),
+    ),
+  ]
+}
+
+/// 新旧のデータを比較して変更履歴を作る
+fn diff_fields<T>(
+  time: DateTime<FixedOffset>,
+  old_lst: &HashMap<String, T>,
+  new_lst: &HashMap<String, T>,
+  fields: fn(&T) -> Vec<(&'static str, String)>,
+) -> Vec<AuditEntry> {
+  let mut v = Vec::new();
+  for (id, new_data) in new_lst.iter() {
+    match old_lst.get(id) {
+      Some(old_data) => {
+        for ((field, old_value), (_, new_value)) in
+          fields(old_data).into_iter().zip(fields(new_data))
+        {
+          if old_value != new_value {
+            v.push(AuditEntry {
+              time,
+              id: id.clone(),
+              field: field.to_string(),
+              old_value,
+              new_value,
+            })
+          }
+        }
+      }
+      None => v.push(AuditEntry {
+        time,
+        id: id.clone(),
+        field: "(追加)".to_string(),
+        old_value: String::new(),
+        new_value: id.clone(),
+      }),
+    }
+  }
+  for id in old_lst.keys() {
+    if !new_lst.contains_key(id) {
+      v.push(AuditEntry {
+        time,
+        id: id.clone(),
+        field: "(削除)".to_string(),
+        old_value: id.clone(),
+        new_value: String::new(),
+      })
+    }
+  }
+  v.sort_by(|a, b| a.id.cmp(&b.id));
+  v
+}
+
+/// スナップショットと現在のデータを比較して変更履歴を作る
+pub fn make_audit_entry_lst(
+  snapshot: &AuditSnapshot,
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+  time: DateTime<FixedOffset>,
+) -> Vec<AuditEntry> {
+  let mut v = diff_fields(
+    time,
+    &snapshot.gui_photo_data_lst,
+    gui_photo_data_lst,
+    photo_data_fields,
+  );
+  v.append(&mut diff_fields(
+    time,
+    &snapshot.gui_group_data_lst,
+    gui_group_data_lst,
+    group_data_fields,
+  ));
+  v
+}
+
+fn audit_log_path(work_dir: &str, generation: usize) -> String {
+  if generation == 0 {
+    format!("{}/audit.json", work_dir)
+  } else {
+    format!("{}/audit.{}.json", work_dir, generation)
+  }
+}
+
+/// 外部に保存した変更履歴を読み込む
+/// ファイルが無い場合は空になる
+pub fn load_audit_log(work_dir: &str) -> Result<Vec<AuditEntry>> {
+  match File::open(audit_log_path(work_dir, 0)) {
+    Ok(file) => {
+      let reader = BufReader::new(file);
+      let data: Vec<AuditEntry> = serde_json::from_reader(reader)?;
+      Ok(data)
+    }
+    Err(_) => Ok(Vec::new()),
+  }
+}
+
+/// `audit.json`が上限を超えていたら世代をずらして退避する
+/// 一番古い世代は上書きされて消える
+fn rotate_audit_log(work_dir: &str) -> Result<()> {
+  let size = match fs::metadata(audit_log_path(work_dir, 0)) {
+    Ok(metadata) => metadata.len(),
+    Err(_) => return Ok(()),
+  };
+  if size < AUDIT_LOG_MAX_SIZE {
+    return Ok(());
+  }
+  for generation in (0..AUDIT_LOG_ROTATE_COUNT).rev() {
+    let from = audit_log_path(work_dir, generation);
+    if fs::metadata(&from).is_ok() {
+      fs::rename(from, audit_log_path(work_dir, generation + 1))?;
+    }
+  }
+  Ok(())
+}
+
+/// 変更履歴を`audit.json`に追記する
+pub fn append_audit_log(work_dir: &str, entry_lst: &[AuditEntry]) -> Result<()> {
+  if entry_lst.is_empty() {
+    return Ok(());
+  }
+  rotate_audit_log(work_dir)?;
+  let mut v = load_audit_log(work_dir)?;
+  v.extend_from_slice(entry_lst);
+  let mut file = File::create(audit_log_path(work_dir, 0))?;
+  let json_str = serde_json::to_string_pretty(&v)?;
+  let buf = json_str.into_bytes();
+  file.write_all(&buf)?;
+  file.flush()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::photodata;
+
+  #[test]
+  fn is_changed_until_the_next_save() {
+    let mut gui_photo_data_lst = HashMap::new();
+    gui_photo_data_lst.insert(
+      "1".to_string(),
+      GUIPhotoData {
+        photo_id: "1".to_string(),
+        alt: "海".to_string(),
+        ..photodata::make_dummy_gui_photo_data()
+      },
+    );
+    let gui_group_data_lst = HashMap::new();
+    let mut snapshot = AuditSnapshot::new(&gui_photo_data_lst, &gui_group_data_lst);
+    assert!(!snapshot.is_changed(&gui_photo_data_lst, &gui_group_data_lst));
+
+    gui_photo_data_lst.get_mut("1").unwrap().alt = "夕日の海".to_string();
+    assert!(snapshot.is_changed(&gui_photo_data_lst, &gui_group_data_lst));
+
+    // 保存したときと同じように、差分を記録してからスナップショットを取り直す
+    let now = DateTime::parse_from_rfc3339("2022-08-01T12:00:00+09:00").unwrap();
+    let audit_entry_lst =
+      make_audit_entry_lst(&snapshot, &gui_photo_data_lst, &gui_group_data_lst, now);
+    assert_eq!(
+      audit_entry_lst,
+      vec![AuditEntry {
+        time: now,
+        id: "1".to_string(),
+        field: "alt".to_string(),
+        old_value: "海".to_string(),
+        new_value: "夕日の海".to_string(),
+      }]
+    );
+    snapshot = AuditSnapshot::new(&gui_photo_data_lst, &gui_group_data_lst);
+    assert!(!snapshot.is_changed(&gui_photo_data_lst, &gui_group_data_lst));
+
+    // 元の値に戻す編集も変更として扱う
+    gui_photo_data_lst.get_mut("1").unwrap().alt = "海".to_string();
+    assert!(snapshot.is_changed(&gui_photo_data_lst, &gui_group_data_lst));
+  }
+}
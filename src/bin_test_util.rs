@@ -0,0 +1,20 @@
+//! バイナリ側のテストで使う一時ディレクトリを用意する
+//! ライブラリの`test_util`はバイナリのテストからは使えないので、同じものを置いておく
+
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static TEMP_DIR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// テストごとに別の空の一時ディレクトリを作り、そのpathを返す
+pub fn temp_dir(name: &str) -> String {
+  let path = std::env::temp_dir().join(format!(
+    "photag-bin-test-{}-{}-{}",
+    name,
+    std::process::id(),
+    TEMP_DIR_COUNT.fetch_add(1, Ordering::SeqCst)
+  ));
+  let _ = fs::remove_dir_all(&path);
+  fs::create_dir_all(&path).unwrap();
+  path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,157 @@
+//! 作業ディレクトリごとの設定を管理する
+//! 設定は作業ディレクトリの`photag.toml`に保存する
+
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::image;
+use crate::save;
+
+/// 保存間隔として設定できる最短の時間（秒）
+pub const MIN_SAVE_DIFF_TIME: i32 = 10;
+/// 保存間隔として設定できる最長の時間（秒）
+pub const MAX_SAVE_DIFF_TIME: i32 = 60 * 60;
+
+/// 自動保存についての設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveConfig {
+  /// 画像の更新を確認して保存する間隔（秒）
+  pub save_image_diff_time: i32,
+  /// JSONファイルを保存する間隔（秒）
+  pub save_json_diff_time: i32,
+  /// 自動保存を行わず、保存ボタンを押したときだけ保存する
+  pub manual_save_only: bool,
+}
+
+impl Default for SaveConfig {
+  fn default() -> Self {
+    SaveConfig {
+      save_image_diff_time: save::SAVE_IMAGE_DIFF_TIME,
+      save_json_diff_time: save::SAVE_JSON_DIFF_TIME,
+      manual_save_only: false,
+    }
+  }
+}
+
+/// 機材ごとに自動でかける補正
+/// `photag.toml`には`[[profile]]`として並べる
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EquipmentProfile {
+  /// 対象の機材名（ボディ名またはレンズ名と完全に一致させる）
+  pub equipment: String,
+  #[serde(default)]
+  pub brightness: i32,
+  #[serde(default)]
+  pub contrast: f32,
+  #[serde(default)]
+  pub sharpness: f32,
+}
+
+/// `photag.toml`の内容
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+  pub save: SaveConfig,
+  /// 出力する画像の大きさごとの品質
+  pub quality: image::QualityCurve,
+  #[serde(rename = "profile", skip_serializing_if = "Vec::is_empty")]
+  pub profile_lst: Vec<EquipmentProfile>,
+}
+
+/// 写真のボディ名・レンズ名に対応する補正を探す
+/// 周辺減光などはレンズに依存するので、レンズのプロファイルを優先する
+/// 対応するプロファイルが無い場合は補正をかけない
+pub fn find_adjustment(
+  profile_lst: &[EquipmentProfile],
+  body: &str,
+  lens: &str,
+) -> image::Adjustment {
+  let find = |equipment: &str| {
+    let equipment = equipment.trim();
+    profile_lst
+      .iter()
+      .find(|profile| !equipment.is_empty() && profile.equipment.trim() == equipment)
+  };
+  match find(lens).or_else(|| find(body)) {
+    Some(profile) => image::Adjustment {
+      brightness: profile.brightness,
+      contrast: profile.contrast,
+      sharpness: profile.sharpness,
+    },
+    None => image::Adjustment::default(),
+  }
+}
+
+fn config_path(work_dir: &str) -> String {
+  format!("{}/photag.toml", work_dir)
+}
+
+/// 設定を読み込む
+/// ファイルが無い場合や読み込めない場合は既定の設定になる
+pub fn load_config(work_dir: &str) -> Config {
+  let text = match fs::read_to_string(config_path(work_dir)) {
+    Ok(text) => text,
+    Err(_) => return Config::default(),
+  };
+  match toml::from_str::<Config>(&text) {
+    Ok(mut config) => {
+      config.save.save_image_diff_time = config
+        .save
+        .save_image_diff_time
+        .clamp(MIN_SAVE_DIFF_TIME, MAX_SAVE_DIFF_TIME);
+      config.save.save_json_diff_time = config
+        .save
+        .save_json_diff_time
+        .clamp(MIN_SAVE_DIFF_TIME, MAX_SAVE_DIFF_TIME);
+      config
+    }
+    Err(err) => {
+      warn!("photag.tomlを読み込めませんでした: {}", err);
+      Config::default()
+    }
+  }
+}
+
+pub fn save_config(work_dir: &str, config: &Config) -> Result<()> {
+  let text = toml::to_string_pretty(config)?;
+  fs::write(config_path(work_dir), text)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  #[test]
+  fn save_diff_time_is_clamped() {
+    let work_dir = test_util::temp_dir("config");
+    fs::write(
+      config_path(&work_dir),
+      "[save]\nsave_image_diff_time = 1\nsave_json_diff_time = 100000\n",
+    )
+    .unwrap();
+    let config = load_config(&work_dir);
+    assert_eq!(config.save.save_image_diff_time, MIN_SAVE_DIFF_TIME);
+    assert_eq!(config.save.save_json_diff_time, MAX_SAVE_DIFF_TIME);
+    fs::write(
+      config_path(&work_dir),
+      "[save]\nsave_image_diff_time = 30\nsave_json_diff_time = 3600\n",
+    )
+    .unwrap();
+    let config = load_config(&work_dir);
+    assert_eq!(config.save.save_image_diff_time, 30);
+    assert_eq!(config.save.save_json_diff_time, 3600);
+  }
+
+  #[test]
+  fn missing_or_broken_config_is_default() {
+    let work_dir = test_util::temp_dir("config_default");
+    assert_eq!(load_config(&work_dir), Config::default());
+    fs::write(config_path(&work_dir), "[save\n").unwrap();
+    assert_eq!(load_config(&work_dir), Config::default());
+  }
+}
@@ -0,0 +1,1774 @@
+//! 作業ディレクトリに書き出したデータから静的なHTMLのギャラリーを生成する
+//! トップページにグループの一覧を、グループごとのページに写真を並べる
+//! 他に、写真のExifやメタデータをまとめてCSVやJSONに書き出したり、グループのAtomフィードや撮影統計のレポートを作ったりする
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::image;
+use crate::mapping::PhotoField;
+use crate::photodata::{self, GUIGroupData, GUIPhotoData, GroupData, ImportPhotoData, PhotoData};
+use crate::save;
+use crate::tag_style::{self, TagStyle};
+
+/// 全てのページで共通のスタイル
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+.photo { background-size: cover; background-position: center; display: inline-block; }
+.photo img { display: block; max-width: 100%; height: auto; }
+figure { margin: 0 0 2em 0; }
+figcaption { color: #555; font-size: 0.9em; }
+.cover { display: block; width: 200px; height: 200px; object-fit: cover; }
+.bar { background: #4a90d9; height: 1em; }";
+
+/// HTMLの特殊文字をエスケープする
+pub fn escape_html(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      _ => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+/// `/images/normal/...`のようなpathを、出力先のフォルダからの相対pathにする
+/// 画像のURLの前に付けたpathは取り除く
+fn relative_src(src: &str) -> &str {
+  photodata::strip_url_prefix(src)
+}
+
+/// 写真の撮影情報を1行の説明文にする
+/// 値が無い項目は省略する
+fn photo_caption(photo_data: &PhotoData) -> String {
+  let equipment = [&photo_data.body, &photo_data.lens]
+    .iter()
+    .filter_map(|s| s.as_deref())
+    .filter(|s| !s.is_empty())
+    .collect::<Vec<&str>>()
+    .join(" + ");
+  let date = match (&photo_data.year, &photo_data.month, &photo_data.day) {
+    (Some(year), Some(month), Some(day)) => Some(format!("{}/{}/{}", year, month, day)),
+    _ => None,
+  };
+  let v = [
+    Some(equipment).filter(|s| !s.is_empty()),
+    photo_data.focal_length.as_ref().map(|s| format!("{}mm", s)),
+    photo_data.f_value.as_ref().map(|s| format!("F{}", s)),
+    photo_data.time.as_ref().map(|s| format!("{}s", s)),
+    photo_data.iso.as_ref().map(|s| format!("ISO{}", s)),
+    date,
+  ];
+  v.into_iter()
+    .flatten()
+    .filter(|s| !s.is_empty())
+    .collect::<Vec<String>>()
+    .join(" / ")
+}
+
+/// 幅の異なる画像がある場合は`srcset`属性を作る
+fn srcset_attribute(photo_data: &PhotoData) -> String {
+  if photo_data.srcset.is_empty() {
+    return String::new();
+  }
+  let srcset = photo_data
+    .srcset
+    .iter()
+    .map(|image| format!("{} {}w", relative_src(&image.src), image.width))
+    .collect::<Vec<String>>()
+    .join(", ");
+  format!(" srcset=\"{}\" sizes=\"100vw\"", escape_html(&srcset))
+}
+
+/// 写真のキーワードを、タグのスタイルに従って色とアイコンを付けた`<span>`として並べる
+fn keyword_tags(photo_data: &PhotoData, tag_style_lst: &BTreeMap<String, TagStyle>) -> String {
+  photo_data
+    .keywords
+    .iter()
+    .map(|keyword| {
+      format!(
+        "<span class=\"{}\">{}</span>",
+        tag_style::tag_class(tag_style_lst, keyword),
+        escape_html(&tag_style::find_tag_style(tag_style_lst, keyword).label(keyword))
+      )
+    })
+    .collect::<Vec<String>>()
+    .join("")
+}
+
+/// WebP版の画像がある場合は`<picture>`要素を開き、WebPの`<source>`を置く
+/// WebPに対応していないブラウザでは続く`<img>`のJPEGが表示される
+fn picture_start(photo_data: &PhotoData) -> String {
+  match &photo_data.photo_webp_src {
+    Some(src) => format!(
+      "<picture>\n<source srcset=\"{}\" type=\"image/webp\">\n",
+      escape_html(relative_src(src))
+    ),
+    None => String::new(),
+  }
+}
+
+/// 言語ごとのページのファイル名
+/// 既定の言語は`index.html`、それ以外の言語は`index.en.html`のように言語コードを付ける
+pub fn page_file_name(name: &str, language: &str) -> String {
+  let name = photodata::sanitize_id(name);
+  if language == photodata::DEFAULT_LANGUAGE {
+    format!("{}.html", name)
+  } else {
+    format!("{}.{}.html", name, language)
+  }
+}
+
+/// `extra_style`は全てのページで共通のスタイルの後に加える
+fn make_page(title: &str, body: &str, language: &str, extra_style: &str) -> String {
+  format!(
+    "<!DOCTYPE html>
+<html lang=\"{}\">
+<head>
+<meta charset=\"utf-8\">
+<title>{}</title>
+<style>
+{}
+{}</style>
+</head>
+<body>
+{}
+</body>
+</html>
+",
+    escape_html(language),
+    escape_html(title),
+    STYLE,
+    extra_style,
+    body
+  )
+}
+
+/// グループ一覧のページを作る
+/// `cover_src_lst`にカバー画像のpathがあるグループはカバー画像も表示する
+/// グループのページへのリンクは`language`の言語のページにする
+pub fn make_index_html(
+  group_data_lst: &[GroupData],
+  cover_src_lst: &HashMap<String, String>,
+  language: &str,
+) -> String {
+  let mut body = String::from("<h1>写真一覧</h1>\n<ul>\n");
+  for group_data in group_data_lst.iter() {
+    let cover = match cover_src_lst.get(&group_data.group_id) {
+      Some(src) => format!(
+        "<img class=\"cover\" src=\"{}\" alt=\"{}\" loading=\"lazy\">",
+        escape_html(relative_src(src)),
+        escape_html(&group_data.title)
+      ),
+      None => String::new(),
+    };
+    body.push_str(&format!(
+      "<li><a href=\"{}\">{}{}</a>（{}枚）</li>\n",
+      escape_html(&page_file_name(&group_data.group_id, language)),
+      cover,
+      escape_html(&group_data.title),
+      group_data.photo_id_list.len()
+    ));
+  }
+  body.push_str("</ul>");
+  make_page("写真一覧", &body, language, "")
+}
+
+/// グループごとのページを作る
+/// 遅延表示用の画像をぼかしたプレースホルダとして背景に敷く
+/// 写真の説明とタイトルは`language`の言語のものを使い、無い場合は既定の言語のものを使う
+/// 写真のキーワードは`tag_style_lst`の色とアイコンを付けたタグとして並べる
+pub fn make_group_html(
+  group_data: &GroupData,
+  photo_data_lst: &HashMap<String, PhotoData>,
+  language: &str,
+  tag_style_lst: &BTreeMap<String, TagStyle>,
+) -> String {
+  let mut body = format!(
+    "<p><a href=\"{}\">一覧に戻る</a></p>\n<h1>{}</h1>\n<p>{}</p>\n",
+    escape_html(&page_file_name("index", language)),
+    escape_html(&group_data.title),
+    escape_html(&group_data.description)
+  );
+  for photo_id in group_data.photo_id_list.iter() {
+    let photo_data = match photo_data_lst.get(photo_id) {
+      Some(photo_data) => photo_data,
+      None => continue,
+    };
+    let title = photo_data
+      .title
+      .as_ref()
+      .map(|title| title.get(language))
+      .unwrap_or_default();
+    body.push_str(&format!(
+      "<figure id=\"{}\">
+<div class=\"photo\" style=\"background-image: url('{}')\">
+{}<img src=\"{}\"{} alt=\"{}\" title=\"{}\" loading=\"lazy\">{}
+</div>
+<figcaption>{}<br>{}{}</figcaption>
+</figure>
+",
+      escape_html(&photo_data.photo_id),
+      escape_html(relative_src(&photo_data.photo_lazy_src)),
+      picture_start(photo_data),
+      escape_html(relative_src(&photo_data.photo_src)),
+      srcset_attribute(photo_data),
+      escape_html(photo_data.alt.get(language)),
+      escape_html(title),
+      if photo_data.photo_webp_src.is_some() {
+        "\n</picture>"
+      } else {
+        ""
+      },
+      escape_html(title),
+      escape_html(&photo_caption(photo_data)),
+      if photo_data.keywords.is_empty() {
+        String::new()
+      } else {
+        format!("<br>{}", keyword_tags(photo_data, tag_style_lst))
+      },
+    ));
+  }
+  make_page(
+    &group_data.title,
+    &body,
+    language,
+    &tag_style::make_tag_css(tag_style_lst),
+  )
+}
+
+/// 画像ファイルを出力先のフォルダにコピーする
+/// 元のファイルが無い場合は何もしない
+fn copy_image(work_directory: &str, output_directory: &str, src: &str) -> Result<()> {
+  let from = Path::new(work_directory).join(photodata::src_to_path(src));
+  if !from.exists() {
+    warn!("画像ファイルが見つかりません: {}", from.display());
+    return Ok(());
+  }
+  let to = Path::new(output_directory).join(photodata::src_to_path(src));
+  if let Some(parent) = to.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  fs::copy(from, to)?;
+  Ok(())
+}
+
+/// 書き出す対象の写真だけを残す
+/// グループは対象の写真を含むものだけを残し、グループの写真の一覧も対象の写真だけにする
+/// `target_id_lst`が`None`の場合は全ての写真が対象になる
+pub fn filter_export_target(
+  photo_data_lst: Vec<PhotoData>,
+  group_data_lst: Vec<GroupData>,
+  target_id_lst: Option<&[String]>,
+) -> (Vec<PhotoData>, Vec<GroupData>) {
+  let target_id_lst = match target_id_lst {
+    Some(target_id_lst) => target_id_lst,
+    None => return (photo_data_lst, group_data_lst),
+  };
+  let photo_data_lst = photo_data_lst
+    .into_iter()
+    .filter(|photo_data| target_id_lst.contains(&photo_data.photo_id))
+    .collect();
+  let group_data_lst = group_data_lst
+    .into_iter()
+    .filter_map(|group_data| {
+      let photo_id_list = group_data
+        .photo_id_list
+        .iter()
+        .filter(|id| target_id_lst.contains(id))
+        .cloned()
+        .collect::<Vec<String>>();
+      if photo_id_list.is_empty() {
+        None
+      } else {
+        // カバー写真が書き出す対象に含まれない場合はカバーを解除する
+        let cover_photo_id = group_data
+          .cover_photo_id
+          .filter(|id| photo_id_list.contains(id));
+        Some(GroupData {
+          photo_id_list,
+          cover_photo_id,
+          ..group_data
+        })
+      }
+    })
+    .collect();
+  (photo_data_lst, group_data_lst)
+}
+
+/// 不採用のフラグが付いている写真を書き出す対象から外す
+/// 不採用の写真しか含まないグループも書き出さない
+fn exclude_rejected(
+  photo_data_lst: Vec<PhotoData>,
+  group_data_lst: Vec<GroupData>,
+) -> (Vec<PhotoData>, Vec<GroupData>) {
+  let (rejected_lst, photo_data_lst): (Vec<PhotoData>, Vec<PhotoData>) = photo_data_lst
+    .into_iter()
+    .partition(|photo_data| photodata::is_rejected(photo_data.flag.as_deref()));
+  if rejected_lst.is_empty() {
+    return (photo_data_lst, group_data_lst);
+  }
+  let rejected_id_lst = rejected_lst
+    .into_iter()
+    .map(|photo_data| photo_data.photo_id)
+    .collect::<Vec<String>>();
+  let group_data_lst = group_data_lst
+    .into_iter()
+    .filter_map(|group_data| {
+      let photo_id_list = group_data
+        .photo_id_list
+        .iter()
+        .filter(|id| !rejected_id_lst.contains(id))
+        .cloned()
+        .collect::<Vec<String>>();
+      if photo_id_list.is_empty() && !group_data.photo_id_list.is_empty() {
+        return None;
+      }
+      let cover_photo_id = group_data
+        .cover_photo_id
+        .filter(|id| !rejected_id_lst.contains(id));
+      Some(GroupData {
+        photo_id_list,
+        cover_photo_id,
+        ..group_data
+      })
+    })
+    .collect();
+  (photo_data_lst, group_data_lst)
+}
+
+/// 評価で絞り込んだ結果、写真が無くなったグループの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyGroupHandling {
+  /// グループごと書き出さない
+  Remove,
+  /// 写真の無いグループとして書き出す
+  Keep,
+}
+
+/// 写真の評価による書き出し対象の絞り込み
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RatingFilter {
+  /// 書き出す写真の最低評価
+  /// 0の場合は絞り込まない
+  pub min_rating: u8,
+  pub empty_group: EmptyGroupHandling,
+}
+
+impl Default for RatingFilter {
+  fn default() -> Self {
+    RatingFilter {
+      min_rating: 0,
+      empty_group: EmptyGroupHandling::Remove,
+    }
+  }
+}
+
+/// 評価が最低評価に満たない写真を取り除く
+/// グループの写真の一覧からも取り除き、空になったグループは`empty_group`に従って扱う
+pub fn filter_by_rating(
+  photo_data_lst: Vec<PhotoData>,
+  group_data_lst: Vec<GroupData>,
+  rating_filter: &RatingFilter,
+) -> (Vec<PhotoData>, Vec<GroupData>) {
+  if rating_filter.min_rating == 0 {
+    return (photo_data_lst, group_data_lst);
+  }
+  let photo_data_lst = photo_data_lst
+    .into_iter()
+    .filter(|photo_data| photo_data.rating >= rating_filter.min_rating)
+    .collect::<Vec<PhotoData>>();
+  let photo_id_lst = photo_data_lst
+    .iter()
+    .map(|photo_data| photo_data.photo_id.clone())
+    .collect::<Vec<String>>();
+  let group_data_lst = group_data_lst
+    .into_iter()
+    .filter_map(|group_data| {
+      let photo_id_list = group_data
+        .photo_id_list
+        .iter()
+        .filter(|id| photo_id_lst.contains(id))
+        .cloned()
+        .collect::<Vec<String>>();
+      if photo_id_list.is_empty() && rating_filter.empty_group == EmptyGroupHandling::Remove {
+        return None;
+      }
+      let cover_photo_id = group_data
+        .cover_photo_id
+        .filter(|id| photo_id_list.contains(id));
+      Some(GroupData {
+        photo_id_list,
+        cover_photo_id,
+        ..group_data
+      })
+    })
+    .collect();
+  (photo_data_lst, group_data_lst)
+}
+
+/// グループに存在しない写真のIDが含まれている場合は警告を表示する
+/// 存在しない写真は書き出さない
+fn warn_dangling_photo_ids(photo_data_lst: &[PhotoData], group_data_lst: &[GroupData]) {
+  let photo_id_lst = photo_data_lst
+    .iter()
+    .map(|photo_data| photo_data.photo_id.clone())
+    .collect::<Vec<String>>();
+  for dangling in photodata::find_dangling_photo_ids(group_data_lst, &photo_id_lst).iter() {
+    warn!(
+      "グループ「{}」に存在しない画像が含まれています: {}",
+      dangling.group_id,
+      dangling.photo_id_lst.join(", ")
+    );
+  }
+}
+
+/// 写真の空欄の撮影場所と撮影日時を、写真を含むグループとその祖先のグループの値で補完する
+fn inherit_group_metadata(
+  photo_data_lst: Vec<PhotoData>,
+  group_data_lst: &[GroupData],
+) -> Vec<PhotoData> {
+  let group_id_lst = group_data_lst
+    .iter()
+    .map(|group_data| group_data.group_id.clone())
+    .collect::<Vec<String>>();
+  let gui_group_data_lst = group_data_lst
+    .iter()
+    .map(|group_data| {
+      (
+        group_data.group_id.clone(),
+        photodata::group_data_to_gui_group_data(group_data.clone()),
+      )
+    })
+    .collect::<HashMap<String, GUIGroupData>>();
+  photo_data_lst
+    .into_iter()
+    .map(|photo_data| {
+      let gui_photo_data = photodata::photo_data_to_gui_photo_data(photo_data);
+      photodata::gui_photo_data_to_photo_data(photodata::inherit_from_groups(
+        &gui_photo_data,
+        &group_id_lst,
+        &gui_group_data_lst,
+      ))
+    })
+    .collect()
+}
+
+/// 作業ディレクトリのデータを読み込み、書き出す対象だけを残す
+/// `inherit_group`が`true`の場合は、写真の空欄の撮影場所と撮影日時をグループの値で補完する
+/// 補完は書き出すデータにだけ行い、作業ディレクトリのデータは変更しない
+fn load_export_target(
+  work_directory: &str,
+  target_id_lst: Option<&[String]>,
+  rating_filter: &RatingFilter,
+  inherit_group: bool,
+) -> Result<(Vec<PhotoData>, Vec<GroupData>)> {
+  let photo_data_lst = photodata::load_photo_data_from_work_directory(work_directory)?;
+  let group_data_lst = photodata::load_group_data_from_work_directory(work_directory)?;
+  warn_dangling_photo_ids(&photo_data_lst, &group_data_lst);
+  // 祖先のグループは書き出す対象から外れることがあるので、絞り込む前に補完する
+  let photo_data_lst = if inherit_group {
+    inherit_group_metadata(photo_data_lst, &group_data_lst)
+  } else {
+    photo_data_lst
+  };
+  let (photo_data_lst, group_data_lst) =
+    filter_export_target(photo_data_lst, group_data_lst, target_id_lst);
+  let (photo_data_lst, group_data_lst) = exclude_rejected(photo_data_lst, group_data_lst);
+  Ok(filter_by_rating(
+    photo_data_lst,
+    group_data_lst,
+    rating_filter,
+  ))
+}
+
+/// 写真の画像ファイルを全て出力先のフォルダにコピーする
+fn copy_photo_images(
+  work_directory: &str,
+  output_directory: &str,
+  photo_data: &PhotoData,
+) -> Result<()> {
+  copy_image(work_directory, output_directory, &photo_data.photo_src)?;
+  copy_image(work_directory, output_directory, &photo_data.photo_lazy_src)?;
+  for image in photo_data.srcset.iter() {
+    copy_image(work_directory, output_directory, &image.src)?;
+  }
+  Ok(())
+}
+
+/// 書き出すWebP画像の品質
+const WEBP_QUALITY: f32 = 85.0;
+
+/// 表示用画像のWebP版のpath
+/// 表示用画像の`photo_src`の拡張子を変えたものにする
+pub fn webp_src(photo_src: &str) -> String {
+  format!(
+    "{}.webp",
+    photo_src.strip_suffix(".JPG").unwrap_or(photo_src)
+  )
+}
+
+/// 作業ディレクトリの表示用画像をWebPに変換して出力先のフォルダに書き出し、`photo_webp_src`に設定する
+/// 出力先に表示用画像より新しいWebP画像が既にある場合は変換し直さない
+/// 表示用画像が無い場合は何もしない
+fn write_webp_image(
+  work_directory: &str,
+  output_directory: &str,
+  photo_data: &mut PhotoData,
+) -> Result<()> {
+  let from = Path::new(work_directory).join(photodata::src_to_path(&photo_data.photo_src));
+  if !from.exists() {
+    warn!("画像ファイルが見つかりません: {}", from.display());
+    return Ok(());
+  }
+  let src = webp_src(&photo_data.photo_src);
+  let to = Path::new(output_directory).join(photodata::src_to_path(&src));
+  let is_up_to_date = match (fs::metadata(&from), fs::metadata(&to)) {
+    (Ok(from), Ok(to)) => matches!(
+      (from.modified(), to.modified()),
+      (Ok(from), Ok(to)) if to >= from
+    ),
+    _ => false,
+  };
+  if !is_up_to_date {
+    let buf = image::convert_to_webp(&fs::read(&from)?, WEBP_QUALITY)
+      .with_context(|| format!("WebPに変換できません: {}", from.display()))?;
+    if let Some(parent) = to.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(to, buf)?;
+  }
+  photo_data.photo_webp_src = Some(src);
+  Ok(())
+}
+
+/// `dual_format`が`true`の場合は全ての写真のWebP版の画像を書き出す
+fn write_webp_images(
+  work_directory: &str,
+  output_directory: &str,
+  photo_data_lst: &mut [PhotoData],
+  dual_format: bool,
+) -> Result<()> {
+  if !dual_format {
+    return Ok(());
+  }
+  for photo_data in photo_data_lst.iter_mut() {
+    write_webp_image(work_directory, output_directory, photo_data)?;
+  }
+  Ok(())
+}
+
+/// ウォーターマーク付きの画像を書き出すときの設定
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatermarkOptions {
+  /// ウォーターマーク付きの画像を書き出すフォルダ
+  pub directory: String,
+  pub source: image::WatermarkSource,
+  pub position: image::WatermarkPosition,
+  /// 0から1の不透明度
+  pub opacity: f32,
+  /// 画像の幅に対するウォーターマークの幅の割合
+  pub size: f32,
+}
+
+/// 対象の写真の表示用画像にウォーターマークを入れて`watermark.directory`に書き出す
+/// 公開前のプレビューを配るためのもので、フォルダの構成は通常の書き出しと同じにする
+/// 書き出した枚数を返す
+pub fn export_watermark(
+  work_directory: &str,
+  target_id_lst: Option<&[String]>,
+  rating_filter: &RatingFilter,
+  watermark: &WatermarkOptions,
+) -> Result<usize> {
+  let (photo_data_lst, _) =
+    load_export_target(work_directory, target_id_lst, rating_filter, false)?;
+  let mut count = 0;
+  for photo_data in photo_data_lst.iter() {
+    let from = Path::new(work_directory).join(photodata::src_to_path(&photo_data.photo_src));
+    if !from.exists() {
+      warn!("画像ファイルが見つかりません: {}", from.display());
+      continue;
+    }
+    let buf = image::apply_watermark(
+      &fs::read(&from)?,
+      &watermark.source,
+      watermark.position,
+      watermark.opacity,
+      watermark.size,
+    )
+    .with_context(|| format!("{}にウォーターマークを入れられません", from.display()))?;
+    let to = Path::new(&watermark.directory).join(photodata::src_to_path(&photo_data.photo_src));
+    if let Some(parent) = to.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(to, buf)?;
+    count += 1;
+  }
+  Ok(count)
+}
+
+/// 作業ディレクトリの`photo_data.json`と`group_data.json`のうち、
+/// 対象の写真と、それを含むグループだけを書き出す
+/// 対象の写真の画像も出力先のフォルダにコピーする
+/// 評価が`rating_filter`の最低評価に満たない写真は書き出さない
+/// `dual_format`が`true`の場合は表示用画像のWebP版も書き出し、そのpathを`photo_webp_src`に記録する
+/// JSONファイルは`json_style`の形式で書き出す
+/// `inherit_group`が`true`の場合は、写真の空欄の撮影場所と撮影日時をグループの値で補完して書き出す
+pub fn export_json(
+  work_directory: &str,
+  output_directory: &str,
+  target_id_lst: Option<&[String]>,
+  rating_filter: &RatingFilter,
+  dual_format: bool,
+  json_style: save::JsonStyle,
+  inherit_group: bool,
+) -> Result<()> {
+  let (mut photo_data_lst, group_data_lst) =
+    load_export_target(work_directory, target_id_lst, rating_filter, inherit_group)?;
+  fs::create_dir_all(output_directory)?;
+  write_webp_images(
+    work_directory,
+    output_directory,
+    &mut photo_data_lst,
+    dual_format,
+  )?;
+  fs::write(
+    Path::new(output_directory).join("photo_data.json"),
+    json_style.to_versioned_json_string(&photo_data_lst)?,
+  )?;
+  fs::write(
+    Path::new(output_directory).join("group_data.json"),
+    json_style.to_versioned_json_string(&group_data_lst)?,
+  )?;
+  for photo_data in photo_data_lst.iter() {
+    copy_photo_images(work_directory, output_directory, photo_data)?;
+  }
+  Ok(())
+}
+
+/// 前回公開したときから追加・変更・削除されたID
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeList {
+  pub added: Vec<String>,
+  pub modified: Vec<String>,
+  pub removed: Vec<String>,
+}
+
+impl ChangeList {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+  }
+}
+
+/// 差分だけを公開したときに書き出す、写真とグループの変更の一覧
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishChangeList {
+  pub photo: ChangeList,
+  pub group: ChangeList,
+}
+
+/// 前回公開したときの情報と、今回のIDとハッシュ値の組を比べて変更の一覧を作る
+/// 追加と変更は今回の並び順、削除は前回の並び順にする
+pub fn diff_publish_info(
+  old_info_lst: &[save::PublishInfo],
+  new_hash_lst: &[(String, String)],
+) -> ChangeList {
+  let old_hash_lst = old_info_lst
+    .iter()
+    .map(|info| (info.id.as_str(), info.hash.as_str()))
+    .collect::<HashMap<&str, &str>>();
+  let mut change_list = ChangeList::default();
+  for (id, hash) in new_hash_lst.iter() {
+    match old_hash_lst.get(id.as_str()) {
+      None => change_list.added.push(id.clone()),
+      Some(old_hash) if old_hash != hash => change_list.modified.push(id.clone()),
+      Some(_) => (),
+    }
+  }
+  change_list.removed = old_info_lst
+    .iter()
+    .filter(|info| !new_hash_lst.iter().any(|(id, _)| *id == info.id))
+    .map(|info| info.id.clone())
+    .collect();
+  change_list
+}
+
+/// 今回のハッシュ値から次回の比較元にする情報を作る
+/// 内容が変わっていないデータは前回の時刻のままにする
+fn update_publish_info(
+  old_info_lst: &[save::PublishInfo],
+  new_hash_lst: Vec<(String, String)>,
+  now: DateTime<FixedOffset>,
+) -> Vec<save::PublishInfo> {
+  new_hash_lst
+    .into_iter()
+    .map(|(id, hash)| {
+      let time = old_info_lst
+        .iter()
+        .find(|info| info.id == id && info.hash == hash)
+        .map_or(now, |info| info.time);
+      save::PublishInfo { id, time, hash }
+    })
+    .collect()
+}
+
+/// 写真のメタデータと表示用画像の更新時刻から、公開する内容のハッシュ値を求める
+fn photo_publish_hash(work_directory: &str, photo_data: &PhotoData) -> Result<String> {
+  let image_path = Path::new(work_directory).join(photodata::src_to_path(&photo_data.photo_src));
+  let modified = save::get_file_modified_timestamp(&image_path.to_string_lossy())
+    .map(|time| time.to_rfc3339())
+    .unwrap_or_default();
+  let json_str = serde_json::to_string(photo_data)?;
+  Ok(save::content_hash(
+    format!("{}\n{}", json_str, modified).as_bytes(),
+  ))
+}
+
+/// 前回公開したときから変わった写真とグループだけを書き出す
+/// `photo_data.json`と`group_data.json`は全体を書き出し、画像は追加・変更された写真のものだけをコピーする
+/// 変更の一覧は出力先の`changelist.json`に書き出し、
+/// 次回の比較元にする各データのハッシュ値は`publish.json`に保存する
+/// 他の引数は`export_json`と同じ
+pub fn export_changed(
+  work_directory: &str,
+  output_directory: &str,
+  rating_filter: &RatingFilter,
+  dual_format: bool,
+  json_style: save::JsonStyle,
+  inherit_group: bool,
+) -> Result<PublishChangeList> {
+  let (mut photo_data_lst, group_data_lst) =
+    load_export_target(work_directory, None, rating_filter, inherit_group)?;
+  fs::create_dir_all(output_directory)?;
+  write_webp_images(
+    work_directory,
+    output_directory,
+    &mut photo_data_lst,
+    dual_format,
+  )?;
+  let manifest = save::get_publish_manifest(output_directory)?;
+  let photo_hash_lst = photo_data_lst
+    .iter()
+    .map(|photo_data| -> Result<(String, String)> {
+      Ok((
+        photo_data.photo_id.clone(),
+        photo_publish_hash(work_directory, photo_data)?,
+      ))
+    })
+    .collect::<Result<Vec<(String, String)>>>()?;
+  let group_hash_lst = group_data_lst
+    .iter()
+    .map(|group_data| -> Result<(String, String)> {
+      Ok((
+        group_data.group_id.clone(),
+        save::content_hash(serde_json::to_string(group_data)?.as_bytes()),
+      ))
+    })
+    .collect::<Result<Vec<(String, String)>>>()?;
+  let change_list = PublishChangeList {
+    photo: diff_publish_info(&manifest.photo, &photo_hash_lst),
+    group: diff_publish_info(&manifest.group, &group_hash_lst),
+  };
+  fs::write(
+    Path::new(output_directory).join("photo_data.json"),
+    json_style.to_versioned_json_string(&photo_data_lst)?,
+  )?;
+  fs::write(
+    Path::new(output_directory).join("group_data.json"),
+    json_style.to_versioned_json_string(&group_data_lst)?,
+  )?;
+  for photo_data in photo_data_lst.iter() {
+    let is_changed = change_list.photo.added.contains(&photo_data.photo_id)
+      || change_list.photo.modified.contains(&photo_data.photo_id);
+    if is_changed {
+      copy_photo_images(work_directory, output_directory, photo_data)?;
+    }
+  }
+  fs::write(
+    Path::new(output_directory).join("changelist.json"),
+    serde_json::to_string_pretty(&change_list)?,
+  )?;
+  let now = save::get_now();
+  save::save_publish_manifest(
+    output_directory,
+    &save::PublishManifest {
+      photo: update_publish_info(&manifest.photo, photo_hash_lst, now),
+      group: update_publish_info(&manifest.group, group_hash_lst, now),
+    },
+  )?;
+  Ok(change_list)
+}
+
+/// 作業ディレクトリの`photo_data.json`と`group_data.json`からHTMLのギャラリーを書き出す
+/// `target_id_lst`を指定した場合は、対象の写真と、それを含むグループだけを書き出す
+/// 評価が`rating_filter`の最低評価に満たない写真は書き出さない
+/// 画像も出力先のフォルダにコピーする
+/// `dual_format`が`true`の場合はWebP版の画像も書き出し、`<picture>`要素でJPEGにフォールバックさせる
+/// 写真の説明やタイトルに既定の言語以外の言語がある場合は、`index.en.html`のように言語ごとのページも書き出す
+/// `inherit_group`が`true`の場合は、写真の空欄の撮影場所と撮影日時をグループの値で補完して書き出す
+/// 写真のキーワードは作業ディレクトリの`tag_styles.json`の色とアイコンで表示する
+pub fn export_html(
+  work_directory: &str,
+  output_directory: &str,
+  target_id_lst: Option<&[String]>,
+  rating_filter: &RatingFilter,
+  dual_format: bool,
+  inherit_group: bool,
+  url_prefix: &str,
+) -> Result<()> {
+  let (mut photo_data_lst, group_data_lst) =
+    load_export_target(work_directory, target_id_lst, rating_filter, inherit_group)?;
+  let tag_style_lst = tag_style::load_tag_styles(work_directory)?;
+  fs::create_dir_all(output_directory)?;
+  write_webp_images(
+    work_directory,
+    output_directory,
+    &mut photo_data_lst,
+    dual_format,
+  )?;
+  let photo_data_lst = photo_data_lst
+    .into_iter()
+    .map(|photo_data| (photo_data.photo_id.clone(), photo_data))
+    .collect::<HashMap<String, PhotoData>>();
+  // モンタージュのカバー画像が生成されているグループはそれを、
+  // 無い場合はカバー写真に指定されている写真をカバー画像にする
+  let mut cover_src_lst = HashMap::new();
+  for group_data in group_data_lst.iter() {
+    let cover_src = photodata::cover_src(url_prefix, &group_data.group_id);
+    if Path::new(work_directory)
+      .join(photodata::src_to_path(&cover_src))
+      .exists()
+    {
+      copy_image(work_directory, output_directory, &cover_src)?;
+      cover_src_lst.insert(group_data.group_id.clone(), cover_src);
+    } else if let Some(photo_data) = group_data
+      .cover_photo_id
+      .as_ref()
+      .and_then(|id| photo_data_lst.get(id))
+    {
+      cover_src_lst.insert(group_data.group_id.clone(), photo_data.photo_src.clone());
+    }
+  }
+  let language_lst = std::iter::once(photodata::DEFAULT_LANGUAGE.to_string())
+    .chain(photodata::translation_language_lst(photo_data_lst.values()))
+    .collect::<Vec<String>>();
+  for language in language_lst.iter() {
+    fs::write(
+      Path::new(output_directory).join(page_file_name("index", language)),
+      make_index_html(&group_data_lst, &cover_src_lst, language),
+    )?;
+    for group_data in group_data_lst.iter() {
+      fs::write(
+        Path::new(output_directory).join(page_file_name(&group_data.group_id, language)),
+        make_group_html(group_data, &photo_data_lst, language, &tag_style_lst),
+      )?;
+    }
+  }
+  for group_data in group_data_lst.iter() {
+    for photo_id in group_data.photo_id_list.iter() {
+      if let Some(photo_data) = photo_data_lst.get(photo_id) {
+        copy_photo_images(work_directory, output_directory, photo_data)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// 写真の撮影日時と、それをsitemapの`lastmod`の形式にしたもの
+/// 時差が分かっている場合は時刻まで、分からない場合は日付だけにする
+fn photo_lastmod(photo_data: &PhotoData) -> Option<(NaiveDateTime, String)> {
+  let gui_photo_data = photodata::photo_data_to_gui_photo_data(photo_data.clone());
+  if let Some(datetime) = photodata::photo_datetime_with_offset(&gui_photo_data) {
+    return Some((
+      datetime.naive_local(),
+      datetime.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+    ));
+  }
+  let datetime = photodata::photo_datetime(&gui_photo_data)
+    .or_else(|| photodata::photo_date(&gui_photo_data)?.and_hms_opt(0, 0, 0))?;
+  Some((datetime, datetime.format("%Y-%m-%d").to_string()))
+}
+
+/// 全てのページのURLを並べたsitemap.xmlを作る
+/// グループのページには含まれる写真の画像とキャプションを画像sitemapの形式で付け、
+/// 含まれる写真の中で最も新しい撮影日時（写真に無い場合はグループの撮影日）を`lastmod`にする
+/// トップページの`lastmod`は全てのグループの中で最も新しいものにする
+pub fn export_sitemap(
+  photo_data_lst: &[PhotoData],
+  group_data_lst: &[GroupData],
+  base_url: &str,
+  url_prefix: &str,
+) -> String {
+  let photo_data_lst = photo_data_lst
+    .iter()
+    .map(|photo_data| (photo_data.photo_id.as_str(), photo_data))
+    .collect::<HashMap<&str, &PhotoData>>();
+  let mut url_lst = Vec::new();
+  for group_data in group_data_lst.iter() {
+    let group_photo_lst = group_data
+      .photo_id_list
+      .iter()
+      .filter_map(|id| photo_data_lst.get(id.as_str()).copied())
+      .collect::<Vec<&PhotoData>>();
+    let lastmod = group_photo_lst
+      .iter()
+      .filter_map(|photo_data| photo_lastmod(photo_data))
+      .max_by_key(|(datetime, _)| *datetime)
+      .or_else(|| {
+        group_datetime(group_data)
+          .map(|datetime| (datetime, datetime.format("%Y-%m-%d").to_string()))
+      });
+    let mut image_lst = String::new();
+    for photo_data in group_photo_lst.iter() {
+      let caption = match photo_data.alt.default_text() {
+        "" => photo_caption(photo_data),
+        alt => alt.to_string(),
+      };
+      image_lst.push_str("<image:image>\n");
+      image_lst.push_str(&format!(
+        "<image:loc>{}</image:loc>\n",
+        escape_html(&photodata::build_public_url(
+          base_url,
+          &photo_data.photo_src
+        ))
+      ));
+      if !caption.is_empty() {
+        image_lst.push_str(&format!(
+          "<image:caption>{}</image:caption>\n",
+          escape_html(&caption)
+        ));
+      }
+      image_lst.push_str("</image:image>\n");
+    }
+    url_lst.push((
+      photodata::group_page_src(url_prefix, &group_data.group_id),
+      lastmod,
+      image_lst,
+    ));
+  }
+  let index_lastmod = url_lst
+    .iter()
+    .filter_map(|(_, lastmod, _)| lastmod.clone())
+    .max_by_key(|(datetime, _)| *datetime);
+  url_lst.insert(
+    0,
+    (
+      photodata::join_url_prefix(
+        url_prefix,
+        &page_file_name("index", photodata::DEFAULT_LANGUAGE),
+      ),
+      index_lastmod,
+      String::new(),
+    ),
+  );
+  let mut sitemap = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+  sitemap.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" xmlns:image=\"http://www.google.com/schemas/sitemap-image/1.1\">\n");
+  for (src, lastmod, image_lst) in url_lst.iter() {
+    sitemap.push_str("<url>\n");
+    sitemap.push_str(&format!(
+      "<loc>{}</loc>\n",
+      escape_html(&photodata::build_public_url(base_url, src))
+    ));
+    if let Some((_, lastmod)) = lastmod {
+      sitemap.push_str(&format!("<lastmod>{}</lastmod>\n", lastmod));
+    }
+    sitemap.push_str(image_lst);
+    sitemap.push_str("</url>\n");
+  }
+  sitemap.push_str("</urlset>\n");
+  sitemap
+}
+
+/// 作業ディレクトリのデータから、`export_html`で書き出すページのsitemap.xmlを出力先のフォルダに書き出す
+/// `inherit_group`は`export_html`と同じ
+pub fn write_sitemap(
+  work_directory: &str,
+  output_directory: &str,
+  base_url: &str,
+  url_prefix: &str,
+  inherit_group: bool,
+) -> Result<()> {
+  let (photo_data_lst, group_data_lst) = load_export_target(
+    work_directory,
+    None,
+    &RatingFilter::default(),
+    inherit_group,
+  )?;
+  fs::create_dir_all(output_directory)?;
+  fs::write(
+    Path::new(output_directory).join("sitemap.xml"),
+    export_sitemap(&photo_data_lst, &group_data_lst, base_url, url_prefix),
+  )?;
+  Ok(())
+}
+
+/// グループの撮影日時
+/// 年月日が揃っていない場合は`None`にし、時刻が無い場合は0時0分とする
+fn group_datetime(group_data: &GroupData) -> Option<NaiveDateTime> {
+  let parse = |value: &Option<String>| value.as_deref().and_then(|v| v.trim().parse::<u32>().ok());
+  let date = NaiveDate::from_ymd_opt(
+    parse(&group_data.year)? as i32,
+    parse(&group_data.month)?,
+    parse(&group_data.day)?,
+  )?;
+  date.and_hms_opt(
+    parse(&group_data.hour).unwrap_or(0),
+    parse(&group_data.minutes).unwrap_or(0),
+    0,
+  )
+}
+
+/// 公開先のURLが分かっている場合は完全なURLに、分からない場合は相対的なpathにする
+/// 相対的なpathにするときは、URLの前に付けたpathを取り除く
+fn feed_url(base_url: Option<&str>, url_prefix: &str, src: &str) -> String {
+  match base_url {
+    Some(base_url) => photodata::build_public_url(base_url, src),
+    None => src
+      .strip_prefix(&photodata::join_url_prefix(url_prefix, ""))
+      .unwrap_or_else(|| relative_src(src))
+      .to_string(),
+  }
+}
+
+/// グループの一覧からAtomフィードを作る
+/// 撮影日の新しい順に並べ、撮影日の無いグループは元の順番のまま最後に置く
+fn make_atom_feed(
+  group_data_lst: &[GroupData],
+  cover_src_lst: &HashMap<String, String>,
+  base_url: Option<&str>,
+  url_prefix: &str,
+  now: DateTime<FixedOffset>,
+) -> String {
+  let format_time = |time: DateTime<FixedOffset>| time.format("%Y-%m-%dT%H:%M:%S%:z").to_string();
+  let mut entry_lst = group_data_lst
+    .iter()
+    .map(|group_data| (group_datetime(group_data), group_data))
+    .collect::<Vec<_>>();
+  // 安定ソートなので撮影日の無いグループの順番は変わらない
+  entry_lst.sort_by(|(a, _), (b, _)| b.cmp(a));
+  let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+  feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+  feed.push_str("<title>photag</title>\n");
+  match base_url {
+    Some(base_url) => {
+      feed.push_str(&format!("<id>{}</id>\n", escape_html(base_url)));
+      feed.push_str(&format!("<link href=\"{}\"/>\n", escape_html(base_url)));
+    }
+    None => feed.push_str("<id>urn:photag:feed</id>\n"),
+  }
+  feed.push_str(&format!("<updated>{}</updated>\n", format_time(now)));
+  // Atomではフィードかエントリのどちらかに著者が必要なので、フィード全体に付ける
+  feed.push_str("<author><name>photag</name></author>\n");
+  for (datetime, group_data) in entry_lst.into_iter() {
+    let link = feed_url(
+      base_url,
+      url_prefix,
+      &photodata::group_page_src(url_prefix, &group_data.group_id),
+    );
+    let id = match base_url {
+      Some(_) => link.clone(),
+      None => format!("urn:photag:group:{}", group_data.group_id),
+    };
+    // 撮影日の無いグループはフィードを作った時刻を更新日時にする
+    let updated = datetime
+      .and_then(|datetime| now.offset().from_local_datetime(&datetime).single())
+      .unwrap_or(now);
+    feed.push_str("<entry>\n");
+    feed.push_str(&format!(
+      "<title>{}</title>\n",
+      escape_html(&group_data.title)
+    ));
+    feed.push_str(&format!("<id>{}</id>\n", escape_html(&id)));
+    feed.push_str(&format!(
+      "<link rel=\"alternate\" href=\"{}\"/>\n",
+      escape_html(&link)
+    ));
+    feed.push_str(&format!("<updated>{}</updated>\n", format_time(updated)));
+    feed.push_str(&format!(
+      "<summary>{}</summary>\n",
+      escape_html(&group_data.description)
+    ));
+    if let Some(cover_src) = cover_src_lst.get(&group_data.group_id) {
+      feed.push_str(&format!(
+        "<link rel=\"enclosure\" type=\"image/jpeg\" href=\"{}\"/>\n",
+        escape_html(&feed_url(base_url, url_prefix, cover_src))
+      ));
+    }
+    feed.push_str("</entry>\n");
+  }
+  feed.push_str("</feed>\n");
+  feed
+}
+
+/// グループの一覧をAtomフィードとして書き出す
+/// 各グループのカバー画像（無い場合は最初の写真）を添付画像にする
+pub fn export_feed(
+  work_directory: &str,
+  output_path: &str,
+  base_url: Option<&str>,
+  url_prefix: &str,
+) -> Result<()> {
+  let photo_data_lst = photodata::load_photo_data_from_work_directory(work_directory)?
+    .into_iter()
+    .map(|photo_data| (photo_data.photo_id.clone(), photo_data))
+    .collect::<HashMap<String, PhotoData>>();
+  let group_data_lst = photodata::load_group_data_from_work_directory(work_directory)?;
+  let mut cover_src_lst = HashMap::new();
+  for group_data in group_data_lst.iter() {
+    let cover_src = photodata::cover_src(url_prefix, &group_data.group_id);
+    let photo_src = group_data
+      .cover_photo_id
+      .iter()
+      .chain(group_data.photo_id_list.iter())
+      .find_map(|id| photo_data_lst.get(id))
+      .map(|photo_data| photo_data.photo_src.clone());
+    if Path::new(work_directory)
+      .join(photodata::src_to_path(&cover_src))
+      .exists()
+    {
+      cover_src_lst.insert(group_data.group_id.clone(), cover_src);
+    } else if let Some(photo_src) = photo_src {
+      cover_src_lst.insert(group_data.group_id.clone(), photo_src);
+    }
+  }
+  fs::write(
+    output_path,
+    make_atom_feed(
+      &group_data_lst,
+      &cover_src_lst,
+      base_url,
+      url_prefix,
+      save::get_now(),
+    ),
+  )?;
+  Ok(())
+}
+
+/// 1枚の写真のExifの全フィールド
+struct ExifDump {
+  photo_id: String,
+  file_name: String,
+  field_lst: Vec<(String, String)>,
+}
+
+/// 全ての写真のExifを読み込む
+/// 読み込めなかった写真はフィールドを空にする
+fn load_exif_dump_lst(
+  import_photo_data_lst: &[ImportPhotoData],
+  original_directory: &str,
+) -> Vec<ExifDump> {
+  import_photo_data_lst
+    .iter()
+    .map(|import_photo_data| {
+      let path = format!("{}/{}", original_directory, import_photo_data.file_name);
+      let field_lst = photodata::dump_all_exif(&path).unwrap_or_else(|err| {
+        warn!("Exif情報を読み込めませんでした（{}）: {}", path, err);
+        Vec::new()
+      });
+      ExifDump {
+        photo_id: import_photo_data.id.clone(),
+        file_name: import_photo_data.file_name.clone(),
+        field_lst,
+      }
+    })
+    .collect()
+}
+
+/// CSVの1つのセルに入れる文字列をエスケープする
+fn escape_csv(s: &str) -> String {
+  if s.contains([',', '"', '\n', '\r']) {
+    format!("\"{}\"", s.replace('"', "\"\""))
+  } else {
+    s.to_string()
+  }
+}
+
+/// タグを列、写真を行にしたCSVを作る
+/// 列は全ての写真に含まれるタグの和集合で、値が無いセルは空にする
+fn make_exif_csv(dump_lst: &[ExifDump]) -> String {
+  let mut tag_lst: Vec<&str> = Vec::new();
+  for dump in dump_lst.iter() {
+    for (tag, _) in dump.field_lst.iter() {
+      if !tag_lst.contains(&tag.as_str()) {
+        tag_lst.push(tag);
+      }
+    }
+  }
+  let mut csv = ["photo_id", "file_name"]
+    .iter()
+    .chain(tag_lst.iter())
+    .map(|s| escape_csv(s))
+    .collect::<Vec<String>>()
+    .join(",");
+  csv.push('\n');
+  for dump in dump_lst.iter() {
+    let value_lst = tag_lst.iter().map(|tag| {
+      dump
+        .field_lst
+        .iter()
+        .find(|(t, _)| t == tag)
+        .map(|(_, value)| value.as_str())
+        .unwrap_or_default()
+    });
+    let row = [dump.photo_id.as_str(), dump.file_name.as_str()]
+      .into_iter()
+      .chain(value_lst)
+      .map(escape_csv)
+      .collect::<Vec<String>>()
+      .join(",");
+    csv.push_str(&row);
+    csv.push('\n');
+  }
+  csv
+}
+
+/// 写真ごとにタグと値の組をまとめたJSONを作る
+fn make_exif_json(dump_lst: &[ExifDump]) -> Result<String> {
+  let v = dump_lst
+    .iter()
+    .map(|dump| {
+      let exif = dump
+        .field_lst
+        .iter()
+        .map(|(tag, value)| (tag.clone(), serde_json::Value::String(value.clone())))
+        .collect::<serde_json::Map<String, serde_json::Value>>();
+      serde_json::json!({
+        "photo_id": dump.photo_id,
+        "file_name": dump.file_name,
+        "exif": exif,
+      })
+    })
+    .collect::<Vec<serde_json::Value>>();
+  Ok(serde_json::to_string_pretty(&v)?)
+}
+
+/// 入力のJSONファイルに書かれた全ての写真のExifをまとめて書き出す
+/// 出力先の拡張子が`.csv`の場合はCSV、それ以外の場合はJSONで書き出す
+pub fn export_exif(
+  input_json_path: &str,
+  original_directory: &str,
+  output_path: &str,
+) -> Result<()> {
+  let import_photo_data_lst = photodata::load_import_json_file(input_json_path)?;
+  photodata::ensure_unique_ids(&import_photo_data_lst)?;
+  photodata::ensure_files_exist(&import_photo_data_lst, original_directory)?;
+  let dump_lst = load_exif_dump_lst(&import_photo_data_lst, original_directory);
+  let is_csv = Path::new(output_path)
+    .extension()
+    .map(|ext| ext.eq_ignore_ascii_case("csv"))
+    .unwrap_or(false);
+  let content = if is_csv {
+    make_exif_csv(&dump_lst)
+  } else {
+    make_exif_json(&dump_lst)?
+  };
+  fs::write(output_path, content)?;
+  Ok(())
+}
+
+/// 写真ごとに1行のCSVを書き出す
+/// 表計算ソフトで編集した後に取り込めるように、列名は`photo_data.json`の項目名にする
+pub fn export_csv(work_directory: &str, output_path: &str) -> Result<()> {
+  let photo_data_lst = photodata::load_photo_data_from_work_directory(work_directory)?;
+  let mut writer = csv::Writer::from_path(output_path)?;
+  let mut header = vec!["photo_id", "file_name"];
+  header.extend(PhotoField::ALL.iter().map(|field| field.name()));
+  writer.write_record(&header)?;
+  for photo_data in photo_data_lst.into_iter() {
+    let photo_data = photodata::photo_data_to_gui_photo_data(photo_data);
+    let mut record = vec![photo_data.photo_id.as_str(), photo_data.file_name.as_str()];
+    record.extend(PhotoField::ALL.iter().map(|field| field.get(&photo_data)));
+    writer.write_record(&record)?;
+  }
+  writer.flush()?;
+  Ok(())
+}
+
+/// 撮影統計のレポートの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+  Html,
+  Markdown,
+}
+
+impl ReportFormat {
+  /// 書き出すファイルの拡張子
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ReportFormat::Html => "html",
+      ReportFormat::Markdown => "md",
+    }
+  }
+}
+
+/// 集計の対象にする撮影日の期間
+/// `None`の場合はその側を制限しない
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReportPeriod {
+  pub from: Option<NaiveDate>,
+  pub to: Option<NaiveDate>,
+}
+
+impl ReportPeriod {
+  /// 撮影日が期間に含まれるかどうか
+  /// 期間が指定されている場合、撮影日が分からない写真は含めない
+  fn contains(&self, photo_data: &GUIPhotoData) -> bool {
+    if self.from.is_none() && self.to.is_none() {
+      return true;
+    }
+    match photodata::photo_date(photo_data) {
+      Some(date) => {
+        self.from.map(|from| from <= date).unwrap_or(true)
+          && self.to.map(|to| date <= to).unwrap_or(true)
+      }
+      None => false,
+    }
+  }
+
+  fn label(&self) -> String {
+    let format = |date: Option<NaiveDate>| {
+      date
+        .map(|date| date.format("%Y/%m/%d").to_string())
+        .unwrap_or_default()
+    };
+    if self.from.is_none() && self.to.is_none() {
+      "全期間".to_string()
+    } else {
+      format!("{}〜{}", format(self.from), format(self.to))
+    }
+  }
+}
+
+/// 値が無い項目を集計するときの名前
+const UNKNOWN_LABEL: &str = "不明";
+
+/// 名前ごとに数を数える
+fn count_by<F: Fn(&GUIPhotoData) -> String>(
+  photo_data_lst: &[&GUIPhotoData],
+  key: F,
+) -> Vec<(String, usize)> {
+  let mut count_lst: Vec<(String, usize)> = Vec::new();
+  for photo_data in photo_data_lst.iter() {
+    let key = key(photo_data);
+    match count_lst.iter_mut().find(|(k, _)| *k == key) {
+      Some((_, count)) => *count += 1,
+      None => count_lst.push((key, 1)),
+    }
+  }
+  count_lst
+}
+
+/// 機材（ボディとレンズの組）ごとの枚数を多い順に並べる
+fn count_by_equipment(photo_data_lst: &[&GUIPhotoData]) -> Vec<(String, usize)> {
+  let mut count_lst = count_by(photo_data_lst, |photo_data| {
+    let equipment = [&photo_data.body, &photo_data.lens]
+      .iter()
+      .filter(|s| !s.is_empty())
+      .map(|s| s.as_str())
+      .collect::<Vec<&str>>()
+      .join(" + ");
+    if equipment.is_empty() {
+      UNKNOWN_LABEL.to_string()
+    } else {
+      equipment
+    }
+  });
+  count_lst.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then(a_key.cmp(b_key)));
+  count_lst
+}
+
+/// 焦点距離ごとの枚数を焦点距離の短い順に並べる
+fn count_by_focal_length(photo_data_lst: &[&GUIPhotoData]) -> Vec<(String, usize)> {
+  let mut count_lst = count_by(photo_data_lst, |photo_data| {
+    photodata::normalize_focal_length(&photo_data.focal_length)
+      .map(|focal_length| format!("{}mm", focal_length))
+      .unwrap_or_else(|| UNKNOWN_LABEL.to_string())
+  });
+  let focal_length_key = |key: &str| {
+    key
+      .trim_end_matches("mm")
+      .parse::<f64>()
+      .unwrap_or(f64::MAX)
+  };
+  count_lst.sort_by(|(a, _), (b, _)| focal_length_key(a).total_cmp(&focal_length_key(b)));
+  count_lst
+}
+
+/// 撮影月ごとの枚数を古い順に並べる
+fn count_by_month(photo_data_lst: &[&GUIPhotoData]) -> Vec<(String, usize)> {
+  let mut count_lst = count_by(photo_data_lst, |photo_data| {
+    photodata::photo_date(photo_data)
+      .map(|date| date.format("%Y/%m").to_string())
+      .unwrap_or_else(|| UNKNOWN_LABEL.to_string())
+  });
+  // 「不明」は最後に並べる
+  count_lst.sort_by_key(|(key, _)| (key == UNKNOWN_LABEL, key.clone()));
+  count_lst
+}
+
+/// 集計結果の表と棒グラフをHTMLで作る
+fn make_html_table(heading: &str, count_lst: &[(String, usize)]) -> String {
+  let max = count_lst.iter().map(|(_, count)| *count).max().unwrap_or(1);
+  let mut html = format!(
+    "<h2>{}</h2>\n<table>\n<tr><th>項目</th><th>枚数</th><th></th></tr>\n",
+    escape_html(heading)
+  );
+  for (key, count) in count_lst.iter() {
+    html.push_str(&format!(
+      "<tr><td>{}</td><td>{}</td><td><div class=\"bar\" style=\"width: {}px\"></div></td></tr>\n",
+      escape_html(key),
+      count,
+      count * 300 / max
+    ));
+  }
+  html.push_str("</table>\n");
+  html
+}
+
+/// 集計結果の表と棒グラフをMarkdownで作る
+fn make_markdown_table(heading: &str, count_lst: &[(String, usize)]) -> String {
+  let max = count_lst.iter().map(|(_, count)| *count).max().unwrap_or(1);
+  let mut markdown = format!(
+    "## {}\n\n| 項目 | 枚数 | グラフ |\n| --- | ---: | --- |\n",
+    heading
+  );
+  for (key, count) in count_lst.iter() {
+    markdown.push_str(&format!(
+      "| {} | {} | {} |\n",
+      key.replace('|', "\\|"),
+      count,
+      "█".repeat((count * 20 / max).max(1))
+    ));
+  }
+  markdown.push('\n');
+  markdown
+}
+
+/// 機材別・焦点距離別・月別の撮影枚数のレポートを作る
+/// 撮影日が`period`に含まれる写真だけを集計する
+pub fn export_statistics_report(
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  format: ReportFormat,
+  period: ReportPeriod,
+) -> String {
+  let photo_data_lst = gui_photo_data_lst
+    .values()
+    .filter(|photo_data| period.contains(photo_data))
+    .collect::<Vec<&GUIPhotoData>>();
+  let table_lst = [
+    ("機材別", count_by_equipment(&photo_data_lst)),
+    ("焦点距離別", count_by_focal_length(&photo_data_lst)),
+    ("月別", count_by_month(&photo_data_lst)),
+  ];
+  let summary = format!("期間：{}、写真：{}枚", period.label(), photo_data_lst.len());
+  match format {
+    ReportFormat::Html => {
+      let mut body = format!("<h1>撮影統計</h1>\n<p>{}</p>\n", escape_html(&summary));
+      for (heading, count_lst) in table_lst.iter() {
+        body.push_str(&make_html_table(heading, count_lst));
+      }
+      make_page("撮影統計", &body, photodata::DEFAULT_LANGUAGE, "")
+    }
+    ReportFormat::Markdown => {
+      let mut markdown = format!("# 撮影統計\n\n{}\n\n", summary);
+      for (heading, count_lst) in table_lst.iter() {
+        markdown.push_str(&make_markdown_table(heading, count_lst));
+      }
+      markdown
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn make_test_group_data(group_id: &str, title: &str, photo_id_lst: &[&str]) -> GroupData {
+    photodata::gui_group_data_to_group_data(GUIGroupData {
+      group_id: group_id.to_string(),
+      title: title.to_string(),
+      photo_id_list: photo_id_lst.iter().map(|id| id.to_string()).collect(),
+      ..photodata::make_dummy_gui_group_data()
+    })
+  }
+
+  #[test]
+  fn escape_html_escapes_special_characters() {
+    assert_eq!(
+      escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+      "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+    );
+    assert_eq!(escape_html("夕焼けの海"), "夕焼けの海");
+    // 既にエスケープされた文字列はもう一度エスケープする
+    assert_eq!(escape_html("&amp;"), "&amp;amp;");
+  }
+
+  #[test]
+  fn index_html_links_every_group() {
+    let group_data_lst = vec![
+      make_test_group_data("sea", "海", &["1", "2"]),
+      make_test_group_data("mountain", "<山>", &["3"]),
+    ];
+    let html = make_index_html(
+      &group_data_lst,
+      &HashMap::new(),
+      photodata::DEFAULT_LANGUAGE,
+    );
+    for group_data in group_data_lst.iter() {
+      let page = page_file_name(&group_data.group_id, photodata::DEFAULT_LANGUAGE);
+      assert!(html.contains(&format!("<a href=\"{}\">", page)));
+    }
+    assert!(html.contains("海</a>（2枚）"));
+    assert!(html.contains("&lt;山&gt;</a>（1枚）"));
+    assert!(!html.contains("<山>"));
+    // 他の言語のページには言語コード付きのファイル名で繋ぐ
+    let html = make_index_html(&group_data_lst, &HashMap::new(), "en");
+    assert!(html.contains("<a href=\"sea.en.html\">"));
+    assert!(html.contains("<a href=\"mountain.en.html\">"));
+  }
+
+  #[test]
+  fn csv_export_then_import_changes_nothing() {
+    let work_dir = crate::test_util::temp_dir("csv_round_trip");
+    let photo_data_lst = [("1", "海, \"夕日\""), ("2", "山\n雪")]
+      .map(|(id, alt)| {
+        photodata::gui_photo_data_to_photo_data(GUIPhotoData {
+          photo_id: id.to_string(),
+          file_name: format!("{}.jpg", id),
+          alt: alt.to_string(),
+          location: "東京".to_string(),
+          keywords: "海, 夕日".to_string(),
+          year: "2022".to_string(),
+          iso: "200".to_string(),
+          ..photodata::make_dummy_gui_photo_data()
+        })
+      })
+      .to_vec();
+    save::save_work_data(&work_dir, &photo_data_lst, &[], save::JsonStyle::Pretty).unwrap();
+    let csv_path = format!("{}/photo_data.csv", work_dir);
+    export_csv(&work_dir, &csv_path).unwrap();
+    assert_eq!(
+      crate::mapping::import_csv(&work_dir, &csv_path, save::JsonStyle::Pretty).unwrap(),
+      0
+    );
+    assert_eq!(
+      photodata::load_photo_data_from_work_directory(&work_dir).unwrap(),
+      photo_data_lst
+    );
+    // 表計算ソフトで編集した項目だけが反映される
+    let csv = fs::read_to_string(&csv_path).unwrap();
+    fs::write(&csv_path, csv.replacen("東京", "大阪", 1)).unwrap();
+    assert_eq!(
+      crate::mapping::import_csv(&work_dir, &csv_path, save::JsonStyle::Pretty).unwrap(),
+      1
+    );
+    let loaded = photodata::load_photo_data_from_work_directory(&work_dir).unwrap();
+    assert_eq!(loaded[0].location, "大阪");
+    assert_eq!(loaded[1], photo_data_lst[1]);
+  }
+
+  /// XMLの開始タグと終了タグが入れ子として正しく対応していることを確かめる
+  fn assert_balanced_xml(xml: &str) {
+    let mut stack = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+      let end = start + rest[start..].find('>').unwrap();
+      let tag = &rest[start + 1..end];
+      rest = &rest[end + 1..];
+      // XML宣言と空要素タグは対応する終了タグが無い
+      if tag.starts_with('?') || tag.ends_with('/') {
+        continue;
+      }
+      match tag.strip_prefix('/') {
+        Some(name) => assert_eq!(stack.pop(), Some(name), "{}", xml),
+        None => stack.push(tag.split_whitespace().next().unwrap()),
+      }
+    }
+    assert!(stack.is_empty(), "{}", xml);
+  }
+
+  #[test]
+  fn atom_feed_has_an_entry_for_every_group() {
+    let dated_group_data = |group_id: &str, title: &str, month: &str| GroupData {
+      year: Some("2022".to_string()),
+      month: Some(month.to_string()),
+      day: Some("1".to_string()),
+      ..make_test_group_data(group_id, title, &["1"])
+    };
+    let group_data_lst = vec![
+      make_test_group_data("river", "<川>", &[]),
+      dated_group_data("sea", "海", "8"),
+      dated_group_data("mountain", "山", "9"),
+    ];
+    let now = DateTime::parse_from_rfc3339("2022-10-01T12:00:00+09:00").unwrap();
+    let feed = make_atom_feed(
+      &group_data_lst,
+      &HashMap::new(),
+      None,
+      photodata::DEFAULT_URL_PREFIX,
+      now,
+    );
+    assert!(feed.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed "));
+    assert!(feed.ends_with("</feed>\n"));
+    assert_eq!(feed.matches("<entry>").count(), 3);
+    assert_eq!(feed.matches("</entry>").count(), 3);
+    assert!(feed.contains("<title>&lt;川&gt;</title>"));
+    assert_balanced_xml(&feed);
+    // 著者はエントリごとではなくフィードに1つだけ付ける
+    assert_eq!(
+      feed.matches("<author><name>photag</name></author>").count(),
+      1
+    );
+    assert!(feed.find("<author>").unwrap() < feed.find("<entry>").unwrap());
+    // 撮影日の新しい順に並び、撮影日の無いグループは最後になる
+    let position = |group_id: &str| {
+      feed
+        .find(&format!("<id>urn:photag:group:{}</id>", group_id))
+        .unwrap()
+    };
+    assert!(position("mountain") < position("sea"));
+    assert!(position("sea") < position("river"));
+    assert!(feed.contains("<updated>2022-09-01T00:00:00+09:00</updated>"));
+
+    let feed = make_atom_feed(
+      &group_data_lst,
+      &HashMap::new(),
+      Some("https://example.com/"),
+      photodata::DEFAULT_URL_PREFIX,
+      now,
+    );
+    for group_data in group_data_lst.iter() {
+      assert!(feed.contains(&format!(
+        "<link rel=\"alternate\" href=\"https://example.com/{}.html\"/>",
+        group_data.group_id
+      )));
+    }
+    assert_balanced_xml(&feed);
+  }
+
+  #[test]
+  fn atom_feed_links_respect_url_prefix() {
+    let group_data_lst = vec![make_test_group_data("sea", "海", &["1"])];
+    let mut cover_src_lst = HashMap::new();
+    cover_src_lst.insert("sea".to_string(), photodata::cover_src("/gallery/", "sea"));
+    let now = DateTime::parse_from_rfc3339("2022-10-01T12:00:00+09:00").unwrap();
+    let feed = make_atom_feed(
+      &group_data_lst,
+      &cover_src_lst,
+      Some("https://example.com/"),
+      "/gallery/",
+      now,
+    );
+    assert!(
+      feed.contains("<link rel=\"alternate\" href=\"https://example.com/gallery/sea.html\"/>")
+    );
+    assert!(feed.contains(
+      "<link rel=\"enclosure\" type=\"image/jpeg\" href=\"https://example.com/gallery/images/covers/sea.JPG\"/>"
+    ));
+    // 公開先が分からない場合は、前に付けたpathを除いた相対的なpathにする
+    let feed = make_atom_feed(&group_data_lst, &cover_src_lst, None, "/gallery/", now);
+    assert!(feed.contains("<link rel=\"alternate\" href=\"sea.html\"/>"));
+    assert!(
+      feed.contains("<link rel=\"enclosure\" type=\"image/jpeg\" href=\"images/covers/sea.JPG\"/>")
+    );
+  }
+
+  #[test]
+  fn publish_diff_reports_only_edited_photos() {
+    let work_dir = crate::test_util::temp_dir("publish_diff");
+    let mut photo_data_lst = ["1", "2", "3"]
+      .map(|id| {
+        photodata::gui_photo_data_to_photo_data(GUIPhotoData {
+          photo_id: id.to_string(),
+          photo_src: photodata::photo_src(photodata::DEFAULT_URL_PREFIX, id),
+          ..photodata::make_dummy_gui_photo_data()
+        })
+      })
+      .to_vec();
+    let hash_lst = |photo_data_lst: &[PhotoData]| {
+      photo_data_lst
+        .iter()
+        .map(|photo_data| {
+          (
+            photo_data.photo_id.clone(),
+            photo_publish_hash(&work_dir, photo_data).unwrap(),
+          )
+        })
+        .collect::<Vec<_>>()
+    };
+    let now = DateTime::parse_from_rfc3339("2022-08-01T12:00:00+09:00").unwrap();
+    let old_info_lst = update_publish_info(&[], hash_lst(&photo_data_lst), now);
+    assert!(diff_publish_info(&old_info_lst, &hash_lst(&photo_data_lst)).is_empty());
+
+    photo_data_lst[1].location = "京都".to_string();
+    assert_eq!(
+      diff_publish_info(&old_info_lst, &hash_lst(&photo_data_lst)),
+      ChangeList {
+        modified: vec!["2".to_string()],
+        ..ChangeList::default()
+      }
+    );
+
+    let removed = photo_data_lst.remove(0);
+    photo_data_lst.push(PhotoData {
+      photo_id: "4".to_string(),
+      ..removed
+    });
+    let change_list = diff_publish_info(&old_info_lst, &hash_lst(&photo_data_lst));
+    assert_eq!(change_list.added, vec!["4"]);
+    assert_eq!(change_list.modified, vec!["2"]);
+    assert_eq!(change_list.removed, vec!["1"]);
+  }
+
+  #[test]
+  fn rejected_photos_are_removed_from_photos_and_groups() {
+    let photo_data_lst = [
+      ("1", None),
+      ("2", Some(photodata::FLAG_REJECT)),
+      ("3", Some(photodata::FLAG_PICK)),
+    ]
+    .map(|(id, flag)| {
+      photodata::gui_photo_data_to_photo_data(GUIPhotoData {
+        photo_id: id.to_string(),
+        flag: flag.map(str::to_string),
+        ..photodata::make_dummy_gui_photo_data()
+      })
+    })
+    .to_vec();
+    let group_data_lst = vec![
+      GroupData {
+        cover_photo_id: Some("2".to_string()),
+        ..make_test_group_data("sea", "海", &["1", "2"])
+      },
+      make_test_group_data("mountain", "山", &["2"]),
+      make_test_group_data("empty", "空", &[]),
+    ];
+    let (photo_data_lst, group_data_lst) = exclude_rejected(photo_data_lst, group_data_lst);
+    assert_eq!(
+      photo_data_lst
+        .iter()
+        .map(|photo_data| photo_data.photo_id.as_str())
+        .collect::<Vec<_>>(),
+      vec!["1", "3"]
+    );
+    // 不採用の写真だけのグループは書き出さないが、元から空のグループは残す
+    assert_eq!(
+      group_data_lst
+        .iter()
+        .map(|group_data| group_data.group_id.as_str())
+        .collect::<Vec<_>>(),
+      vec!["sea", "empty"]
+    );
+    assert_eq!(group_data_lst[0].photo_id_list, vec!["1"]);
+    assert_eq!(group_data_lst[0].cover_photo_id, None);
+  }
+}
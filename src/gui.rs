@@ -1,22 +1,37 @@
-use chrono::{DateTime, FixedOffset};
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset, NaiveDate};
 use eframe::{
   egui,
   egui::{FontData, FontDefinitions, FontFamily},
 };
 use egui_extras::RetainedImage;
-use std::collections::HashMap;
-use std::fs::File;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
 use std::io::Write;
+use std::sync::Arc;
 
+use crate::audit;
+use crate::config;
+use crate::export;
 use crate::image;
-use crate::photodata::{self, GUIGroupData, GUIPhotoData};
+use crate::mapping;
+use crate::photodata::{self, GUIGroupData, GUIPhotoData, GroupData, ImportPhotoData, PhotoData};
 use crate::save;
+use crate::session;
+use crate::startup;
+use crate::tag_style;
+use crate::template;
+use crate::undo;
+use crate::validate;
 
 #[derive(Clone, Debug)]
 pub struct PhotagApp {
   /// 現在のメイン画面に表示するものを決めるためにモードを保持したい
   /// - 写真データの編集モード
   /// - 写真グループの編集モード
+  /// - 写真の一覧表示モード
   pub mode: Mode,
   /// 画像IDのリスト
   pub photo_id_lst: Vec<String>,
@@ -28,6 +43,12 @@ pub struct PhotagApp {
   pub gui_group_data_lst: HashMap<String, photodata::GUIGroupData>,
   /// idと現像後の画像への絶対pathのペアを保持する
   pub thumbnail_lst: HashMap<String, Vec<u8>>,
+  /// 一覧表示用に正方形に切り抜いたサムネイル
+  /// 表示するときに作り、毎フレーム読み込み直さないように読み込んだ画像を保持する
+  pub square_thumbnail_lst: HashMap<String, LoadedImage>,
+  /// グループ編集画面の一覧用に縮小したサムネイル
+  /// 表示するときに作る
+  pub group_thumbnail_lst: HashMap<String, Vec<u8>>,
   /// 現像時に手で作ったJSONファイルへのpath
   pub input_json_path: String,
   /// オリジナル画像が入っているフォルダへのpath
@@ -45,15 +66,595 @@ pub struct PhotagApp {
   pub json_save_time: DateTime<FixedOffset>,
   /// 画像を書き出した時刻を保持する
   pub image_save_time: DateTime<FixedOffset>,
+  /// 変更履歴を作るための前回保存時のデータ
+  pub audit_snapshot: audit::AuditSnapshot,
+  /// 変更履歴のウィンドウを表示するかどうか
+  pub show_audit_log: bool,
+  /// 変更履歴のウィンドウに表示する内容
+  pub audit_log_lst: Vec<audit::AuditEntry>,
+  /// 2つの`photo_data.json`を比較するウィンドウを表示するかどうか
+  pub show_json_diff: bool,
+  /// 比較元の`photo_data.json`へのpath
+  pub json_diff_path_a: String,
+  /// 比較先の`photo_data.json`へのpath
+  pub json_diff_path_b: String,
+  /// 比較した結果
+  /// 読み込めなかった場合はその理由が入る
+  pub json_diff_result: Option<Result<Vec<save::JsonDiff>, String>>,
+  /// 撮影日時とファイルの日時のずれを確認するウィンドウを表示するかどうか
+  pub show_timestamp_discrepancy: bool,
+  /// ずれとみなす閾値（時間）
+  pub timestamp_discrepancy_hours: i64,
+  /// 撮影日時とファイルの日時がずれている写真の一覧
+  pub timestamp_discrepancy_lst: Vec<photodata::TimestampDiscrepancy>,
+  /// 削除や編集を取り消すための履歴
+  pub undo_stack: undo::UndoStack,
+  /// 圧縮プレビューを作るためのデータ（画像IDとセットで保持する）
+  /// 画像を読み込めなかった場合は`None`が入る
+  pub preview_compressor: Option<(String, Option<image::PreviewCompressor>)>,
+  /// 表示用に読み込んだ圧縮プレビュー（圧縮したときの品質とセットで保持する）
+  /// 写真か品質が変わったときだけ読み込み直す
+  pub preview_image: Option<(f32, LoadedImage)>,
+  /// 圧縮プレビューの品質
+  pub preview_quality: f32,
+  /// オリジナルの画像から読み込んだExifの全てのフィールド（画像IDとセットで保持する）
+  /// Exifが無いか読み込めなかった場合は空になる
+  pub exif_dump: Option<(String, Vec<(String, String)>)>,
+  /// グループの説明文のテンプレート
+  pub description_template_lst: Vec<template::DescriptionTemplate>,
+  /// 説明文のテンプレートを編集するウィンドウを表示するかどうか
+  pub show_description_template: bool,
+  /// タグごとの表示の色とアイコン
+  pub tag_style_lst: BTreeMap<String, tag_style::TagStyle>,
+  /// タグの表示を編集するウィンドウを表示するかどうか
+  pub show_tag_style: bool,
+  /// 連番リネームのウィンドウを表示するかどうか
+  pub show_renumber: bool,
+  /// 連番を振るときの並び順
+  pub renumber_order: save::RenumberOrder,
+  /// 連番の開始番号
+  pub renumber_start: usize,
+  /// 連番の桁数
+  pub renumber_padding: usize,
+  /// 連番リネームの結果のメッセージ
+  pub renumber_message: String,
+  /// 画像ID一覧を絞り込むための条件
+  pub photo_filter: PhotoFilter,
+  /// どのグループにも含まれていない写真だけを表示するかどうか
+  pub show_orphan_only: bool,
+  /// 画面下部に一時的に表示するメッセージ
+  pub toast: Option<Toast>,
+  /// プレビュー画像上でクリックした位置の色
+  pub picked_color: Option<[u8; 3]>,
+  /// 前の写真の撮影日時に足す分数
+  pub relative_minutes: i64,
+  /// 検証で問題が見つかった画像
+  pub invalid_image_lst: Vec<InvalidImage>,
+  /// 元画像のフォルダに見つからず、読み込まなかった画像
+  /// 入力のJSONファイルを保存するときは消さずに残す
+  pub missing_file_lst: Vec<ImportPhotoData>,
+  /// 入力のJSONファイルで複数の画像に付けられていたID
+  /// 先頭の画像だけを読み込み、残りは入力のJSONファイルを保存するときに消さずに残す
+  pub duplicate_id_lst: Vec<photodata::DuplicateId>,
+  /// 前回読み込んだ入力のJSONファイルの内容（見つからない画像は除く）
+  /// 読み直したときに差分だけを反映するために使う
+  pub last_import_photo_data_lst: Vec<ImportPhotoData>,
+  /// 見つからない画像の一覧を表示するかどうか
+  pub show_missing_file: bool,
+  /// 重複しているIDの一覧を表示するかどうか
+  pub show_duplicate_id: bool,
+  /// 画像の検証結果のウィンドウを表示するかどうか
+  pub show_invalid_image: bool,
+  /// 破損しているため圧縮などの処理対象から外した画像のID
+  pub excluded_photo_id_lst: Vec<String>,
+  /// `srcset`用に生成する画像の幅
+  pub srcset_width_lst: Vec<u32>,
+  /// 画像のURLの前に付けるpath
+  pub url_prefix: String,
+  /// 書き出す画像のJPEGのスキャンの方式
+  pub jpeg_scan: image::JpegScan,
+  /// 一部の写真だけを書き出すときの出力先のフォルダ
+  pub export_directory_path: String,
+  /// 書き出す写真の評価による絞り込み
+  pub export_rating_filter: export::RatingFilter,
+  /// 書き出すときに表示用画像をWebPとJPEGの両方で出力する
+  pub dual_format: bool,
+  /// 書き出すときに写真の空欄の撮影場所と撮影日時をグループの値で補完する
+  pub export_inherit_group: bool,
+  /// 作業ディレクトリと入力のJSONファイルを保存するときの形式
+  pub work_json_style: save::JsonStyle,
+  /// 書き出す`photo_data.json`と`group_data.json`の形式
+  pub export_json_style: save::JsonStyle,
+  /// 書き出すときにウォーターマーク付きの画像も別のフォルダに書き出す設定
+  pub watermark: Option<export::WatermarkOptions>,
+  /// 書き出しの結果のメッセージ
+  pub export_message: String,
+  /// 撮影統計のレポートの形式
+  pub report_format: export::ReportFormat,
+  /// 撮影統計の集計を始める日（空欄の場合は制限しない）
+  pub report_from: String,
+  /// 撮影統計の集計を終える日（空欄の場合は制限しない）
+  pub report_to: String,
+  /// グループのカバー画像を生成するときの写真の配置
+  pub montage_layout: image::MontageLayout,
+  /// 撮影データの正規化の確認ウィンドウを表示するかどうか
+  pub show_normalize_confirm: bool,
+  /// 画像ID一覧のスクロール位置
+  pub photo_list_scroll_offset: f32,
+  /// 起動時に復元するスクロール位置
+  /// 一度反映させたら`None`にする
+  pub restore_scroll_offset: Option<f32>,
+  /// タイムラインで写真をまとめる時間の単位
+  pub timeline_scale: photodata::TimelineScale,
+  /// 比較ウィンドウに固定している写真
+  pub compare_view: Option<CompareView>,
+  /// 原寸表示のウィンドウで表示している写真
+  pub full_size_view: Option<FullSizeView>,
+  /// `photag.toml`に保存する設定
+  pub config: config::Config,
+  /// 設定のウィンドウを表示するかどうか
+  pub show_config: bool,
+  /// 保存ボタンが押されたので、次の更新で画像の更新も確認する
+  pub image_save_requested: bool,
+  /// 起動時の画像の圧縮を行うスレッドとの間で共有する処理状況
+  pub startup_loader: startup::StartupLoader,
+  /// 起動時の画像の圧縮の進み具合
+  pub startup_progress: startup::StartupProgress,
+  /// 遅延処理モードで、まだ表示用の画像を生成していない写真のID
+  pub unprocessed_photo_id_lst: Vec<String>,
+  /// 未処理の画像を処理し終えてから書き出す写真のID
+  pub pending_export_id_lst: Option<Vec<String>>,
+  /// 表示中の写真から作ろうとしているグループ
+  /// タイトルと説明を入力してから作成する
+  pub group_from_list: Option<GUIGroupData>,
+  /// ロックの解除を確認している写真のID
+  pub unlock_confirm_id: Option<String>,
+  /// 未保存の変更があるときに、終了する前の確認を表示するかどうか
+  pub show_close_confirm: bool,
+  /// 終了の確認で保存するかどうかを選び終えたかどうか
+  pub is_close_confirmed: bool,
+  /// 画面の配色
+  pub theme: Theme,
+  /// 公開先のURL
+  pub base_url: Option<String>,
+  /// 画像ID一覧で一括適用の対象に選んでいる写真のID
+  pub selected_photo_id_lst: Vec<String>,
+  /// 選択した写真にまとめて適用する変更
+  pub photo_data_patch: photodata::PhotoDataPatch,
+  /// メタデータを取り込む外部のJSON・CSVファイルへのpath
+  pub metadata_import_path: String,
+  /// 取り込みを確認中のメタデータ
+  pub metadata_import: Option<MetadataImport>,
+  /// 写真編集ページで提案中のタグ
+  pub auto_tag_suggestion: Option<AutoTagSuggestion>,
+  /// 写真の説明とタイトルを編集している言語
+  pub photo_language: String,
+  /// 追加する言語の言語コードの入力欄
+  pub new_language: String,
+  /// 範囲選択の起点になる写真のID
+  pub selection_anchor: Option<String>,
+  /// 選択した写真を追加するグループ
+  pub bulk_group_id: Option<String>,
+  /// 選択した写真に追加するキーワード
+  pub bulk_keywords: String,
+  /// 名前を変更する撮影セッション
+  pub session_rename_from: String,
+  /// 撮影セッションの新しい名前
+  pub session_rename_to: String,
+  /// 編集中のグループの元のIDと、グループIDの入力欄の文字列
+  /// 入力中に他のグループと重複しても入力欄は書き換えず、問題の無いIDだけをグループに反映する
+  pub group_id_edit: Option<(String, String)>,
 }
 
-/// メイン画面に表示するものを決めるためのモード情報
+/// 自動タグ付けで提案したタグと、それぞれを採用するかどうか
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AutoTagSuggestion {
+  /// 提案した写真のID
+  pub photo_id: String,
+  pub tag_lst: Vec<(String, bool)>,
+}
+
+/// 外部ファイルのメタデータを取り込む前に、対応づけを確認している状態
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetadataImport {
+  pub table: mapping::ExternalTable,
+  pub mapping: mapping::FieldMapping,
+}
+
+/// 画像を拡大・移動して表示するときの表示位置
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoomView {
+  /// 拡大率（1.0で全体を表示する）
+  pub zoom: f32,
+  /// 表示している範囲の中心（画像の幅と高さを1とした相対位置）
+  pub center: egui::Pos2,
+}
+
+impl Default for ZoomView {
+  fn default() -> Self {
+    ZoomView {
+      zoom: 1.0,
+      center: egui::pos2(0.5, 0.5),
+    }
+  }
+}
+
+impl ZoomView {
+  /// 拡大率と中心から、表示する範囲をテクスチャ座標で求める
+  /// 範囲が画像からはみ出さないように中心を調整する
+  pub fn uv_rect(&mut self) -> egui::Rect {
+    let half = 0.5 / self.zoom;
+    self.center.x = self.center.x.clamp(half, 1.0 - half);
+    self.center.y = self.center.y.clamp(half, 1.0 - half);
+    egui::Rect::from_center_size(self.center, egui::vec2(half * 2.0, half * 2.0))
+  }
+
+  /// 大きさが`size`で表示している画像を`delta`だけドラッグしたときに表示範囲を動かす
+  pub fn drag(&mut self, delta: egui::Vec2, size: egui::Vec2) {
+    let uv = self.uv_rect();
+    self.center.x -= delta.x / size.x * uv.width();
+    self.center.y -= delta.y / size.y * uv.height();
+  }
+
+  /// マウスホイールの回転量に合わせて、`min`から`max`の範囲で拡大率を変える
+  pub fn scroll(&mut self, scroll_delta: f32, min: f32, max: f32) {
+    self.zoom = (self.zoom * (1.0 + scroll_delta / 500.0)).clamp(min, max);
+  }
+}
+
+/// 写真を並べて比較するために固定表示する写真と、その表示位置
+/// メイン画面の選択を変えても保持される
+/// 画像は固定したときに一度だけ読み込んでおく
+#[derive(Clone, Debug)]
+pub struct CompareView {
+  pub photo_id: String,
+  pub view: ZoomView,
+  image: LoadedImage,
+}
+
+impl CompareView {
+  pub fn new(photo_id: String, image_buf: Option<&[u8]>) -> Self {
+    CompareView {
+      image: LoadedImage::new(&photo_id, image_buf),
+      photo_id,
+      view: ZoomView::default(),
+    }
+  }
+}
+
+/// 原寸表示のウィンドウの最大の拡大率
+const FULL_SIZE_MAX_ZOOM: f32 = 16.0;
+
+/// 原寸表示のウィンドウで表示している写真
+/// 画像はウィンドウを開いて初めて表示するときに読み込み、閉じたらこの値ごと破棄する
+#[derive(Clone)]
+pub struct FullSizeView {
+  pub photo_id: String,
+  pub view: ZoomView,
+  /// 読み込んだ画像か、読み込めなかった理由
+  /// まだ読み込んでいない場合は`None`
+  image: Option<std::result::Result<Arc<RetainedImage>, String>>,
+}
+
+impl std::fmt::Debug for FullSizeView {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("FullSizeView")
+      .field("photo_id", &self.photo_id)
+      .field("view", &self.view)
+      .field(
+        "image",
+        &self
+          .image
+          .as_ref()
+          .map(|image| image.as_ref().map(|image| image.size())),
+      )
+      .finish()
+  }
+}
+
+impl FullSizeView {
+  pub fn new(photo_id: String) -> Self {
+    FullSizeView {
+      photo_id,
+      view: ZoomView::default(),
+      image: None,
+    }
+  }
+
+  /// 表示用の画像（長辺2048px）を読み込む
+  /// 表示用の画像がまだ書き出されていない場合は、オリジナルの画像をその場で縮小して使う
+  fn load(&mut self, work_directory_path: &str, original_image_path: &str) {
+    let normal_path = save::normal_image_path(work_directory_path, &self.photo_id);
+    let result = fs::read(&normal_path)
+      .or_else(|_| {
+        fs::read(original_image_path)
+          .map_err(anyhow::Error::from)
+          .and_then(|raw_data| {
+            image::compression(
+              &raw_data,
+              NORMAL_IMAGE_QUALITY,
+              NORMAL_IMAGE_SIZE,
+              false,
+              image::JpegScan::Baseline,
+            )
+          })
+      })
+      .map_err(|err| format!("{}: {:#}", IMAGE_LOAD_ERROR_MESSAGE, err))
+      .and_then(|image_buf| {
+        RetainedImage::from_image_bytes(&self.photo_id, &image_buf)
+          .map(Arc::new)
+          .map_err(|err| format!("{}: {}", IMAGE_LOAD_ERROR_MESSAGE, err))
+      });
+    self.image = Some(result);
+  }
+}
+
+/// 表示用に読み込んだ画像
+/// 毎フレーム読み込み直すと重いので、元のデータが変わるまで保持しておく
+/// 読み込めなかった場合は`None`が入る
+#[derive(Clone)]
+pub struct LoadedImage(Option<Arc<RetainedImage>>);
+
+impl std::fmt::Debug for LoadedImage {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("LoadedImage")
+      .field(&self.0.as_ref().map(|image| image.size()))
+      .finish()
+  }
+}
+
+impl LoadedImage {
+  pub fn new(debug_name: &str, image_buf: Option<&[u8]>) -> Self {
+    LoadedImage(
+      image_buf
+        .and_then(|image_buf| load_retained_image(debug_name, image_buf))
+        .map(Arc::new),
+    )
+  }
+
+  pub fn get(&self) -> Option<&RetainedImage> {
+    self.0.as_deref()
+  }
+}
+
+/// 起動時の処理についての設定
+#[derive(Clone, Debug, Default)]
+pub struct StartupOptions {
+  /// 起動時に画像の検証を行わない
+  pub skip_validation: bool,
+  /// 破損している画像を圧縮などの処理対象から外す
+  pub exclude_broken: bool,
+  /// `srcset`用に生成する画像の幅
+  /// 空の場合は生成しない
+  pub srcset_width_lst: Vec<u32>,
+  /// 画像のURLの前に付けるpath
+  pub url_prefix: String,
+  /// 書き出す画像のJPEGのスキャンの方式
+  pub jpeg_scan: image::JpegScan,
+  /// 前回終了時の作業状態を復元しない
+  pub fresh: bool,
+  /// Exifの解析に使うスレッド数
+  pub exif_thread_count: usize,
+  /// 公開先のURL（`https://example.com/photos`のように指定する）
+  pub base_url: Option<String>,
+  /// 書き出すときに表示用画像をWebPとJPEGの両方で出力する
+  pub dual_format: bool,
+  /// 作業ディレクトリと入力のJSONファイルを保存するときの形式
+  pub work_json_style: save::JsonStyle,
+  /// 書き出す`photo_data.json`と`group_data.json`の形式
+  pub export_json_style: save::JsonStyle,
+  /// 書き出すときにウォーターマーク付きの画像も別のフォルダに書き出す設定
+  pub watermark: Option<export::WatermarkOptions>,
+  /// 起動時に画像を処理せず、サムネイルは表示するとき、表示用の画像は書き出すときに生成する
+  pub lazy_processing: bool,
+  /// 保存時刻や保存してあるサムネイルを無視して、全ての画像を作り直す
+  pub force_regenerate: bool,
+  /// 画面の配色
+  /// 指定した場合は前回終了時の配色より優先する
+  pub theme: Option<Theme>,
+}
+
+/// 検証で問題が見つかった画像
 #[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidImage {
+  pub photo_id: String,
+  pub file_name: String,
+  /// 問題の内容
+  pub reason: String,
+}
+
+/// メイン画面に表示するものを決めるためのモード情報
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Mode {
   /// 写真データの編集モード
   EditPhotoData,
   /// 写真グループの編集モード
   EditGroupData,
+  /// 全ての写真のサムネイルを一覧表示するモード
+  Gallery,
+  /// 写真を撮影日時順に横に並べて表示するモード
+  Timeline,
+}
+
+/// 画面の配色
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+  /// 明るい配色
+  #[default]
+  Light,
+  /// 暗い配色
+  Dark,
+}
+
+impl std::str::FromStr for Theme {
+  type Err = String;
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    match s {
+      "light" => Ok(Theme::Light),
+      "dark" => Ok(Theme::Dark),
+      _ => Err(format!(
+        "{}は使えません（lightかdarkを指定してください）",
+        s
+      )),
+    }
+  }
+}
+
+impl Theme {
+  pub fn visuals(&self) -> egui::Visuals {
+    match self {
+      Theme::Light => egui::Visuals::light(),
+      Theme::Dark => egui::Visuals::dark(),
+    }
+  }
+
+  /// 起動時のウィンドウの配色
+  pub fn native_theme(&self) -> eframe::Theme {
+    match self {
+      Theme::Light => eframe::Theme::Light,
+      Theme::Dark => eframe::Theme::Dark,
+    }
+  }
+}
+
+/// 画面下部に一時的に表示するメッセージ
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Toast {
+  pub message: String,
+  /// 表示を始めた時刻
+  pub time: DateTime<FixedOffset>,
+}
+
+impl Toast {
+  pub fn new(message: String) -> Self {
+    Toast {
+      message,
+      time: save::get_now(),
+    }
+  }
+}
+
+/// 写真を絞り込むための条件
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PhotoCondition {
+  /// マニュアル露出で撮影された写真
+  Manual,
+  /// 指定した秒数以上の露光時間で撮影された写真
+  LongExposure(f64),
+  /// 開放F値で撮影された写真
+  WideOpen,
+  /// 指定した標高（m）以上で撮影された写真
+  HighAltitude(f64),
+}
+
+impl PhotoCondition {
+  fn matches(&self, photo_data: &GUIPhotoData) -> bool {
+    match self {
+      PhotoCondition::Manual => photo_data.exposure_program == photodata::exposure_program_label(1),
+      PhotoCondition::LongExposure(sec) => photodata::parse_exposure_seconds(&photo_data.time)
+        .map(|time| time >= *sec)
+        .unwrap_or(false),
+      PhotoCondition::WideOpen => match (
+        photodata::parse_f_value(&photo_data.f_value),
+        photodata::parse_f_value(&photo_data.max_f_value),
+      ) {
+        // 開放F値は小数点以下1桁に丸めて記録しているので、多少の誤差を許容する
+        (Some(f_value), Some(max_f_value)) => (f_value - max_f_value).abs() < 0.1,
+        _ => false,
+      },
+      PhotoCondition::HighAltitude(altitude) => photodata::parse_altitude(&photo_data.altitude)
+        .map(|value| value >= *altitude)
+        .unwrap_or(false),
+    }
+  }
+}
+
+/// 複数の条件の組み合わせ方
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterCombination {
+  /// 全ての条件を満たす
+  And,
+  /// いずれかの条件を満たす
+  Or,
+}
+
+/// 写真を絞り込むための条件の組
+/// 条件が一つも無い場合は全ての写真が対象になる
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PhotoFilter {
+  pub condition_lst: Vec<PhotoCondition>,
+  pub combination: FilterCombination,
+  /// 撮影日の期間の始まり（空欄の場合は指定しない）
+  #[serde(default)]
+  pub date_from: String,
+  /// 撮影日の期間の終わり（空欄の場合は指定しない）
+  #[serde(default)]
+  pub date_to: String,
+  /// 表示する撮影セッション（`None`の場合は絞り込まず、空文字列の場合はセッションが無い写真）
+  #[serde(default)]
+  pub session: Option<String>,
+  /// 表示するフラグ（`None`の場合は絞り込まず、空文字列の場合は保留の写真）
+  #[serde(default)]
+  pub flag: Option<String>,
+}
+
+impl Default for PhotoFilter {
+  fn default() -> Self {
+    PhotoFilter {
+      condition_lst: Vec::new(),
+      combination: FilterCombination::And,
+      date_from: String::new(),
+      date_to: String::new(),
+      session: None,
+      flag: None,
+    }
+  }
+}
+
+impl PhotoFilter {
+  pub fn contains(&self, condition: &PhotoCondition) -> bool {
+    self.condition_lst.contains(condition)
+  }
+
+  /// 条件を追加または削除する
+  pub fn set_condition(&mut self, condition: PhotoCondition, enabled: bool) {
+    if enabled {
+      if !self.contains(&condition) {
+        self.condition_lst.push(condition)
+      }
+    } else {
+      self.condition_lst.retain(|c| c != &condition)
+    }
+  }
+
+  /// 撮影日の期間・撮影セッション・フラグと、条件の組の全てを満たすかどうか
+  /// 日付の形式が正しくない期間の端は指定していないものとして扱う
+  pub fn matches(&self, photo_data: &GUIPhotoData) -> bool {
+    let from = parse_date_input(&self.date_from).ok().flatten();
+    let to = parse_date_input(&self.date_to).ok().flatten();
+    let is_session_match = self
+      .session
+      .iter()
+      .all(|session| photo_data.session.trim() == session);
+    let is_flag_match = self
+      .flag
+      .iter()
+      .all(|flag| photo_data.flag.as_deref().unwrap_or_default() == flag);
+    photodata::is_in_date_range(photodata::photo_date(photo_data), from, to)
+      && is_session_match
+      && is_flag_match
+      && self.matches_condition(photo_data)
+  }
+
+  fn matches_condition(&self, photo_data: &GUIPhotoData) -> bool {
+    if self.condition_lst.is_empty() {
+      return true;
+    }
+    match self.combination {
+      FilterCombination::And => self.condition_lst.iter().all(|c| c.matches(photo_data)),
+      FilterCombination::Or => self.condition_lst.iter().any(|c| c.matches(photo_data)),
+    }
+  }
 }
 
 fn setup_japanese_fonts(ctx: &egui::Context) {
@@ -83,14 +684,52 @@ impl PhotagApp {
     input_json_path: String,
     original_image_folder_path: String,
     work_directory_path: String,
+    startup_options: StartupOptions,
   ) -> Self {
     setup_japanese_fonts(&cc.egui_ctx);
-    let import_photo_data_lst = photodata::load_import_json_file(&input_json_path).unwrap();
     let photo_data_opt = photodata::load_photo_data_opt(&work_directory_path);
+    // 入力のJSONファイルを読み込めない場合は、保存済みのデータから作り直して編集を続けられるようにする
+    let (mut import_photo_data_lst, import_json_error) =
+      match photodata::load_import_json_file(&input_json_path) {
+        Ok(import_photo_data_lst) => (import_photo_data_lst, None),
+        Err(err) => {
+          error!("{}を読み込めませんでした: {:#}", input_json_path, err);
+          (
+            photodata::import_photo_data_from_photo_data(&photo_data_opt),
+            Some(format!(
+              "{}を読み込めないため、保存済みのデータを表示しています（{:#}）",
+              input_json_path, err
+            )),
+          )
+        }
+      };
+    // 元画像のフォルダに見つからない画像は読み込まず、一覧にして知らせる
+    let missing_file_lst =
+      photodata::find_missing_files(&import_photo_data_lst, &original_image_folder_path);
+    import_photo_data_lst.retain(|import_photo_data| !missing_file_lst.contains(import_photo_data));
+    for missing_file in missing_file_lst.iter() {
+      warn!(
+        "画像ファイルが見つかりません: {}（{}）",
+        missing_file.file_name, missing_file.id
+      );
+    }
+    // 同じIDが付いている画像は先頭の画像だけを読み込み、一覧にして知らせる
+    let duplicate_id_lst = photodata::find_duplicate_ids(&import_photo_data_lst);
+    photodata::dedup_import_photo_data(&mut import_photo_data_lst);
+    for duplicate_id in duplicate_id_lst.iter() {
+      warn!(
+        "同じIDが複数の画像に付けられています: {}（{}）",
+        duplicate_id.id,
+        duplicate_id.file_names()
+      );
+    }
+    let last_import_photo_data_lst = import_photo_data_lst.clone();
     let (photo_id_lst, photo_data_lst) = photodata::merge_photo_data_based_and_import_photo_data(
       &photo_data_opt,
       &import_photo_data_lst,
       &original_image_folder_path,
+      startup_options.exif_thread_count,
+      &startup_options.url_prefix,
     )
     .unwrap();
     let mut gui_photo_data_lst = HashMap::new();
@@ -100,6 +739,11 @@ impl PhotagApp {
         photodata::photo_data_to_gui_photo_data(photo_data.clone()),
       );
     }
+    photodata::fill_new_photos_from_xmp(
+      &mut gui_photo_data_lst,
+      &photo_data_opt,
+      &original_image_folder_path,
+    );
     let group_data_lst =
       photodata::load_group_data_from_work_directory(&work_directory_path).unwrap();
     let mut group_id_lst = Vec::new();
@@ -111,108 +755,249 @@ impl PhotagApp {
         photodata::group_data_to_gui_group_data(group_data.clone()),
       );
     }
-    let mut time_info_lst = save::get_time_info_lst(&work_directory_path);
-    let mut thumbnail_lst = HashMap::new();
-    for import_photo_data in import_photo_data_lst.iter() {
-      // 画像ファイルは重いので、アクセスする階数をできるだけ減らしたい
-      let image_path = format!(
-        "{}/{}",
-        original_image_folder_path, import_photo_data.file_name
+    // グループに含まれている存在しない画像を取り除く
+    let pruned_count = photodata::prune_dangling_photo_ids(&mut gui_group_data_lst, &photo_id_lst);
+    let mut message_lst = Vec::new();
+    if let Some(message) = import_json_error {
+      message_lst.push(message);
+    }
+    if pruned_count > 0 {
+      let message = format!(
+        "{}件の存在しない画像をグループから除外しました",
+        pruned_count
       );
-      // ファイルのバイナリデータを取り出す
-      let raw_data = image::open_file(&image_path).unwrap();
-      // 起動時に処理する画像は固定されているため、
-      // このタイミングで画像を圧縮して保存すれば
-      // 次の起動まで何もしなくて良い
-      if let Some(time) = time_info_lst.get(&import_photo_data.id) {
-        // 書き出し時刻がある場合の処理
-        let time_stamp = save::get_file_timestamp(&image_path);
-        match time_stamp {
-          Some(time_stamp) => {
-            if time < &time_stamp {
-              // 画像のタイムスタンプの方が遅いため、新規画像と判定して書き出し処理を行う
-              save_image_compression_lazy(
-                &raw_data,
-                &format!(
-                  "{}/images/lazy/{}.JPG",
-                  work_directory_path, import_photo_data.id
-                ),
-              );
-              save_image_compression_normal(
-                &raw_data,
-                &format!(
-                  "{}/images/normal/{}.JPG",
-                  work_directory_path, import_photo_data.id
-                ),
-              );
-              let now = save::get_now();
-              time_info_lst.insert(import_photo_data.id.to_string(), now);
-            }
-          }
-          None => {
-            // タイムスタンプが無いので念のため書き出す
-            // 画像のタイムスタンプの方が遅いため、新規画像と判定して書き出し処理を行う
-            save_image_compression_lazy(
-              &raw_data,
-              &format!(
-                "{}/images/lazy/{}.JPG",
-                work_directory_path, import_photo_data.id
-              ),
-            );
-            save_image_compression_normal(
-              &raw_data,
-              &format!(
-                "{}/images/normal/{}.JPG",
-                work_directory_path, import_photo_data.id
-              ),
-            );
-            let now = save::get_now();
-            time_info_lst.insert(import_photo_data.id.to_string(), now);
-          }
-        }
-      } else {
-        // 書き出し時刻がないため「新規画像」と認定して書き出し処理を行う
-        save_image_compression_lazy(
-          &raw_data,
-          &format!(
-            "{}/images/lazy/{}.JPG",
-            work_directory_path, import_photo_data.id
-          ),
-        );
-        save_image_compression_normal(
-          &raw_data,
-          &format!(
-            "{}/images/normal/{}.JPG",
-            work_directory_path, import_photo_data.id
-          ),
-        );
-        let now = save::get_now();
-        time_info_lst.insert(import_photo_data.id.to_string(), now);
-      };
-      // サムネイル用に圧縮したデータを生成して登録
-      thumbnail_lst.insert(
-        import_photo_data.id.to_string(),
-        image::compression(&raw_data, 70.0, 600).unwrap(),
+      info!("{}", message);
+      message_lst.push(message);
+    }
+    // ファイル名やURLにそのまま使えないIDはエンコードして書き出すので、知らせておく
+    let unsafe_id_lst = photo_id_lst
+      .iter()
+      .chain(group_id_lst.iter())
+      .filter(|id| !photodata::is_valid_id(id))
+      .collect::<Vec<_>>();
+    for id in unsafe_id_lst.iter() {
+      warn!(
+        "IDにファイル名やURLに使えない文字が含まれています: {}（{}として書き出します）",
+        id,
+        photodata::sanitize_id(id)
       );
     }
+    if !unsafe_id_lst.is_empty() {
+      message_lst.push(format!(
+        "{}件のIDにファイル名やURLに使えない文字が含まれています",
+        unsafe_id_lst.len()
+      ));
+    }
+    let toast = if message_lst.is_empty() {
+      None
+    } else {
+      Some(Toast::new(message_lst.join("\n")))
+    };
+    let time_info_lst =
+      save::startup_time_info_lst(&work_directory_path, startup_options.force_regenerate);
+    let config = config::load_config(&work_directory_path);
+    // 遅延処理モードでは起動時に画像を処理しないので、圧縮し直す必要のある画像を未処理として控えておく
+    let unprocessed_photo_id_lst = if startup_options.lazy_processing {
+      import_photo_data_lst
+        .iter()
+        .filter(|import_photo_data| {
+          let image_path = format!(
+            "{}/{}",
+            original_image_folder_path, import_photo_data.file_name
+          );
+          save::check_image_staleness(
+            &time_info_lst,
+            &work_directory_path,
+            &import_photo_data.id,
+            &image_path,
+          )
+          .is_stale()
+        })
+        .map(|import_photo_data| import_photo_data.id.clone())
+        .collect::<Vec<String>>()
+    } else {
+      Vec::new()
+    };
+    // 機材ごとの補正は写真データから決まるので、別のスレッドに渡す前に求めておく
+    let startup_photo_lst = import_photo_data_lst
+      .into_iter()
+      .map(|import_photo_data| {
+        let adjustment = gui_photo_data_lst
+          .get(&import_photo_data.id)
+          .map(|photo_data| {
+            config::find_adjustment(&config.profile_lst, &photo_data.body, &photo_data.lens)
+          })
+          .unwrap_or_default();
+        (import_photo_data, adjustment)
+      })
+      .collect::<Vec<_>>();
+    // 画像の圧縮は時間がかかるので、別のスレッドで行って画面を先に表示する
+    // 遅延処理モードではサムネイルを表示するときに生成するので、何も処理しない
+    let startup_photo_lst = if startup_options.lazy_processing {
+      Vec::new()
+    } else {
+      startup_photo_lst
+    };
+    let startup_progress = startup::StartupProgress::new(startup_photo_lst.len());
+    let startup_loader = startup::StartupLoader::new(startup_progress.total);
+    spawn_startup_compression(
+      cc.egui_ctx.clone(),
+      startup_loader.clone(),
+      startup_photo_lst,
+      original_image_folder_path.clone(),
+      work_directory_path.clone(),
+      time_info_lst.clone(),
+      config.quality,
+      startup_options.clone(),
+    );
+
+    let description_template_lst = template::load_description_templates(&work_directory_path)
+      .unwrap_or_else(|err| {
+        error!("説明文のテンプレートを読み込めませんでした: {:#}", err);
+        Vec::new()
+      });
+    let tag_style_lst = tag_style::load_tag_styles(&work_directory_path).unwrap_or_else(|err| {
+      error!("タグの表示の設定を読み込めませんでした: {:#}", err);
+      BTreeMap::new()
+    });
 
     let now = save::get_now();
+    let audit_snapshot = audit::AuditSnapshot::new(&gui_photo_data_lst, &gui_group_data_lst);
+    let export_directory_path = format!("{}/export", work_directory_path);
+    let json_diff_path_b = format!("{}/photo_data.json", work_directory_path);
+
+    // 前回終了時の作業状態を復元する
+    // 開いていた写真やグループが無くなっている場合は何も開いていない状態にする
+    let session = if startup_options.fresh {
+      None
+    } else {
+      session::load_session(&work_directory_path)
+    };
+    let (mode, now_id, photo_filter, renumber_order, restore_scroll_offset, theme) = match session {
+      Some(mut session) => {
+        session.clear_stale_now_id(&photo_id_lst, &group_id_lst);
+        (
+          session.mode,
+          session.now_id,
+          session.photo_filter,
+          session.sort_order,
+          Some(session.scroll_offset),
+          session.theme,
+        )
+      }
+      None => (
+        Mode::EditPhotoData,
+        String::new(),
+        PhotoFilter::default(),
+        save::RenumberOrder::Date,
+        None,
+        Theme::default(),
+      ),
+    };
+    let theme = startup_options.theme.unwrap_or(theme);
+    cc.egui_ctx.set_visuals(theme.visuals());
 
     PhotagApp {
-      mode: Mode::EditPhotoData,
+      mode,
       photo_id_lst,
       gui_photo_data_lst,
       group_id_lst,
       gui_group_data_lst,
-      thumbnail_lst,
+      thumbnail_lst: HashMap::new(),
+      square_thumbnail_lst: HashMap::new(),
+      group_thumbnail_lst: HashMap::new(),
       input_json_path,
       original_image_folder_path,
       work_directory_path,
-      now_id: String::new(),
+      now_id,
       dummy_group_data: photodata::make_dummy_gui_group_data(),
       image_save_time_lst: time_info_lst,
       json_save_time: now,
       image_save_time: now,
+      audit_snapshot,
+      show_audit_log: false,
+      audit_log_lst: Vec::new(),
+      show_json_diff: false,
+      json_diff_path_a: String::new(),
+      json_diff_path_b,
+      json_diff_result: None,
+      show_timestamp_discrepancy: false,
+      timestamp_discrepancy_hours: photodata::TIMESTAMP_DISCREPANCY_HOURS,
+      timestamp_discrepancy_lst: Vec::new(),
+      undo_stack: undo::UndoStack::default(),
+      preview_compressor: None,
+      preview_image: None,
+      exif_dump: None,
+      preview_quality: NORMAL_IMAGE_QUALITY,
+      description_template_lst,
+      show_description_template: false,
+      tag_style_lst,
+      show_tag_style: false,
+      show_renumber: false,
+      renumber_order,
+      renumber_start: 1,
+      renumber_padding: 3,
+      renumber_message: String::new(),
+      photo_filter,
+      show_orphan_only: false,
+      toast,
+      picked_color: None,
+      relative_minutes: 5,
+      show_invalid_image: false,
+      invalid_image_lst: Vec::new(),
+      show_missing_file: !missing_file_lst.is_empty(),
+      missing_file_lst,
+      show_duplicate_id: !duplicate_id_lst.is_empty(),
+      duplicate_id_lst,
+      last_import_photo_data_lst,
+      excluded_photo_id_lst: Vec::new(),
+      srcset_width_lst: startup_options.srcset_width_lst,
+      url_prefix: startup_options.url_prefix,
+      jpeg_scan: startup_options.jpeg_scan,
+      base_url: startup_options.base_url,
+      selected_photo_id_lst: Vec::new(),
+      photo_data_patch: photodata::PhotoDataPatch::default(),
+      metadata_import_path: String::new(),
+      metadata_import: None,
+      auto_tag_suggestion: None,
+      photo_language: photodata::DEFAULT_LANGUAGE.to_string(),
+      new_language: String::new(),
+      selection_anchor: None,
+      bulk_group_id: None,
+      bulk_keywords: String::new(),
+      session_rename_from: String::new(),
+      session_rename_to: String::new(),
+      group_id_edit: None,
+      export_directory_path,
+      export_rating_filter: export::RatingFilter::default(),
+      dual_format: startup_options.dual_format,
+      export_inherit_group: false,
+      work_json_style: startup_options.work_json_style,
+      export_json_style: startup_options.export_json_style,
+      watermark: startup_options.watermark,
+      export_message: String::new(),
+      report_format: export::ReportFormat::Html,
+      report_from: String::new(),
+      report_to: String::new(),
+      montage_layout: image::MontageLayout::Grid2x2,
+      show_normalize_confirm: false,
+      photo_list_scroll_offset: restore_scroll_offset.unwrap_or_default(),
+      restore_scroll_offset,
+      timeline_scale: photodata::TimelineScale::Month,
+      compare_view: None,
+      full_size_view: None,
+      config,
+      show_config: false,
+      image_save_requested: false,
+      startup_loader,
+      startup_progress,
+      unprocessed_photo_id_lst,
+      pending_export_id_lst: None,
+      group_from_list: None,
+      unlock_confirm_id: None,
+      show_close_confirm: false,
+      is_close_confirmed: false,
+      theme,
     }
   }
 }
@@ -228,25 +1013,60 @@ impl eframe::App for PhotagApp {
       input_json_path,
       work_directory_path,
       image_save_time_lst,
+      audit_snapshot,
+      mode,
+      now_id,
+      photo_filter,
+      renumber_order,
+      photo_list_scroll_offset,
+      theme,
+      show_close_confirm,
+      is_close_confirmed,
+      work_json_style,
+      missing_file_lst,
+      duplicate_id_lst,
       ..
     } = self;
-    // JSONファイルを保存
-    save_file(
-      photo_id_lst,
-      gui_photo_data_lst,
-      group_id_lst,
-      gui_group_data_lst,
-      input_json_path,
-      work_directory_path,
-    );
+    // 未保存の変更がある場合は、保存するかどうかを選んでもらうまで終了しない
+    if !*is_close_confirmed {
+      if audit_snapshot.is_changed(gui_photo_data_lst, gui_group_data_lst) {
+        *show_close_confirm = true;
+        return false;
+      }
+      // JSONファイルを保存
+      save_file(
+        photo_id_lst,
+        gui_photo_data_lst,
+        group_id_lst,
+        gui_group_data_lst,
+        input_json_path,
+        work_directory_path,
+        audit_snapshot,
+        *work_json_style,
+        missing_file_lst,
+        duplicate_id_lst,
+      );
+    }
     // ファイルの保存時刻の情報を保存
     save::save_time_info_lst(work_directory_path, image_save_time_lst).unwrap();
+    // 作業状態を保存
+    let session = session::Session {
+      now_id: now_id.clone(),
+      mode: mode.clone(),
+      photo_filter: photo_filter.clone(),
+      sort_order: *renumber_order,
+      scroll_offset: *photo_list_scroll_offset,
+      theme: *theme,
+    };
+    if let Err(err) = session::save_session(work_directory_path, &session) {
+      error!("作業状態を保存できませんでした: {}", err);
+    }
 
     // trueのときはそのまま終了イベントが継続する
     true
   }
 
-  fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+  fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
     let Self {
       mode,
       photo_id_lst,
@@ -254,6 +1074,8 @@ impl eframe::App for PhotagApp {
       group_id_lst,
       gui_group_data_lst,
       thumbnail_lst,
+      square_thumbnail_lst,
+      group_thumbnail_lst,
       now_id,
       input_json_path,
       original_image_folder_path,
@@ -261,32 +1083,247 @@ impl eframe::App for PhotagApp {
       image_save_time_lst,
       json_save_time,
       image_save_time,
+      audit_snapshot,
+      show_audit_log,
+      audit_log_lst,
+      show_json_diff,
+      json_diff_path_a,
+      json_diff_path_b,
+      json_diff_result,
+      show_timestamp_discrepancy,
+      timestamp_discrepancy_hours,
+      timestamp_discrepancy_lst,
+      undo_stack,
+      description_template_lst,
+      show_description_template,
+      tag_style_lst,
+      show_tag_style,
+      show_renumber,
+      renumber_order,
+      renumber_start,
+      renumber_padding,
+      renumber_message,
+      photo_filter,
+      show_orphan_only,
+      invalid_image_lst,
+      show_invalid_image,
+      missing_file_lst,
+      duplicate_id_lst,
+      last_import_photo_data_lst,
+      show_missing_file,
+      show_duplicate_id,
+      excluded_photo_id_lst,
+      srcset_width_lst,
+      url_prefix,
+      jpeg_scan,
+      export_directory_path,
+      export_rating_filter,
+      dual_format,
+      export_inherit_group,
+      work_json_style,
+      export_json_style,
+      watermark,
+      export_message,
+      report_format,
+      report_from,
+      report_to,
+      show_normalize_confirm,
+      toast,
+      photo_list_scroll_offset,
+      restore_scroll_offset,
+      timeline_scale,
+      compare_view,
+      full_size_view,
+      config,
+      show_config,
+      image_save_requested,
+      startup_loader,
+      startup_progress,
+      unprocessed_photo_id_lst,
+      pending_export_id_lst,
+      group_from_list,
+      unlock_confirm_id,
+      show_close_confirm,
+      is_close_confirmed,
+      theme,
+      selected_photo_id_lst,
+      photo_data_patch,
+      metadata_import_path,
+      metadata_import,
+      selection_anchor,
+      bulk_group_id,
+      bulk_keywords,
+      session_rename_from,
+      session_rename_to,
+      group_id_edit,
       ..
     } = self;
 
+    // Ctrl+Zで直前の削除や編集を取り消し、Ctrl+Shift+ZかCtrl+Yでやり直す
+    // テキスト入力中は入力欄の取り消しを優先する
+    let (undo_pressed, redo_pressed) = {
+      let input = ctx.input();
+      let z = input.modifiers.command && input.key_pressed(egui::Key::Z);
+      let y = input.modifiers.command && input.key_pressed(egui::Key::Y);
+      (
+        z && !input.modifiers.shift,
+        (z && input.modifiers.shift) || y,
+      )
+    };
+    if (undo_pressed || redo_pressed) && ctx.memory().focus().is_none() {
+      let target = if undo_pressed {
+        undo_stack.undo(
+          photo_id_lst,
+          gui_photo_data_lst,
+          thumbnail_lst,
+          group_id_lst,
+          gui_group_data_lst,
+        )
+      } else {
+        undo_stack.redo(
+          photo_id_lst,
+          gui_photo_data_lst,
+          thumbnail_lst,
+          group_id_lst,
+          gui_group_data_lst,
+        )
+      };
+      match target {
+        Some(undo::UndoTarget::Photo(id)) => {
+          *mode = Mode::EditPhotoData;
+          *now_id = id;
+        }
+        Some(undo::UndoTarget::Group(id)) => {
+          *mode = Mode::EditGroupData;
+          *now_id = id;
+        }
+        None => {
+          // やり直しで削除された写真やグループを開いたままにしない
+          if !photo_id_lst.contains(now_id) && !group_id_lst.contains(now_id) {
+            *now_id = String::new();
+          }
+        }
+      }
+    }
+
+    // 起動時の画像の圧縮結果を反映させる
+    if !startup_progress.is_complete() {
+      let (processed_image_lst, progress) = startup_loader.take();
+      for processed_image in processed_image_lst.into_iter() {
+        let id = processed_image.photo_id;
+        if let Some(invalid_image) = processed_image.invalid_image {
+          invalid_image_lst.push(invalid_image);
+          *show_invalid_image = true;
+        }
+        if processed_image.excluded {
+          excluded_photo_id_lst.push(id.clone());
+        }
+        if let Some((srcset, time)) = processed_image.compressed.or(processed_image.copied) {
+          if let Some(gui_photo_data) = gui_photo_data_lst.get_mut(&id) {
+            gui_photo_data.srcset = srcset;
+          }
+          image_save_time_lst.insert(id.clone(), time);
+        }
+        if let Some(thumbnail) = processed_image.thumbnail {
+          thumbnail_lst.insert(id.clone(), thumbnail);
+          square_thumbnail_lst.remove(&id);
+          group_thumbnail_lst.remove(&id);
+        }
+      }
+      *startup_progress = progress;
+      if startup_progress.is_complete() {
+        // 起動時の圧縮が終わったので保存時刻を書き出す
+        save::save_time_info_lst(work_directory_path, image_save_time_lst).unwrap();
+      }
+    }
+
     let now = save::get_now();
-    if save::time_add_sec(*json_save_time, save::SAVE_JSON_DIFF_TIME) > now {
+    // 手動保存のみの設定の場合は自動保存を行わない
+    // 自動保存は前回の保存から設定した間隔が経過した（前回の保存時刻に間隔を足した時刻が今以前になった）ときに行う
+    let is_auto_save = !config.save.manual_save_only;
+    if is_auto_save && save::time_add_sec(*json_save_time, config.save.save_json_diff_time) <= now {
       // 一定時間が経過したので、JSONファイルの読み込み等を行って更新が無いかを確認する
       // 更新があった場合、データのアップデートと新規保存を行う
       match save::get_file_timestamp(input_json_path) {
-        Some(timestamp) => {
-            let import_photo_data_lst = photodata::load_import_json_file(input_json_path).unwrap();
-            let (new_gui_photo_data_lst, new_gui_group_data_lst) =
-              photodata::merge_gui_photo_data_based_and_import_photo_data(
-                gui_photo_data_lst,
-                gui_group_data_lst,
-                &import_photo_data_lst,
-                original_image_folder_path,
-              );
-            // JSONファイルを保存
-            save_file(
-              photo_id_lst,
-              &new_gui_photo_data_lst,
-              group_id_lst,
-              &new_gui_group_data_lst,
-              input_json_path,
-              work_directory_path,
-            );
+        Some(_) => {
+          // 読み込めない場合は手で直されるまで反映を見送る
+          match photodata::load_import_json_file(input_json_path) {
+            Ok(mut import_photo_data_lst) => {
+              *missing_file_lst =
+                photodata::find_missing_files(&import_photo_data_lst, original_image_folder_path);
+              import_photo_data_lst
+                .retain(|import_photo_data| !missing_file_lst.contains(import_photo_data));
+              *duplicate_id_lst = photodata::find_duplicate_ids(&import_photo_data_lst);
+              photodata::dedup_import_photo_data(&mut import_photo_data_lst);
+              // 前回読み込んだ内容から変わった写真だけを反映する
+              let diff =
+                photodata::diff_import_data(last_import_photo_data_lst, &import_photo_data_lst);
+              if !diff.is_empty() {
+                debug!(
+                  "入力のJSONファイルの変更を反映します（追加{}件・削除{}件・変更{}件）",
+                  diff.added.len(),
+                  diff.removed.len(),
+                  diff.changed.len()
+                );
+                for id in diff.removed.iter() {
+                  gui_photo_data_lst.remove(id);
+                  thumbnail_lst.remove(id);
+                  square_thumbnail_lst.remove(id);
+                  group_thumbnail_lst.remove(id);
+                }
+                photo_id_lst.retain(|id| !diff.removed.contains(id));
+                for import_photo_data in diff.changed.iter() {
+                  // 元画像が差し替わった場合は圧縮し直す
+                  let is_file_changed = gui_photo_data_lst
+                    .get(&import_photo_data.id)
+                    .map_or(true, |photo_data| {
+                      photo_data.file_name != import_photo_data.file_name
+                    });
+                  if is_file_changed {
+                    image_save_time_lst.remove(&import_photo_data.id);
+                  }
+                }
+                for import_photo_data in diff.added.iter() {
+                  if !photo_id_lst.contains(&import_photo_data.id) {
+                    photo_id_lst.push(import_photo_data.id.clone());
+                  }
+                }
+                let update_lst = diff
+                  .added
+                  .into_iter()
+                  .chain(diff.changed.into_iter())
+                  .collect::<Vec<_>>();
+                *gui_group_data_lst = photodata::merge_gui_photo_data_based_and_import_photo_data(
+                  gui_photo_data_lst,
+                  gui_group_data_lst,
+                  &update_lst,
+                  original_image_folder_path,
+                  url_prefix,
+                );
+                *last_import_photo_data_lst = import_photo_data_lst;
+              }
+            }
+            Err(err) => {
+              error!("{}を読み込めませんでした: {:#}", input_json_path, err);
+              *toast = Some(Toast::new(format!(
+                "{}を読み込めないため、変更を反映できません（{:#}）",
+                input_json_path, err
+              )));
+            }
+          }
+          // JSONファイルを保存
+          save_file(
+            photo_id_lst,
+            gui_photo_data_lst,
+            group_id_lst,
+            gui_group_data_lst,
+            input_json_path,
+            work_directory_path,
+            audit_snapshot,
+            *work_json_style,
+            missing_file_lst,
+            duplicate_id_lst,
+          );
         }
         None => {
           // JSONファイルを保存
@@ -297,106 +1334,912 @@ impl eframe::App for PhotagApp {
             gui_group_data_lst,
             input_json_path,
             work_directory_path,
+            audit_snapshot,
+            *work_json_style,
+            missing_file_lst,
+            duplicate_id_lst,
           );
         }
       }
       *json_save_time = save::get_now();
     }
 
-    if save::time_add_sec(*image_save_time, save::SAVE_IMAGE_DIFF_TIME) > now {
+    // 起動時の圧縮が終わるまでは同じ画像を二重に処理しないように待つ
+    let is_image_save_time = *image_save_requested
+      || (is_auto_save
+        && save::time_add_sec(*image_save_time, config.save.save_image_diff_time) <= now);
+    if startup_progress.is_complete() && is_image_save_time {
+      *image_save_requested = false;
       // 一定時間が経過したので、画像ファイルに更新が無いかを確認する
       // 更新があった場合、当該ファイルの書き出し処理も行う
+      let mut srcset_update_lst = Vec::new();
+      // 同じ内容のオリジナルの画像は一度だけ圧縮し、2枚目からは圧縮した画像をコピーする
+      let mut compressed_original_lst = HashMap::new();
       for (id, gui_photo_data) in gui_photo_data_lst.iter() {
+        if excluded_photo_id_lst.contains(id) {
+          // 破損している画像は処理しない
+          continue;
+        }
+        if unprocessed_photo_id_lst.contains(id) {
+          // 遅延処理モードで未処理の画像は書き出すときに処理する
+          continue;
+        }
         let image_path = format!(
           "{}/{}",
           original_image_folder_path, gui_photo_data.file_name
         );
-        if let Some(time) = image_save_time_lst.get(&gui_photo_data.photo_id) {
-          // 書き出し時刻がある場合の処理
-          let time_stamp = save::get_file_timestamp(&image_path);
-          match time_stamp {
-            Some(time_stamp) => {
-              if time < &time_stamp {
-                // 画像のタイムスタンプの方が遅いため、新規画像と判定して書き出し処理を行う
-                let raw_data = thumbnail_lst.get(id).unwrap();
-                save_image_compression_lazy(
-                  raw_data,
-                  &format!("{}/images/lazy/{}.JPG", work_directory_path, id),
-                );
-                save_image_compression_normal(
-                  raw_data,
-                  &format!("{}/images/normal/{}.JPG", work_directory_path, id),
-                );
-                let now = save::get_now();
-                image_save_time_lst.insert(id.to_string(), now);
-              }
-            }
-            None => {
-              // タイムスタンプが無い・ファイルが無いので念のため書き出す
-              // 画像のタイムスタンプの方が遅いため、新規画像と判定して書き出し処理を行う
-              let raw_data = thumbnail_lst.get(id).unwrap();
-              save_image_compression_lazy(
-                raw_data,
-                &format!("{}/images/lazy/{}.JPG", work_directory_path, id),
-              );
-              save_image_compression_normal(
-                raw_data,
-                &format!("{}/images/normal/{}.JPG", work_directory_path, id),
-              );
-              let now = save::get_now();
-              image_save_time_lst.insert(id.to_string(), now);
-            }
+        let staleness =
+          save::check_image_staleness(image_save_time_lst, work_directory_path, id, &image_path);
+        if !staleness.is_stale() {
+          continue;
+        }
+        // 先にサムネイルを作り、読み込めない画像は処理対象から外す
+        let adjustment = config::find_adjustment(
+          &config.profile_lst,
+          &gui_photo_data.body,
+          &gui_photo_data.lens,
+        );
+        // デコードは一度だけ行い、サムネイルと全ての大きさの画像をその結果から作る
+        let result = image::open_file(&image_path).and_then(|raw_data| {
+          let content_hash = save::content_hash(&raw_data);
+          let copied = compressed_original_lst
+            .get(&content_hash)
+            .filter(|original| save::is_same_content(&original.original_path, &raw_data))
+            .and_then(|original| {
+              copy_compressed_original(work_directory_path, original, id, url_prefix)
+            });
+          if let Some((srcset, Some(thumbnail))) = copied {
+            return Ok((content_hash, srcset, thumbnail));
           }
-        } else {
-          // 書き出し時刻がないため「新規画像」と認定して書き出し処理を行う
-          let raw_data = image::open_file(&image_path).unwrap();
-          save_image_compression_lazy(
-            &raw_data,
-            &format!("{}/images/lazy/{}.JPG", work_directory_path, id),
-          );
-          save_image_compression_normal(
-            &raw_data,
-            &format!("{}/images/normal/{}.JPG", work_directory_path, id),
-          );
-          thumbnail_lst.insert(
-            id.to_string(),
-            image::compression(&raw_data, 70.0, 600).unwrap(),
+          let decoded = image::decode(&raw_data)?;
+          let thumbnail = image::compression_decoded(
+            &decoded,
+            70.0,
+            600,
+            false,
+            &adjustment,
+            image::JpegScan::Baseline,
+          )?;
+          let srcset = save_image_compression(
+            &decoded,
+            work_directory_path,
+            id,
+            srcset_width_lst,
+            &adjustment,
+            &config.quality,
+            url_prefix,
+            *jpeg_scan,
           );
-          let now = save::get_now();
-          image_save_time_lst.insert(id.to_string(), now);
-        };
+          Ok((content_hash, srcset, thumbnail))
+        });
+        match result {
+          Ok((content_hash, srcset, thumbnail)) => {
+            compressed_original_lst
+              .entry(content_hash)
+              .or_insert_with(|| CompressedOriginal {
+                photo_id: id.clone(),
+                original_path: image_path.clone(),
+                srcset: srcset.clone(),
+                thumbnail: Some(thumbnail.clone()),
+              });
+            srcset_update_lst.push((id.clone(), srcset));
+            store_thumbnail_cache(work_directory_path, id, &thumbnail);
+            thumbnail_lst.insert(id.to_string(), thumbnail);
+            square_thumbnail_lst.remove(id);
+            group_thumbnail_lst.remove(id);
+            let now = save::get_now();
+            image_save_time_lst.insert(id.to_string(), now);
+          }
+          Err(err) => {
+            warn!("{}（{}）: {:#}", IMAGE_LOAD_ERROR_MESSAGE, id, err);
+            excluded_photo_id_lst.push(id.to_string());
+          }
+        }
+      }
+      for (id, srcset) in srcset_update_lst.into_iter() {
+        if let Some(gui_photo_data) = gui_photo_data_lst.get_mut(&id) {
+          gui_photo_data.srcset = srcset;
+        }
       }
       // ファイルの保存時刻の情報を保存
       save::save_time_info_lst(work_directory_path, image_save_time_lst).unwrap();
       *image_save_time = save::get_now();
     }
 
-    egui::SidePanel::left("side_panel")
-      .min_width(50.0)
-      .show(ctx, |ui| match mode {
-        Mode::EditPhotoData => {
-          ui.heading("画像データ編集ページ");
-          let keep_button = ui.button("保存").clicked();
-          ui.heading("グループデータ編集ページ");
-          let switch_button = ui.button("切り替え").clicked();
-          if switch_button {
-            *mode = Mode::EditGroupData;
-            *now_id = String::new();
+    if audit_snapshot.is_changed(gui_photo_data_lst, gui_group_data_lst) {
+      egui::TopBottomPanel::bottom("unsaved_changes").show(ctx, |ui| {
+        ui.label("未保存の変更あり");
+      });
+    }
+
+    let mut is_group_from_list_finished = false;
+    if let Some(group_data) = group_from_list {
+      egui::Window::new("現在の一覧からグループを作成")
+        .collapsible(false)
+        .show(ctx, |ui| {
+          ui.label(format!(
+            "{}枚の写真を含むグループを作成します",
+            group_data.photo_id_list.len()
+          ));
+          ui.horizontal(|ui| {
+            ui.label("グループID");
+            ui.text_edit_singleline(&mut group_data.group_id);
+          });
+          let id_validation = validate::validate_group_id(&group_data.group_id, group_id_lst, None);
+          if let Err(err) = &id_validation {
+            ui.colored_label(egui::Color32::RED, err);
           }
-          ui.heading("画像ID一覧");
-          egui::ScrollArea::vertical().show(ui, |ui| {
-            for photo_id in photo_id_lst.iter() {
+          ui.horizontal(|ui| {
+            ui.label("タイトル（必須）");
+            ui.text_edit_singleline(&mut group_data.title);
+          });
+          ui.horizontal(|ui| {
+            ui.label("説明（必須）");
+            ui.text_edit_singleline(&mut group_data.description);
+          });
+          let can_make = id_validation.is_ok()
+            && !group_data.group_id.is_empty()
+            && !group_data.title.is_empty()
+            && !group_data.description.is_empty();
+          ui.horizontal(|ui| {
+            if ui
+              .add_enabled(can_make, egui::Button::new("作成"))
+              .clicked()
+            {
+              group_id_lst.push(group_data.group_id.clone());
+              gui_group_data_lst.insert(group_data.group_id.clone(), group_data.clone());
+              *toast = Some(Toast::new(format!(
+                "{}枚の写真でグループ「{}」を作成しました",
+                group_data.photo_id_list.len(),
+                group_data.group_id
+              )));
+              is_group_from_list_finished = true;
+            }
+            if ui.button("キャンセル").clicked() {
+              is_group_from_list_finished = true;
+            }
+          });
+        });
+    }
+    if is_group_from_list_finished {
+      *group_from_list = None;
+    }
+
+    if let Some(photo_id) = unlock_confirm_id.clone() {
+      egui::Window::new("ロックの解除")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+          ui.label(format!(
+            "{}のロックを解除して編集できるようにしますか？",
+            photo_id
+          ));
+          ui.horizontal(|ui| {
+            if ui.button("解除する").clicked() {
+              if let Some(photo_data) = gui_photo_data_lst.get_mut(&photo_id) {
+                undo_stack.push(undo::UndoEntry::EditPhoto(vec![photo_data.clone()]));
+                photo_data.locked = false;
+              }
+              *unlock_confirm_id = None;
+            }
+            if ui.button("キャンセル").clicked() {
+              *unlock_confirm_id = None;
+            }
+          });
+        });
+    }
+
+    if *show_close_confirm {
+      egui::Window::new("未保存の変更があります")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+          ui.label("終了する前に変更を保存しますか？");
+          ui.horizontal(|ui| {
+            if ui.button("保存して終了").clicked() {
+              save_file(
+                photo_id_lst,
+                gui_photo_data_lst,
+                group_id_lst,
+                gui_group_data_lst,
+                input_json_path,
+                work_directory_path,
+                audit_snapshot,
+                *work_json_style,
+                missing_file_lst,
+                duplicate_id_lst,
+              );
+              *is_close_confirmed = true;
+              frame.close();
+            }
+            if ui.button("保存せずに終了").clicked() {
+              *is_close_confirmed = true;
+              frame.close();
+            }
+            if ui.button("キャンセル").clicked() {
+              *show_close_confirm = false;
+            }
+          });
+        });
+    }
+
+    if !startup_progress.is_complete() {
+      egui::TopBottomPanel::top("startup_progress").show(ctx, |ui| {
+        ui.add(egui::ProgressBar::new(startup_progress.fraction()).text(startup_progress.label()));
+      });
+    }
+
+    egui::SidePanel::left("side_panel")
+      .min_width(50.0)
+      .show(ctx, |ui| match mode {
+        Mode::EditPhotoData => {
+          ui.heading("画像データ編集ページ");
+          theme_toggle(ui, ctx, theme);
+          let keep_button = ui.button("保存").clicked();
+          ui.heading("グループデータ編集ページ");
+          let switch_button = ui.button("切り替え").clicked();
+          if switch_button {
+            *mode = Mode::EditGroupData;
+            *now_id = String::new();
+          }
+          ui.heading("画像一覧ページ");
+          let gallery_button = ui.button("切り替え").clicked();
+          if gallery_button {
+            *mode = Mode::Gallery;
+            *now_id = String::new();
+          }
+          ui.heading("タイムライン");
+          if ui.button("切り替え").clicked() {
+            *mode = Mode::Timeline;
+            *now_id = String::new();
+          }
+          ui.heading("変更履歴");
+          if ui.button("表示").clicked() {
+            *audit_log_lst = audit::load_audit_log(work_directory_path).unwrap_or_default();
+            *show_audit_log = true;
+          }
+          ui.heading("データの比較");
+          if ui.button("開く").clicked() {
+            *show_json_diff = true;
+          }
+          ui.heading("撮影日時のずれ");
+          if ui.button("確認").clicked() {
+            *show_timestamp_discrepancy = true;
+          }
+          ui.heading("画像の作り直し");
+          if ui
+            .button("全ての画像を作り直す")
+            .on_hover_text("保存時刻に関係なく、全ての画像をオリジナルから圧縮し直します")
+            .clicked()
+          {
+            image_save_time_lst.clear();
+            unprocessed_photo_id_lst.clear();
+            *image_save_requested = true;
+            *toast = Some(Toast::new("全ての画像を作り直します".to_string()));
+          }
+          ui.heading("設定");
+          if ui.button("開く").clicked() {
+            *show_config = true;
+          }
+          ui.heading("連番リネーム");
+          if ui.button("開く").clicked() {
+            *show_renumber = true;
+          }
+          ui.heading("altの雛形");
+          if ui.button("空のaltに雛形を適用").clicked() {
+            let mut count = 0;
+            for photo_data in gui_photo_data_lst.values_mut() {
+              if photo_data.alt.is_empty() {
+                let alt = photodata::suggest_alt(photo_data);
+                if !alt.is_empty() {
+                  photo_data.alt = alt;
+                  count += 1;
+                }
+              }
+            }
+            *toast = Some(Toast::new(format!(
+              "{}枚の写真のaltに雛形を入れました",
+              count
+            )));
+          }
+          ui.heading("メタデータの取り込み");
+          ui.horizontal(|ui| {
+            ui.label("ファイル：");
+            ui.text_edit_singleline(metadata_import_path);
+          });
+          if ui.button("対応づけを確認").clicked() {
+            match mapping::load_external_table(metadata_import_path) {
+              Ok(table) => {
+                *metadata_import = Some(MetadataImport {
+                  mapping: mapping::FieldMapping::guess(&table.column_lst),
+                  table,
+                })
+              }
+              Err(err) => *toast = Some(Toast::new(format!("{:#}", err))),
+            }
+          }
+          ui.heading("撮影データの表記");
+          if ui.button("メタデータを正規化").clicked() {
+            *show_normalize_confirm = true;
+          }
+          if ui
+            .button("グループの撮影場所・日時を写真の空欄に書き込む")
+            .clicked()
+          {
+            let before_lst = photodata::apply_group_inheritance(
+              photo_id_lst,
+              gui_photo_data_lst,
+              group_id_lst,
+              gui_group_data_lst,
+            );
+            *toast = Some(Toast::new(format!(
+              "{}枚の写真の空欄をグループの値で補完しました",
+              before_lst.len()
+            )));
+            if !before_lst.is_empty() {
+              undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+            }
+          }
+          ui.heading("画像の検証");
+          if ui.button("実行").clicked() {
+            *invalid_image_lst =
+              validate_all_photos(photo_id_lst, gui_photo_data_lst, original_image_folder_path);
+            *show_invalid_image = true;
+          }
+          ui.heading("選別");
+          let (pick_count, reject_count, unflagged_count) =
+            photodata::count_by_flag(gui_photo_data_lst.values());
+          ui.label(format!(
+            "採用：{}枚　不採用：{}枚　保留：{}枚",
+            pick_count, reject_count, unflagged_count
+          ))
+          .on_hover_text("Pで採用、Xで不採用、Uで保留（選択中の写真か開いている写真）");
+          let delete_rejected_button = ui
+            .add_enabled(
+              reject_count > 0,
+              egui::Button::new("不採用の写真を全て削除"),
+            )
+            .clicked();
+          ui.heading("絞り込み");
+          filter_checkbox(ui, photo_filter, PhotoCondition::Manual, "マニュアルのみ");
+          filter_checkbox(
+            ui,
+            photo_filter,
+            PhotoCondition::LongExposure(1.0),
+            "1秒以上の長秒露光",
+          );
+          filter_checkbox(ui, photo_filter, PhotoCondition::WideOpen, "開放F値で撮影");
+          filter_checkbox(
+            ui,
+            photo_filter,
+            PhotoCondition::HighAltitude(1000.0),
+            "標高1000m以上",
+          );
+          ui.horizontal(|ui| {
+            ui.radio_value(
+              &mut photo_filter.combination,
+              FilterCombination::And,
+              "全てを満たす",
+            );
+            ui.radio_value(
+              &mut photo_filter.combination,
+              FilterCombination::Or,
+              "いずれかを満たす",
+            );
+          });
+          ui.horizontal(|ui| {
+            ui.label("撮影日：");
+            ui.add(egui::TextEdit::singleline(&mut photo_filter.date_from).desired_width(90.0));
+            ui.label("〜");
+            ui.add(egui::TextEdit::singleline(&mut photo_filter.date_to).desired_width(90.0));
+          });
+          for date in [&photo_filter.date_from, &photo_filter.date_to] {
+            if let Err(err) = parse_date_input(date) {
+              ui.colored_label(egui::Color32::RED, err);
+            }
+          }
+          let session_lst = photodata::session_lst(gui_photo_data_lst.values());
+          ui.horizontal(|ui| {
+            ui.label("撮影セッション：");
+            egui::ComboBox::from_id_source("session_filter")
+              .selected_text(match &photo_filter.session {
+                None => "全て",
+                Some(session) if session.is_empty() => "（未設定）",
+                Some(session) => session,
+              })
+              .show_ui(ui, |ui| {
+                ui.selectable_value(&mut photo_filter.session, None, "全て");
+                ui.selectable_value(&mut photo_filter.session, Some(String::new()), "（未設定）");
+                for session in session_lst.iter() {
+                  ui.selectable_value(&mut photo_filter.session, Some(session.clone()), session);
+                }
+              });
+          });
+          ui.horizontal(|ui| {
+            ui.label("セッション名の変更：");
+            egui::ComboBox::from_id_source("session_rename")
+              .selected_text(session_rename_from.as_str())
+              .show_ui(ui, |ui| {
+                for session in session_lst.iter() {
+                  ui.selectable_value(session_rename_from, session.clone(), session);
+                }
+              });
+            ui.label("→");
+            ui.add(egui::TextEdit::singleline(session_rename_to).desired_width(100.0));
+            let is_renamable =
+              !session_rename_from.is_empty() && !session_rename_to.trim().is_empty();
+            if ui
+              .add_enabled(is_renamable, egui::Button::new("変更"))
+              .clicked()
+            {
+              let before_lst = photodata::rename_session(
+                gui_photo_data_lst,
+                session_rename_from,
+                session_rename_to,
+              );
+              *toast = Some(Toast::new(format!(
+                "{}枚の写真のセッション名を変更しました",
+                before_lst.len()
+              )));
+              if !before_lst.is_empty() {
+                undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+              }
+              // 絞り込んでいたセッションの名前も合わせて変える
+              if photo_filter.session.as_deref() == Some(session_rename_from.as_str()) {
+                photo_filter.session = Some(session_rename_to.trim().to_string());
+              }
+              *session_rename_from = session_rename_to.trim().to_string();
+              session_rename_to.clear();
+            }
+          });
+          ui.horizontal(|ui| {
+            ui.label("フラグ：");
+            egui::ComboBox::from_id_source("flag_filter")
+              .selected_text(match &photo_filter.flag {
+                None => "全て",
+                Some(flag) if flag.is_empty() => photodata::flag_label(None),
+                Some(flag) => photodata::flag_label(Some(flag.as_str())),
+              })
+              .show_ui(ui, |ui| {
+                ui.selectable_value(&mut photo_filter.flag, None, "全て");
+                for flag in [photodata::FLAG_PICK, photodata::FLAG_REJECT, ""] {
+                  ui.selectable_value(
+                    &mut photo_filter.flag,
+                    Some(flag.to_string()),
+                    photodata::flag_label(Some(flag)),
+                  );
+                }
+              });
+          });
+          ui.checkbox(show_orphan_only, "グループに未所属のみ");
+          // 絞り込み条件とグループ未所属の条件の両方を満たすものを表示する
+          let orphan_id_lst = photodata::orphan_photo_id_lst(photo_id_lst, gui_group_data_lst);
+          let is_visible = |id: &String| {
+            let is_match = gui_photo_data_lst
+              .get(id)
+              .map(|photo_data| photo_filter.matches(photo_data))
+              .unwrap_or(false);
+            is_match && (!*show_orphan_only || orphan_id_lst.contains(id))
+          };
+          ui.heading("書き出し");
+          ui.horizontal(|ui| {
+            ui.label("出力先：");
+            ui.text_edit_singleline(export_directory_path);
+          });
+          ui.horizontal(|ui| {
+            ui.label("エクスポート最低評価：");
+            ui.add(
+              egui::Slider::new(
+                &mut export_rating_filter.min_rating,
+                0..=photodata::MAX_RATING,
+              )
+              .suffix("★以上"),
+            );
+          });
+          ui.horizontal(|ui| {
+            ui.label("空になったグループ：");
+            ui.radio_value(
+              &mut export_rating_filter.empty_group,
+              export::EmptyGroupHandling::Remove,
+              "除外",
+            );
+            ui.radio_value(
+              &mut export_rating_filter.empty_group,
+              export::EmptyGroupHandling::Keep,
+              "残す",
+            );
+          });
+          ui.checkbox(dual_format, "WebPとJPEGの両方で書き出す");
+          ui.checkbox(
+            export_inherit_group,
+            "空欄の撮影場所・日時をグループの値で補完して書き出す",
+          );
+          let export_selected_button = ui.button("選択中のみ書き出し").clicked();
+          let export_filtered_button = ui.button("フィルタ結果を書き出し").clicked();
+          let mut export_target_id_lst = pending_export_id_lst.take();
+          if export_selected_button {
+            export_target_id_lst = Some(
+              photo_id_lst
+                .iter()
+                .filter(|id| *id == now_id)
+                .cloned()
+                .collect::<Vec<String>>(),
+            );
+          } else if export_filtered_button {
+            export_target_id_lst = Some(
+              photo_id_lst
+                .iter()
+                .filter(|id| is_visible(id))
+                .cloned()
+                .collect::<Vec<String>>(),
+            );
+          }
+          if let Some(target_id_lst) = export_target_id_lst {
+            if target_id_lst
+              .iter()
+              .any(|id| unprocessed_photo_id_lst.contains(id))
+            {
+              // 未処理の画像は次の更新で圧縮し、それが終わってから書き出す
+              unprocessed_photo_id_lst.retain(|id| !target_id_lst.contains(id));
+              *image_save_requested = true;
+              *pending_export_id_lst = Some(target_id_lst);
+              *export_message = "未処理の画像を処理してから書き出します".to_string();
+              ctx.request_repaint();
+            } else {
+              // 書き出す前に現在のデータを保存する
+              save_file(
+                photo_id_lst,
+                gui_photo_data_lst,
+                group_id_lst,
+                gui_group_data_lst,
+                input_json_path,
+                work_directory_path,
+                audit_snapshot,
+                *work_json_style,
+                missing_file_lst,
+                duplicate_id_lst,
+              );
+              *export_message = export_photos(
+                work_directory_path,
+                export_directory_path,
+                &target_id_lst,
+                gui_photo_data_lst,
+                export_rating_filter,
+                *dual_format,
+                *export_json_style,
+                *export_inherit_group,
+                url_prefix,
+                watermark.as_ref(),
+              );
+            }
+          }
+          ui.label("撮影統計のレポート");
+          ui.horizontal(|ui| {
+            ui.radio_value(report_format, export::ReportFormat::Html, "HTML");
+            ui.radio_value(report_format, export::ReportFormat::Markdown, "Markdown");
+          });
+          ui.horizontal(|ui| {
+            ui.label("期間：");
+            ui.add(egui::TextEdit::singleline(report_from).desired_width(90.0));
+            ui.label("〜");
+            ui.add(egui::TextEdit::singleline(report_to).desired_width(90.0));
+          });
+          if ui.button("レポートを書き出し").clicked() {
+            *export_message = export_report(
+              gui_photo_data_lst,
+              export_directory_path,
+              *report_format,
+              report_from,
+              report_to,
+            );
+          }
+          if !export_message.is_empty() {
+            ui.label(&*export_message);
+          }
+          let visible_id_lst = photo_id_lst
+            .iter()
+            .filter(|id| is_visible(id))
+            .cloned()
+            .collect::<Vec<String>>();
+          let selection_key = SelectionKey::read(ctx);
+          if selection_key.select_all {
+            *selected_photo_id_lst = visible_id_lst.clone();
+          }
+          // 上下の矢印キーで開く写真を動かし、Shiftを押している場合は範囲を選択する
+          if selection_key.step != 0 && !visible_id_lst.is_empty() {
+            let next = match visible_id_lst.iter().position(|id| id == now_id) {
+              Some(i) => (i as i32 + selection_key.step).clamp(0, visible_id_lst.len() as i32 - 1),
+              None => 0,
+            } as usize;
+            let next_id = visible_id_lst[next].clone();
+            if selection_key.shift {
+              let anchor = selection_anchor
+                .get_or_insert_with(|| {
+                  if now_id.is_empty() {
+                    next_id.clone()
+                  } else {
+                    now_id.clone()
+                  }
+                })
+                .clone();
+              *selected_photo_id_lst = select_range(&visible_id_lst, &anchor, &next_id);
+            } else {
+              *selection_anchor = None;
+            }
+            *mode = Mode::EditPhotoData;
+            *now_id = next_id;
+            undo_stack.seal();
+          }
+          ui.heading("選択した画像に一括適用");
+          ui.label(format!("{}枚を選択中", selected_photo_id_lst.len()))
+            .on_hover_text("Ctrl+クリックで追加、Shift+矢印キーで範囲選択、Ctrl+Aで全て選択");
+          ui.horizontal(|ui| {
+            if ui.button("表示中を全て選択").clicked() {
+              *selected_photo_id_lst = visible_id_lst.clone();
+            }
+            if ui.button("選択を解除").clicked() {
+              selected_photo_id_lst.clear();
+              *selection_anchor = None;
+            }
+          });
+          // 選択している写真があればそれを、無ければ表示中の写真を、一覧の並び順のまま使う
+          if ui
+            .add_enabled(
+              !visible_id_lst.is_empty(),
+              egui::Button::new("現在の一覧からグループを作成"),
+            )
+            .clicked()
+          {
+            let id_lst = visible_id_lst
+              .iter()
+              .filter(|id| selected_photo_id_lst.is_empty() || selected_photo_id_lst.contains(id))
+              .cloned()
+              .collect::<Vec<String>>();
+            *group_from_list = Some(photodata::make_gui_group_data_from_photo_id_lst(&id_lst));
+          }
+          patch_field(ui, "撮影地点", &mut photo_data_patch.location);
+          patch_field(ui, "ボディ", &mut photo_data_patch.body);
+          patch_field(ui, "レンズ", &mut photo_data_patch.lens);
+          let can_apply = !selected_photo_id_lst.is_empty() && !photo_data_patch.is_empty();
+          // 絞り込みで写真データを参照しているので、適用は一覧を表示した後に行う
+          let bulk_apply_button = ui
+            .add_enabled(can_apply, egui::Button::new("選択した画像に一括適用"))
+            .clicked();
+          // 以下の操作は、何も選択していない場合は開いている写真に対して行う
+          ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("bulk_group_id")
+              .selected_text(bulk_group_id.as_deref().unwrap_or("追加先グループ"))
+              .show_ui(ui, |ui| {
+                for group_id in group_id_lst.iter() {
+                  ui.selectable_value(bulk_group_id, Some(group_id.clone()), group_id);
+                }
+              });
+          });
+          let add_to_group_button = ui
+            .add_enabled(
+              bulk_group_id.is_some(),
+              egui::Button::new("グループに追加（Ctrl+G）"),
+            )
+            .clicked();
+          ui.horizontal(|ui| {
+            ui.label("キーワード：");
+            ui.text_edit_singleline(bulk_keywords);
+          });
+          let add_keywords_button = ui
+            .add_enabled(
+              !bulk_keywords.trim().is_empty(),
+              egui::Button::new("キーワードを追加（Ctrl+T）"),
+            )
+            .clicked();
+          let delete_selected_button = ui.button("削除（Delete）").clicked();
+          ui.heading("画像ID一覧");
+          if !unprocessed_photo_id_lst.is_empty() {
+            ui.horizontal(|ui| {
+              ui.label(format!(
+                "未処理の画像：{}枚",
+                unprocessed_photo_id_lst.len()
+              ))
+              .on_hover_text("表示用の画像は書き出すときに生成します");
+              if ui.button("全て処理する").clicked() {
+                unprocessed_photo_id_lst.clear();
+                *image_save_requested = true;
+              }
+            });
+          }
+          let mut scroll_area = egui::ScrollArea::vertical();
+          if let Some(offset) = restore_scroll_offset.take() {
+            scroll_area = scroll_area.vertical_scroll_offset(offset);
+          }
+          let scroll_output = scroll_area.show(ui, |ui| {
+            for photo_id in photo_id_lst.iter() {
+              if !is_visible(photo_id) {
+                continue;
+              }
               let button = if photo_id == now_id {
                 egui::Button::new(photo_id).fill(egui::Color32::KHAKI)
               } else {
                 egui::Button::new(photo_id)
               };
-              if ui.add(button).clicked() {
-                *mode = Mode::EditPhotoData;
-                *now_id = photo_id.clone();
-              }
+              ui.horizontal(|ui| {
+                let mut is_selected = selected_photo_id_lst.contains(photo_id);
+                if ui.checkbox(&mut is_selected, "").changed() {
+                  if is_selected {
+                    selected_photo_id_lst.push(photo_id.clone());
+                  } else {
+                    selected_photo_id_lst.retain(|id| id != photo_id);
+                  }
+                }
+                let response = ui.add(button);
+                let is_locked = gui_photo_data_lst
+                  .get(photo_id)
+                  .map(|photo_data| photo_data.locked)
+                  .unwrap_or(false);
+                if is_locked {
+                  ui.label("🔒").on_hover_text("ロック中");
+                }
+                let flag = gui_photo_data_lst
+                  .get(photo_id)
+                  .and_then(|photo_data| photo_data.flag.as_deref());
+                match flag {
+                  Some(photodata::FLAG_PICK) => {
+                    ui.colored_label(egui::Color32::DARK_GREEN, "✔")
+                      .on_hover_text(photodata::flag_label(flag));
+                  }
+                  Some(photodata::FLAG_REJECT) => {
+                    ui.colored_label(egui::Color32::RED, "✖")
+                      .on_hover_text(photodata::flag_label(flag));
+                  }
+                  _ => (),
+                }
+                if unprocessed_photo_id_lst.contains(photo_id) {
+                  ui.weak("未処理");
+                }
+                if response.clicked() {
+                  let modifiers = ui.input().modifiers;
+                  if modifiers.command {
+                    // Ctrl+クリックは開く写真を変えずに選択に加える（選択済みなら外す）
+                    if selected_photo_id_lst.contains(photo_id) {
+                      selected_photo_id_lst.retain(|id| id != photo_id);
+                    } else {
+                      selected_photo_id_lst.push(photo_id.clone());
+                    }
+                    *selection_anchor = Some(photo_id.clone());
+                  } else {
+                    if modifiers.shift && !now_id.is_empty() {
+                      let anchor = selection_anchor
+                        .get_or_insert_with(|| now_id.clone())
+                        .clone();
+                      *selected_photo_id_lst = select_range(&visible_id_lst, &anchor, photo_id);
+                    } else {
+                      *selection_anchor = None;
+                    }
+                    *mode = Mode::EditPhotoData;
+                    *now_id = photo_id.clone();
+                    undo_stack.seal();
+                  }
+                }
+              });
             }
           });
+          *photo_list_scroll_offset = scroll_output.state.offset.y;
+          let target_id_lst = if selected_photo_id_lst.is_empty() {
+            photo_id_lst
+              .iter()
+              .filter(|id| *id == now_id)
+              .cloned()
+              .collect::<Vec<String>>()
+          } else {
+            selected_photo_id_lst.clone()
+          };
+          if add_to_group_button || (selection_key.add_to_group && bulk_group_id.is_some()) {
+            let group_data = bulk_group_id
+              .as_ref()
+              .and_then(|group_id| gui_group_data_lst.get_mut(group_id));
+            if let Some(group_data) = group_data {
+              let before = group_data.clone();
+              for photo_id in target_id_lst.iter() {
+                if !group_data.photo_id_list.contains(photo_id) {
+                  group_data.photo_id_list.push(photo_id.clone());
+                }
+              }
+              let count = group_data.photo_id_list.len() - before.photo_id_list.len();
+              *toast = Some(Toast::new(format!(
+                "{}枚の画像を{}に追加しました",
+                count, group_data.group_id
+              )));
+              if count > 0 {
+                undo_stack.push(undo::UndoEntry::EditGroup(before));
+              }
+            }
+          }
+          if add_keywords_button || (selection_key.add_keywords && !bulk_keywords.trim().is_empty())
+          {
+            let before_lst =
+              photodata::add_keywords(gui_photo_data_lst, &target_id_lst, bulk_keywords);
+            *toast = Some(Toast::new(format!(
+              "{}枚の画像にキーワードを追加しました",
+              before_lst.len()
+            )));
+            if !before_lst.is_empty() {
+              undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+            }
+          }
+          if let Some(flag) = selection_key.flag {
+            let before_lst = photodata::set_flag(gui_photo_data_lst, &target_id_lst, flag);
+            if !before_lst.is_empty() {
+              *toast = Some(Toast::new(format!(
+                "{}枚の画像を{}にしました",
+                before_lst.len(),
+                photodata::flag_label(flag)
+              )));
+              undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+            }
+          }
+          if delete_selected_button || selection_key.delete || delete_rejected_button {
+            let target_id_lst = if delete_rejected_button {
+              photo_id_lst
+                .iter()
+                .filter(|id| {
+                  gui_photo_data_lst
+                    .get(*id)
+                    .map(|photo_data| photodata::is_rejected(photo_data.flag.as_deref()))
+                    .unwrap_or(false)
+                })
+                .cloned()
+                .collect::<Vec<String>>()
+            } else {
+              target_id_lst
+            };
+            // ロックされている写真は削除しない
+            let unlocked_id_lst =
+              photodata::unlocked_photo_id_lst(&target_id_lst, gui_photo_data_lst);
+            let locked_count = target_id_lst.len() - unlocked_id_lst.len();
+            if locked_count > 0 {
+              *toast = Some(Toast::new(format!(
+                "ロック中の{}枚の画像は削除しませんでした",
+                locked_count
+              )));
+            }
+            for photo_id in unlocked_id_lst.iter() {
+              if let Some(entry) = undo::UndoEntry::delete_photo(
+                photo_id,
+                photo_id_lst,
+                gui_photo_data_lst,
+                thumbnail_lst,
+                gui_group_data_lst,
+              ) {
+                undo_stack.push(entry);
+              }
+              photodata::delete_photo_data(
+                photo_id,
+                photo_id_lst,
+                gui_photo_data_lst,
+                thumbnail_lst,
+                gui_group_data_lst,
+              );
+              image_save_time_lst.remove(photo_id.as_str());
+              remove_image_files(work_directory_path, photo_id);
+            }
+            if unlocked_id_lst.contains(now_id) {
+              *now_id = String::new();
+            }
+            selected_photo_id_lst.clear();
+            *selection_anchor = None;
+          }
+          if bulk_apply_button {
+            let before_lst = photodata::apply_photo_data_patch(
+              gui_photo_data_lst,
+              selected_photo_id_lst,
+              photo_data_patch,
+            );
+            *toast = Some(Toast::new(format!(
+              "{}枚の画像を更新しました",
+              before_lst.len()
+            )));
+            if !before_lst.is_empty() {
+              undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+            }
+          }
           if keep_button {
             // JSONファイルを保存
             save_file(
@@ -406,14 +2249,21 @@ impl eframe::App for PhotagApp {
               gui_group_data_lst,
               input_json_path,
               work_directory_path,
+              audit_snapshot,
+              *work_json_style,
+              missing_file_lst,
+              duplicate_id_lst,
             );
             // ファイルの保存時刻の情報を保存
             save::save_time_info_lst(work_directory_path, image_save_time_lst).unwrap();
             *json_save_time = save::get_now();
+            // 画像の更新の確認は次の更新で行う
+            *image_save_requested = true;
           }
         }
         Mode::EditGroupData => {
           ui.heading("グループデータ作成ページ");
+          theme_toggle(ui, ctx, theme);
           let keep_button = ui.button("保存").clicked();
           ui.heading("画像データ編集ページ");
           let switch_button = ui.button("切り替え").clicked();
@@ -421,6 +2271,38 @@ impl eframe::App for PhotagApp {
             *mode = Mode::EditPhotoData;
             *now_id = String::new();
           }
+          ui.heading("画像一覧ページ");
+          let gallery_button = ui.button("切り替え").clicked();
+          if gallery_button {
+            *mode = Mode::Gallery;
+            *now_id = String::new();
+          }
+          ui.heading("タイムライン");
+          if ui.button("切り替え").clicked() {
+            *mode = Mode::Timeline;
+            *now_id = String::new();
+          }
+          ui.heading("変更履歴");
+          if ui.button("表示").clicked() {
+            *audit_log_lst = audit::load_audit_log(work_directory_path).unwrap_or_default();
+            *show_audit_log = true;
+          }
+          ui.heading("データの比較");
+          if ui.button("開く").clicked() {
+            *show_json_diff = true;
+          }
+          ui.heading("設定");
+          if ui.button("開く").clicked() {
+            *show_config = true;
+          }
+          ui.heading("説明文テンプレート");
+          if ui.button("編集").clicked() {
+            *show_description_template = true;
+          }
+          ui.heading("タグの表示");
+          if ui.button("編集").clicked() {
+            *show_tag_style = true;
+          }
           ui.heading("グループID一覧");
           egui::ScrollArea::vertical().show(ui, |ui| {
             let new_button = ui.button("新規").clicked();
@@ -428,15 +2310,23 @@ impl eframe::App for PhotagApp {
               *mode = Mode::EditGroupData;
               *now_id = String::new();
             }
-            for group_id in group_id_lst.iter() {
-              let button = if group_id == now_id {
-                egui::Button::new(group_id).fill(egui::Color32::KHAKI)
+            // 子グループは親グループの下に字下げして並べる
+            for (group_id, depth) in photodata::group_tree_order(group_id_lst, gui_group_data_lst) {
+              let button = if &group_id == now_id {
+                egui::Button::new(&group_id).fill(egui::Color32::KHAKI)
               } else {
-                egui::Button::new(group_id)
+                egui::Button::new(&group_id)
               };
-              if ui.add(button).clicked() {
+              let is_clicked = ui
+                .horizontal(|ui| {
+                  ui.add_space(depth as f32 * 16.0);
+                  ui.add(button).clicked()
+                })
+                .inner;
+              if is_clicked {
                 *mode = Mode::EditGroupData;
-                *now_id = group_id.clone();
+                *now_id = group_id;
+                undo_stack.seal();
               }
             }
           });
@@ -449,12 +2339,755 @@ impl eframe::App for PhotagApp {
               gui_group_data_lst,
               input_json_path,
               work_directory_path,
+              audit_snapshot,
+              *work_json_style,
+              missing_file_lst,
+              duplicate_id_lst,
             );
             // ファイルの保存時刻の情報を保存
             save::save_time_info_lst(work_directory_path, image_save_time_lst).unwrap();
             *json_save_time = save::get_now();
+            // 画像の更新の確認は次の更新で行う
+            *image_save_requested = true;
+          }
+        }
+        Mode::Gallery => {
+          ui.heading("画像一覧ページ");
+          theme_toggle(ui, ctx, theme);
+          ui.heading("画像データ編集ページ");
+          let photo_switch_button = ui.button("切り替え").clicked();
+          if photo_switch_button {
+            *mode = Mode::EditPhotoData;
+            *now_id = String::new();
+          }
+          ui.heading("グループデータ編集ページ");
+          let group_switch_button = ui.button("切り替え").clicked();
+          if group_switch_button {
+            *mode = Mode::EditGroupData;
+            *now_id = String::new();
+          }
+          ui.heading("タイムライン");
+          if ui.button("切り替え").clicked() {
+            *mode = Mode::Timeline;
+            *now_id = String::new();
+          }
+        }
+        Mode::Timeline => {
+          ui.heading("タイムライン");
+          theme_toggle(ui, ctx, theme);
+          ui.heading("画像データ編集ページ");
+          if ui.button("切り替え").clicked() {
+            *mode = Mode::EditPhotoData;
+            *now_id = String::new();
+          }
+          ui.heading("グループデータ編集ページ");
+          if ui.button("切り替え").clicked() {
+            *mode = Mode::EditGroupData;
+            *now_id = String::new();
+          }
+          ui.heading("画像一覧ページ");
+          if ui.button("切り替え").clicked() {
+            *mode = Mode::Gallery;
+            *now_id = String::new();
+          }
+          ui.heading("時間の単位");
+          ui.radio_value(timeline_scale, photodata::TimelineScale::Year, "年");
+          ui.radio_value(timeline_scale, photodata::TimelineScale::Month, "月");
+          ui.radio_value(timeline_scale, photodata::TimelineScale::Day, "日");
+        }
+      });
+
+    egui::Window::new("変更履歴")
+      .open(show_audit_log)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        if audit_log_lst.is_empty() {
+          ui.label("変更履歴はありません");
+        } else {
+          egui::Grid::new("audit_log_grid")
+            .striped(true)
+            .show(ui, |ui| {
+              ui.strong("時刻");
+              ui.strong("ID");
+              ui.strong("項目");
+              ui.strong("変更前");
+              ui.strong("変更後");
+              ui.end_row();
+              // 新しいものから順に表示する
+              for entry in audit_log_lst.iter().rev() {
+                ui.label(entry.time.format("%Y/%m/%d %H:%M:%S").to_string());
+                ui.label(&entry.id);
+                ui.label(&entry.field);
+                ui.label(&entry.old_value);
+                ui.label(&entry.new_value);
+                ui.end_row();
+              }
+            });
+        }
+      });
+
+    // 比較結果から開く写真は、ウィンドウを閉じた後に切り替える
+    let mut json_diff_jump_id = None;
+    egui::Window::new("データの比較")
+      .open(show_json_diff)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        ui.horizontal(|ui| {
+          ui.label("比較元：");
+          ui.text_edit_singleline(json_diff_path_a);
+        });
+        ui.horizontal(|ui| {
+          ui.label("比較先：");
+          ui.text_edit_singleline(json_diff_path_b);
+        });
+        if ui.button("比較").clicked() {
+          *json_diff_result = Some(
+            save::diff_json_files(json_diff_path_a, json_diff_path_b)
+              .map_err(|err| format!("{:#}", err)),
+          );
+        }
+        match json_diff_result {
+          None => (),
+          Some(Err(err)) => {
+            ui.label(format!("比較できませんでした: {}", err));
+          }
+          Some(Ok(json_diff_lst)) if json_diff_lst.is_empty() => {
+            ui.label("差分はありません");
+          }
+          Some(Ok(json_diff_lst)) => {
+            for json_diff in json_diff_lst.iter() {
+              let is_exist = photo_id_lst.contains(&json_diff.photo_id);
+              let mut open_button = |ui: &mut egui::Ui| {
+                if ui
+                  .add_enabled(is_exist, egui::Button::new("開く"))
+                  .clicked()
+                {
+                  json_diff_jump_id = Some(json_diff.photo_id.clone());
+                }
+              };
+              match &json_diff.kind {
+                save::JsonDiffKind::Added => {
+                  ui.horizontal(|ui| {
+                    ui.label(format!("＋ {}（追加）", json_diff.photo_id));
+                    open_button(ui);
+                  });
+                }
+                save::JsonDiffKind::Removed => {
+                  ui.horizontal(|ui| {
+                    ui.label(format!("－ {}（削除）", json_diff.photo_id));
+                    open_button(ui);
+                  });
+                }
+                save::JsonDiffKind::Changed(changed_lst) => {
+                  egui::CollapsingHeader::new(format!(
+                    "{}（{}項目を変更）",
+                    json_diff.photo_id,
+                    changed_lst.len()
+                  ))
+                  .id_source(("json_diff", &json_diff.photo_id))
+                  .show(ui, |ui| {
+                    open_button(ui);
+                    egui::Grid::new(("json_diff_grid", &json_diff.photo_id))
+                      .striped(true)
+                      .show(ui, |ui| {
+                        ui.strong("項目");
+                        ui.strong("比較元");
+                        ui.strong("比較先");
+                        ui.end_row();
+                        for (field, old_value, new_value) in changed_lst.iter() {
+                          ui.label(field);
+                          ui.label(old_value);
+                          ui.label(new_value);
+                          ui.end_row();
+                        }
+                      });
+                  });
+                }
+              }
+            }
+          }
+        }
+      });
+    if let Some(photo_id) = json_diff_jump_id {
+      *mode = Mode::EditPhotoData;
+      *now_id = photo_id;
+      undo_stack.seal();
+    }
+
+    egui::Window::new("撮影日時とファイルの日時のずれ")
+      .open(show_timestamp_discrepancy)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        ui.horizontal(|ui| {
+          ui.label("閾値：");
+          ui.add(
+            egui::DragValue::new(timestamp_discrepancy_hours)
+              .clamp_range(1..=24 * 365)
+              .suffix("時間"),
+          );
+          if ui.button("検出").clicked() {
+            *timestamp_discrepancy_lst = photodata::find_timestamp_discrepancies(
+              photo_id_lst,
+              gui_photo_data_lst,
+              original_image_folder_path,
+              chrono::Duration::hours(*timestamp_discrepancy_hours),
+            );
+          }
+        });
+        if timestamp_discrepancy_lst.is_empty() {
+          ui.label("ずれている写真はありません");
+          return;
+        }
+        egui::Grid::new("timestamp_discrepancy_grid")
+          .striped(true)
+          .show(ui, |ui| {
+            ui.strong("ID");
+            ui.strong("撮影日時");
+            ui.strong("ファイルの日時");
+            ui.strong("ずれ");
+            ui.end_row();
+            for discrepancy in timestamp_discrepancy_lst.iter() {
+              ui.label(&discrepancy.photo_id);
+              ui.label(
+                discrepancy
+                  .exif_datetime
+                  .format("%Y/%m/%d %H:%M")
+                  .to_string(),
+              );
+              ui.label(
+                discrepancy
+                  .file_datetime
+                  .format("%Y/%m/%d %H:%M")
+                  .to_string(),
+              );
+              ui.label(discrepancy.label());
+              ui.end_row();
+            }
+          });
+        if ui.button("撮影日時をファイルの日時に合わせる").clicked() {
+          let before_lst =
+            photodata::align_datetime_to_file(gui_photo_data_lst, timestamp_discrepancy_lst);
+          *toast = Some(Toast::new(format!(
+            "{}枚の写真の撮影日時を書き換えました",
+            before_lst.len()
+          )));
+          if !before_lst.is_empty() {
+            undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+          }
+          timestamp_discrepancy_lst.clear();
+        }
+        if ui.button("ファイルの日時を撮影日時に合わせる").clicked() {
+          let message = match photodata::align_file_to_datetime(
+            gui_photo_data_lst,
+            timestamp_discrepancy_lst,
+            original_image_folder_path,
+          ) {
+            Ok(count) => format!("{}個のファイルの日時を書き換えました", count),
+            Err(err) => format!("ファイルの日時を書き換えられませんでした: {:#}", err),
+          };
+          *toast = Some(Toast::new(message));
+          timestamp_discrepancy_lst.clear();
+        }
+      });
+
+    egui::Window::new("見つからない画像ファイル")
+      .open(show_missing_file)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        ui.label("元画像のフォルダに無いため、次の画像は読み込んでいません");
+        ui.label("ファイル名を確認して起動し直してください（入力のJSONファイルからは消えません）");
+        egui::Grid::new("missing_file_grid")
+          .striped(true)
+          .show(ui, |ui| {
+            ui.strong("ID");
+            ui.strong("ファイル名");
+            ui.end_row();
+            for missing_file in missing_file_lst.iter() {
+              ui.label(&missing_file.id);
+              ui.label(&missing_file.file_name);
+              ui.end_row();
+            }
+          });
+      });
+
+    egui::Window::new("重複しているID")
+      .open(show_duplicate_id)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        ui.label(
+          "同じIDが複数の画像に付けられているため、それぞれ先頭の画像だけを読み込んでいます",
+        );
+        ui.label("IDを付け直して起動し直してください（入力のJSONファイルからは消えません）");
+        egui::Grid::new("duplicate_id_grid")
+          .striped(true)
+          .show(ui, |ui| {
+            ui.strong("ID");
+            ui.strong("ファイル名");
+            ui.end_row();
+            for duplicate_id in duplicate_id_lst.iter() {
+              ui.label(&duplicate_id.id);
+              ui.label(duplicate_id.file_names());
+              ui.end_row();
+            }
+          });
+      });
+
+    egui::Window::new("画像の検証結果")
+      .open(show_invalid_image)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        if invalid_image_lst.is_empty() {
+          ui.label("問題のある画像はありません");
+        } else {
+          egui::Grid::new("invalid_image_grid")
+            .striped(true)
+            .show(ui, |ui| {
+              ui.strong("ID");
+              ui.strong("ファイル名");
+              ui.strong("内容");
+              ui.end_row();
+              for invalid_image in invalid_image_lst.iter() {
+                ui.label(&invalid_image.photo_id);
+                ui.label(&invalid_image.file_name);
+                if excluded_photo_id_lst.contains(&invalid_image.photo_id) {
+                  ui.label(format!("{}（処理対象から除外）", invalid_image.reason));
+                } else {
+                  ui.label(&invalid_image.reason);
+                }
+                ui.end_row();
+              }
+            });
+        }
+      });
+
+    let mut normalize_open = *show_normalize_confirm;
+    egui::Window::new("メタデータを正規化")
+      .open(&mut normalize_open)
+      .collapsible(false)
+      .show(ctx, |ui| {
+        ui.label("全ての写真のF値・焦点距離・シャッタースピード・ISO感度の表記を揃えます");
+        ui.label("（例：f/2.8 → 2.8、24 mm → 24、ISO 100 → 100）");
+        ui.horizontal(|ui| {
+          if ui.button("実行").clicked() {
+            let (count, before_lst) = photodata::normalize_all_photo_metadata(gui_photo_data_lst);
+            *toast = Some(Toast::new(format!("{}件の項目を正規化しました", count)));
+            if !before_lst.is_empty() {
+              undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+            }
+            *show_normalize_confirm = false;
           }
+          if ui.button("キャンセル").clicked() {
+            *show_normalize_confirm = false;
+          }
+        });
+      });
+    if !normalize_open {
+      *show_normalize_confirm = false;
+    }
+
+    let mut metadata_import_open = metadata_import.is_some();
+    let mut is_import_finished = false;
+    if let Some(MetadataImport { table, mapping }) = metadata_import {
+      egui::Window::new("メタデータの取り込み")
+        .open(&mut metadata_import_open)
+        .vscroll(true)
+        .show(ctx, |ui| {
+          ui.label(format!("{}行を読み込みました", table.row_lst.len()));
+          ui.heading("対応づけ");
+          egui::Grid::new("mapping_grid").show(ui, |ui| {
+            ui.label("IDの列");
+            egui::ComboBox::from_id_source("mapping_id_column")
+              .selected_text(mapping.id_column.as_deref().unwrap_or("なし"))
+              .show_ui(ui, |ui| {
+                ui.selectable_value(&mut mapping.id_column, None, "なし");
+                for column in table.column_lst.iter() {
+                  ui.selectable_value(&mut mapping.id_column, Some(column.clone()), column);
+                }
+              });
+            ui.end_row();
+            for (column, target) in mapping.target_lst.iter_mut() {
+              ui.label(column.as_str());
+              egui::ComboBox::from_id_source(format!("mapping_{}", column))
+                .selected_text(target.map(|field| field.name()).unwrap_or("取り込まない"))
+                .show_ui(ui, |ui| {
+                  ui.selectable_value(target, None, "取り込まない");
+                  for field in mapping::PhotoField::ALL.iter() {
+                    ui.selectable_value(target, Some(*field), field.name());
+                  }
+                });
+              ui.end_row();
+            }
+          });
+          for warning in mapping::mapping_warning_lst(table, mapping, gui_photo_data_lst) {
+            ui.colored_label(egui::Color32::RED, warning);
+          }
+          ui.heading(format!("先頭{}件のプレビュー", METADATA_PREVIEW_COUNT));
+          egui::Grid::new("mapping_preview_grid")
+            .striped(true)
+            .show(ui, |ui| {
+              ui.strong("ID");
+              ui.strong("項目");
+              ui.strong("現在の値");
+              ui.strong("取り込んだ後の値");
+              ui.end_row();
+              let preview_lst =
+                mapping::preview(table, mapping, gui_photo_data_lst, METADATA_PREVIEW_COUNT);
+              for preview_row in preview_lst.iter() {
+                if !preview_row.is_known {
+                  ui.label(&preview_row.photo_id);
+                  ui.colored_label(egui::Color32::RED, "作業データに無いID");
+                  ui.end_row();
+                  continue;
+                }
+                for (field, old_value, new_value) in preview_row.change_lst.iter() {
+                  ui.label(&preview_row.photo_id);
+                  ui.label(field.name());
+                  ui.label(old_value);
+                  ui.label(new_value);
+                  ui.end_row();
+                }
+              }
+            });
+          ui.horizontal(|ui| {
+            if ui.button("取り込む").clicked() {
+              let before_lst = mapping::apply_mapping(table, mapping, gui_photo_data_lst);
+              *toast = Some(Toast::new(format!(
+                "{}枚の写真のメタデータを取り込みました",
+                before_lst.len()
+              )));
+              if !before_lst.is_empty() {
+                undo_stack.push(undo::UndoEntry::EditPhoto(before_lst));
+              }
+              is_import_finished = true;
+            }
+            if ui.button("キャンセル").clicked() {
+              is_import_finished = true;
+            }
+          });
+        });
+    }
+    if !metadata_import_open || is_import_finished {
+      *metadata_import = None;
+    }
+
+    egui::Window::new("説明文テンプレート")
+      .open(show_description_template)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        for (placeholder, description) in template::PLACEHOLDER_LST.iter() {
+          ui.label(format!("{}：{}", placeholder, description));
+        }
+        ui.separator();
+        let mut delete_index = None;
+        for (i, description_template) in description_template_lst.iter_mut().enumerate() {
+          ui.horizontal(|ui| {
+            ui.label("名前");
+            ui.text_edit_singleline(&mut description_template.name);
+            if ui.button("削除").clicked() {
+              delete_index = Some(i);
+            }
+          });
+          ui.text_edit_multiline(&mut description_template.body);
+          ui.separator();
         }
+        if let Some(i) = delete_index {
+          description_template_lst.remove(i);
+        }
+        ui.horizontal(|ui| {
+          if ui.button("追加").clicked() {
+            description_template_lst.push(template::DescriptionTemplate {
+              name: "新しいテンプレート".to_string(),
+              body: String::new(),
+            });
+          }
+          if ui.button("保存").clicked() {
+            let message = match template::save_description_templates(
+              work_directory_path,
+              description_template_lst,
+            ) {
+              Ok(()) => "テンプレートを保存しました".to_string(),
+              Err(err) => format!("テンプレートを保存できませんでした: {}", err),
+            };
+            *toast = Some(Toast::new(message));
+          }
+        });
+      });
+
+    egui::Window::new("タグの表示")
+      .open(show_tag_style)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        ui.label("色やアイコンを設定していないタグは既定の色で表示します");
+        ui.separator();
+        let mut tag_lst = tag_style::used_tag_lst(gui_photo_data_lst.values());
+        for tag in tag_style_lst.keys() {
+          if !tag_lst.contains(tag) {
+            tag_lst.push(tag.clone());
+          }
+        }
+        tag_lst.sort();
+        let mut reset_tag = None;
+        egui::Grid::new("tag_style_grid").show(ui, |ui| {
+          for tag in tag_lst.iter() {
+            let mut style = tag_style::find_tag_style(tag_style_lst, tag);
+            tag_chip(ui, tag, &style);
+            let mut rgb = style.rgb();
+            let is_color_changed = ui.color_edit_button_srgb(&mut rgb).changed();
+            let is_icon_changed = ui
+              .add(
+                egui::TextEdit::singleline(&mut style.icon)
+                  .hint_text("アイコン")
+                  .desired_width(60.0),
+              )
+              .changed();
+            if is_color_changed || is_icon_changed {
+              style.color = tag_style::format_color(rgb);
+              tag_style_lst.insert(tag.clone(), style);
+            }
+            if ui
+              .add_enabled(
+                tag_style_lst.contains_key(tag),
+                egui::Button::new("既定に戻す"),
+              )
+              .clicked()
+            {
+              reset_tag = Some(tag.clone());
+            }
+            ui.end_row();
+          }
+        });
+        if let Some(tag) = reset_tag {
+          tag_style_lst.remove(&tag);
+        }
+        if ui.button("保存").clicked() {
+          let message = match tag_style::save_tag_styles(work_directory_path, tag_style_lst) {
+            Ok(()) => "タグの表示を保存しました".to_string(),
+            Err(err) => format!("タグの表示を保存できませんでした: {}", err),
+          };
+          *toast = Some(Toast::new(message));
+        }
+      });
+
+    // 固定した写真をメイン画面とは独立して拡大・移動できるようにする
+    if let Some(view) = compare_view {
+      let mut open = true;
+      egui::Window::new(format!("比較：{}", view.photo_id))
+        .open(&mut open)
+        .show(ctx, |ui| {
+          ui.add(egui::Slider::new(&mut view.view.zoom, 1.0..=8.0).text("拡大率"));
+          match view.image.get() {
+            Some(image) => {
+              let size = calculate_image_size(PREVIEW_SIZE as f32, &image.size());
+              let uv = view.view.uv_rect();
+              let response = ui.add(
+                egui::Image::new(image.texture_id(ctx), size)
+                  .uv(uv)
+                  .sense(egui::Sense::drag()),
+              );
+              // ドラッグした分だけ表示範囲を動かす
+              view.view.drag(response.drag_delta(), size);
+            }
+            None => {
+              ui.label(IMAGE_LOAD_ERROR_MESSAGE);
+            }
+          }
+        });
+      if !open {
+        *compare_view = None;
+      }
+    }
+
+    // 表示用の画像を拡大・移動して、ピントや細部を確認できるようにする
+    // 閉じたら読み込んだ画像ごと破棄する
+    if let Some(full_size) = full_size_view {
+      let mut open = true;
+      egui::Window::new(format!("原寸表示：{}", full_size.photo_id))
+        .open(&mut open)
+        .default_size(egui::vec2(800.0, 600.0))
+        .show(ctx, |ui| {
+          if full_size.image.is_none() {
+            let original_image_path = gui_photo_data_lst
+              .get(&full_size.photo_id)
+              .map(|photo_data| format!("{}/{}", original_image_folder_path, photo_data.file_name))
+              .unwrap_or_default();
+            full_size.load(work_directory_path, &original_image_path);
+          }
+          ui.add(
+            egui::Slider::new(&mut full_size.view.zoom, 1.0..=FULL_SIZE_MAX_ZOOM)
+              .logarithmic(true)
+              .text("拡大率"),
+          );
+          match full_size.image.clone() {
+            Some(Ok(image)) => {
+              let size = calculate_image_size(ui.available_width(), &image.size());
+              let uv = full_size.view.uv_rect();
+              let response = ui.add(
+                egui::Image::new(image.texture_id(ctx), size)
+                  .uv(uv)
+                  .sense(egui::Sense::drag()),
+              );
+              full_size.view.drag(response.drag_delta(), size);
+              if response.hovered() {
+                let scroll_delta = ui.input().scroll_delta.y;
+                full_size.view.scroll(scroll_delta, 1.0, FULL_SIZE_MAX_ZOOM);
+              }
+            }
+            Some(Err(err)) => {
+              ui.colored_label(egui::Color32::RED, err);
+            }
+            None => (),
+          }
+        });
+      if !open {
+        *full_size_view = None;
+      }
+    }
+
+    egui::Window::new("設定").open(show_config).show(ctx, |ui| {
+      let mut changed = false;
+      ui.heading("自動保存");
+      changed |= ui
+        .checkbox(&mut config.save.manual_save_only, "手動保存のみ")
+        .changed();
+      ui.add_enabled_ui(!config.save.manual_save_only, |ui| {
+        let range = config::MIN_SAVE_DIFF_TIME..=config::MAX_SAVE_DIFF_TIME;
+        ui.label("JSONファイルを保存する間隔");
+        changed |= ui
+          .add(
+            egui::Slider::new(&mut config.save.save_json_diff_time, range.clone())
+              .logarithmic(true)
+              .suffix("秒"),
+          )
+          .changed();
+        ui.label("画像の更新を確認する間隔");
+        changed |= ui
+          .add(
+            egui::Slider::new(&mut config.save.save_image_diff_time, range)
+              .logarithmic(true)
+              .suffix("秒"),
+          )
+          .changed();
+      });
+      ui.heading("画像の品質");
+      ui.label("小さい画像ほど品質を下げ、その間は大きさの対数に比例して品質を上げる");
+      let quality = &mut config.quality;
+      ui.horizontal(|ui| {
+        ui.label("最小");
+        changed |= ui
+          .add(
+            egui::DragValue::new(&mut quality.min_size)
+              .clamp_range(1..=quality.max_size)
+              .suffix("px"),
+          )
+          .changed();
+        changed |= ui
+          .add(egui::Slider::new(&mut quality.min_quality, 1.0..=100.0).text("品質"))
+          .changed();
+      });
+      ui.horizontal(|ui| {
+        ui.label("最大");
+        changed |= ui
+          .add(
+            egui::DragValue::new(&mut quality.max_size)
+              .clamp_range(quality.min_size..=8192)
+              .suffix("px"),
+          )
+          .changed();
+        changed |= ui
+          .add(egui::Slider::new(&mut quality.max_quality, 1.0..=100.0).text("品質"))
+          .changed();
+      });
+      ui.label(format!(
+        "遅延読み込み用：{:.0}　表示用：{:.0}",
+        quality.quality_for_size(LAZY_IMAGE_SIZE),
+        quality.quality_for_size(NORMAL_IMAGE_SIZE)
+      ));
+      ui.label(
+        "変更は次に画像を圧縮し直したときから反映される（--force-regenerateで全て作り直せる）",
+      );
+      if changed {
+        if let Err(err) = config::save_config(work_directory_path, config) {
+          error!("photag.tomlを保存できませんでした: {}", err);
+        }
+      }
+    });
+
+    egui::Window::new("連番リネーム")
+      .open(show_renumber)
+      .vscroll(true)
+      .show(ctx, |ui| {
+        ui.horizontal(|ui| {
+          ui.radio_value(renumber_order, save::RenumberOrder::Date, "撮影日時順");
+          ui.radio_value(renumber_order, save::RenumberOrder::Group, "グループ順");
+          ui.radio_value(renumber_order, save::RenumberOrder::Altitude, "標高順");
+        });
+        ui.horizontal(|ui| {
+          ui.label("開始番号");
+          ui.add(egui::DragValue::new(renumber_start));
+          ui.label("桁数");
+          ui.add(egui::DragValue::new(renumber_padding).clamp_range(1..=10));
+        });
+        let sorted_id_lst = save::sort_photo_id_lst(
+          *renumber_order,
+          photo_id_lst,
+          gui_photo_data_lst,
+          group_id_lst,
+          gui_group_data_lst,
+        );
+        let renumber_map = save::renumber_files(
+          &sorted_id_lst,
+          gui_photo_data_lst,
+          *renumber_start,
+          *renumber_padding,
+        );
+        // 対応表を確認してから実行する
+        if ui.button("実行").clicked() {
+          match save::apply_renumber_files(
+            original_image_folder_path,
+            work_directory_path,
+            &renumber_map,
+            gui_photo_data_lst,
+          ) {
+            Ok(()) => {
+              // 入力元のJSONファイルにも新しいファイル名を反映させる
+              save_file(
+                photo_id_lst,
+                gui_photo_data_lst,
+                group_id_lst,
+                gui_group_data_lst,
+                input_json_path,
+                work_directory_path,
+                audit_snapshot,
+                *work_json_style,
+                missing_file_lst,
+                duplicate_id_lst,
+              );
+              *renumber_message = "ファイル名を変更しました".to_string();
+            }
+            Err(err) => {
+              *renumber_message = format!("ファイル名を変更できませんでした: {}", err);
+            }
+          }
+        }
+        ui.label(renumber_message.as_str());
+        egui::Grid::new("renumber_grid")
+          .striped(true)
+          .show(ui, |ui| {
+            ui.strong("ID");
+            ui.strong("現在のファイル名");
+            ui.strong("新しいファイル名");
+            ui.end_row();
+            for id in sorted_id_lst.iter() {
+              if let (Some(photo_data), Some(file_name)) =
+                (gui_photo_data_lst.get(id), renumber_map.get(id))
+              {
+                ui.label(id);
+                ui.label(&photo_data.file_name);
+                ui.label(file_name);
+                ui.end_row();
+              }
+            }
+          });
       });
 
     egui::CentralPanel::default().show(ctx, |ui| {
@@ -465,87 +3098,542 @@ impl eframe::App for PhotagApp {
         gui_group_data_lst,
         now_id,
         dummy_group_data,
+        undo_stack,
+        preview_compressor,
+        preview_image,
+        preview_quality,
+        exif_dump,
+        description_template_lst,
+        tag_style_lst,
+        toast,
+        relative_minutes,
+        montage_layout,
+        picked_color,
+        base_url,
+        auto_tag_suggestion,
+        photo_language,
+        new_language,
         ..
       } = self;
       match mode {
         Mode::EditPhotoData => {
-          if !now_id.is_empty() {
-            let mut photo_data = gui_photo_data_lst.get(now_id).unwrap().clone();
+          // 削除などでデータが無くなっている場合は何も表示しない
+          if let Some(mut photo_data) = gui_photo_data_lst.get(now_id).cloned() {
             ui.heading(format!("{}({})", &now_id, photo_data.file_name));
-            ui.vertical(|ui| {
-              ui.set_width(300.0);
-              ui.horizontal(|ui| {
-                ui.label("alt：");
-                ui.text_edit_singleline(&mut photo_data.alt);
-              });
-              ui.horizontal(|ui| {
-                ui.label("title：");
-                ui.text_edit_singleline(&mut photo_data.title);
-              });
-              ui.horizontal(|ui| {
-                ui.label("撮影場所：");
-                ui.text_edit_singleline(&mut photo_data.location);
-              });
-              ui.horizontal(|ui| {
-                ui.label("ISO感度：");
-                ui.text_edit_singleline(&mut photo_data.iso);
-              });
-              ui.horizontal(|ui| {
-                ui.label("F値：");
-                ui.text_edit_singleline(&mut photo_data.f_value);
-              });
-              ui.horizontal(|ui| {
-                ui.label("シャッタースピード：");
-                ui.text_edit_singleline(&mut photo_data.time);
-              });
-              ui.horizontal(|ui| {
-                ui.label("撮影日時：");
-                ui.text_edit_singleline(&mut photo_data.year);
-                ui.label("/");
-                ui.text_edit_singleline(&mut photo_data.month);
-                ui.label("/");
-                ui.text_edit_singleline(&mut photo_data.day);
-                ui.label(", ");
-                ui.text_edit_singleline(&mut photo_data.hour);
-                ui.label(":");
-                ui.text_edit_singleline(&mut photo_data.minutes);
-              });
-              ui.horizontal(|ui| {
-                ui.label("使用機材：");
-                ui.text_edit_singleline(&mut photo_data.body);
-              });
-              ui.horizontal(|ui| {
-                ui.label("               + ");
-                ui.text_edit_singleline(&mut photo_data.lens);
-              });
-              ui.horizontal(|ui| {
-                ui.label("焦点距離：");
-                ui.text_edit_singleline(&mut photo_data.focal_length);
-                ui.label("mm");
-              });
-              // サムネイル生成
-              let image_buf = thumbnail_lst.get(now_id).unwrap();
-              let image = RetainedImage::from_image_bytes(&*now_id, image_buf).unwrap();
-              image.show_size(ui, calculate_image_size(300.0, &image.size()));
+            if !photodata::is_valid_id(now_id) {
+              ui.colored_label(
+                egui::Color32::RED,
+                format!(
+                  "IDにファイル名やURLに使えない文字が含まれています（{}として書き出します）",
+                  photodata::sanitize_id(now_id)
+                ),
+              );
+            }
+            let is_locked = photo_data.locked;
+            ui.horizontal(|ui| {
+              if is_locked {
+                ui.label("🔒 ロック中（編集できません）");
+                if ui.button("ロックを解除").clicked() {
+                  *unlock_confirm_id = Some(now_id.clone());
+                }
+              } else if ui.button("ロック").clicked() {
+                photo_data.locked = true;
+              }
             });
-            ui.label("グループへの登録");
-            let mut group_check_lst =
-              make_group_check_lst(now_id, group_id_lst, gui_group_data_lst);
-            egui::ScrollArea::vertical().show(ui, |ui| {
-              for i in 0..group_check_lst.len() {
-                ui.horizontal(|ui| {
-                  let group_id = &group_id_lst[i];
-                  let group_data = gui_group_data_lst.get(group_id).unwrap();
-                  ui.checkbox(&mut group_check_lst[i].1, group_id);
-                  ui.label(format!(
-                    "  {}（{}）",
-                    group_data.title, group_data.description
-                  ));
-                });
+            ui.horizontal(|ui| {
+              ui.set_enabled(!is_locked);
+              ui.label("フラグ：");
+              for (flag, key) in [
+                (Some(photodata::FLAG_PICK), "P"),
+                (Some(photodata::FLAG_REJECT), "X"),
+                (None, "U"),
+              ] {
+                ui.selectable_value(
+                  &mut photo_data.flag,
+                  flag.map(str::to_string),
+                  photodata::flag_label(flag),
+                )
+                .on_hover_text(key);
+              }
+            });
+            let delete_button = ui
+              .add_enabled(!is_locked, egui::Button::new("削除"))
+              .clicked();
+            ui.horizontal(|ui| {
+              if ui.button("比較ウィンドウに固定").clicked() {
+                *compare_view = Some(CompareView::new(
+                  now_id.clone(),
+                  thumbnail_lst.get(now_id).map(Vec::as_slice),
+                ));
+              }
+              if ui.button("原寸で表示").clicked() {
+                *full_size_view = Some(FullSizeView::new(now_id.clone()));
               }
             });
-            update_group_data(now_id, &group_check_lst, group_id_lst, gui_group_data_lst);
-            gui_photo_data_lst.insert(now_id.clone(), photo_data);
+            if let Some(base_url) = base_url {
+              if ui.button("URLをコピー").clicked() {
+                ui.output().copied_text =
+                  photodata::build_public_url(base_url, &photo_data.photo_src);
+              }
+            }
+            if delete_button {
+              if let Some(entry) = undo::UndoEntry::delete_photo(
+                now_id,
+                photo_id_lst,
+                gui_photo_data_lst,
+                thumbnail_lst,
+                gui_group_data_lst,
+              ) {
+                undo_stack.push(entry);
+              }
+              photodata::delete_photo_data(
+                now_id,
+                photo_id_lst,
+                gui_photo_data_lst,
+                thumbnail_lst,
+                gui_group_data_lst,
+              );
+              image_save_time_lst.remove(now_id.as_str());
+              remove_image_files(work_directory_path, now_id);
+              *now_id = String::new();
+            }
+            if !delete_button {
+              if ui
+                .add_enabled(!is_locked, egui::Button::new("Exifから空欄を補完"))
+                .clicked()
+              {
+                let image_path = format!("{}/{}", original_image_folder_path, photo_data.file_name);
+                let message = match photodata::parse_exif_data(&image_path) {
+                  Ok(exif) => {
+                    let count = photodata::fill_empty_fields_from_exif(&mut photo_data, exif);
+                    format!("{}件の項目をExif情報から補完しました", count)
+                  }
+                  Err(err) => format!("Exif情報を読み込めませんでした: {}", err),
+                };
+                *toast = Some(Toast::new(message));
+              }
+              // 取り込んだ値がおかしいときに原因を調べられるように、Exifをそのまま表示する
+              ui.collapsing("EXIF情報を表示", |ui| {
+                let is_loaded = matches!(exif_dump, Some((id, _)) if id == now_id);
+                if !is_loaded {
+                  let image_path =
+                    format!("{}/{}", original_image_folder_path, photo_data.file_name);
+                  let field_lst = photodata::dump_all_exif(&image_path).unwrap_or_else(|err| {
+                    debug!("Exif情報を読み込めませんでした（{}）: {:#}", now_id, err);
+                    Vec::new()
+                  });
+                  *exif_dump = Some((now_id.clone(), field_lst));
+                }
+                match exif_dump {
+                  Some((_, field_lst)) if !field_lst.is_empty() => {
+                    egui::ScrollArea::vertical()
+                      .id_source("exif_dump")
+                      .max_height(300.0)
+                      .show(ui, |ui| {
+                        egui::Grid::new("exif_dump_grid")
+                          .striped(true)
+                          .show(ui, |ui| {
+                            for (tag, value) in field_lst.iter() {
+                              ui.label(tag);
+                              ui.label(value);
+                              ui.end_row();
+                            }
+                          });
+                      });
+                  }
+                  _ => {
+                    ui.label("EXIF情報なし");
+                  }
+                }
+              });
+              let file_name = photo_data.file_name.clone();
+              let adjustment =
+                config::find_adjustment(&config.profile_lst, &photo_data.body, &photo_data.lens);
+              let focus_area = photo_data.focus_area;
+              // サムネイルと圧縮プレビューを表示する
+              let mut show_preview = |ui: &mut egui::Ui| {
+                // サムネイル生成
+                regenerate_thumbnail(
+                  now_id,
+                  &file_name,
+                  &adjustment,
+                  original_image_folder_path,
+                  work_directory_path,
+                  thumbnail_lst,
+                  excluded_photo_id_lst,
+                );
+                let max = ui.available_width().min(PREVIEW_SIZE as f32);
+                let image_rect = show_image(
+                  ui,
+                  now_id,
+                  thumbnail_lst.get(now_id).map(Vec::as_slice),
+                  max,
+                );
+                if let (Some(image_rect), Some(focus_area)) = (image_rect, &focus_area) {
+                  paint_focus_area(ui, image_rect, focus_area);
+                }
+                // クリックした位置の色を元画像から取り出す
+                if let Some(image_rect) = image_rect {
+                  let response = ui.interact(
+                    image_rect,
+                    ui.id().with("color_picker"),
+                    egui::Sense::click(),
+                  );
+                  if let (true, Some(pos)) = (response.clicked(), response.interact_pointer_pos()) {
+                    let relative = (pos - image_rect.min) / image_rect.size();
+                    let image_path = format!("{}/{}", original_image_folder_path, file_name);
+                    *picked_color = match pick_color(&image_path, relative) {
+                      Ok(color) => Some(color),
+                      Err(err) => {
+                        *toast = Some(Toast::new(format!("色を取得できませんでした: {}", err)));
+                        None
+                      }
+                    };
+                  }
+                }
+                if let Some([r, g, b]) = *picked_color {
+                  ui.horizontal(|ui| {
+                    let (rect, _) =
+                      ui.allocate_exact_size(egui::vec2(20.0, 20.0), egui::Sense::hover());
+                    ui.painter()
+                      .rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+                    let hex = format!("#{:02X}{:02X}{:02X}", r, g, b);
+                    ui.label(format!("RGB({}, {}, {}) {}", r, g, b, hex));
+                    if ui.button("コピー").clicked() {
+                      ui.output().copied_text = hex;
+                    }
+                  });
+                }
+                // 品質を変えたときの圧縮結果を確認する
+                ui.collapsing("圧縮プレビュー", |ui| {
+                  ui.add(egui::Slider::new(preview_quality, 10.0..=100.0).text("品質"));
+                  let is_loaded = matches!(preview_compressor, Some((id, _)) if id == now_id);
+                  if !is_loaded {
+                    let image_path = format!("{}/{}", original_image_folder_path, file_name);
+                    let compressor = image::open_file(&image_path)
+                      .and_then(|raw_data| image::PreviewCompressor::new(&raw_data, PREVIEW_SIZE))
+                      .ok();
+                    *preview_compressor = Some((now_id.clone(), compressor));
+                    *preview_image = None;
+                  }
+                  let buf = preview_compressor
+                    .as_mut()
+                    .and_then(|(_, compressor)| compressor.as_mut())
+                    .and_then(|compressor| compressor.compress(*preview_quality).ok());
+                  match buf {
+                    Some(buf) => {
+                      ui.label(format!("{:.1} KB", buf.len() as f32 / 1024.0));
+                      let is_cached =
+                        matches!(preview_image, Some((quality, _)) if *quality == *preview_quality);
+                      if !is_cached {
+                        *preview_image =
+                          Some((*preview_quality, LoadedImage::new("preview", Some(buf))));
+                      }
+                      if let Some((_, image)) = preview_image {
+                        show_retained_image(ui, image.get(), max);
+                      }
+                    }
+                    None => {
+                      ui.label("画像を読み込めませんでした");
+                    }
+                  }
+                });
+              };
+              let previous_datetime =
+                photodata::previous_photo_datetime(now_id, photo_id_lst, gui_photo_data_lst);
+              let mut group_check_lst =
+                make_group_check_lst(now_id, group_id_lst, gui_group_data_lst);
+              let group_title_lst = make_group_title_lst(&group_check_lst, gui_group_data_lst);
+              // サムネイルを参照するので、タグの提案はフォームを表示した後に行う
+              let mut is_tag_suggestion_requested = false;
+              // 編集項目とグループへの登録を表示する
+              // フォームが長くてもプレビューが隠れないよう、フォームだけをスクロールさせる
+              let mut show_form = |ui: &mut egui::Ui| {
+                egui::ScrollArea::vertical()
+                  .id_source("photo_form")
+                  .show(ui, |ui| {
+                    // ロック中は読み取り専用にする
+                    ui.set_enabled(!is_locked);
+                    // 説明とタイトルは言語を切り替えて編集する
+                    ui.horizontal(|ui| {
+                      ui.label("言語：");
+                      let mut language_lst = vec![photodata::DEFAULT_LANGUAGE.to_string()];
+                      let used_language_lst = photo_data
+                        .alt_translation_lst
+                        .keys()
+                        .chain(photo_data.title_translation_lst.keys())
+                        .chain(std::iter::once(&*photo_language));
+                      for language in used_language_lst {
+                        if !language_lst.contains(language) {
+                          language_lst.push(language.clone());
+                        }
+                      }
+                      for language in language_lst.iter() {
+                        ui.selectable_value(photo_language, language.clone(), language);
+                      }
+                      ui.add(egui::TextEdit::singleline(new_language).desired_width(40.0));
+                      if ui
+                        .add_enabled(
+                          !new_language.trim().is_empty(),
+                          egui::Button::new("言語を追加"),
+                        )
+                        .clicked()
+                      {
+                        *photo_language = new_language.trim().to_string();
+                        new_language.clear();
+                      }
+                    });
+                    if photo_language == photodata::DEFAULT_LANGUAGE {
+                      ui.horizontal(|ui| {
+                        ui.label("alt：");
+                        ui.text_edit_singleline(&mut photo_data.alt);
+                        if ui
+                          .button("雛形")
+                          .on_hover_text("撮影場所・日時・機材から雛形を作ります")
+                          .clicked()
+                        {
+                          photo_data.alt = photodata::suggest_alt(&photo_data);
+                        }
+                      });
+                      ui.horizontal(|ui| {
+                        ui.label("title：");
+                        ui.text_edit_singleline(&mut photo_data.title);
+                      });
+                    } else {
+                      translation_text_edit(
+                        ui,
+                        "alt：",
+                        &mut photo_data.alt_translation_lst,
+                        photo_language,
+                        &photo_data.alt,
+                      );
+                      translation_text_edit(
+                        ui,
+                        "title：",
+                        &mut photo_data.title_translation_lst,
+                        photo_language,
+                        &photo_data.title,
+                      );
+                    }
+                    ui.horizontal(|ui| {
+                      ui.label("評価：");
+                      rating_selector(ui, &mut photo_data.rating);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("キーワード：");
+                      ui.text_edit_singleline(&mut photo_data.keywords)
+                        .on_hover_text("カンマ区切りで入力してください");
+                    });
+                    let keyword_lst = photodata::parse_keywords(&photo_data.keywords);
+                    if !keyword_lst.is_empty() {
+                      ui.horizontal_wrapped(|ui| {
+                        for keyword in keyword_lst.iter() {
+                          tag_chip(
+                            ui,
+                            keyword,
+                            &tag_style::find_tag_style(tag_style_lst, keyword),
+                          );
+                        }
+                      });
+                    }
+                    if ui.button("タグを提案").clicked() {
+                      is_tag_suggestion_requested = true;
+                    }
+                    // 別の写真を開いた場合は提案を捨てる
+                    if auto_tag_suggestion
+                      .as_ref()
+                      .map(|suggestion| &suggestion.photo_id != now_id)
+                      .unwrap_or(false)
+                    {
+                      *auto_tag_suggestion = None;
+                    }
+                    let mut is_suggestion_closed = false;
+                    if let Some(suggestion) = auto_tag_suggestion.as_mut() {
+                      if !suggestion.tag_lst.is_empty() {
+                        ui.horizontal_wrapped(|ui| {
+                          for (tag, is_accepted) in suggestion.tag_lst.iter_mut() {
+                            ui.checkbox(is_accepted, tag.as_str());
+                          }
+                        });
+                        ui.horizontal(|ui| {
+                          if ui.button("選んだタグを追加").clicked() {
+                            let mut keyword_lst = photodata::parse_keywords(&photo_data.keywords);
+                            for (tag, is_accepted) in suggestion.tag_lst.iter() {
+                              if *is_accepted && !keyword_lst.contains(tag) {
+                                keyword_lst.push(tag.clone());
+                              }
+                            }
+                            photo_data.keywords = photodata::join_keywords(&keyword_lst);
+                            is_suggestion_closed = true;
+                          }
+                          if ui.button("提案を閉じる").clicked() {
+                            is_suggestion_closed = true;
+                          }
+                        });
+                      }
+                    }
+                    if is_suggestion_closed {
+                      *auto_tag_suggestion = None;
+                    }
+                    ui.horizontal(|ui| {
+                      ui.label("撮影場所：");
+                      ui.text_edit_singleline(&mut photo_data.location);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("撮影セッション：");
+                      ui.text_edit_singleline(&mut photo_data.session);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("ISO感度：");
+                      validated_text_edit(ui, &mut photo_data.iso, validate::validate_iso);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("F値：");
+                      validated_text_edit(ui, &mut photo_data.f_value, validate::validate_f_value);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("開放F値：");
+                      validated_text_edit(
+                        ui,
+                        &mut photo_data.max_f_value,
+                        validate::validate_f_value,
+                      );
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("標高（m）：");
+                      validated_text_edit(
+                        ui,
+                        &mut photo_data.altitude,
+                        validate::validate_altitude,
+                      );
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("露出モード：");
+                      ui.text_edit_singleline(&mut photo_data.exposure_program);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("シャッタースピード：");
+                      ui.text_edit_singleline(&mut photo_data.time);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("撮影日時：");
+                      ui.text_edit_singleline(&mut photo_data.year);
+                      ui.label("/");
+                      validated_text_edit(ui, &mut photo_data.month, validate::validate_month);
+                      ui.label("/");
+                      validated_text_edit(ui, &mut photo_data.day, validate::validate_day);
+                      ui.label(", ");
+                      validated_text_edit(ui, &mut photo_data.hour, validate::validate_hour);
+                      ui.label(":");
+                      validated_text_edit(ui, &mut photo_data.minutes, validate::validate_minutes);
+                      // 時差が記録されている場合は協定世界時での日時も確認できるようにする
+                      if let Some(datetime) = photodata::photo_datetime_with_offset(&photo_data) {
+                        ui.label(format!("（UTC{}）", photo_data.offset_time))
+                          .on_hover_text(format!("UTC：{}", datetime.naive_utc()));
+                      }
+                    });
+                    // 連続撮影した写真のために、前の写真の撮影日時を基準に入力する
+                    ui.horizontal(|ui| {
+                      let enabled = previous_datetime.is_some();
+                      if ui
+                        .add_enabled(enabled, egui::Button::new("前の写真の日時をコピー"))
+                        .clicked()
+                      {
+                        if let Some(datetime) = previous_datetime {
+                          photodata::set_photo_datetime(&mut photo_data, datetime);
+                        }
+                      }
+                      if ui
+                        .add_enabled(enabled, egui::Button::new("前の写真＋"))
+                        .clicked()
+                      {
+                        if let Some(datetime) = previous_datetime {
+                          let datetime = photodata::add_minutes(datetime, *relative_minutes);
+                          photodata::set_photo_datetime(&mut photo_data, datetime);
+                        }
+                      }
+                      ui.add(egui::DragValue::new(relative_minutes).suffix("分"));
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("使用機材：");
+                      ui.text_edit_singleline(&mut photo_data.body);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("               + ");
+                      ui.text_edit_singleline(&mut photo_data.lens);
+                    });
+                    ui.horizontal(|ui| {
+                      ui.label("焦点距離：");
+                      validated_text_edit(
+                        ui,
+                        &mut photo_data.focal_length,
+                        validate::validate_focal_length,
+                      );
+                      ui.label("mm");
+                      if !photo_data.focal_length_35mm.is_empty() {
+                        ui.label(format!(
+                          "（≈{}mm 35mm判換算）",
+                          photo_data.focal_length_35mm
+                        ));
+                      }
+                    });
+                    if group_title_lst.is_empty() {
+                      ui.weak("どのグループにも属していません");
+                    } else {
+                      ui.label(format!("所属グループ：{}", group_title_lst.join("、")));
+                    }
+                    ui.label("グループへの登録");
+                    for i in 0..group_check_lst.len() {
+                      ui.horizontal(|ui| {
+                        let group_id = &group_id_lst[i];
+                        ui.checkbox(&mut group_check_lst[i].1, group_id);
+                        if let Some(group_data) = gui_group_data_lst.get(group_id) {
+                          ui.label(format!(
+                            "  {}（{}）",
+                            group_data.title, group_data.description
+                          ));
+                        }
+                      });
+                    }
+                  });
+              };
+              if ui.available_width() >= TWO_COLUMN_MIN_WIDTH {
+                // 画面幅が十分あるときは左にフォーム、右にプレビューを並べる
+                ui.columns(2, |columns| {
+                  show_form(&mut columns[0]);
+                  show_preview(&mut columns[1]);
+                });
+              } else {
+                show_preview(ui);
+                show_form(ui);
+              }
+              if is_tag_suggestion_requested {
+                let features = thumbnail_lst
+                  .get(now_id.as_str())
+                  .ok_or_else(|| anyhow::anyhow!(IMAGE_LOAD_ERROR_MESSAGE))
+                  .and_then(|thumbnail| image::image_features(thumbnail));
+                match features {
+                  Ok(features) => {
+                    let tag_lst = photodata::suggest_auto_tags(&photo_data, &features);
+                    if tag_lst.is_empty() {
+                      *toast = Some(Toast::new("提案できるタグはありません".to_string()));
+                    }
+                    *auto_tag_suggestion = Some(AutoTagSuggestion {
+                      photo_id: now_id.clone(),
+                      tag_lst: tag_lst.into_iter().map(|tag| (tag, false)).collect(),
+                    });
+                  }
+                  Err(err) => *toast = Some(Toast::new(format!("{:#}", err))),
+                }
+              }
+              update_group_data(now_id, &group_check_lst, group_id_lst, gui_group_data_lst);
+              if let Some(before) = gui_photo_data_lst.get(now_id.as_str()) {
+                if before != &photo_data {
+                  undo_stack.push_photo_edit(before.clone());
+                }
+              }
+              gui_photo_data_lst.insert(now_id.clone(), photo_data);
+            }
           }
         }
         Mode::EditGroupData => {
@@ -558,7 +3646,11 @@ impl eframe::App for PhotagApp {
                 || dummy_group_data.description.is_empty()
               {
                 ui.label("必須のデータが入力されていません");
-                eprintln!("必須のデータが入力されていないため、グループを新規に作成できません");
+                warn!("必須のデータが入力されていないため、グループを新規に作成できません");
+              } else if let Err(err) =
+                validate::validate_group_id(&dummy_group_data.group_id, group_id_lst, None)
+              {
+                warn!("{}", err);
               } else {
                 group_id_lst.push(dummy_group_data.clone().group_id);
                 gui_group_data_lst
@@ -571,6 +3663,11 @@ impl eframe::App for PhotagApp {
               ui.horizontal(|ui| {
                 ui.label("グループID");
                 ui.text_edit_singleline(&mut dummy_group_data.group_id);
+                if let Err(err) =
+                  validate::validate_group_id(&dummy_group_data.group_id, group_id_lst, None)
+                {
+                  ui.colored_label(egui::Color32::RED, err);
+                }
               });
               ui.horizontal(|ui| {
                 ui.label("タイトル（必須）");
@@ -579,6 +3676,12 @@ impl eframe::App for PhotagApp {
               ui.horizontal(|ui| {
                 ui.label("説明（必須）");
                 ui.text_edit_singleline(&mut dummy_group_data.description);
+                description_template_combo_box(
+                  ui,
+                  "new_group_description_template",
+                  description_template_lst,
+                  dummy_group_data,
+                );
               });
               ui.horizontal(|ui| {
                 ui.label("撮影地点");
@@ -601,8 +3704,30 @@ impl eframe::App for PhotagApp {
             });
           } else {
             ui.heading(now_id.clone());
+            if !photodata::is_valid_id(now_id) {
+              ui.colored_label(
+                egui::Color32::RED,
+                format!(
+                  "IDにファイル名やURLに使えない文字が含まれています（{}として書き出します）",
+                  photodata::sanitize_id(now_id)
+                ),
+              );
+            }
             let delete_button = ui.button("削除").clicked();
+            if let Some(base_url) = base_url {
+              if ui.button("URLをコピー").clicked() {
+                ui.output().copied_text = photodata::build_public_url(
+                  base_url,
+                  &photodata::group_page_src(url_prefix, now_id),
+                );
+              }
+            }
             if delete_button {
+              if let Some(entry) =
+                undo::UndoEntry::delete_group(now_id, group_id_lst, gui_group_data_lst)
+              {
+                undo_stack.push(entry);
+              }
               *group_id_lst = group_id_lst
                 .iter()
                 .filter(|id| id.to_string() != now_id.clone())
@@ -610,13 +3735,65 @@ impl eframe::App for PhotagApp {
                 .collect();
               *now_id = String::new();
             }
-            if !delete_button {
-              let mut group_data = gui_group_data_lst.get(now_id).unwrap().clone();
+            let group_data = if delete_button {
+              None
+            } else {
+              gui_group_data_lst.get(now_id).cloned()
+            };
+            if let Some(mut group_data) = group_data {
+              // 移動はこのグループの編集内容を反映した後に行う
+              let mut next_group_id = None;
+              ui.horizontal(|ui| {
+                for ancestor_id in photodata::group_ancestors(now_id, gui_group_data_lst) {
+                  if ui.link(&ancestor_id).clicked() {
+                    next_group_id = Some(ancestor_id);
+                  }
+                  ui.label(">");
+                }
+                ui.label(now_id.as_str());
+              });
+              let child_id_lst =
+                photodata::child_group_id_lst(now_id, group_id_lst, gui_group_data_lst);
+              if !child_id_lst.is_empty() {
+                ui.horizontal_wrapped(|ui| {
+                  ui.label("子グループ：");
+                  for child_id in child_id_lst {
+                    if ui.link(&child_id).clicked() {
+                      next_group_id = Some(child_id);
+                    }
+                  }
+                });
+              }
               ui.vertical(|ui| {
                 ui.set_width(500.0);
                 ui.horizontal(|ui| {
                   ui.label("グループID");
-                  ui.text_edit_singleline(&mut group_data.group_id);
+                  // 別のグループを開いたときと、取り消しなどで入力欄の外からIDが変わったときは入力欄を作り直す
+                  let is_stale = match &*group_id_edit {
+                    Some((id, group_id_input)) => {
+                      id.as_str() != now_id.as_str()
+                        || (*group_id_input != group_data.group_id
+                          && validate::validate_group_id(
+                            group_id_input,
+                            group_id_lst,
+                            Some(now_id),
+                          )
+                          .is_ok())
+                    }
+                    None => true,
+                  };
+                  if is_stale {
+                    *group_id_edit = Some((now_id.clone(), group_data.group_id.clone()));
+                  }
+                  if let Some((_, group_id_input)) = group_id_edit {
+                    ui.text_edit_singleline(group_id_input);
+                    // 他のグループと重複するIDには変更させない
+                    if let Err(err) =
+                      apply_group_id_edit(&mut group_data, group_id_input, group_id_lst, now_id)
+                    {
+                      ui.colored_label(egui::Color32::RED, err);
+                    }
+                  }
                 });
                 ui.horizontal(|ui| {
                   ui.label("タイトル（必須）");
@@ -625,6 +3802,12 @@ impl eframe::App for PhotagApp {
                 ui.horizontal(|ui| {
                   ui.label("説明（必須）");
                   ui.text_edit_singleline(&mut group_data.description);
+                  description_template_combo_box(
+                    ui,
+                    "group_description_template",
+                    description_template_lst,
+                    &mut group_data,
+                  );
                 });
                 ui.horizontal(|ui| {
                   ui.label("撮影地点");
@@ -644,30 +3827,1011 @@ impl eframe::App for PhotagApp {
                   ui.label(":");
                   ui.text_edit_singleline(&mut group_data.minutes);
                 });
+                ui.horizontal(|ui| {
+                  ui.label("親グループ");
+                  egui::ComboBox::from_id_source("parent_group_id")
+                    .selected_text(group_data.parent_group_id.as_deref().unwrap_or("なし"))
+                    .show_ui(ui, |ui| {
+                      ui.selectable_value(&mut group_data.parent_group_id, None, "なし");
+                      // 自分自身や子孫のグループは親にできないので選択肢に出さない
+                      for group_id in group_id_lst.iter() {
+                        if photodata::is_valid_parent(now_id, group_id, gui_group_data_lst) {
+                          ui.selectable_value(
+                            &mut group_data.parent_group_id,
+                            Some(group_id.clone()),
+                            group_id,
+                          );
+                        }
+                      }
+                    });
+                });
               });
-              ui.heading("グループに含まれる画像");
-              egui::ScrollArea::vertical().show(ui, |ui| {
-                for photo_id in group_data.photo_id_list.iter() {
-                  let photo_data = gui_photo_data_lst.get(photo_id).unwrap();
-                  ui.horizontal(|ui| {
-                    ui.label(format!("・{}（{}）", photo_data.photo_id, photo_data.alt));
-                    let thumbnail = thumbnail_lst.get(photo_id).unwrap();
-                    let thumbnail = image::compression(thumbnail, 65.0, 300).unwrap();
-                    let image = RetainedImage::from_image_bytes(&*now_id, &thumbnail).unwrap();
-                    image.show_size(ui, calculate_image_size(30.0, &image.size()));
+              ui.heading("カバー画像");
+              ui.horizontal(|ui| {
+                ui.label("カバー写真");
+                egui::ComboBox::from_id_source("cover_photo_id")
+                  .selected_text(group_data.cover_photo_id.as_deref().unwrap_or("なし"))
+                  .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut group_data.cover_photo_id, None, "なし");
+                    // グループに含まれる写真の中からだけ選べるようにする
+                    for photo_id in group_data.photo_id_list.clone().iter() {
+                      ui.selectable_value(
+                        &mut group_data.cover_photo_id,
+                        Some(photo_id.clone()),
+                        photo_id,
+                      );
+                    }
                   });
+              });
+              ui.horizontal(|ui| {
+                ui.radio_value(montage_layout, image::MontageLayout::Grid2x2, "2x2");
+                ui.radio_value(montage_layout, image::MontageLayout::OneAndThree, "1+3");
+                if ui.button("モンタージュを生成").clicked() {
+                  let message = match save_group_cover(
+                    work_directory_path,
+                    &group_data,
+                    thumbnail_lst,
+                    *montage_layout,
+                  ) {
+                    Ok(()) => "カバー画像を生成しました".to_string(),
+                    Err(err) => format!("カバー画像を生成できませんでした: {}", err),
+                  };
+                  *toast = Some(Toast::new(message));
                 }
               });
+              ui.heading("グループに含まれる画像");
+              // 並び替えはループの後でまとめて行う
+              let mut move_request = None;
+              let photo_count = group_data.photo_id_list.len();
+              // 写真の多いグループでも重くならないように、見えている行だけを描画する
+              let row_height = GROUP_IMAGE_SIZE.max(ui.spacing().interact_size.y);
+              egui::ScrollArea::vertical().show_rows(
+                ui,
+                row_height,
+                photo_count,
+                |ui, row_range| {
+                  for i in row_range {
+                    let photo_id = &group_data.photo_id_list[i];
+                    let photo_data = match gui_photo_data_lst.get(photo_id) {
+                      Some(photo_data) => photo_data,
+                      None => {
+                        warn!("写真データがありません: {}", photo_id);
+                        ui.label(format!("・{}（写真データがありません）", photo_id));
+                        continue;
+                      }
+                    };
+                    // 起動時の圧縮が終わっていない画像はその結果を待つ
+                    if startup_progress.is_complete() {
+                      regenerate_thumbnail(
+                        photo_id,
+                        &photo_data.file_name,
+                        &config::find_adjustment(
+                          &config.profile_lst,
+                          &photo_data.body,
+                          &photo_data.lens,
+                        ),
+                        original_image_folder_path,
+                        work_directory_path,
+                        thumbnail_lst,
+                        excluded_photo_id_lst,
+                      );
+                    }
+                    ui.horizontal(|ui| {
+                      if ui.add_enabled(i > 0, egui::Button::new("↑")).clicked() {
+                        move_request = Some((i, photodata::MoveDirection::Up));
+                      }
+                      if ui
+                        .add_enabled(i + 1 < photo_count, egui::Button::new("↓"))
+                        .clicked()
+                      {
+                        move_request = Some((i, photodata::MoveDirection::Down));
+                      }
+                      ui.label(format!("・{}（{}）", photo_data.photo_id, photo_data.alt));
+                      // 縮小したサムネイルは一度作ったら使い回す
+                      if !group_thumbnail_lst.contains_key(photo_id) {
+                        let group_thumbnail = thumbnail_lst.get(photo_id).and_then(|thumbnail| {
+                          image::compression(thumbnail, 65.0, 300, false, image::JpegScan::Baseline)
+                            .ok()
+                        });
+                        if let Some(group_thumbnail) = group_thumbnail {
+                          group_thumbnail_lst.insert(photo_id.clone(), group_thumbnail);
+                        }
+                      }
+                      show_image(
+                        ui,
+                        photo_id,
+                        group_thumbnail_lst
+                          .get(photo_id)
+                          .map(|image_buf| image_buf.as_slice()),
+                        GROUP_IMAGE_SIZE,
+                      );
+                    });
+                  }
+                },
+              );
+              if let Some((i, direction)) = move_request {
+                photodata::move_photo_id(&mut group_data.photo_id_list, i, direction);
+              }
+              if let Some(before) = gui_group_data_lst.get(now_id.as_str()) {
+                if before != &group_data {
+                  undo_stack.push_group_edit(before.clone());
+                }
+              }
               gui_group_data_lst.insert(now_id.clone(), group_data);
+              if let Some(next_group_id) = next_group_id {
+                *now_id = next_group_id;
+              }
+            }
+          }
+        }
+        Mode::Gallery => {
+          ui.heading("画像一覧");
+          let cell_width = GALLERY_IMAGE_SIZE + ui.spacing().item_spacing.x * 2.0;
+          let columns = ((ui.available_width() / cell_width).floor() as usize).max(1);
+          let rows = photo_id_lst.len().div_ceil(columns);
+          let row_height = GALLERY_IMAGE_SIZE + ui.text_style_height(&egui::TextStyle::Body) * 2.0;
+          // 画面に表示される行のサムネイルだけを読み込む
+          egui::ScrollArea::vertical().show_rows(ui, row_height, rows, |ui, row_range| {
+            for row in row_range {
+              ui.horizontal(|ui| {
+                for photo_id in photo_id_lst.iter().skip(row * columns).take(columns) {
+                  ui.vertical(|ui| {
+                    ui.set_width(GALLERY_IMAGE_SIZE);
+                    ui.set_height(row_height);
+                    // 起動時の圧縮が終わっていない画像はその結果を待つ
+                    let photo_data = gui_photo_data_lst
+                      .get(photo_id)
+                      .filter(|_| startup_progress.is_complete());
+                    if let Some(photo_data) = photo_data {
+                      regenerate_thumbnail(
+                        photo_id,
+                        &photo_data.file_name,
+                        &config::find_adjustment(
+                          &config.profile_lst,
+                          &photo_data.body,
+                          &photo_data.lens,
+                        ),
+                        original_image_folder_path,
+                        work_directory_path,
+                        thumbnail_lst,
+                        excluded_photo_id_lst,
+                      );
+                    }
+                    // 格子状に揃えるため、正方形に切り抜いたサムネイルを表示する
+                    let clicked = square_thumbnail_button(
+                      ui,
+                      ctx,
+                      photo_id,
+                      GALLERY_IMAGE_SIZE,
+                      thumbnail_lst,
+                      square_thumbnail_lst,
+                    );
+                    if clicked {
+                      *mode = Mode::EditPhotoData;
+                      *now_id = photo_id.clone();
+                    }
+                    if let Some(photo_data) = gui_photo_data_lst.get(photo_id) {
+                      ui.label(&photo_data.alt);
+                    }
+                  });
+                }
+              });
             }
+          });
+        }
+        Mode::Timeline => {
+          ui.heading("タイムライン");
+          let (bucket_lst, undated_id_lst) =
+            photodata::group_by_timeline(photo_id_lst, gui_photo_data_lst, *timeline_scale);
+          let mut clicked_id = None;
+          let mut show_column = |ui: &mut egui::Ui, label: &str, id_lst: &[String]| {
+            ui.vertical(|ui| {
+              ui.set_width(TIMELINE_IMAGE_SIZE);
+              ui.label(label);
+              // 同じ区間の写真は縦に積む
+              for photo_id in id_lst.iter() {
+                if let Some(photo_data) = gui_photo_data_lst.get(photo_id) {
+                  if startup_progress.is_complete() {
+                    regenerate_thumbnail(
+                      photo_id,
+                      &photo_data.file_name,
+                      &config::find_adjustment(
+                        &config.profile_lst,
+                        &photo_data.body,
+                        &photo_data.lens,
+                      ),
+                      original_image_folder_path,
+                      work_directory_path,
+                      thumbnail_lst,
+                      excluded_photo_id_lst,
+                    );
+                  }
+                }
+                let clicked = square_thumbnail_button(
+                  ui,
+                  ctx,
+                  photo_id,
+                  TIMELINE_IMAGE_SIZE,
+                  thumbnail_lst,
+                  square_thumbnail_lst,
+                );
+                if clicked {
+                  clicked_id = Some(photo_id.clone());
+                }
+              }
+            });
+          };
+          egui::ScrollArea::horizontal()
+            .id_source("timeline")
+            .show(ui, |ui| {
+              ui.horizontal_top(|ui| {
+                for bucket in bucket_lst.iter() {
+                  show_column(ui, &bucket.label, &bucket.photo_id_lst);
+                }
+              });
+            });
+          // 日時が分からない写真は別の列にまとめる
+          if !undated_id_lst.is_empty() {
+            ui.separator();
+            ui.label("撮影日時が無い写真");
+            egui::ScrollArea::horizontal()
+              .id_source("timeline_undated")
+              .show(ui, |ui| {
+                ui.horizontal_top(|ui| {
+                  for photo_id in undated_id_lst.iter() {
+                    show_column(ui, "", std::slice::from_ref(photo_id));
+                  }
+                });
+              });
+          }
+          if let Some(photo_id) = clicked_id {
+            *mode = Mode::EditPhotoData;
+            *now_id = photo_id;
           }
         }
       }
-    });
+    });
+
+    show_toast(ctx, &mut self.toast);
+  }
+}
+
+/// normal画像を書き出すときの品質
+const NORMAL_IMAGE_QUALITY: f32 = 85.0;
+/// 表示用の画像の長辺
+const NORMAL_IMAGE_SIZE: u32 = 2048;
+/// 遅延読み込み用の画像の長辺
+const LAZY_IMAGE_SIZE: u32 = 32;
+/// 圧縮プレビューの大きさ
+const PREVIEW_SIZE: u32 = 600;
+/// 写真の編集画面をフォームとプレビューの2列にする画面幅
+const TWO_COLUMN_MIN_WIDTH: f32 = 700.0;
+/// 一覧表示でのサムネイルの大きさ
+const GALLERY_IMAGE_SIZE: f32 = 150.0;
+/// グループ編集画面の一覧でのサムネイルの大きさ
+const GROUP_IMAGE_SIZE: f32 = 30.0;
+/// タイムラインでのサムネイルの大きさ
+const TIMELINE_IMAGE_SIZE: f32 = 100.0;
+/// メタデータの取り込み前に対応づけの結果を表示する件数
+const METADATA_PREVIEW_COUNT: usize = 5;
+
+/// 画像を表示できないときに代わりに表示するメッセージ
+const IMAGE_LOAD_ERROR_MESSAGE: &str = "画像を読み込めませんでした";
+
+/// トーストを表示する秒数
+const TOAST_DURATION: i32 = 4;
+
+/// 写真一覧での選択に関するキー入力
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct SelectionKey {
+  /// 上下の矢印キーで動かす向き（上が-1、下が1）
+  step: i32,
+  shift: bool,
+  /// Ctrl+A
+  select_all: bool,
+  /// Delete
+  delete: bool,
+  /// Ctrl+G
+  add_to_group: bool,
+  /// Ctrl+T
+  add_keywords: bool,
+  /// P・X・Uで付けるフラグ（`Some(None)`は保留に戻す）
+  flag: Option<Option<&'static str>>,
+}
+
+impl SelectionKey {
+  /// テキストの入力中は入力欄の操作を優先し、何も押されていないことにする
+  fn read(ctx: &egui::Context) -> Self {
+    if ctx.memory().focus().is_some() {
+      return SelectionKey::default();
+    }
+    let input = ctx.input();
+    let command = input.modifiers.command;
+    let step = if input.key_pressed(egui::Key::ArrowDown) {
+      1
+    } else if input.key_pressed(egui::Key::ArrowUp) {
+      -1
+    } else {
+      0
+    };
+    let flag = if command {
+      None
+    } else if input.key_pressed(egui::Key::P) {
+      Some(Some(photodata::FLAG_PICK))
+    } else if input.key_pressed(egui::Key::X) {
+      Some(Some(photodata::FLAG_REJECT))
+    } else if input.key_pressed(egui::Key::U) {
+      Some(None)
+    } else {
+      None
+    };
+    SelectionKey {
+      step,
+      shift: input.modifiers.shift,
+      select_all: command && input.key_pressed(egui::Key::A),
+      delete: input.key_pressed(egui::Key::Delete),
+      add_to_group: command && input.key_pressed(egui::Key::G),
+      add_keywords: command && input.key_pressed(egui::Key::T),
+      flag,
+    }
+  }
+}
+
+/// `id_lst`の中で`from`から`to`までの範囲のIDを並び順のまま返す
+/// どちらかが含まれていない場合は`to`だけを返す
+fn select_range(id_lst: &[String], from: &str, to: &str) -> Vec<String> {
+  let from_index = id_lst.iter().position(|id| id == from);
+  let to_index = id_lst.iter().position(|id| id == to);
+  match (from_index, to_index) {
+    (Some(a), Some(b)) => id_lst[a.min(b)..=a.max(b)].to_vec(),
+    _ => vec![to.to_string()],
+  }
+}
+
+/// 既定の言語以外の言語の文字列の入力欄
+/// 既定の言語の文字列を薄く表示し、空欄にした場合はその言語の文字列を消す
+fn translation_text_edit(
+  ui: &mut egui::Ui,
+  label: &str,
+  translation_lst: &mut BTreeMap<String, String>,
+  language: &str,
+  default_text: &str,
+) {
+  ui.horizontal(|ui| {
+    ui.label(label);
+    let mut text = translation_lst.get(language).cloned().unwrap_or_default();
+    if ui
+      .add(egui::TextEdit::singleline(&mut text).hint_text(default_text))
+      .changed()
+    {
+      if text.is_empty() {
+        translation_lst.remove(language);
+      } else {
+        translation_lst.insert(language.to_string(), text);
+      }
+    }
+  });
+}
+
+/// 一括適用する項目の入力欄
+/// チェックを入れた項目だけを適用する
+fn patch_field(ui: &mut egui::Ui, label: &str, value: &mut Option<String>) {
+  ui.horizontal(|ui| {
+    let mut is_apply = value.is_some();
+    if ui.checkbox(&mut is_apply, label).changed() {
+      *value = if is_apply { Some(String::new()) } else { None };
+    }
+    if let Some(value) = value {
+      ui.text_edit_singleline(value);
+    }
+  });
+}
+
+/// 明るい配色と暗い配色を切り替えるボタン
+fn theme_toggle(ui: &mut egui::Ui, ctx: &egui::Context, theme: &mut Theme) {
+  let (label, next_theme) = match theme {
+    Theme::Light => ("ダークモードにする", Theme::Dark),
+    Theme::Dark => ("ライトモードにする", Theme::Light),
+  };
+  if ui.button(label).clicked() {
+    *theme = next_theme;
+    ctx.set_visuals(theme.visuals());
+  }
+}
+
+/// トーストを画面下部に表示する
+/// 表示時間を過ぎたら消す
+fn show_toast(ctx: &egui::Context, toast: &mut Option<Toast>) {
+  if let Some(t) = toast {
+    if save::time_add_sec(t.time, TOAST_DURATION) < save::get_now() {
+      *toast = None;
+      return;
+    }
+    egui::Area::new("toast")
+      .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -20.0))
+      .show(ctx, |ui| {
+        egui::Frame::popup(ui.style()).show(ui, |ui| {
+          ui.label(&t.message);
+        });
+      });
+    // 表示時間を過ぎたときに消えるように再描画を続ける
+    ctx.request_repaint();
   }
 }
 
 /// 適切な画像のサイズを計算する
+/// 指定した写真だけをJSONとHTMLに書き出し、結果のメッセージを返す
+/// `watermark`を指定した場合はウォーターマーク付きの画像も書き出す
+#[allow(clippy::too_many_arguments)]
+fn export_photos(
+  work_directory_path: &str,
+  export_directory_path: &str,
+  target_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  rating_filter: &export::RatingFilter,
+  dual_format: bool,
+  json_style: save::JsonStyle,
+  inherit_group: bool,
+  url_prefix: &str,
+  watermark: Option<&export::WatermarkOptions>,
+) -> String {
+  // 評価が足りない写真は書き出されないので、件数にも含めない
+  let count = target_id_lst
+    .iter()
+    .filter_map(|id| gui_photo_data_lst.get(id))
+    .filter(|photo_data| photo_data.rating >= rating_filter.min_rating)
+    .count();
+  if count == 0 {
+    return "書き出す写真がありません".to_string();
+  }
+  let result = export::export_json(
+    work_directory_path,
+    export_directory_path,
+    Some(target_id_lst),
+    rating_filter,
+    dual_format,
+    json_style,
+    inherit_group,
+  )
+  .and_then(|_| {
+    export::export_html(
+      work_directory_path,
+      export_directory_path,
+      Some(target_id_lst),
+      rating_filter,
+      dual_format,
+      inherit_group,
+      url_prefix,
+    )
+  })
+  .and_then(|_| match watermark {
+    Some(watermark) => export::export_watermark(
+      work_directory_path,
+      Some(target_id_lst),
+      rating_filter,
+      watermark,
+    )
+    .map(|_| ()),
+    None => Ok(()),
+  });
+  match result {
+    Ok(()) => format!("{}枚の写真を書き出しました", count),
+    Err(err) => format!("書き出せませんでした: {}", err),
+  }
+}
+
+/// 画像のバイナリデータを表示できる形に読み込む
+/// 読み込めない場合はログを残して`None`を返す
+fn load_retained_image(debug_name: &str, image_buf: &[u8]) -> Option<RetainedImage> {
+  match RetainedImage::from_image_bytes(debug_name, image_buf) {
+    Ok(image) => Some(image),
+    Err(err) => {
+      warn!("{}（{}）: {}", IMAGE_LOAD_ERROR_MESSAGE, debug_name, err);
+      None
+    }
+  }
+}
+
+/// タグを設定された色とアイコンで表示する
+fn tag_chip(ui: &mut egui::Ui, tag: &str, style: &tag_style::TagStyle) {
+  let [r, g, b] = style.rgb();
+  let [text_r, text_g, text_b] = style.text_rgb();
+  ui.label(
+    egui::RichText::new(style.label(tag))
+      .background_color(egui::Color32::from_rgb(r, g, b))
+      .color(egui::Color32::from_rgb(text_r, text_g, text_b)),
+  );
+}
+
+/// 正方形に切り抜いたサムネイルをボタンとして表示し、クリックされたかどうかを返す
+/// 切り抜いて読み込んだサムネイルは`square_thumbnail_lst`に保持しておく
+/// 元のサムネイルがまだ無い場合は保持せず、作られてから切り抜く
+fn square_thumbnail_button(
+  ui: &mut egui::Ui,
+  ctx: &egui::Context,
+  photo_id: &str,
+  size: f32,
+  thumbnail_lst: &HashMap<String, Vec<u8>>,
+  square_thumbnail_lst: &mut HashMap<String, LoadedImage>,
+) -> bool {
+  if !square_thumbnail_lst.contains_key(photo_id) {
+    if let Some(thumbnail) = thumbnail_lst.get(photo_id) {
+      let square_thumbnail =
+        image::thumbnail_square(thumbnail, 70.0, GALLERY_IMAGE_SIZE as u32).ok();
+      square_thumbnail_lst.insert(
+        photo_id.to_string(),
+        LoadedImage::new(photo_id, square_thumbnail.as_deref()),
+      );
+    }
+  }
+  match square_thumbnail_lst
+    .get(photo_id)
+    .and_then(LoadedImage::get)
+  {
+    Some(image) => {
+      let button = egui::ImageButton::new(image.texture_id(ctx), egui::vec2(size, size));
+      ui.add(button).clicked()
+    }
+    None => ui.button(IMAGE_LOAD_ERROR_MESSAGE).clicked(),
+  }
+}
+
+/// 画像を表示する
+/// 画像が無い・読み込めない場合は代わりにメッセージを表示する
+/// 表示した画像の範囲を返す
+fn show_image(
+  ui: &mut egui::Ui,
+  debug_name: &str,
+  image_buf: Option<&[u8]>,
+  max: f32,
+) -> Option<egui::Rect> {
+  let image = image_buf.and_then(|image_buf| load_retained_image(debug_name, image_buf));
+  show_retained_image(ui, image.as_ref(), max)
+}
+
+/// 読み込み済みの画像を表示する
+/// 画像が無い場合は代わりにメッセージを表示する
+/// 表示した画像の範囲を返す
+fn show_retained_image(
+  ui: &mut egui::Ui,
+  image: Option<&RetainedImage>,
+  max: f32,
+) -> Option<egui::Rect> {
+  match image {
+    Some(image) => {
+      let response = image.show_size(ui, calculate_image_size(max, &image.size()));
+      Some(response.rect)
+    }
+    None => {
+      ui.label(IMAGE_LOAD_ERROR_MESSAGE);
+      None
+    }
+  }
+}
+
+/// 撮影統計のレポートや絞り込みの期間の日付を読み取る
+/// `2022-08-01`と`2022/8/1`のどちらの形式でも良く、空欄の場合は`None`になる
+fn parse_date_input(date: &str) -> Result<Option<NaiveDate>, String> {
+  let date = date.trim();
+  if date.is_empty() {
+    return Ok(None);
+  }
+  NaiveDate::parse_from_str(date, "%Y-%m-%d")
+    .or_else(|_| NaiveDate::parse_from_str(date, "%Y/%m/%d"))
+    .map(Some)
+    .map_err(|_| format!("日付の形式が正しくありません: {}", date))
+}
+
+/// 撮影統計のレポートを出力先のフォルダに書き出し、結果のメッセージを返す
+fn export_report(
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  export_directory_path: &str,
+  format: export::ReportFormat,
+  report_from: &str,
+  report_to: &str,
+) -> String {
+  let period = match (parse_date_input(report_from), parse_date_input(report_to)) {
+    (Ok(from), Ok(to)) => export::ReportPeriod { from, to },
+    (Err(err), _) | (_, Err(err)) => return err,
+  };
+  let report = export::export_statistics_report(gui_photo_data_lst, format, period);
+  let path = format!("{}/report.{}", export_directory_path, format.extension());
+  match fs::create_dir_all(export_directory_path).and_then(|_| fs::write(&path, report)) {
+    Ok(()) => format!("{}に書き出しました", path),
+    Err(err) => format!("レポートを書き出せませんでした: {}", err),
+  }
+}
+
+/// 元画像を読み込み、画像上の相対位置（0.0から1.0）のピクセルの色を取り出す
+fn pick_color(image_path: &str, relative: egui::Vec2) -> anyhow::Result<[u8; 3]> {
+  let raw_data = image::open_file(image_path)?;
+  let (width, height) = image::image_size(&raw_data)?;
+  let (x, y) = image::relative_to_pixel(relative.x, relative.y, width, height);
+  image::pixel_color_at(&raw_data, x, y)
+}
+
+/// ピント位置の枠を画像の上に重ねて表示する
+fn paint_focus_area(ui: &egui::Ui, image_rect: egui::Rect, focus_area: &photodata::FocusArea) {
+  let scale_x = image_rect.width() / focus_area.image_width as f32;
+  let scale_y = image_rect.height() / focus_area.image_height as f32;
+  // 点で記録されている場合でも見えるように、枠の大きさの下限を決めておく
+  let min_size = image_rect.width().min(image_rect.height()) * 0.05;
+  let size = egui::vec2(
+    (focus_area.width as f32 * scale_x).max(min_size),
+    (focus_area.height as f32 * scale_y).max(min_size),
+  );
+  let center =
+    image_rect.min + egui::vec2(focus_area.x as f32 * scale_x, focus_area.y as f32 * scale_y);
+  ui.painter().rect_stroke(
+    egui::Rect::from_center_size(center, size),
+    0.0,
+    egui::Stroke::new(2.0, egui::Color32::LIGHT_GREEN),
+  );
+}
+
+/// 起動時に読み込んだ画像の検証・圧縮・サムネイルの生成を別のスレッドで行う
+/// 写真ごとに機材に応じた補正を組にして渡す
+/// 1枚処理するごとに結果を`startup_loader`に積み、画面の更新を要求する
+#[allow(clippy::too_many_arguments)]
+fn spawn_startup_compression(
+  ctx: egui::Context,
+  startup_loader: startup::StartupLoader,
+  startup_photo_lst: Vec<(photodata::ImportPhotoData, image::Adjustment)>,
+  original_image_folder_path: String,
+  work_directory_path: String,
+  time_info_lst: HashMap<String, DateTime<FixedOffset>>,
+  quality_curve: image::QualityCurve,
+  startup_options: StartupOptions,
+) {
+  std::thread::spawn(move || {
+    // 同じ内容のオリジナルの画像は一度だけ圧縮し、2枚目からは圧縮した画像をコピーする
+    let mut compressed_original_lst = HashMap::new();
+    for (import_photo_data, adjustment) in startup_photo_lst.iter() {
+      let processed_image = process_startup_image(
+        import_photo_data,
+        &original_image_folder_path,
+        &work_directory_path,
+        &time_info_lst,
+        adjustment,
+        &quality_curve,
+        &startup_options,
+        &mut compressed_original_lst,
+      );
+      startup_loader.push(processed_image);
+      ctx.request_repaint();
+    }
+  });
+}
+
+/// 入力中のグループIDを検証し、問題が無い場合だけ`group_data`のIDに反映する
+/// 問題がある場合は`group_data`を変えずにエラーを返す
+fn apply_group_id_edit(
+  group_data: &mut GUIGroupData,
+  group_id_input: &str,
+  group_id_lst: &[String],
+  current_id: &str,
+) -> Result<(), String> {
+  validate::validate_group_id(group_id_input, group_id_lst, Some(current_id))?;
+  group_data.group_id = group_id_input.to_string();
+  Ok(())
+}
+
+/// 同じ内容のオリジナルの画像を圧縮した結果
+/// オリジナルの画像の内容のハッシュ値から引き、重複している画像の圧縮を省くために使う
+/// ハッシュ値が一致しても、オリジナルの画像の内容を比べて同じだった場合だけコピーする
+#[derive(Clone, Debug)]
+struct CompressedOriginal {
+  /// 最初に圧縮した画像のID
+  photo_id: String,
+  /// 最初に圧縮した画像のオリジナルのpath
+  original_path: String,
+  srcset: Vec<photodata::SrcsetImage>,
+  thumbnail: Option<Vec<u8>>,
+}
+
+/// 同じ内容のオリジナルの画像から圧縮した画像を、`id`の画像としてコピーする
+/// コピーした`srcset`用の画像の一覧とサムネイルを返し、コピーできなかった場合は`None`を返す
+fn copy_compressed_original(
+  work_directory_path: &str,
+  original: &CompressedOriginal,
+  id: &str,
+  url_prefix: &str,
+) -> Option<(Vec<photodata::SrcsetImage>, Option<Vec<u8>>)> {
+  match save::copy_compressed_images(
+    work_directory_path,
+    &original.photo_id,
+    id,
+    &original.srcset,
+    url_prefix,
+  ) {
+    Ok(srcset) => {
+      if let Some(thumbnail) = &original.thumbnail {
+        store_thumbnail_cache(work_directory_path, id, thumbnail);
+      }
+      Some((srcset, original.thumbnail.clone()))
+    }
+    Err(err) => {
+      warn!(
+        "{}と同じ画像ですが、圧縮した画像をコピーできませんでした（{}）: {:#}",
+        original.photo_id, id, err
+      );
+      None
+    }
+  }
+}
+
+/// 起動時に1枚の画像を処理する
+/// 圧縮した画像は`compressed_original_lst`に記録し、同じ内容の画像は圧縮せずにコピーする
+#[allow(clippy::too_many_arguments)]
+fn process_startup_image(
+  import_photo_data: &photodata::ImportPhotoData,
+  original_image_folder_path: &str,
+  work_directory_path: &str,
+  time_info_lst: &HashMap<String, DateTime<FixedOffset>>,
+  adjustment: &image::Adjustment,
+  quality_curve: &image::QualityCurve,
+  startup_options: &StartupOptions,
+  compressed_original_lst: &mut HashMap<String, CompressedOriginal>,
+) -> startup::ProcessedImage {
+  let mut processed_image = startup::ProcessedImage {
+    photo_id: import_photo_data.id.clone(),
+    thumbnail: None,
+    invalid_image: None,
+    excluded: false,
+    compressed: None,
+    copied: None,
+  };
+  // 画像ファイルは重いので、アクセスする階数をできるだけ減らしたい
+  let image_path = format!(
+    "{}/{}",
+    original_image_folder_path, import_photo_data.file_name
+  );
+  let staleness = save::check_image_staleness(
+    time_info_lst,
+    work_directory_path,
+    &import_photo_data.id,
+    &image_path,
+  );
+  let cached_thumbnail = if startup_options.force_regenerate {
+    None
+  } else {
+    save::load_thumbnail_cache(work_directory_path, &import_photo_data.id, &image_path)
+  };
+  // 検証も圧縮もしない場合は、保存してあるサムネイルがあればオリジナルの画像を読み込まずに済ませる
+  if startup_options.skip_validation && !staleness.is_stale() && cached_thumbnail.is_some() {
+    processed_image.thumbnail = cached_thumbnail;
+    return processed_image;
+  }
+  // ファイルのバイナリデータを取り出す
+  let raw_data = match image::open_file(&image_path) {
+    Ok(raw_data) => raw_data,
+    Err(err) => {
+      warn!(
+        "{}（{}）: {:#}",
+        IMAGE_LOAD_ERROR_MESSAGE, import_photo_data.id, err
+      );
+      processed_image.excluded = true;
+      return processed_image;
+    }
+  };
+  if !startup_options.skip_validation {
+    processed_image.invalid_image = validate_photo(
+      &import_photo_data.id,
+      &import_photo_data.file_name,
+      &raw_data,
+    );
+    if processed_image.invalid_image.is_some() && startup_options.exclude_broken {
+      processed_image.excluded = true;
+      return processed_image;
+    }
+  }
+  if !staleness.is_stale() && cached_thumbnail.is_some() {
+    processed_image.thumbnail = cached_thumbnail;
+    return processed_image;
+  }
+  let content_hash = save::content_hash(&raw_data);
+  if staleness.is_stale() {
+    let copied = compressed_original_lst
+      .get(&content_hash)
+      .filter(|original| save::is_same_content(&original.original_path, &raw_data))
+      .and_then(|original| {
+        copy_compressed_original(
+          work_directory_path,
+          original,
+          &import_photo_data.id,
+          &startup_options.url_prefix,
+        )
+      });
+    if let Some((srcset, thumbnail)) = copied {
+      processed_image.copied = Some((srcset, save::get_now()));
+      processed_image.thumbnail = thumbnail;
+      return processed_image;
+    }
+  }
+  // 圧縮し直す画像とサムネイルは、一度だけデコードした結果から作る
+  let decoded = match image::decode(&raw_data) {
+    Ok(decoded) => decoded,
+    Err(err) => {
+      warn!(
+        "{}（{}）: {:#}",
+        IMAGE_LOAD_ERROR_MESSAGE, import_photo_data.id, err
+      );
+      processed_image.excluded = true;
+      return processed_image;
+    }
+  };
+  // 起動時に処理する画像は固定されているため、
+  // このタイミングで画像を圧縮して保存すれば
+  // 次の起動まで何もしなくて良い
+  if staleness.is_stale() {
+    let srcset = save_image_compression(
+      &decoded,
+      work_directory_path,
+      &import_photo_data.id,
+      &startup_options.srcset_width_lst,
+      adjustment,
+      quality_curve,
+      &startup_options.url_prefix,
+      startup_options.jpeg_scan,
+    );
+    processed_image.compressed = Some((srcset, save::get_now()));
+  }
+  // サムネイル用に圧縮したデータを生成
+  // 保存してあるサムネイルがオリジナルの画像より新しい場合はそれを使う
+  processed_image.thumbnail = match cached_thumbnail {
+    Some(thumbnail) => Some(thumbnail),
+    None => match image::compression_decoded(
+      &decoded,
+      70.0,
+      600,
+      false,
+      adjustment,
+      image::JpegScan::Baseline,
+    ) {
+      Ok(thumbnail) => {
+        store_thumbnail_cache(work_directory_path, &import_photo_data.id, &thumbnail);
+        Some(thumbnail)
+      }
+      Err(err) => {
+        warn!(
+          "{}（{}）: {:#}",
+          IMAGE_LOAD_ERROR_MESSAGE, import_photo_data.id, err
+        );
+        None
+      }
+    },
+  };
+  if let Some((srcset, _)) = &processed_image.compressed {
+    compressed_original_lst
+      .entry(content_hash)
+      .or_insert_with(|| CompressedOriginal {
+        photo_id: import_photo_data.id.clone(),
+        original_path: image_path.clone(),
+        srcset: srcset.clone(),
+        thumbnail: processed_image.thumbnail.clone(),
+      });
+  }
+  processed_image
+}
+
+/// サムネイルが無い場合にオリジナルの画像から作り直す
+/// 作り直せなかった画像は処理対象から外し、毎フレーム読み込み直さないようにする
+fn regenerate_thumbnail(
+  photo_id: &str,
+  file_name: &str,
+  adjustment: &image::Adjustment,
+  original_image_folder_path: &str,
+  work_directory_path: &str,
+  thumbnail_lst: &mut HashMap<String, Vec<u8>>,
+  excluded_photo_id_lst: &mut Vec<String>,
+) {
+  if thumbnail_lst.contains_key(photo_id) || excluded_photo_id_lst.iter().any(|id| id == photo_id) {
+    return;
+  }
+  let image_path = format!("{}/{}", original_image_folder_path, file_name);
+  if let Some(thumbnail) = save::load_thumbnail_cache(work_directory_path, photo_id, &image_path) {
+    thumbnail_lst.insert(photo_id.to_string(), thumbnail);
+    return;
+  }
+  let thumbnail = image::open_file(&image_path).and_then(|raw_data| {
+    image::compression_with_adjustment(
+      &raw_data,
+      70.0,
+      600,
+      false,
+      adjustment,
+      image::JpegScan::Baseline,
+    )
+  });
+  match thumbnail {
+    Ok(thumbnail) => {
+      store_thumbnail_cache(work_directory_path, photo_id, &thumbnail);
+      thumbnail_lst.insert(photo_id.to_string(), thumbnail);
+    }
+    Err(err) => {
+      warn!("{}（{}）: {:#}", IMAGE_LOAD_ERROR_MESSAGE, photo_id, err);
+      excluded_photo_id_lst.push(photo_id.to_string());
+    }
+  }
+}
+
+/// 生成したサムネイルを作業ディレクトリに保存する
+/// 保存できなくても次回の起動時に作り直すだけなので、ログを残して続ける
+fn store_thumbnail_cache(work_directory_path: &str, photo_id: &str, thumbnail: &[u8]) {
+  if let Err(err) = save::save_thumbnail_cache(work_directory_path, photo_id, thumbnail) {
+    warn!("サムネイルを保存できませんでした（{}）: {}", photo_id, err);
+  }
+}
+
+/// 画像を検証し、問題があればその内容を返す
+fn validate_photo(photo_id: &str, file_name: &str, raw_data: &[u8]) -> Option<InvalidImage> {
+  image::validate_image(raw_data)
+    .err()
+    .map(|err| InvalidImage {
+      photo_id: photo_id.to_string(),
+      file_name: file_name.to_string(),
+      reason: format!("{:#}", err),
+    })
+}
+
+/// 全ての画像を検証し、問題のあった画像を列挙する
+fn validate_all_photos(
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  original_image_folder_path: &str,
+) -> Vec<InvalidImage> {
+  let mut v = Vec::new();
+  for id in photo_id_lst.iter() {
+    if let Some(gui_photo_data) = gui_photo_data_lst.get(id) {
+      let image_path = format!(
+        "{}/{}",
+        original_image_folder_path, gui_photo_data.file_name
+      );
+      let invalid_image = match image::open_file(&image_path) {
+        Ok(raw_data) => validate_photo(id, &gui_photo_data.file_name, &raw_data),
+        Err(err) => Some(InvalidImage {
+          photo_id: id.clone(),
+          file_name: gui_photo_data.file_name.clone(),
+          reason: format!("{:#}", err),
+        }),
+      };
+      if let Some(invalid_image) = invalid_image {
+        v.push(invalid_image)
+      }
+    }
+  }
+  v
+}
+
+/// 星の数で評価を選ぶ
+/// 選択中の星をもう一度押すと未評価に戻す
+fn rating_selector(ui: &mut egui::Ui, rating: &mut u8) {
+  for n in 1..=photodata::MAX_RATING {
+    let star = if n <= *rating { "★" } else { "☆" };
+    if ui.small_button(star).clicked() {
+      *rating = if *rating == n { 0 } else { n };
+    }
+  }
+}
+
+/// 入力値を検証しながら1行の入力欄を表示する
+/// 不正な値の場合は赤枠で囲み、その理由をツールチップで表示する
+fn validated_text_edit(
+  ui: &mut egui::Ui,
+  text: &mut String,
+  validator: fn(&str) -> Result<(), String>,
+) {
+  let response = ui.text_edit_singleline(text);
+  if let Err(message) = validator(text) {
+    ui.painter().rect_stroke(
+      response.rect,
+      2.0,
+      egui::Stroke::new(1.5, egui::Color32::RED),
+    );
+    response.on_hover_text(message);
+  }
+}
+
 fn calculate_image_size(max: f32, size: &[usize; 2]) -> egui::Vec2 {
   let width = size[0];
   let height = size[1];
@@ -679,10 +4843,11 @@ fn calculate_image_size(max: f32, size: &[usize; 2]) -> egui::Vec2 {
   egui::vec2(width as f32 * (max / x), height as f32 * (max / x))
 }
 
-/// PhotoDataをJSON文字列に変換する
+/// PhotoDataをバージョンを付けたJSON文字列に変換する
 pub fn make_photo_data_json_str(
   photo_id_lst: &[String],
   photo_data_lst: &HashMap<String, GUIPhotoData>,
+  json_style: save::JsonStyle,
 ) -> String {
   let mut v = Vec::new();
   for photo_id in photo_id_lst.iter() {
@@ -690,13 +4855,17 @@ pub fn make_photo_data_json_str(
       photo_data_lst.get(photo_id).unwrap().clone(),
     ))
   }
-  serde_json::to_string_pretty(&v).unwrap()
+  json_style.to_versioned_json_string(&v).unwrap()
 }
 
 /// ImportPhotoDataをJSON文字列に変換する
+/// 見つからずに読み込まなかった画像と、IDが重複していて読み込まなかった画像は消さずに最後に並べる
 pub fn make_import_photo_data_json_str(
   photo_id_lst: &[String],
   photo_data_lst: &HashMap<String, GUIPhotoData>,
+  missing_file_lst: &[ImportPhotoData],
+  duplicate_id_lst: &[photodata::DuplicateId],
+  json_style: save::JsonStyle,
 ) -> String {
   let mut v = Vec::new();
   for photo_id in photo_id_lst.iter() {
@@ -704,13 +4873,18 @@ pub fn make_import_photo_data_json_str(
       photo_data_lst.get(photo_id).unwrap().clone(),
     ))
   }
-  serde_json::to_string_pretty(&v).unwrap()
+  v.extend(missing_file_lst.iter().cloned());
+  for duplicate_id in duplicate_id_lst.iter() {
+    v.extend(duplicate_id.skipped().iter().cloned());
+  }
+  json_style.to_json_string(&v).unwrap()
 }
 
-/// GroupDataをJSON文字列に変換する
+/// GroupDataをバージョンを付けたJSON文字列に変換する
 pub fn make_group_data_json_str(
   photo_id_lst: &[String],
   group_data_lst: &HashMap<String, GUIGroupData>,
+  json_style: save::JsonStyle,
 ) -> String {
   let mut v = Vec::new();
   for photo_id in photo_id_lst.iter() {
@@ -718,7 +4892,7 @@ pub fn make_group_data_json_str(
       group_data_lst.get(photo_id).unwrap().clone(),
     ))
   }
-  serde_json::to_string_pretty(&v).unwrap()
+  json_style.to_versioned_json_string(&v).unwrap()
 }
 
 /// JSON文字列をファイルに書き出して保存する
@@ -729,6 +4903,40 @@ pub fn save_json_str(json_str: String, path: &str) {
   file.flush().unwrap();
 }
 
+/// 説明文のテンプレートを選んで挿入するためのコンボボックス
+fn description_template_combo_box(
+  ui: &mut egui::Ui,
+  id_source: &str,
+  description_template_lst: &[template::DescriptionTemplate],
+  group_data: &mut GUIGroupData,
+) {
+  egui::ComboBox::from_id_source(id_source)
+    .selected_text("テンプレートから挿入")
+    .show_ui(ui, |ui| {
+      for description_template in description_template_lst.iter() {
+        if ui
+          .selectable_label(false, &description_template.name)
+          .clicked()
+        {
+          template::insert_description_template(&description_template.body, group_data);
+        }
+      }
+    });
+}
+
+/// 絞り込み条件を切り替えるためのチェックボックス
+fn filter_checkbox(
+  ui: &mut egui::Ui,
+  photo_filter: &mut PhotoFilter,
+  condition: PhotoCondition,
+  text: &str,
+) {
+  let mut checked = photo_filter.contains(&condition);
+  if ui.checkbox(&mut checked, text).changed() {
+    photo_filter.set_condition(condition, checked);
+  }
+}
+
 /// 与えられた写真のIDがグループに含まれるかどうかを検索する
 fn make_group_check_lst(
   now_id: &str,
@@ -746,6 +4954,20 @@ fn make_group_check_lst(
   v
 }
 
+/// 写真が所属しているグループのタイトルの一覧を作る
+/// `make_group_check_lst`の結果でチェックが入っているものを集める
+fn make_group_title_lst(
+  group_check_lst: &[(String, bool)],
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> Vec<String> {
+  group_check_lst
+    .iter()
+    .filter(|(_, is_check)| *is_check)
+    .filter_map(|(group_id, _)| gui_group_data_lst.get(group_id))
+    .map(|group_data| group_data.title.clone())
+    .collect()
+}
+
 /// checkboxへの入力を元にグループデータを更新する
 /// 新しく写真が追加されたグループを最新に持ってくるようにする
 fn update_group_data(
@@ -777,13 +4999,13 @@ fn update_group_data(
         update_group_id_lst.push(group_id.clone());
       }
     }
-    gui_group_data_lst.insert(
-      group_id.clone(),
-      GUIGroupData {
-        photo_id_list: photo_id_lst.clone(),
-        ..group_data.clone()
-      },
-    );
+    let mut new_group_data = GUIGroupData {
+      photo_id_list: photo_id_lst.clone(),
+      ..group_data.clone()
+    };
+    // カバー写真をグループから外した場合はカバーも解除する
+    photodata::clear_invalid_cover(&mut new_group_data);
+    gui_group_data_lst.insert(group_id.clone(), new_group_data);
   }
   // 更新する
   for group_id in group_id_lst.iter() {
@@ -794,27 +5016,189 @@ fn update_group_data(
   *group_id_lst = update_group_id_lst;
 }
 
+/// 書き出し済みの圧縮画像を削除する
+/// まだ書き出されていない場合もあるので、削除に失敗しても無視する
+fn remove_image_files(work_directory_path: &str, photo_id: &str) {
+  let _ = fs::remove_file(format!(
+    "{}/images/lazy/{}.JPG",
+    work_directory_path,
+    photodata::sanitize_id(photo_id)
+  ));
+  let _ = fs::remove_file(save::normal_image_path(work_directory_path, photo_id));
+  let _ = fs::remove_file(save::thumbnail_cache_path(work_directory_path, photo_id));
+}
+
 /// 遅延読み込み用に使うかなり圧縮した画像を生成する
 /// convertコマンドを動かすだけ
 /// WindowsではWSLを経由してconvertコマンドを実行する
-fn save_image_compression_lazy(original_raw_data: &[u8], output_path: &str) {
-  let image_buf = image::compression(original_raw_data, 75.0, 32).unwrap();
+fn save_image_compression_lazy(
+  decoded: &image::DecodedImage,
+  output_path: &str,
+  adjustment: &image::Adjustment,
+  quality_curve: &image::QualityCurve,
+  jpeg_scan: image::JpegScan,
+) {
+  let quality = quality_curve.quality_for_size(LAZY_IMAGE_SIZE);
+  let image_buf = image::compression_decoded(
+    decoded,
+    quality,
+    LAZY_IMAGE_SIZE,
+    false,
+    adjustment,
+    jpeg_scan,
+  )
+  .unwrap();
   let mut file = File::create(output_path).unwrap();
   file.write_all(&image_buf).unwrap();
   file.flush().unwrap();
+  debug!("遅延読み込み用の画像を書き出しました: {}", output_path);
+}
+
+/// グループに含まれる先頭の4枚の写真からモンタージュ画像を作り、カバー画像として保存する
+fn save_group_cover(
+  work_directory_path: &str,
+  group_data: &GUIGroupData,
+  thumbnail_lst: &HashMap<String, Vec<u8>>,
+  layout: image::MontageLayout,
+) -> anyhow::Result<()> {
+  let thumbnails = group_data
+    .photo_id_list
+    .iter()
+    .filter_map(|id| thumbnail_lst.get(id).cloned())
+    .take(4)
+    .collect::<Vec<Vec<u8>>>();
+  let image_buf = image::make_montage(&thumbnails, layout)?;
+  fs::create_dir_all(format!("{}/images/covers", work_directory_path))?;
+  fs::write(
+    format!(
+      "{}/{}",
+      work_directory_path,
+      // 作業ディレクトリの中のpathにするので、URLの前に付けるpathは関係ない
+      photodata::src_to_path(&photodata::cover_src(
+        photodata::DEFAULT_URL_PREFIX,
+        &group_data.group_id
+      ))
+    ),
+    image_buf,
+  )?;
+  Ok(())
+}
+
+/// `srcset`用に幅の異なる画像を生成する
+/// 書き出した画像の一覧を返し、そのpathは`url_prefix`から始める
+#[allow(clippy::too_many_arguments)]
+fn save_image_compression_srcset(
+  decoded: &image::DecodedImage,
+  work_directory_path: &str,
+  id: &str,
+  width_lst: &[u32],
+  adjustment: &image::Adjustment,
+  quality_curve: &image::QualityCurve,
+  url_prefix: &str,
+  jpeg_scan: image::JpegScan,
+) -> anyhow::Result<Vec<photodata::SrcsetImage>> {
+  let mut v = Vec::new();
+  if width_lst.is_empty() {
+    return Ok(v);
+  }
+  fs::create_dir_all(format!("{}/images/srcset", work_directory_path))?;
+  let image_lst =
+    image::compression_srcset(decoded, quality_curve, width_lst, adjustment, jpeg_scan)?;
+  for (width, image_buf) in image_lst.into_iter() {
+    let src = photodata::srcset_src(url_prefix, id, width);
+    let path = format!("{}/{}", work_directory_path, photodata::src_to_path(&src));
+    let mut file = File::create(&path).with_context(|| format!("{}を作成できません", path))?;
+    file.write_all(&image_buf)?;
+    file.flush()?;
+    debug!("srcset用の画像を書き出しました: {}", src);
+    v.push(photodata::SrcsetImage { src, width });
+  }
+  Ok(v)
+}
+
+/// 書き出す全ての大きさの画像を生成する
+/// 機材ごとの補正がある場合は全ての大きさの画像にかける
+/// 品質は`quality_curve`に従って大きさごとに変える
+/// オリジナルの画像は呼び出し側で一度だけデコードし、全ての大きさの画像をそこから縮小する
+/// `srcset`用に書き出した画像の一覧を返す
+#[allow(clippy::too_many_arguments)]
+fn save_image_compression(
+  decoded: &image::DecodedImage,
+  work_directory_path: &str,
+  id: &str,
+  srcset_width_lst: &[u32],
+  adjustment: &image::Adjustment,
+  quality_curve: &image::QualityCurve,
+  url_prefix: &str,
+  jpeg_scan: image::JpegScan,
+) -> Vec<photodata::SrcsetImage> {
+  debug!("画像を圧縮します: {}", id);
+  save_image_compression_lazy(
+    decoded,
+    &format!(
+      "{}/images/lazy/{}.JPG",
+      work_directory_path,
+      photodata::sanitize_id(id)
+    ),
+    adjustment,
+    quality_curve,
+    jpeg_scan,
+  );
+  save_image_compression_normal(
+    decoded,
+    &save::normal_image_path(work_directory_path, id),
+    adjustment,
+    quality_curve,
+    jpeg_scan,
+  );
+  // srcset用の画像を書き出せなかった場合は、その写真のsrcsetを空にして他の写真の処理を続ける
+  match save_image_compression_srcset(
+    decoded,
+    work_directory_path,
+    id,
+    srcset_width_lst,
+    adjustment,
+    quality_curve,
+    url_prefix,
+    jpeg_scan,
+  ) {
+    Ok(srcset_lst) => srcset_lst,
+    Err(err) => {
+      error!("srcset用の画像を書き出せませんでした: {}: {:#}", id, err);
+      Vec::new()
+    }
+  }
 }
 
 /// 実際に表示するためのやや圧縮した画像を生成する
 /// convertコマンドを動かすだけ
 /// WindowsではWSLを経由してconvertコマンドを実行する
-fn save_image_compression_normal(original_raw_data: &[u8], output_path: &str) {
-  let image_buf = image::compression(original_raw_data, 85.0, 2048).unwrap();
+fn save_image_compression_normal(
+  decoded: &image::DecodedImage,
+  output_path: &str,
+  adjustment: &image::Adjustment,
+  quality_curve: &image::QualityCurve,
+  jpeg_scan: image::JpegScan,
+) {
+  let quality = quality_curve.quality_for_size(NORMAL_IMAGE_SIZE);
+  let image_buf = image::compression_decoded(
+    decoded,
+    quality,
+    NORMAL_IMAGE_SIZE,
+    false,
+    adjustment,
+    jpeg_scan,
+  )
+  .unwrap();
   let mut file = File::create(output_path).unwrap();
   file.write_all(&image_buf).unwrap();
   file.flush().unwrap();
+  debug!("表示用の画像を書き出しました: {}", output_path);
 }
 
 /// ファイル系の保存
+/// 単一ファイルの形式のJSONと入力のJSONファイルは`json_style`の形式で書き出す
+#[allow(clippy::too_many_arguments)]
 fn save_file(
   photo_id_lst: &[String],
   gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
@@ -822,16 +5206,295 @@ fn save_file(
   gui_group_data_lst: &HashMap<String, GUIGroupData>,
   input_json_path: &str,
   work_directory_path: &str,
+  audit_snapshot: &mut audit::AuditSnapshot,
+  json_style: save::JsonStyle,
+  missing_file_lst: &[ImportPhotoData],
+  duplicate_id_lst: &[photodata::DuplicateId],
 ) {
-  // PhotoDataを保存
-  let photo_data_json_str = make_photo_data_json_str(photo_id_lst, gui_photo_data_lst);
-  let photo_data_json_path = format!("{}/photo_data.json", work_directory_path);
-  save_json_str(photo_data_json_str, &photo_data_json_path);
-  // GroupDataを保存
-  let group_data_json_str = make_group_data_json_str(group_id_lst, gui_group_data_lst);
-  let group_data_json_path = format!("{}/group_data.json", work_directory_path);
-  save_json_str(group_data_json_str, &group_data_json_path);
+  if save::is_split(work_directory_path) {
+    // 分割保存の形式の場合は、変更のあったデータのファイルだけを書き換える
+    let photo_data_lst = photo_id_lst
+      .iter()
+      .map(|id| photodata::gui_photo_data_to_photo_data(gui_photo_data_lst[id].clone()))
+      .collect::<Vec<PhotoData>>();
+    let group_data_lst = group_id_lst
+      .iter()
+      .map(|id| photodata::gui_group_data_to_group_data(gui_group_data_lst[id].clone()))
+      .collect::<Vec<GroupData>>();
+    match save::save_split(work_directory_path, &photo_data_lst, &group_data_lst) {
+      Ok(count) => debug!("分割保存しました（{}個のファイルを書き換えました）", count),
+      Err(err) => error!("分割保存できませんでした: {}", err),
+    }
+  } else {
+    // PhotoDataを保存
+    let photo_data_json_str =
+      make_photo_data_json_str(photo_id_lst, gui_photo_data_lst, json_style);
+    let photo_data_json_path = format!("{}/photo_data.json", work_directory_path);
+    save_json_str(photo_data_json_str, &photo_data_json_path);
+    // GroupDataを保存
+    let group_data_json_str =
+      make_group_data_json_str(group_id_lst, gui_group_data_lst, json_style);
+    let group_data_json_path = format!("{}/group_data.json", work_directory_path);
+    save_json_str(group_data_json_str, &group_data_json_path);
+    debug!(
+      "写真とグループのデータを保存しました: {}",
+      work_directory_path
+    );
+  }
   // ImportPhotoDataを保存
-  let group_data_json_str = make_import_photo_data_json_str(photo_id_lst, gui_photo_data_lst);
-  save_json_str(group_data_json_str, input_json_path);
+  // 読み込めない入力のJSONファイルを上書きすると手で直す前の内容が失われるので書き換えない
+  if photodata::can_overwrite_import_json_file(input_json_path) {
+    let group_data_json_str = make_import_photo_data_json_str(
+      photo_id_lst,
+      gui_photo_data_lst,
+      missing_file_lst,
+      duplicate_id_lst,
+      json_style,
+    );
+    save_json_str(group_data_json_str, input_json_path);
+  } else {
+    warn!(
+      "{}を読み込めないため、書き換えませんでした",
+      input_json_path
+    );
+  }
+  // 前回保存時からの変更を履歴に追記
+  let audit_entry_lst = audit::make_audit_entry_lst(
+    audit_snapshot,
+    gui_photo_data_lst,
+    gui_group_data_lst,
+    save::get_now(),
+  );
+  if !audit_entry_lst.is_empty() {
+    if let Err(err) = audit::append_audit_log(work_directory_path, &audit_entry_lst) {
+      error!("変更履歴を保存できませんでした: {}", err);
+    }
+    *audit_snapshot = audit::AuditSnapshot::new(gui_photo_data_lst, gui_group_data_lst);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bin_test_util;
+
+  fn make_test_group_data(group_id: &str, title: &str, photo_id_lst: &[&str]) -> GUIGroupData {
+    GUIGroupData {
+      group_id: group_id.to_string(),
+      title: title.to_string(),
+      photo_id_list: photo_id_lst.iter().map(|id| id.to_string()).collect(),
+      ..photodata::make_dummy_gui_group_data()
+    }
+  }
+
+  #[test]
+  fn group_title_lst_lists_groups_containing_the_photo_in_order() {
+    let group_id_lst = vec!["b".to_string(), "a".to_string(), "c".to_string()];
+    let mut gui_group_data_lst = HashMap::new();
+    gui_group_data_lst.insert(
+      "a".to_string(),
+      make_test_group_data("a", "山", &["1", "2"]),
+    );
+    gui_group_data_lst.insert("b".to_string(), make_test_group_data("b", "海", &["2"]));
+    gui_group_data_lst.insert("c".to_string(), make_test_group_data("c", "街", &["1"]));
+    let group_check_lst = make_group_check_lst("2", &group_id_lst, &gui_group_data_lst);
+    assert_eq!(
+      group_check_lst,
+      vec![
+        ("b".to_string(), true),
+        ("a".to_string(), true),
+        ("c".to_string(), false)
+      ]
+    );
+    assert_eq!(
+      make_group_title_lst(&group_check_lst, &gui_group_data_lst),
+      vec!["海", "山"]
+    );
+    let group_check_lst = make_group_check_lst("3", &group_id_lst, &gui_group_data_lst);
+    assert!(make_group_title_lst(&group_check_lst, &gui_group_data_lst).is_empty());
+  }
+
+  fn assert_pos_eq(actual: egui::Pos2, expected: egui::Pos2) {
+    assert!(
+      (actual - expected).length() < 1e-5,
+      "{:?} != {:?}",
+      actual,
+      expected
+    );
+  }
+
+  #[test]
+  fn zoom_view_uv_rect_stays_inside_image() {
+    let mut view = ZoomView::default();
+    assert_eq!(
+      view.uv_rect(),
+      egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0))
+    );
+    let mut view = ZoomView {
+      zoom: 2.0,
+      center: egui::pos2(0.9, 0.1),
+    };
+    let uv = view.uv_rect();
+    assert_pos_eq(view.center, egui::pos2(0.75, 0.25));
+    assert_pos_eq(uv.min, egui::pos2(0.5, 0.0));
+    assert_pos_eq(uv.max, egui::pos2(1.0, 0.5));
+  }
+
+  #[test]
+  fn zoom_view_drag_is_clamped() {
+    let size = egui::vec2(200.0, 100.0);
+    // 全体を表示しているときはドラッグしても動かない
+    let mut view = ZoomView::default();
+    view.drag(egui::vec2(50.0, 50.0), size);
+    assert_pos_eq(view.uv_rect().min, egui::pos2(0.0, 0.0));
+
+    let mut view = ZoomView {
+      zoom: 2.0,
+      ..ZoomView::default()
+    };
+    // 右に画像の表示幅の半分だけドラッグすると、表示範囲は左に範囲の幅の半分だけ動く
+    view.drag(egui::vec2(100.0, 0.0), size);
+    assert_pos_eq(view.center, egui::pos2(0.25, 0.5));
+    view.drag(egui::vec2(0.0, -1000.0), size);
+    view.drag(egui::vec2(1000.0, 0.0), size);
+    let uv = view.uv_rect();
+    assert_pos_eq(uv.min, egui::pos2(0.0, 0.5));
+    assert_pos_eq(uv.max, egui::pos2(0.5, 1.0));
+  }
+
+  #[test]
+  fn zoom_view_scroll_is_clamped() {
+    let mut view = ZoomView::default();
+    view.scroll(500.0, 1.0, FULL_SIZE_MAX_ZOOM);
+    assert!((view.zoom - 2.0).abs() < 1e-5);
+    view.scroll(-250.0, 1.0, FULL_SIZE_MAX_ZOOM);
+    assert!((view.zoom - 1.0).abs() < 1e-5);
+    view.scroll(-400.0, 1.0, FULL_SIZE_MAX_ZOOM);
+    assert_eq!(view.zoom, 1.0);
+    for _ in 0..20 {
+      view.scroll(500.0, 1.0, FULL_SIZE_MAX_ZOOM);
+    }
+    assert_eq!(view.zoom, FULL_SIZE_MAX_ZOOM);
+  }
+
+  /// 単色のJPEG画像をオリジナルとして書き出し、その内容を返す
+  fn write_test_original(original_dir: &str, file_name: &str, rgb: [u8; 3]) -> Vec<u8> {
+    let path = format!("{}/{}", original_dir, file_name);
+    ::image::RgbImage::from_pixel(32, 24, ::image::Rgb(rgb))
+      .save(&path)
+      .unwrap();
+    fs::read(path).unwrap()
+  }
+
+  #[test]
+  fn duplicate_originals_are_copied_only_when_contents_match() {
+    let root = bin_test_util::temp_dir("dedup");
+    let original_dir = format!("{}/original", root);
+    let work_dir = format!("{}/work", root);
+    fs::create_dir_all(&original_dir).unwrap();
+    save::ensure_work_directories(&work_dir).unwrap();
+    write_test_original(&original_dir, "a.jpg", [200, 100, 50]);
+    write_test_original(&original_dir, "b.jpg", [200, 100, 50]);
+    let c_raw_data = write_test_original(&original_dir, "c.jpg", [50, 100, 200]);
+    let startup_options = StartupOptions {
+      skip_validation: true,
+      url_prefix: photodata::DEFAULT_URL_PREFIX.to_string(),
+      ..StartupOptions::default()
+    };
+    let mut compressed_original_lst = HashMap::new();
+    let process =
+      |id: &str,
+       file_name: &str,
+       compressed_original_lst: &mut HashMap<String, CompressedOriginal>| {
+        process_startup_image(
+          &ImportPhotoData {
+            file_name: file_name.to_string(),
+            id: id.to_string(),
+            alt: String::new(),
+            location: String::new(),
+          },
+          &original_dir,
+          &work_dir,
+          &HashMap::new(),
+          &image::Adjustment::default(),
+          &image::QualityCurve::default(),
+          &startup_options,
+          compressed_original_lst,
+        )
+      };
+    let normal_image = |id: &str| fs::read(save::normal_image_path(&work_dir, id)).unwrap();
+
+    let a = process("a", "a.jpg", &mut compressed_original_lst);
+    assert!(a.compressed.is_some());
+    assert_eq!(compressed_original_lst.len(), 1);
+    // 内容が同じ画像は圧縮せずにコピーする
+    let b = process("b", "b.jpg", &mut compressed_original_lst);
+    assert!(b.compressed.is_none());
+    assert!(b.copied.is_some());
+    assert_eq!(b.thumbnail, a.thumbnail);
+    assert_eq!(normal_image("b"), normal_image("a"));
+    // 同じファイルに別のIDを付けた場合もコピーする
+    let d = process("d", "a.jpg", &mut compressed_original_lst);
+    assert!(d.compressed.is_none());
+    assert!(d.copied.is_some());
+    assert_eq!(d.thumbnail, a.thumbnail);
+    assert_eq!(normal_image("d"), normal_image("a"));
+    assert_eq!(compressed_original_lst.len(), 1);
+    // ハッシュ値が一致しても内容が違う画像は圧縮し直す
+    let a_original = compressed_original_lst.values().next().unwrap().clone();
+    compressed_original_lst.insert(save::content_hash(&c_raw_data), a_original);
+    let c = process("c", "c.jpg", &mut compressed_original_lst);
+    assert!(c.compressed.is_some());
+    assert_ne!(c.thumbnail, a.thumbnail);
+    assert_ne!(normal_image("c"), normal_image("a"));
+    fs::remove_dir_all(&root).unwrap();
+  }
+
+  #[test]
+  fn colliding_group_id_edit_leaves_the_group_untouched() {
+    let group_id_lst = vec!["sea".to_string(), "mountain".to_string()];
+    let original = make_test_group_data("mountain", "山", &["1", "2"]);
+    let mut group_data = original.clone();
+    // `sea-2`を入力する途中で`sea`と重複しても、グループは元のまま
+    for group_id_input in ["s", "se"] {
+      assert_eq!(
+        apply_group_id_edit(&mut group_data, group_id_input, &group_id_lst, "mountain"),
+        Ok(())
+      );
+    }
+    assert_eq!(
+      apply_group_id_edit(&mut group_data, "sea", &group_id_lst, "mountain"),
+      Err("グループID「sea」は既に使われています".to_string())
+    );
+    assert_eq!(
+      group_data,
+      GUIGroupData {
+        group_id: "se".to_string(),
+        ..original.clone()
+      }
+    );
+    assert_eq!(
+      apply_group_id_edit(&mut group_data, "sea-2", &group_id_lst, "mountain"),
+      Ok(())
+    );
+    assert_eq!(
+      group_data,
+      GUIGroupData {
+        group_id: "sea-2".to_string(),
+        ..original
+      }
+    );
+  }
+
+  #[test]
+  fn only_decodable_image_bytes_are_loaded() {
+    let img = ::image::RgbImage::from_pixel(16, 8, ::image::Rgb([10, 20, 30]));
+    let jpeg = image::encode(&img, &[], 90.0, image::JpegScan::Baseline).unwrap();
+    let loaded = load_retained_image("valid", &jpeg).unwrap();
+    assert_eq!(loaded.size(), [16, 8]);
+    // 途中で切れている・画像ではない場合は読み込まずに代わりのメッセージを表示する
+    assert!(load_retained_image("truncated", &jpeg[..jpeg.len() / 4]).is_none());
+    assert!(load_retained_image("garbage", b"not an image").is_none());
+    assert!(load_retained_image("empty", &[]).is_none());
+  }
 }
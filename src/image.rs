@@ -1,26 +1,242 @@
-use anyhow::{anyhow, Result};
-use image::{self, imageops::FilterType::Lanczos3, DynamicImage, RgbImage};
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use anyhow::{anyhow, Context, Result};
+use image::{
+  self, imageops::FilterType::Lanczos3, DynamicImage, GenericImage, RgbImage, Rgba, RgbaImage,
+};
 use mozjpeg::{ColorSpace, Compress, Decompress, Marker, ScanMode, ALL_MARKERS};
+use serde::{Deserialize, Serialize};
 use std::fs;
+use std::panic;
 
 pub fn open_file(path: &str) -> Result<Vec<u8>> {
-  let raw_data = fs::read(path)?;
+  let raw_data = fs::read(path).with_context(|| format!("{}を読み込めません", path))?;
   Ok(raw_data)
 }
 
-pub fn compression(raw_data: &[u8], quality: f32, size: u32) -> Result<Vec<u8>> {
-  let decomp = Decompress::with_markers(ALL_MARKERS).from_mem(raw_data)?;
+/// 入力画像の形式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+  Jpeg,
+  Png,
+  Heic,
+}
+
+/// 先頭のバイト列から画像の形式を判定する
+/// 対応していない形式の場合は`None`になる
+pub fn detect_format(raw_data: &[u8]) -> Option<InputFormat> {
+  if raw_data.starts_with(&[0xFF, 0xD8]) {
+    Some(InputFormat::Jpeg)
+  } else if raw_data.starts_with(b"\x89PNG\r\n\x1a\n") {
+    Some(InputFormat::Png)
+  } else if raw_data.len() >= 12
+    && &raw_data[4..8] == b"ftyp"
+    && [
+      b"heic", b"heix", b"hevc", b"hevx", b"mif1", b"msf1", b"heim", b"heis",
+    ]
+    .iter()
+    .any(|brand| &raw_data[8..12] == *brand)
+  {
+    Some(InputFormat::Heic)
+  } else {
+    None
+  }
+}
+
+/// PNGの`eXIf`チャンクに記録されているExifを、JPEGのAPP1マーカーの形にして取り出す
+fn png_exif_marker(raw_data: &[u8]) -> Option<(Marker, Vec<u8>)> {
+  // シグネチャの後に「長さ・種類・データ・CRC」のチャンクが並ぶ
+  let mut pos = 8;
+  while pos + 8 <= raw_data.len() {
+    let length = u32::from_be_bytes(raw_data[pos..pos + 4].try_into().ok()?) as usize;
+    let chunk_type = &raw_data[pos + 4..pos + 8];
+    let data = raw_data.get(pos + 8..pos + 8 + length)?;
+    match chunk_type {
+      b"eXIf" => {
+        let mut v = b"Exif\0\0".to_vec();
+        v.extend_from_slice(data);
+        return Some((Marker::APP(1), v));
+      }
+      b"IEND" => return None,
+      _ => pos += length + 12,
+    }
+  }
+  None
+}
+
+/// PNGを`image`クレートでデコードする
+fn decode_png(raw_data: &[u8]) -> Result<DecodedImage> {
+  let image = image::load_from_memory_with_format(raw_data, image::ImageFormat::Png)
+    .context("PNGをデコードできません")?;
+  Ok(DecodedImage {
+    image,
+    markers: png_exif_marker(raw_data).into_iter().collect(),
+  })
+}
+
+/// HEICに記録されているExifを、JPEGのAPP1マーカーの形にして取り出す
+#[cfg(feature = "heic")]
+fn heic_exif_marker(raw_data: &[u8]) -> Option<(Marker, Vec<u8>)> {
+  let exif = exif::Reader::new()
+    .read_from_container(&mut std::io::Cursor::new(raw_data))
+    .ok()?;
+  let mut v = b"Exif\0\0".to_vec();
+  v.extend_from_slice(exif.buf());
+  Some((Marker::APP(1), v))
+}
+
+#[cfg(feature = "heic")]
+fn decode_heic(raw_data: &[u8]) -> Result<DecodedImage> {
+  use libheif_rs::{ColorSpace as HeifColorSpace, HeifContext, RgbChroma};
+  let context = HeifContext::read_from_bytes(raw_data).context("HEICを読み込めません")?;
+  let handle = context
+    .primary_image_handle()
+    .context("HEICに主画像がありません")?;
+  let heif_image = handle
+    .decode(HeifColorSpace::Rgb(RgbChroma::Rgb), false)
+    .context("HEICをデコードできません")?;
+  let plane = heif_image
+    .planes()
+    .interleaved
+    .ok_or_else(|| anyhow!("HEICの画素データを読み込めません"))?;
+  let (width, height) = (plane.width, plane.height);
+  // 各行の末尾には詰め物があるので、幅の分だけ取り出す
+  let data = plane
+    .data
+    .chunks(plane.stride)
+    .take(height as usize)
+    .flat_map(|row| &row[..width as usize * 3])
+    .cloned()
+    .collect();
+  let image_buffer = RgbImage::from_raw(width, height, data)
+    .ok_or_else(|| anyhow!("画素データの大きさが{}x{}と合いません", width, height))?;
+  Ok(DecodedImage {
+    image: DynamicImage::ImageRgb8(image_buffer),
+    markers: heic_exif_marker(raw_data).into_iter().collect(),
+  })
+}
+
+#[cfg(not(feature = "heic"))]
+fn decode_heic(_raw_data: &[u8]) -> Result<DecodedImage> {
+  Err(anyhow!(
+    "HEIC形式の画像をデコードするには`heic`フィーチャーを有効にしてビルドしてください"
+  ))
+}
+
+/// 圧縮に失敗したときに原因を調べるための、JPEGのヘッダーの情報
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JpegHeader {
+  pub width: usize,
+  pub height: usize,
+  /// ファイルに記録されている色空間
+  pub color_space: String,
+  /// 各成分のサンプリング係数（`2x2`のような形式）
+  pub sampling_lst: Vec<String>,
+  /// 含まれているマーカー
+  pub marker_lst: Vec<String>,
+}
+
+impl std::fmt::Display for JpegHeader {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{}x{}, 色空間: {}, サンプリング: {}, マーカー: [{}]",
+      self.width,
+      self.height,
+      self.color_space,
+      self.sampling_lst.join(" "),
+      self.marker_lst.join(", ")
+    )
+  }
+}
+
+/// パニックしたときの値からメッセージを取り出す
+/// mozjpegは内部のエラーメッセージを付けてパニックする
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+  match payload.downcast::<String>() {
+    Ok(message) => *message,
+    Err(payload) => match payload.downcast::<&str>() {
+      Ok(message) => message.to_string(),
+      Err(_) => "不明なエラー".to_string(),
+    },
+  }
+}
+
+/// 画像全体をデコードせずに、ヘッダーの情報を読み取る
+pub fn read_jpeg_header(raw_data: &[u8]) -> Result<JpegHeader> {
+  panic::catch_unwind(|| -> Result<JpegHeader> {
+    let decomp = Decompress::with_markers(ALL_MARKERS).from_mem(raw_data)?;
+    Ok(JpegHeader {
+      width: decomp.width(),
+      height: decomp.height(),
+      color_space: format!("{:?}", decomp.color_space()),
+      sampling_lst: decomp
+        .components()
+        .iter()
+        .map(|c| format!("{}x{}", c.h_samp_factor, c.v_samp_factor))
+        .collect(),
+      marker_lst: decomp
+        .markers()
+        .map(|m| format!("{:?}({}バイト)", m.marker, m.data.len()))
+        .collect(),
+    })
+  })
+  .map_err(|payload| anyhow!("{}", panic_message(payload)))?
+}
+
+/// エラーのログに含めるための画像の情報
+/// ヘッダーも読めない場合はファイルの大きさだけになる
+fn describe_jpeg(raw_data: &[u8]) -> String {
+  match read_jpeg_header(raw_data) {
+    Ok(header) => format!("{}バイト, {}", raw_data.len(), header),
+    Err(err) => format!("{}バイト, ヘッダーを読めません（{}）", raw_data.len(), err),
+  }
+}
+
+/// デコード済みの画像と、元画像に含まれていたマーカー
+#[derive(Clone, Debug)]
+pub struct DecodedImage {
+  pub image: DynamicImage,
+  /// markers の中に Exif 情報がある
+  pub markers: Vec<(Marker, Vec<u8>)>,
+}
+
+/// 画像のバイナリデータをデコードする
+/// JPEGはmozjpegで、PNGは`image`クレートでデコードする
+/// HEICは`heic`フィーチャーを有効にしたときだけlibheifでデコードする
+/// 破損している場合はエラーになる
+/// 失敗した場合は画像の寸法・色空間・マーカーなどをエラーに含める
+pub fn decode(raw_data: &[u8]) -> Result<DecodedImage> {
+  #[cfg(test)]
+  tests::DECODE_COUNT.with(|count| count.set(count.get() + 1));
+  match detect_format(raw_data) {
+    Some(InputFormat::Jpeg) => (),
+    Some(InputFormat::Png) => return decode_png(raw_data),
+    Some(InputFormat::Heic) => return decode_heic(raw_data),
+    None => return Err(anyhow!("対応していない形式の画像です")),
+  }
+  // mozjpeg はデコードに失敗するとパニックするので捕捉する
+  panic::catch_unwind(|| decode_jpeg(raw_data))
+    .map_err(|payload| anyhow!("画像が破損しています: {}", panic_message(payload)))
+    .and_then(|result| result)
+    .with_context(|| format!("デコードに失敗しました（{}）", describe_jpeg(raw_data)))
+}
+
+fn decode_jpeg(raw_data: &[u8]) -> Result<DecodedImage> {
+  let decomp = Decompress::with_markers(ALL_MARKERS)
+    .from_mem(raw_data)
+    .context("ヘッダーを読み込めません")?;
 
-  #[allow(clippy::needless_collect)]
   // markers の中に Exif 情報がある
   let markers: Vec<(Marker, Vec<u8>)> = decomp
     .markers()
-    .into_iter()
     .map(|m| (m.marker, m.data.to_owned()))
     .collect();
 
   // RGB 形式でデコード開始
-  let mut decomp_started = decomp.rgb()?;
+  let color_space = decomp.color_space();
+  let mut decomp_started = decomp
+    .rgb()
+    .with_context(|| format!("{:?}からRGBへの変換を開始できません", color_space))?;
 
   // 幅・高さ取得
   let width = decomp_started.width();
@@ -29,7 +245,7 @@ pub fn compression(raw_data: &[u8], quality: f32, size: u32) -> Result<Vec<u8>>
   // デコードされたデータの取得
   let data = decomp_started
     .read_scanlines::<[u8; 3]>()
-    .ok_or_else(|| anyhow!("read_scanlines error"))?
+    .ok_or_else(|| anyhow!("画素データを読み込めません（read_scanlines）"))?
     .iter()
     .flatten()
     .cloned()
@@ -40,35 +256,168 @@ pub fn compression(raw_data: &[u8], quality: f32, size: u32) -> Result<Vec<u8>>
 
   // image crate の DynamicImage に変換
   let image_buffer = RgbImage::from_raw(width as u32, height as u32, data)
-    .ok_or_else(|| anyhow!("from_raw error"))?;
-  let img = DynamicImage::ImageRgb8(image_buffer);
+    .ok_or_else(|| anyhow!("画素データの大きさが{}x{}と合いません", width, height))?;
+  let image = DynamicImage::ImageRgb8(image_buffer);
+  Ok(DecodedImage { image, markers })
+}
 
+/// アスペクト比を保ったまま、長辺が`size`になるようにリサイズする
+/// `upscale`が`false`のときは、長辺が`size`以下の画像を拡大せず原寸のまま返す
+pub fn resize(img: &DynamicImage, size: u32, upscale: bool) -> RgbImage {
+  if !upscale && img.width().max(img.height()) <= size {
+    return img.to_rgb8();
+  }
   // リサイズとシャープ処理
   // 1) resize はアスペクトレシオを保持する
   // 2) unshrpen の一つ目の引数はどの程度ぼかしを入れるか（0.5~5.0 ぐらい？）
   // 　　二つ目の引数はしきい値（1~10 ぐらい？）
   // 　　どのぐらいの数値が良いかは画像によって変わる
-  let img = img.resize(size, size, Lanczos3);
+  img.resize(size, size, Lanczos3).into_rgb8()
+}
+
+/// アスペクト比を保ったまま、幅が`width`になるようにリサイズする
+pub fn resize_to_width(img: &DynamicImage, width: u32) -> RgbImage {
+  img.resize(width, u32::MAX, Lanczos3).into_rgb8()
+}
+
+/// `srcset`用に生成する幅を決める
+/// 元画像より大きい幅は除き、小さい順に並べる
+pub fn srcset_width_lst(original_width: u32, width_lst: &[u32]) -> Vec<u32> {
+  let mut v = width_lst
+    .iter()
+    .copied()
+    .filter(|width| 0 < *width && *width <= original_width)
+    .collect::<Vec<u32>>();
+  v.sort_unstable();
+  v.dedup();
+  v
+}
+
+/// ICCプロファイルを格納するAPP2マーカーの先頭に付く識別子
+const ICC_PROFILE_SIGNATURE: &[u8] = b"ICC_PROFILE\0";
+/// 1つのAPP2マーカーに格納できるICCプロファイルの大きさ
+/// マーカーの上限65533バイトから、識別子と通し番号・総数の14バイトを除いたもの
+const ICC_PROFILE_CHUNK_SIZE: usize = 65533 - 14;
+
+fn is_icc_profile_marker(marker: &(Marker, Vec<u8>)) -> bool {
+  marker.0 == Marker::APP(2) && marker.1.starts_with(ICC_PROFILE_SIGNATURE)
+}
+
+/// 複数のAPP2マーカーに分割されているICCプロファイルを通し番号順に繋げる
+/// プロファイルが無い場合や、一部が欠けている場合は`None`になる
+pub fn extract_icc_profile(markers: &[(Marker, Vec<u8>)]) -> Option<Vec<u8>> {
+  let mut chunk_lst = markers
+    .iter()
+    .filter(|marker| is_icc_profile_marker(marker) && marker.1.len() >= 14)
+    .map(|(_, data)| (data[12], data[13], &data[14..]))
+    .collect::<Vec<(u8, u8, &[u8])>>();
+  let count = chunk_lst.first()?.1;
+  chunk_lst.sort_by_key(|(seq_no, _, _)| *seq_no);
+  // 通し番号は1から始まり、総数と同じ数だけ揃っている必要がある
+  let is_complete = chunk_lst.len() == count as usize
+    && chunk_lst
+      .iter()
+      .enumerate()
+      .all(|(i, (seq_no, chunk_count, _))| *seq_no as usize == i + 1 && *chunk_count == count);
+  if !is_complete {
+    return None;
+  }
+  Some(
+    chunk_lst
+      .into_iter()
+      .flat_map(|(_, _, data)| data.to_vec())
+      .collect(),
+  )
+}
 
-  // リサイズ後の幅・高さ取得
+/// ICCプロファイルを通し番号付きのAPP2マーカーに分割する
+pub fn icc_profile_markers(profile: &[u8]) -> Vec<(Marker, Vec<u8>)> {
+  let chunk_lst = profile
+    .chunks(ICC_PROFILE_CHUNK_SIZE)
+    .collect::<Vec<&[u8]>>();
+  let count = chunk_lst.len();
+  if count == 0 || count > u8::MAX as usize {
+    return Vec::new();
+  }
+  chunk_lst
+    .into_iter()
+    .enumerate()
+    .map(|(i, chunk)| {
+      let mut data = ICC_PROFILE_SIGNATURE.to_vec();
+      data.push((i + 1) as u8);
+      data.push(count as u8);
+      data.extend_from_slice(chunk);
+      (Marker::APP(2), data)
+    })
+    .collect()
+}
+
+/// JPEGのスキャンの方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JpegScan {
+  /// 全ての成分をまとめたスキャンで書き出す（これまでの形式）
+  #[default]
+  Baseline,
+  /// 粗い画像から段階的に細かくなるように書き出す
+  /// 読み込み途中でも全体を表示でき、ファイルも小さくなることが多い
+  Progressive,
+}
+
+impl std::str::FromStr for JpegScan {
+  type Err = String;
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    match s {
+      "baseline" => Ok(JpegScan::Baseline),
+      "progressive" => Ok(JpegScan::Progressive),
+      _ => Err(format!(
+        "{}は使えません（baselineかprogressiveを指定してください）",
+        s
+      )),
+    }
+  }
+}
+
+/// RGB データを mozjpeg で JPEG に圧縮する
+/// ICCプロファイルは元のマーカーから組み立て直し、正しい順番のAPP2マーカーとして書き込む
+pub fn encode(
+  img: &RgbImage,
+  markers: &[(Marker, Vec<u8>)],
+  quality: f32,
+  scan: JpegScan,
+) -> Result<Vec<u8>> {
   let width = img.width() as usize;
   let height = img.height() as usize;
-
-  // 変換後の RGB データ取得
-  let data = img.into_rgb8().to_vec();
+  let data = img.as_raw();
 
   // mozjpeg での圧縮処理
   let mut comp = Compress::new(ColorSpace::JCS_RGB);
-  comp.set_scan_optimization_mode(ScanMode::AllComponentsTogether);
+  match scan {
+    JpegScan::Baseline => comp.set_scan_optimization_mode(ScanMode::AllComponentsTogether),
+    JpegScan::Progressive => {
+      // スキャンの分け方はmozjpegに一番小さくなるものを選ばせる
+      comp.set_progressive_mode();
+      comp.set_scan_optimization_mode(ScanMode::Auto);
+      comp.set_optimize_scans(true);
+    }
+  }
   comp.set_quality(quality);
   comp.set_size(width, height);
   comp.set_mem_dest();
   comp.start_compress();
 
   // Exif 情報を書き込む
-  markers.into_iter().for_each(|m| {
-    comp.write_marker(m.0, &m.1);
-  });
+  markers
+    .iter()
+    .filter(|m| !is_icc_profile_marker(m))
+    .for_each(|m| {
+      comp.write_marker(m.0, &m.1);
+    });
+  // ICCプロファイルを書き込む
+  if let Some(profile) = extract_icc_profile(markers) {
+    icc_profile_markers(&profile).iter().for_each(|m| {
+      comp.write_marker(m.0, &m.1);
+    });
+  }
 
   // RGB データを書き込む
   let mut line = 0;
@@ -85,6 +434,788 @@ pub fn compression(raw_data: &[u8], quality: f32, size: u32) -> Result<Vec<u8>>
   comp.finish_compress();
 
   // ファイルに保存
-  let buf = comp.data_to_vec().map_err(|e| anyhow!("{:?}", e))?;
+  let buf = comp
+    .data_to_vec()
+    .map_err(|e| anyhow!("{}x{}の圧縮結果を取り出せません: {:?}", width, height, e))?;
   Ok(buf)
 }
+
+/// RGB データを WebP に圧縮する
+/// WebP には Exif などのマーカーは書き込まない
+pub fn encode_webp(img: &RgbImage, quality: f32) -> Vec<u8> {
+  webp::Encoder::from_rgb(img.as_raw(), img.width(), img.height())
+    .encode(quality)
+    .to_vec()
+}
+
+/// 圧縮済みの画像を同じ大きさのまま WebP に変換する
+pub fn convert_to_webp(raw_data: &[u8], quality: f32) -> Result<Vec<u8>> {
+  let decoded = decode(raw_data)?;
+  Ok(encode_webp(&decoded.image.into_rgb8(), quality))
+}
+
+/// 明るさ・コントラスト・シャープネスの補正
+/// 全て0の場合は何もしない
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Adjustment {
+  /// 明るさ（各画素の値に足す量）
+  pub brightness: i32,
+  /// コントラスト（正の値で強くなる）
+  pub contrast: f32,
+  /// アンシャープマスクのぼかしの強さ（0の場合はかけない）
+  pub sharpness: f32,
+}
+
+/// 出力する画像の大きさから圧縮の品質を決める曲線
+/// 大きい画像ほど品質を上げ、小さい画像ほど圧縮を効かせる
+/// `photag.toml`には`[quality]`として書く
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityCurve {
+  /// この大きさ以下の画像は`min_quality`にする
+  pub min_size: u32,
+  pub min_quality: f32,
+  /// この大きさ以上の画像は`max_quality`にする
+  pub max_size: u32,
+  pub max_quality: f32,
+}
+
+impl Default for QualityCurve {
+  fn default() -> Self {
+    QualityCurve {
+      min_size: 32,
+      min_quality: 50.0,
+      max_size: 2048,
+      max_quality: 85.0,
+    }
+  }
+}
+
+impl QualityCurve {
+  /// 大きさ（長辺または幅）が`size`の画像を圧縮するときの品質
+  /// 見た目の細かさは大きさの比で変わるので、大きさの対数に比例して品質を上げる
+  pub fn quality_for_size(&self, size: u32) -> f32 {
+    if self.max_size <= self.min_size {
+      return self.max_quality;
+    }
+    let size = size.clamp(self.min_size.max(1), self.max_size) as f32;
+    let t = (size / self.min_size.max(1) as f32).ln()
+      / (self.max_size as f32 / self.min_size.max(1) as f32).ln();
+    let quality = self.min_quality + (self.max_quality - self.min_quality) * t;
+    quality.clamp(0.0, 100.0)
+  }
+}
+
+/// リサイズした画像に補正をかける
+pub fn adjust(img: RgbImage, adjustment: &Adjustment) -> RgbImage {
+  let mut img = img;
+  if adjustment.brightness != 0 {
+    img = image::imageops::brighten(&img, adjustment.brightness);
+  }
+  if adjustment.contrast != 0.0 {
+    img = image::imageops::contrast(&img, adjustment.contrast);
+  }
+  if adjustment.sharpness > 0.0 {
+    img = image::imageops::unsharpen(&img, adjustment.sharpness, 1);
+  }
+  img
+}
+
+/// 長辺が`size`になるようにリサイズしてから圧縮する
+/// `size`より小さい画像は`upscale`が`true`のときだけ拡大する
+/// `scan`でベースラインかプログレッシブかを選ぶ
+pub fn compression(
+  raw_data: &[u8],
+  quality: f32,
+  size: u32,
+  upscale: bool,
+  scan: JpegScan,
+) -> Result<Vec<u8>> {
+  compression_with_adjustment(
+    raw_data,
+    quality,
+    size,
+    upscale,
+    &Adjustment::default(),
+    scan,
+  )
+}
+
+/// リサイズした後に補正をかけてから圧縮する
+pub fn compression_with_adjustment(
+  raw_data: &[u8],
+  quality: f32,
+  size: u32,
+  upscale: bool,
+  adjustment: &Adjustment,
+  scan: JpegScan,
+) -> Result<Vec<u8>> {
+  compression_decoded(&decode(raw_data)?, quality, size, upscale, adjustment, scan)
+}
+
+/// デコード済みの画像をリサイズし、補正をかけてから圧縮する
+/// 同じ画像から大きさの異なる画像を複数作るときは、一度だけデコードしてこれを繰り返し呼ぶ
+pub fn compression_decoded(
+  decoded: &DecodedImage,
+  quality: f32,
+  size: u32,
+  upscale: bool,
+  adjustment: &Adjustment,
+  scan: JpegScan,
+) -> Result<Vec<u8>> {
+  let resized = adjust(resize(&decoded.image, size, upscale), adjustment);
+  encode(&resized, &decoded.markers, quality, scan).with_context(|| {
+    format!(
+      "{}x{}を品質{}で圧縮できません",
+      resized.width(),
+      resized.height(),
+      quality
+    )
+  })
+}
+
+/// 画像の中央を正方形に切り抜く
+/// 既に正方形の場合はそのまま返す
+pub fn crop_square(img: &DynamicImage) -> DynamicImage {
+  let (width, height) = (img.width(), img.height());
+  let side = width.min(height);
+  if width == height {
+    return img.clone();
+  }
+  img.crop_imm((width - side) / 2, (height - side) / 2, side, side)
+}
+
+/// 中央を正方形に切り抜いてから`size`x`size`に縮小したサムネイルを作る
+pub fn thumbnail_square(raw_data: &[u8], quality: f32, size: u32) -> Result<Vec<u8>> {
+  let decoded = decode(raw_data)?;
+  let resized = crop_square(&decoded.image)
+    .resize_exact(size, size, Lanczos3)
+    .into_rgb8();
+  encode(&resized, &decoded.markers, quality, JpegScan::Baseline)
+}
+
+/// 画像上の相対位置（0.0から1.0）を、幅`width`・高さ`height`の画像のピクセル座標にする
+/// 範囲外の位置は画像の端に寄せる
+pub fn relative_to_pixel(relative_x: f32, relative_y: f32, width: u32, height: u32) -> (u32, u32) {
+  let to_pixel = |relative: f32, size: u32| {
+    ((relative.clamp(0.0, 1.0) * size as f32) as u32).min(size.saturating_sub(1))
+  };
+  (to_pixel(relative_x, width), to_pixel(relative_y, height))
+}
+
+/// 画像全体をデコードせずに、ヘッダーから画像の幅と高さを読み取る
+pub fn image_size(raw_data: &[u8]) -> Result<(u32, u32)> {
+  // HEICは`image`クレートでは読めないのでデコードする
+  if detect_format(raw_data) == Some(InputFormat::Heic) {
+    let image = decode(raw_data)?.image;
+    return Ok((image.width(), image.height()));
+  }
+  if detect_format(raw_data) != Some(InputFormat::Jpeg) {
+    let size = image::io::Reader::new(std::io::Cursor::new(raw_data))
+      .with_guessed_format()?
+      .into_dimensions()
+      .context("画像の大きさを読み取れません")?;
+    return Ok(size);
+  }
+  let header = read_jpeg_header(raw_data).context("画像が破損しています")?;
+  Ok((header.width as u32, header.height as u32))
+}
+
+/// デコード済みの画像の指定した座標のピクセルの色を取り出す
+fn pixel_color_of(img: &DynamicImage, x: u32, y: u32) -> Result<[u8; 3]> {
+  if x >= img.width() || y >= img.height() {
+    return Err(anyhow!("座標が画像の範囲外です"));
+  }
+  let image::Rgb(rgb) = *img.to_rgb8().get_pixel(x, y);
+  Ok(rgb)
+}
+
+/// 画像のバイナリデータをデコードし、指定した座標のピクセルの色を取り出す
+pub fn pixel_color_at(raw_data: &[u8], x: u32, y: u32) -> Result<[u8; 3]> {
+  pixel_color_of(&decode(raw_data)?.image, x, y)
+}
+
+/// 自動タグ付けに使う画像の簡単な特徴
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageFeatures {
+  /// 全体の平均色
+  pub average_color: [u8; 3],
+  /// 平均の明るさ（0.0〜1.0）
+  pub brightness: f32,
+  /// 幅を高さで割った値
+  pub aspect_ratio: f32,
+}
+
+/// 画像の平均色・明るさ・アスペクト比を求める
+/// 色は縮小した画像から求めるので、サムネイルを渡しても結果はほとんど変わらない
+pub fn image_features(raw_data: &[u8]) -> Result<ImageFeatures> {
+  let img = decode(raw_data)?.image;
+  if img.width() == 0 || img.height() == 0 {
+    return Err(anyhow!("画像の大きさが0です"));
+  }
+  let small = img.thumbnail(64, 64).into_rgb8();
+  let count = (small.width() * small.height()) as u64;
+  let mut sum = [0u64; 3];
+  for image::Rgb(rgb) in small.pixels() {
+    for i in 0..3 {
+      sum[i] += rgb[i] as u64;
+    }
+  }
+  let average_color = sum.map(|s| (s / count) as u8);
+  let [r, g, b] = average_color.map(|c| c as f32 / 255.0);
+  Ok(ImageFeatures {
+    average_color,
+    brightness: 0.299 * r + 0.587 * g + 0.114 * b,
+    aspect_ratio: img.width() as f32 / img.height() as f32,
+  })
+}
+
+/// モンタージュ画像の一辺の大きさ
+pub const MONTAGE_SIZE: u32 = 600;
+
+/// モンタージュ画像の写真の配置
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MontageLayout {
+  /// 2x2に並べる
+  Grid2x2,
+  /// 左に大きく1枚、右に小さく3枚を並べる
+  OneAndThree,
+}
+
+impl MontageLayout {
+  /// 一辺が`size`の正方形の中に写真を配置する領域（x, y, 幅, 高さ）の一覧
+  fn cell_lst(&self, size: u32) -> Vec<(u32, u32, u32, u32)> {
+    match self {
+      MontageLayout::Grid2x2 => {
+        let half = size / 2;
+        vec![
+          (0, 0, half, half),
+          (half, 0, size - half, half),
+          (0, half, half, size - half),
+          (half, half, size - half, size - half),
+        ]
+      }
+      MontageLayout::OneAndThree => {
+        let large = size * 2 / 3;
+        let small = size / 3;
+        vec![
+          (0, 0, large, size),
+          (large, 0, size - large, small),
+          (large, small, size - large, small),
+          (large, small * 2, size - large, size - small * 2),
+        ]
+      }
+    }
+  }
+}
+
+/// 複数の写真を組み合わせたモンタージュ画像を作る
+/// 写真は領域に合わせて中央を切り抜き、配置する領域より少ない場合は先頭から繰り返して使う
+pub fn make_montage(thumbnails: &[Vec<u8>], layout: MontageLayout) -> Result<Vec<u8>> {
+  if thumbnails.is_empty() {
+    return Err(anyhow!("モンタージュに使う画像がありません"));
+  }
+  let image_lst = thumbnails
+    .iter()
+    .map(|raw_data| Ok(decode(raw_data)?.image))
+    .collect::<Result<Vec<DynamicImage>>>()?;
+  let mut canvas = RgbImage::new(MONTAGE_SIZE, MONTAGE_SIZE);
+  for (i, (x, y, width, height)) in layout.cell_lst(MONTAGE_SIZE).into_iter().enumerate() {
+    let cell = image_lst[i % image_lst.len()]
+      .resize_to_fill(width, height, Lanczos3)
+      .into_rgb8();
+    canvas.copy_from(&cell, x, y)?;
+  }
+  encode(&canvas, &[], 80.0, JpegScan::Baseline)
+}
+
+/// デコード済みの画像から幅の異なる画像をまとめて生成する
+/// 品質は`quality_curve`に従って幅ごとに変える
+/// 幅と圧縮した画像の組を小さい順に返す
+pub fn compression_srcset(
+  decoded: &DecodedImage,
+  quality_curve: &QualityCurve,
+  width_lst: &[u32],
+  adjustment: &Adjustment,
+  scan: JpegScan,
+) -> Result<Vec<(u32, Vec<u8>)>> {
+  srcset_width_lst(decoded.image.width(), width_lst)
+    .into_iter()
+    .map(|width| {
+      let resized = adjust(resize_to_width(&decoded.image, width), adjustment);
+      let quality = quality_curve.quality_for_size(width);
+      Ok((width, encode(&resized, &decoded.markers, quality, scan)?))
+    })
+    .collect()
+}
+
+/// 品質を変えながら圧縮結果を確認するためのもの
+/// デコードとリサイズの結果を保持しておき、品質が変わったときはエンコードだけをやり直す
+#[derive(Clone, Debug)]
+pub struct PreviewCompressor {
+  decoded: DecodedImage,
+  resized: RgbImage,
+  /// 直前にエンコードしたときの品質と結果
+  encoded: Option<(f32, Vec<u8>)>,
+}
+
+impl PreviewCompressor {
+  pub fn new(raw_data: &[u8], size: u32) -> Result<Self> {
+    let decoded = decode(raw_data)?;
+    let resized = resize(&decoded.image, size, false);
+    Ok(PreviewCompressor {
+      decoded,
+      resized,
+      encoded: None,
+    })
+  }
+
+  /// 指定した品質で圧縮した結果を返す
+  /// 同じ品質であれば前回の結果をそのまま返す
+  pub fn compress(&mut self, quality: f32) -> Result<&[u8]> {
+    let is_cached = matches!(&self.encoded, Some((q, _)) if *q == quality);
+    if !is_cached {
+      let buf = encode(
+        &self.resized,
+        &self.decoded.markers,
+        quality,
+        JpegScan::Baseline,
+      )?;
+      self.encoded = Some((quality, buf));
+    }
+    Ok(&self.encoded.as_ref().unwrap().1)
+  }
+}
+
+/// 画像を検証したときに得られる情報
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+  pub width: u32,
+  pub height: u32,
+}
+
+/// 画像のバイナリデータが正しくデコードできるかを検証する
+/// 対応していない形式・途中で切れている・破損している場合はエラーになる
+pub fn validate_image(raw_data: &[u8]) -> Result<ImageInfo> {
+  match detect_format(raw_data) {
+    Some(InputFormat::Jpeg) => (),
+    Some(InputFormat::Png) | Some(InputFormat::Heic) => {
+      let decoded = decode(raw_data)?;
+      return Ok(ImageInfo {
+        width: decoded.image.width(),
+        height: decoded.image.height(),
+      });
+    }
+    None => return Err(anyhow!("JPEG・PNG・HEIC形式ではありません")),
+  }
+  // 末尾にEOIマーカーが無い場合は途中で切れている
+  let end = raw_data
+    .iter()
+    .rposition(|b| *b != 0)
+    .map(|i| i + 1)
+    .unwrap_or(0);
+  if !raw_data[..end].ends_with(&[0xFF, 0xD9]) {
+    return Err(anyhow!("ファイルが途中で切れています"));
+  }
+  let decoded = decode(raw_data)?;
+  Ok(ImageInfo {
+    width: decoded.image.width(),
+    height: decoded.image.height(),
+  })
+}
+
+/// ウォーターマークの文字を描画するフォント
+const WATERMARK_FONT: &[u8] = include_bytes!("./../assets/fonts/IPAexfont00401/ipaexg.ttf");
+
+/// ウォーターマークを入れた画像の品質
+const WATERMARK_QUALITY: f32 = 85.0;
+
+/// ウォーターマークとして合成するもの
+#[derive(Clone, Debug, PartialEq)]
+pub enum WatermarkSource {
+  /// 白い文字で描画する文字列
+  Text(String),
+  /// ロゴ画像のバイナリデータ
+  /// PNGの透過はそのまま反映する
+  Image(Vec<u8>),
+}
+
+/// ウォーターマークを置く位置
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WatermarkPosition {
+  TopLeft,
+  TopRight,
+  BottomLeft,
+  #[default]
+  BottomRight,
+  Center,
+}
+
+impl std::str::FromStr for WatermarkPosition {
+  type Err = String;
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    match s {
+      "top-left" => Ok(WatermarkPosition::TopLeft),
+      "top-right" => Ok(WatermarkPosition::TopRight),
+      "bottom-left" => Ok(WatermarkPosition::BottomLeft),
+      "bottom-right" => Ok(WatermarkPosition::BottomRight),
+      "center" => Ok(WatermarkPosition::Center),
+      _ => Err(format!(
+        "{}は使えません（top-left・top-right・bottom-left・bottom-right・centerのどれかを指定してください）",
+        s
+      )),
+    }
+  }
+}
+
+impl WatermarkPosition {
+  /// 大きさが`base`の画像の中に大きさが`mark`のウォーターマークを置くときの左上の座標
+  /// 端に置く場合は短辺の2%の余白を空ける
+  fn offset(&self, base: (u32, u32), mark: (u32, u32)) -> (u32, u32) {
+    let margin = base.0.min(base.1) / 50;
+    let left = margin;
+    let top = margin;
+    let right = base.0.saturating_sub(mark.0 + margin);
+    let bottom = base.1.saturating_sub(mark.1 + margin);
+    match self {
+      WatermarkPosition::TopLeft => (left, top),
+      WatermarkPosition::TopRight => (right, top),
+      WatermarkPosition::BottomLeft => (left, bottom),
+      WatermarkPosition::BottomRight => (right, bottom),
+      WatermarkPosition::Center => (
+        base.0.saturating_sub(mark.0) / 2,
+        base.1.saturating_sub(mark.1) / 2,
+      ),
+    }
+  }
+}
+
+/// 文字列を幅が`width`になる大きさで白く描画する
+fn render_watermark_text(text: &str, width: u32) -> Result<RgbaImage> {
+  let font = FontRef::try_from_slice(WATERMARK_FONT)?;
+  // 基準の大きさで描画したときの幅から、目的の幅になる大きさを求める
+  let base_scale = 100.0;
+  let base_width = text
+    .chars()
+    .map(|c| font.as_scaled(base_scale).h_advance(font.glyph_id(c)))
+    .sum::<f32>();
+  if base_width <= 0.0 {
+    return Err(anyhow!("ウォーターマークの文字列が空です"));
+  }
+  let scaled = font.as_scaled(PxScale::from(base_scale * width as f32 / base_width));
+  let height = (scaled.ascent() - scaled.descent()).ceil().max(1.0) as u32;
+  let mut canvas = RgbaImage::new(width.max(1), height);
+  let mut x = 0.0;
+  for c in text.chars() {
+    let glyph_id = scaled.glyph_id(c);
+    let glyph = glyph_id.with_scale_and_position(scaled.scale(), point(x, scaled.ascent()));
+    x += scaled.h_advance(glyph_id);
+    if let Some(outlined) = font.outline_glyph(glyph) {
+      let bounds = outlined.px_bounds();
+      outlined.draw(|gx, gy, coverage| {
+        let px = bounds.min.x as i32 + gx as i32;
+        let py = bounds.min.y as i32 + gy as i32;
+        if px >= 0 && py >= 0 && (px as u32) < canvas.width() && (py as u32) < canvas.height() {
+          let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+          let alpha = (coverage * 255.0).round() as u8;
+          *pixel = Rgba([255, 255, 255, pixel.0[3].max(alpha)]);
+        }
+      });
+    }
+  }
+  Ok(canvas)
+}
+
+/// ロゴ画像を幅が`width`になるように縦横比を保って縮小・拡大する
+fn render_watermark_image(raw_data: &[u8], width: u32) -> Result<RgbaImage> {
+  let logo = image::load_from_memory(raw_data).context("ロゴ画像を読み込めません")?;
+  let height = (logo.height() as f32 * width as f32 / logo.width().max(1) as f32).round() as u32;
+  Ok(
+    logo
+      .resize_exact(width.max(1), height.max(1), Lanczos3)
+      .into_rgba8(),
+  )
+}
+
+/// 画像にウォーターマークを半透明で合成してから圧縮する
+/// `size`は画像の幅に対するウォーターマークの幅の割合（0より大きく1以下）
+/// `opacity`は0から1の不透明度で、ロゴ画像の透過とも掛け合わせる
+pub fn apply_watermark(
+  raw_data: &[u8],
+  source: &WatermarkSource,
+  position: WatermarkPosition,
+  opacity: f32,
+  size: f32,
+) -> Result<Vec<u8>> {
+  if !(0.0..=1.0).contains(&opacity) {
+    return Err(anyhow!(
+      "不透明度は0から1の間で指定してください: {}",
+      opacity
+    ));
+  }
+  if !(size > 0.0 && size <= 1.0) {
+    return Err(anyhow!(
+      "大きさは0より大きく1以下で指定してください: {}",
+      size
+    ));
+  }
+  let decoded = decode(raw_data)?;
+  let mut base = decoded.image.into_rgb8();
+  let width = (base.width() as f32 * size).round() as u32;
+  let mark = match source {
+    WatermarkSource::Text(text) => render_watermark_text(text, width)?,
+    WatermarkSource::Image(logo) => render_watermark_image(logo, width)?,
+  };
+  let (left, top) = position.offset(base.dimensions(), mark.dimensions());
+  for (x, y, pixel) in mark.enumerate_pixels() {
+    let (bx, by) = (left + x, top + y);
+    if bx >= base.width() || by >= base.height() {
+      continue;
+    }
+    let alpha = pixel.0[3] as f32 / 255.0 * opacity;
+    let base_pixel = base.get_pixel_mut(bx, by);
+    for i in 0..3 {
+      let blended = base_pixel.0[i] as f32 * (1.0 - alpha) + pixel.0[i] as f32 * alpha;
+      base_pixel.0[i] = blended.round() as u8;
+    }
+  }
+  encode(
+    &base,
+    &decoded.markers,
+    WATERMARK_QUALITY,
+    JpegScan::Baseline,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util::{self, make_test_jpeg};
+
+  thread_local! {
+    /// このスレッドで`decode`を呼んだ回数
+    pub static DECODE_COUNT: std::cell::Cell<usize> = std::cell::Cell::new(0);
+  }
+
+  fn decode_count() -> usize {
+    DECODE_COUNT.with(|count| count.get())
+  }
+
+  #[test]
+  fn preview_compressor_resizes_once_and_reuses_encoding() {
+    let raw_data = make_test_jpeg(400, 200);
+    let mut compressor = PreviewCompressor::new(&raw_data, 100).unwrap();
+    assert_eq!(compressor.resized.dimensions(), (100, 50));
+
+    let high = compressor.compress(95.0).unwrap().to_vec();
+    assert_eq!(compressor.compress(95.0).unwrap(), high.as_slice());
+    let low = compressor.compress(10.0).unwrap().to_vec();
+    assert!(low.len() < high.len());
+
+    let decoded = decode(&low).unwrap();
+    assert_eq!(decoded.image.width(), 100);
+    assert_eq!(decoded.image.height(), 50);
+  }
+
+  #[test]
+  fn preview_compressor_rejects_broken_image() {
+    let mut raw_data = make_test_jpeg(64, 64);
+    raw_data.truncate(20);
+    assert!(PreviewCompressor::new(&raw_data, 32).is_err());
+  }
+
+  #[test]
+  fn srcset_width_lst_is_sorted_and_within_original() {
+    assert_eq!(
+      srcset_width_lst(1000, &[800, 400, 1200, 400, 0, 1000]),
+      vec![400, 800, 1000]
+    );
+    assert!(srcset_width_lst(300, &[400, 800]).is_empty());
+    assert!(srcset_width_lst(1000, &[]).is_empty());
+  }
+
+  #[test]
+  fn thumbnail_square_crops_the_center() {
+    // 左から赤・緑・青の帯に分けた横長の画像
+    let img = RgbImage::from_fn(300, 100, |x, _| match x / 100 {
+      0 => image::Rgb([255, 0, 0]),
+      1 => image::Rgb([0, 255, 0]),
+      _ => image::Rgb([0, 0, 255]),
+    });
+    let raw_data = encode(&img, &[], 95.0, JpegScan::Baseline).unwrap();
+    let thumbnail = thumbnail_square(&raw_data, 95.0, 20).unwrap();
+    let decoded = decode(&thumbnail).unwrap().image.into_rgb8();
+    assert_eq!(decoded.dimensions(), (20, 20));
+    // 中央の緑の帯だけが残る
+    for (x, y) in [(1, 1), (10, 10), (18, 18), (1, 18), (18, 1)] {
+      let image::Rgb([r, g, b]) = *decoded.get_pixel(x, y);
+      assert!(
+        g > 200 && r < 60 && b < 60,
+        "({}, {}) = {:?}",
+        x,
+        y,
+        [r, g, b]
+      );
+    }
+  }
+
+  #[test]
+  fn png_is_compressed_to_jpeg() {
+    let raw_data = test_util::make_test_png(64, 32);
+    assert_eq!(detect_format(&raw_data), Some(InputFormat::Png));
+    let decoded = decode(&raw_data).unwrap();
+    assert_eq!((decoded.image.width(), decoded.image.height()), (64, 32));
+    let jpeg = compression(&raw_data, 80.0, 32, false, JpegScan::Baseline).unwrap();
+    assert_eq!(detect_format(&jpeg), Some(InputFormat::Jpeg));
+    assert_eq!(
+      decode(&jpeg).unwrap().image.into_rgb8().dimensions(),
+      (32, 16)
+    );
+  }
+
+  #[test]
+  fn icc_profile_round_trips_through_encode() {
+    // 1つのマーカーに収まらない大きさのプロファイル
+    let profile = (0..150_000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+    let marker_lst = icc_profile_markers(&profile);
+    assert_eq!(marker_lst.len(), 3);
+    assert_eq!(extract_icc_profile(&marker_lst), Some(profile.clone()));
+    // 順番が入れ替わっていても通し番号順に繋げる
+    let reversed = marker_lst.iter().rev().cloned().collect::<Vec<_>>();
+    assert_eq!(extract_icc_profile(&reversed), Some(profile.clone()));
+    // 一部が欠けている場合は使わない
+    assert_eq!(extract_icc_profile(&marker_lst[..2]), None);
+
+    let img = decode(&make_test_jpeg(16, 16)).unwrap().image.into_rgb8();
+    let jpeg = encode(&img, &reversed, 90.0, JpegScan::Baseline).unwrap();
+    let decoded = decode(&jpeg).unwrap();
+    assert_eq!(extract_icc_profile(&decoded.markers), Some(profile));
+  }
+
+  #[test]
+  fn every_size_is_derived_from_one_decode() {
+    let raw_data = make_test_jpeg(400, 200);
+    let before = decode_count();
+    let decoded = decode(&raw_data).unwrap();
+    let quality_curve = QualityCurve::default();
+    let adjustment = Adjustment::default();
+    let size_lst = [40, 200];
+    let image_lst = size_lst
+      .iter()
+      .map(|size| {
+        let quality = quality_curve.quality_for_size(*size);
+        compression_decoded(
+          &decoded,
+          quality,
+          *size,
+          false,
+          &adjustment,
+          JpegScan::Baseline,
+        )
+        .unwrap()
+      })
+      .collect::<Vec<_>>();
+    let srcset_lst = compression_srcset(
+      &decoded,
+      &quality_curve,
+      &[100, 300],
+      &adjustment,
+      JpegScan::Baseline,
+    )
+    .unwrap();
+    assert_eq!(decode_count() - before, 1);
+    assert_eq!(
+      srcset_lst
+        .iter()
+        .map(|(width, _)| *width)
+        .collect::<Vec<_>>(),
+      vec![100, 300]
+    );
+    // 大きさごとにデコードし直した場合と同じ画像になる
+    for (size, image_buf) in size_lst.iter().zip(image_lst.iter()) {
+      let quality = quality_curve.quality_for_size(*size);
+      let expected = compression(&raw_data, quality, *size, false, JpegScan::Baseline).unwrap();
+      assert_eq!(image_buf, &expected);
+    }
+  }
+
+  #[test]
+  fn quality_grows_with_the_log_of_size() {
+    let quality_curve = QualityCurve::default();
+    let approx_eq = |a: f32, b: f32| (a - b).abs() < 1e-3;
+    assert!(approx_eq(quality_curve.quality_for_size(32), 50.0));
+    assert!(approx_eq(quality_curve.quality_for_size(2048), 85.0));
+    // 範囲の外は端の品質にする
+    assert!(approx_eq(quality_curve.quality_for_size(0), 50.0));
+    assert!(approx_eq(quality_curve.quality_for_size(8000), 85.0));
+    // 32と2048の相乗平均の256はちょうど中間の品質になる
+    assert!(approx_eq(quality_curve.quality_for_size(256), 67.5));
+    let quality_lst = [64, 128, 512, 1024].map(|size| quality_curve.quality_for_size(size));
+    assert!(quality_lst.windows(2).all(|w| w[0] < w[1]));
+  }
+
+  #[test]
+  fn quality_curve_handles_unusual_settings() {
+    let quality_curve = QualityCurve {
+      min_size: 1000,
+      min_quality: 40.0,
+      max_size: 500,
+      max_quality: 90.0,
+    };
+    assert_eq!(quality_curve.quality_for_size(10), 90.0);
+    let quality_curve = QualityCurve {
+      min_size: 0,
+      min_quality: 80.0,
+      max_size: 100,
+      max_quality: 150.0,
+    };
+    assert_eq!(quality_curve.quality_for_size(0), 80.0);
+    assert_eq!(quality_curve.quality_for_size(100), 100.0);
+  }
+
+  /// JPEGの最初のスキャンまでのセグメントから、フレームの開始（SOF）のマーカーを取り出す
+  fn sof_marker_lst(jpeg: &[u8]) -> Vec<u8> {
+    let mut marker_lst = Vec::new();
+    let mut i = 2;
+    while jpeg[i] == 0xFF && jpeg[i + 1] != 0xDA {
+      let marker = jpeg[i + 1];
+      if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+        marker_lst.push(marker);
+      }
+      i += 2 + u16::from_be_bytes([jpeg[i + 2], jpeg[i + 3]]) as usize;
+    }
+    marker_lst
+  }
+
+  #[test]
+  fn progressive_scan_is_written_as_sof2() {
+    let raw_data = make_test_jpeg(64, 48);
+    let baseline = compression(&raw_data, 85.0, 64, false, JpegScan::Baseline).unwrap();
+    let progressive = compression(&raw_data, 85.0, 64, false, JpegScan::Progressive).unwrap();
+    let baseline_marker_lst = sof_marker_lst(&baseline);
+    assert_eq!(baseline_marker_lst.len(), 1);
+    assert!([0xC0, 0xC1].contains(&baseline_marker_lst[0]));
+    assert_eq!(sof_marker_lst(&progressive), vec![0xC2]);
+    for jpeg in [baseline, progressive] {
+      let image = decode(&jpeg).unwrap().image;
+      assert_eq!((image.width(), image.height()), (64, 48));
+    }
+  }
+
+  #[test]
+  fn heic_is_detected_by_its_brand() {
+    let mut raw_data = vec![0, 0, 0, 24];
+    raw_data.extend_from_slice(b"ftypheic");
+    raw_data.extend_from_slice(&[0; 12]);
+    assert_eq!(detect_format(&raw_data), Some(InputFormat::Heic));
+    // 中身の無いHEICはデコードできない
+    let message = format!("{:#}", decode(&raw_data).unwrap_err());
+    if cfg!(not(feature = "heic")) {
+      assert!(message.contains("`heic`フィーチャー"), "{}", message);
+    }
+    assert!(validate_image(&raw_data).is_err());
+    // MP4などHEIC以外のブランドは対象外
+    raw_data[8..12].copy_from_slice(b"isom");
+    assert_eq!(detect_format(&raw_data), None);
+  }
+}
@@ -0,0 +1,36 @@
+//! GUIを使わずに、Exifの解析・データの合成・画像の圧縮・JSONファイルの保存を行うためのライブラリ
+//! GUIの`photag`もこのライブラリの上に作られていて、書き出すデータの形式は同じになる
+//!
+//! ```no_run
+//! use photag::{image, photodata, save};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!   let import_photo_data_lst = photodata::load_import_json_file("original_photo_data.json")?;
+//!   let (_, photo_data_lst) = photodata::merge_photo_data_based_and_import_photo_data(
+//!     &photodata::load_photo_data_opt("output"),
+//!     &import_photo_data_lst,
+//!     "original",
+//!     photodata::default_exif_thread_count(),
+//!     photodata::DEFAULT_URL_PREFIX,
+//!   )?;
+//!   for photo_data in photo_data_lst.iter() {
+//!     let raw_data = image::open_file(&format!("original/{}", photo_data.file_name))?;
+//!     let image_buf = image::compression(&raw_data, 85.0, 2048, false, image::JpegScan::Baseline)?;
+//!     std::fs::write(format!("output{}", photo_data.photo_src), image_buf)?;
+//!   }
+//!   save::save_work_data("output", &photo_data_lst, &[], save::JsonStyle::Pretty)?;
+//!   Ok(())
+//! }
+//! ```
+
+pub mod audit;
+pub mod config;
+pub mod export;
+pub mod image;
+pub mod mapping;
+pub mod photodata;
+pub mod save;
+pub mod tag_style;
+
+#[cfg(test)]
+mod test_util;
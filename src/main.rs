@@ -1,11 +1,17 @@
 //#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use clap::Parser;
+use log::LevelFilter;
+use photag::{audit, config, export, image, mapping, photodata, save, tag_style};
 
+#[cfg(test)]
+mod bin_test_util;
 mod gui;
-mod image;
-mod photodata;
-mod save;
+mod session;
+mod startup;
+mod template;
+mod undo;
+mod validate;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -19,15 +25,347 @@ struct Args {
   /// 圧縮した画像ファイルやデータのJSONファイルを出力する作業ディレクトリへのpath
   #[clap(short, long)]
   work: String,
+  /// 起動時に画像が破損していないかを検証しない
+  #[clap(long)]
+  skip_validation: bool,
+  /// 破損している画像を圧縮などの処理対象から外す
+  #[clap(long)]
+  exclude_broken: bool,
+  /// `srcset`用に生成する画像の幅（`480,960,1440,2048`のようにカンマ区切りで指定する）
+  #[clap(long, value_delimiter = ',')]
+  srcset_widths: Vec<u32>,
+  /// 写真データに記録する画像のURLの前に付けるpath（サイトをサブディレクトリに置くときは`/gallery/`のように指定する）
+  #[clap(long, value_name = "PATH", default_value = photodata::DEFAULT_URL_PREFIX)]
+  url_prefix: String,
+  /// 書き出す画像のJPEGのスキャンの方式（`baseline`か`progressive`）
+  /// 書き出し済みの画像を作り直すときは`--force-regenerate`も指定する
+  #[clap(long, value_name = "SCAN", default_value = "baseline")]
+  jpeg_scan: image::JpegScan,
+  /// 作業ディレクトリのデータから静的なHTMLのギャラリーを指定したフォルダに書き出して終了する
+  #[clap(long, value_name = "DIR")]
+  export_html: Option<String>,
+  /// 前回公開したときから変わった写真とグループだけを指定したフォルダに書き出して終了する
+  /// 変更の一覧は`changelist.json`に、次回の比較に使う情報は`publish.json`に書き出す
+  #[clap(long, value_name = "DIR")]
+  export_changed: Option<String>,
+  /// `--export-html`で書き出すときに、全てのページのURLを並べた`sitemap.xml`も書き出す
+  #[clap(long, requires_all = &["export-html", "base-url"])]
+  sitemap: bool,
+  /// 書き出すときに表示用画像をWebPとJPEGの両方で出力し、`<picture>`要素でJPEGにフォールバックさせる
+  #[clap(long)]
+  dual_format: bool,
+  /// 書き出すときに写真の空欄の撮影場所と撮影日時を、写真を含むグループの値で補完する
+  /// 作業ディレクトリのデータは変更しない
+  #[clap(long)]
+  inherit_group_metadata: bool,
+  /// 前回終了時の作業状態を復元せずに起動する
+  #[clap(long)]
+  fresh: bool,
+  /// Exifの解析に使うスレッド数（指定しない場合はCPUのコア数）
+  #[clap(long, value_name = "N")]
+  exif_threads: Option<usize>,
+  /// 全ての写真のExifの全フィールドを指定したファイルに書き出して終了する
+  /// 拡張子が`.csv`の場合はCSV、それ以外の場合はJSONになる
+  #[clap(long, value_name = "FILE")]
+  export_exif: Option<String>,
+  /// 作業ディレクトリのデータを写真・グループごとのファイルに分割する形式に変換して終了する
+  /// 以降は変更のあったファイルだけが書き換わる
+  #[clap(long, conflicts_with = "convert-to-single")]
+  convert_to_split: bool,
+  /// 分割したデータを`photo_data.json`と`group_data.json`にまとめる形式に変換して終了する
+  #[clap(long)]
+  convert_to_single: bool,
+  /// 写真を公開している場所のURL
+  /// 指定すると写真やグループの公開URLをコピーできる
+  #[clap(long, value_name = "URL")]
+  base_url: Option<String>,
+  /// 写真ごとのメタデータを1行ずつ並べたCSVを書き出して終了する
+  #[clap(long, value_name = "FILE")]
+  export_csv: Option<String>,
+  /// `--export-csv`で書き出して編集したCSVの変更を作業ディレクトリのデータに反映して終了する
+  #[clap(long, value_name = "FILE")]
+  import_csv: Option<String>,
+  /// グループの一覧をAtomフィードとして書き出して終了する
+  /// `--base-url`を指定するとリンクを完全なURLにする
+  #[clap(long, value_name = "FILE")]
+  export_feed: Option<String>,
+  /// 起動時に圧縮し直すことになる画像とその理由を表示して終了する
+  /// ファイルは何も書き換えない
+  #[clap(long)]
+  dry_run: bool,
+  /// 起動時に画像を処理せず、サムネイルは表示するとき、表示用の画像は書き出すときに生成する
+  /// 未処理の画像は画像ID一覧に表示される
+  #[clap(long)]
+  lazy_processing: bool,
+  /// `time.json`の保存時刻を無視して、全ての画像をオリジナルから作り直す
+  /// 品質や大きさの設定を変えたときに使う
+  #[clap(long)]
+  force_regenerate: bool,
+  /// 保存するJSONファイルの形式（`pretty`か`minify`）
+  /// 出力先ごとに形式を指定しない場合はこの形式になる
+  #[clap(long, value_name = "STYLE", default_value = "pretty")]
+  json_style: save::JsonStyle,
+  /// 作業ディレクトリと入力のJSONファイルを保存するときの形式（指定しない場合は`--json-style`）
+  #[clap(long, value_name = "STYLE")]
+  work_json_style: Option<save::JsonStyle>,
+  /// 書き出す`photo_data.json`と`group_data.json`の形式（指定しない場合は`--json-style`）
+  #[clap(long, value_name = "STYLE")]
+  export_json_style: Option<save::JsonStyle>,
+  /// 書き出すときに、表示用画像にウォーターマークを入れたものを指定したフォルダにも書き出す
+  /// 公開前のプレビューを配るときに使う
+  #[clap(long, value_name = "DIR")]
+  watermark_dir: Option<String>,
+  /// ウォーターマークとして入れる文字列
+  #[clap(long, value_name = "TEXT", requires = "watermark-dir")]
+  watermark_text: Option<String>,
+  /// ウォーターマークとして入れるロゴ画像（JPEGかPNG）
+  #[clap(
+    long,
+    value_name = "FILE",
+    requires = "watermark-dir",
+    conflicts_with = "watermark-text"
+  )]
+  watermark_image: Option<String>,
+  /// ウォーターマークの位置（`top-left`・`top-right`・`bottom-left`・`bottom-right`・`center`）
+  #[clap(long, value_name = "POSITION", default_value = "bottom-right")]
+  watermark_position: image::WatermarkPosition,
+  /// ウォーターマークの不透明度（0から1）
+  #[clap(long, value_name = "OPACITY", default_value = "0.5")]
+  watermark_opacity: f32,
+  /// 画像の幅に対するウォーターマークの幅の割合（0より大きく1以下）
+  #[clap(long, value_name = "RATIO", default_value = "0.3")]
+  watermark_size: f32,
+  /// ウィンドウの最小の幅
+  #[clap(long, value_name = "PX", default_value = "900")]
+  min_width: f32,
+  /// ウィンドウの最小の高さ
+  #[clap(long, value_name = "PX", default_value = "900")]
+  min_height: f32,
+  /// 画面の配色（`light`か`dark`）
+  /// 指定しない場合は前回終了時の配色にする
+  #[clap(long, value_name = "THEME")]
+  theme: Option<gui::Theme>,
+  /// 画像の圧縮や保存などの詳しいログを表示する
+  /// 環境変数`RUST_LOG`でも表示するログの水準を指定できる
+  #[clap(short, long)]
+  verbose: bool,
 }
 
 fn main() {
   let args = Args::parse();
-  let native_options = eframe::NativeOptions {
-    min_window_size: Some(eframe::egui::vec2(900.0, 900.0)),
-    resizable: true,
-    default_theme: eframe::Theme::Light,
-    ..Default::default()
+  // 詳しいログはphotag自身のものだけを表示し、GUIのライブラリなどのログは警告以上に留める
+  env_logger::Builder::new()
+    .filter_level(LevelFilter::Warn)
+    .filter_module(
+      "photag",
+      if args.verbose {
+        LevelFilter::Debug
+      } else {
+        LevelFilter::Warn
+      },
+    )
+    .format_target(false)
+    .parse_default_env()
+    .init();
+  let work_json_style = args.work_json_style.unwrap_or(args.json_style);
+  let export_json_style = args.export_json_style.unwrap_or(args.json_style);
+  let watermark = match watermark_options(&args) {
+    Ok(watermark) => watermark,
+    Err(err) => {
+      eprintln!("ウォーターマークの設定が正しくありません: {:#}", err);
+      std::process::exit(1);
+    }
+  };
+  if let Some(output_directory) = &args.export_html {
+    if let Err(err) = export::export_html(
+      &args.work,
+      output_directory,
+      None,
+      &export::RatingFilter::default(),
+      args.dual_format,
+      args.inherit_group_metadata,
+      &args.url_prefix,
+    ) {
+      eprintln!("HTMLを書き出せませんでした: {}", err);
+      std::process::exit(1);
+    }
+    if let (true, Some(base_url)) = (args.sitemap, &args.base_url) {
+      if let Err(err) = export::write_sitemap(
+        &args.work,
+        output_directory,
+        base_url,
+        &args.url_prefix,
+        args.inherit_group_metadata,
+      ) {
+        eprintln!("sitemap.xmlを書き出せませんでした: {:#}", err);
+        std::process::exit(1);
+      }
+    }
+    if let Some(watermark) = &watermark {
+      match export::export_watermark(
+        &args.work,
+        None,
+        &export::RatingFilter::default(),
+        watermark,
+      ) {
+        Ok(count) => println!(
+          "{}枚のウォーターマーク付きの画像を{}に書き出しました",
+          count, watermark.directory
+        ),
+        Err(err) => {
+          eprintln!(
+            "ウォーターマーク付きの画像を書き出せませんでした: {:#}",
+            err
+          );
+          std::process::exit(1);
+        }
+      }
+    }
+    return;
+  }
+  if let Some(output_directory) = &args.export_changed {
+    match export::export_changed(
+      &args.work,
+      output_directory,
+      &export::RatingFilter::default(),
+      args.dual_format,
+      export_json_style,
+      args.inherit_group_metadata,
+    ) {
+      Ok(change_list) => {
+        for (label, change_list) in [
+          ("写真", &change_list.photo),
+          ("グループ", &change_list.group),
+        ] {
+          println!(
+            "{}: 追加{}件・変更{}件・削除{}件",
+            label,
+            change_list.added.len(),
+            change_list.modified.len(),
+            change_list.removed.len()
+          );
+        }
+      }
+      Err(err) => {
+        eprintln!("変更のあったデータを書き出せませんでした: {:#}", err);
+        std::process::exit(1);
+      }
+    }
+    return;
+  }
+  if let Some(output_path) = &args.export_exif {
+    if let Err(err) = export::export_exif(&args.input, &args.original, output_path) {
+      eprintln!("Exif情報を書き出せませんでした: {:#}", err);
+      std::process::exit(1);
+    }
+    return;
+  }
+  if args.dry_run {
+    match save::dry_run(&args.input, &args.original, &args.work) {
+      Ok(result_lst) => {
+        for (id, staleness) in result_lst.iter() {
+          if staleness.is_stale() {
+            println!("{}: 圧縮し直す（{}）", id, staleness.label());
+          } else {
+            println!("{}: {}", id, staleness.label());
+          }
+        }
+        let count = result_lst.iter().filter(|(_, s)| s.is_stale()).count();
+        println!("{}枚中{}枚を圧縮し直します", result_lst.len(), count);
+      }
+      Err(err) => {
+        eprintln!("画像の状態を確認できませんでした: {:#}", err);
+        std::process::exit(1);
+      }
+    }
+    return;
+  }
+  if let Some(output_path) = &args.export_feed {
+    if let Err(err) = export::export_feed(
+      &args.work,
+      output_path,
+      args.base_url.as_deref(),
+      &args.url_prefix,
+    ) {
+      eprintln!("フィードを書き出せませんでした: {:#}", err);
+      std::process::exit(1);
+    }
+    return;
+  }
+  if let Some(output_path) = &args.export_csv {
+    if let Err(err) = export::export_csv(&args.work, output_path) {
+      eprintln!("CSVを書き出せませんでした: {:#}", err);
+      std::process::exit(1);
+    }
+    return;
+  }
+  if let Some(csv_path) = &args.import_csv {
+    match mapping::import_csv(&args.work, csv_path, work_json_style) {
+      Ok(count) => println!("{}枚の写真のメタデータを更新しました", count),
+      Err(err) => {
+        eprintln!("CSVを取り込めませんでした: {:#}", err);
+        std::process::exit(1);
+      }
+    }
+    return;
+  }
+  if args.convert_to_split {
+    match save::convert_to_split(&args.work) {
+      Ok(count) => println!(
+        "分割保存の形式に変換しました（{}個のファイルを書き出しました）",
+        count
+      ),
+      Err(err) => {
+        eprintln!("分割保存の形式に変換できませんでした: {}", err);
+        std::process::exit(1);
+      }
+    }
+    return;
+  }
+  if args.convert_to_single {
+    if let Err(err) = save::convert_to_single(&args.work) {
+      eprintln!("単一ファイルの形式に変換できませんでした: {}", err);
+      std::process::exit(1);
+    }
+    return;
+  }
+  if args.url_prefix.contains("://") {
+    eprintln!(
+      "--url-prefixにはドメインを含まないpathを指定してください: {}",
+      args.url_prefix
+    );
+    std::process::exit(1);
+  }
+  // 新しい作業ディレクトリでも画像を書き出せるように、先にフォルダを作っておく
+  if let Err(err) = save::ensure_work_directories(&args.work) {
+    eprintln!("作業ディレクトリを準備できませんでした: {:#}", err);
+    std::process::exit(1);
+  }
+  let native_options = match native_options(&args) {
+    Ok(native_options) => native_options,
+    Err(err) => {
+      eprintln!("ウィンドウの設定が正しくありません: {:#}", err);
+      std::process::exit(1);
+    }
+  };
+  let startup_options = gui::StartupOptions {
+    skip_validation: args.skip_validation,
+    exclude_broken: args.exclude_broken,
+    srcset_width_lst: args.srcset_widths,
+    url_prefix: args.url_prefix,
+    jpeg_scan: args.jpeg_scan,
+    fresh: args.fresh,
+    exif_thread_count: args
+      .exif_threads
+      .unwrap_or_else(photodata::default_exif_thread_count),
+    base_url: args.base_url,
+    dual_format: args.dual_format,
+    lazy_processing: args.lazy_processing,
+    force_regenerate: args.force_regenerate,
+    work_json_style,
+    export_json_style,
+    watermark,
+    theme: args.theme,
   };
   eframe::run_native(
     "photag",
@@ -38,7 +376,111 @@ fn main() {
         args.input,
         args.original,
         args.work,
+        startup_options,
       ))
     }),
   );
 }
+
+/// コマンドライン引数からウィンドウの設定を作る
+fn native_options(args: &Args) -> anyhow::Result<eframe::NativeOptions> {
+  if !(args.min_width > 0.0 && args.min_height > 0.0) {
+    return Err(anyhow::anyhow!(
+      "最小の幅と高さは0より大きい値で指定してください"
+    ));
+  }
+  Ok(eframe::NativeOptions {
+    min_window_size: Some(eframe::egui::vec2(args.min_width, args.min_height)),
+    resizable: true,
+    default_theme: args.theme.unwrap_or_default().native_theme(),
+    ..Default::default()
+  })
+}
+
+/// コマンドライン引数からウォーターマークの設定を作る
+/// `--watermark-dir`を指定していない場合は`None`にする
+fn watermark_options(args: &Args) -> anyhow::Result<Option<export::WatermarkOptions>> {
+  let directory = match &args.watermark_dir {
+    Some(directory) => directory.clone(),
+    None => return Ok(None),
+  };
+  let source = match (&args.watermark_text, &args.watermark_image) {
+    (Some(text), _) => image::WatermarkSource::Text(text.clone()),
+    (None, Some(path)) => image::WatermarkSource::Image(
+      std::fs::read(path).map_err(|e| anyhow::anyhow!("{}を読み込めません: {}", path, e))?,
+    ),
+    (None, None) => {
+      return Err(anyhow::anyhow!(
+        "--watermark-textか--watermark-imageのどちらかを指定してください"
+      ))
+    }
+  };
+  if !(0.0..=1.0).contains(&args.watermark_opacity) {
+    return Err(anyhow::anyhow!("不透明度は0から1の間で指定してください"));
+  }
+  if !(args.watermark_size > 0.0 && args.watermark_size <= 1.0) {
+    return Err(anyhow::anyhow!(
+      "大きさは0より大きく1以下で指定してください"
+    ));
+  }
+  Ok(Some(export::WatermarkOptions {
+    directory,
+    source,
+    position: args.watermark_position,
+    opacity: args.watermark_opacity,
+    size: args.watermark_size,
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const REQUIRED_ARGS: [&str; 7] = ["photag", "-i", "input.json", "-o", "original", "-w", "work"];
+
+  fn try_parse_args(option_lst: &[&str]) -> Result<Args, clap::Error> {
+    Args::try_parse_from(REQUIRED_ARGS.iter().chain(option_lst.iter()).copied())
+  }
+
+  fn parse_args(option_lst: &[&str]) -> Args {
+    try_parse_args(option_lst).unwrap()
+  }
+
+  #[test]
+  fn native_options_use_window_size_and_theme() {
+    let options = native_options(&parse_args(&[])).unwrap();
+    assert_eq!(
+      options.min_window_size,
+      Some(eframe::egui::vec2(900.0, 900.0))
+    );
+    assert_eq!(options.default_theme, eframe::Theme::Light);
+    let options = native_options(&parse_args(&[
+      "--min-width",
+      "640",
+      "--min-height",
+      "480",
+      "--theme",
+      "dark",
+    ]))
+    .unwrap();
+    assert_eq!(
+      options.min_window_size,
+      Some(eframe::egui::vec2(640.0, 480.0))
+    );
+    assert_eq!(options.default_theme, eframe::Theme::Dark);
+  }
+
+  #[test]
+  fn native_options_reject_non_positive_size() {
+    assert!(native_options(&parse_args(&["--min-width", "0"])).is_err());
+    assert!(native_options(&parse_args(&["--min-height", "NaN"])).is_err());
+  }
+
+  #[test]
+  fn unknown_theme_is_rejected() {
+    assert_eq!("dark".parse::<gui::Theme>(), Ok(gui::Theme::Dark));
+    assert_eq!("light".parse::<gui::Theme>(), Ok(gui::Theme::Light));
+    assert!("Dark".parse::<gui::Theme>().is_err());
+    assert!(try_parse_args(&["--theme", "blue"]).is_err());
+  }
+}
@@ -0,0 +1,452 @@
+//! 外部のJSON・CSVファイルから写真のメタデータを取り込むときの対応づけ
+//! 取り込む前に、先頭の数件がどう対応づけられるかを確認できるようにする
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+use std::collections::HashMap;
+use std::{fs::File, io::BufReader, path::Path};
+
+use crate::audit;
+use crate::photodata::{self, GUIPhotoData};
+use crate::save;
+
+/// 取り込み先にできる写真データの項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PhotoField {
+  Alt,
+  Title,
+  Location,
+  Body,
+  Lens,
+  Keywords,
+  Year,
+  Month,
+  Day,
+  Hour,
+  Minutes,
+  Time,
+  FocalLength,
+  FocalLength35mm,
+  FValue,
+  MaxFValue,
+  Iso,
+  ExposureProgram,
+  OffsetTime,
+  Altitude,
+  Session,
+}
+
+impl PhotoField {
+  pub const ALL: [PhotoField; 21] = [
+    PhotoField::Alt,
+    PhotoField::Title,
+    PhotoField::Location,
+    PhotoField::Body,
+    PhotoField::Lens,
+    PhotoField::Keywords,
+    PhotoField::Year,
+    PhotoField::Month,
+    PhotoField::Day,
+    PhotoField::Hour,
+    PhotoField::Minutes,
+    PhotoField::Time,
+    PhotoField::FocalLength,
+    PhotoField::FocalLength35mm,
+    PhotoField::FValue,
+    PhotoField::MaxFValue,
+    PhotoField::Iso,
+    PhotoField::ExposureProgram,
+    PhotoField::OffsetTime,
+    PhotoField::Altitude,
+    PhotoField::Session,
+  ];
+
+  /// 項目名（大文字小文字を除いて`photo_data.json`の項目名と同じ）
+  pub fn name(&self) -> &'static str {
+    match self {
+      PhotoField::Alt => "alt",
+      PhotoField::Title => "title",
+      PhotoField::Location => "location",
+      PhotoField::Body => "body",
+      PhotoField::Lens => "lens",
+      PhotoField::Keywords => "keywords",
+      PhotoField::Year => "year",
+      PhotoField::Month => "month",
+      PhotoField::Day => "day",
+      PhotoField::Hour => "hour",
+      PhotoField::Minutes => "minutes",
+      PhotoField::Time => "time",
+      PhotoField::FocalLength => "focal_length",
+      PhotoField::FocalLength35mm => "focal_length_35mm",
+      PhotoField::FValue => "f_value",
+      PhotoField::MaxFValue => "max_f_value",
+      PhotoField::Iso => "iso",
+      PhotoField::ExposureProgram => "exposure_program",
+      PhotoField::OffsetTime => "offset_time",
+      PhotoField::Altitude => "altitude",
+      PhotoField::Session => "session",
+    }
+  }
+
+  pub fn get<'a>(&self, photo_data: &'a GUIPhotoData) -> &'a str {
+    match self {
+      PhotoField::Alt => &photo_data.alt,
+      PhotoField::Title => &photo_data.title,
+      PhotoField::Location => &photo_data.location,
+      PhotoField::Body => &photo_data.body,
+      PhotoField::Lens => &photo_data.lens,
+      PhotoField::Keywords => &photo_data.keywords,
+      PhotoField::Year => &photo_data.year,
+      PhotoField::Month => &photo_data.month,
+      PhotoField::Day => &photo_data.day,
+      PhotoField::Hour => &photo_data.hour,
+      PhotoField::Minutes => &photo_data.minutes,
+      PhotoField::Time => &photo_data.time,
+      PhotoField::FocalLength => &photo_data.focal_length,
+      PhotoField::FocalLength35mm => &photo_data.focal_length_35mm,
+      PhotoField::FValue => &photo_data.f_value,
+      PhotoField::MaxFValue => &photo_data.max_f_value,
+      PhotoField::Iso => &photo_data.iso,
+      PhotoField::ExposureProgram => &photo_data.exposure_program,
+      PhotoField::OffsetTime => &photo_data.offset_time,
+      PhotoField::Altitude => &photo_data.altitude,
+      PhotoField::Session => &photo_data.session,
+    }
+  }
+
+  fn set(&self, photo_data: &mut GUIPhotoData, value: String) {
+    let field = match self {
+      PhotoField::Alt => &mut photo_data.alt,
+      PhotoField::Title => &mut photo_data.title,
+      PhotoField::Location => &mut photo_data.location,
+      PhotoField::Body => &mut photo_data.body,
+      PhotoField::Lens => &mut photo_data.lens,
+      PhotoField::Keywords => &mut photo_data.keywords,
+      PhotoField::Year => &mut photo_data.year,
+      PhotoField::Month => &mut photo_data.month,
+      PhotoField::Day => &mut photo_data.day,
+      PhotoField::Hour => &mut photo_data.hour,
+      PhotoField::Minutes => &mut photo_data.minutes,
+      PhotoField::Time => &mut photo_data.time,
+      PhotoField::FocalLength => &mut photo_data.focal_length,
+      PhotoField::FocalLength35mm => &mut photo_data.focal_length_35mm,
+      PhotoField::FValue => &mut photo_data.f_value,
+      PhotoField::MaxFValue => &mut photo_data.max_f_value,
+      PhotoField::Iso => &mut photo_data.iso,
+      PhotoField::ExposureProgram => &mut photo_data.exposure_program,
+      PhotoField::OffsetTime => &mut photo_data.offset_time,
+      PhotoField::Altitude => &mut photo_data.altitude,
+      PhotoField::Session => &mut photo_data.session,
+    };
+    *field = value;
+  }
+}
+
+/// 外部ファイルから読み込んだ表
+/// 1行が1枚の写真に対応する
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExternalTable {
+  /// ファイルに現れた順の列名
+  pub column_lst: Vec<String>,
+  pub row_lst: Vec<HashMap<String, String>>,
+}
+
+/// 拡張子が`.csv`の場合はCSV（1行目が列名）、それ以外の場合はオブジェクトの配列のJSONとして読み込む
+pub fn load_external_table(path: &str) -> Result<ExternalTable> {
+  let is_csv = Path::new(path)
+    .extension()
+    .map(|ext| ext.eq_ignore_ascii_case("csv"))
+    .unwrap_or(false);
+  let table = if is_csv {
+    load_csv_table(path)
+  } else {
+    load_json_table(path)
+  };
+  table.with_context(|| format!("{}を読み込めません", path))
+}
+
+fn load_csv_table(path: &str) -> Result<ExternalTable> {
+  let mut reader = csv::Reader::from_path(path)?;
+  let column_lst = reader
+    .headers()?
+    .iter()
+    .map(|s| s.to_string())
+    .collect::<Vec<String>>();
+  let mut row_lst = Vec::new();
+  for record in reader.records() {
+    let record = record?;
+    let row = column_lst
+      .iter()
+      .cloned()
+      .zip(record.iter().map(|s| s.to_string()))
+      .collect();
+    row_lst.push(row);
+  }
+  Ok(ExternalTable {
+    column_lst,
+    row_lst,
+  })
+}
+
+fn load_json_table(path: &str) -> Result<ExternalTable> {
+  let reader = BufReader::new(File::open(path)?);
+  let value: serde_json::Value = serde_json::from_reader(reader)?;
+  let array = value
+    .as_array()
+    .ok_or_else(|| anyhow!("オブジェクトの配列ではありません"))?;
+  let mut column_lst: Vec<String> = Vec::new();
+  let mut row_lst = Vec::new();
+  for (i, item) in array.iter().enumerate() {
+    let object = item
+      .as_object()
+      .ok_or_else(|| anyhow!("{}番目の要素がオブジェクトではありません", i + 1))?;
+    let mut row = HashMap::new();
+    for (key, value) in object.iter() {
+      if !column_lst.contains(key) {
+        column_lst.push(key.clone());
+      }
+      let value = match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        value => value.to_string(),
+      };
+      row.insert(key.clone(), value);
+    }
+    row_lst.push(row);
+  }
+  Ok(ExternalTable {
+    column_lst,
+    row_lst,
+  })
+}
+
+/// 列と写真データの項目の対応
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldMapping {
+  /// 写真のIDが書かれた列
+  pub id_column: Option<String>,
+  /// 各列の取り込み先
+  /// `None`の列は取り込まない
+  pub target_lst: Vec<(String, Option<PhotoField>)>,
+}
+
+impl FieldMapping {
+  /// 列名から対応を推測する
+  /// 項目名と大文字小文字を除いて一致する列を対応づけ、`photo_id`か`id`をIDの列とする
+  pub fn guess(column_lst: &[String]) -> Self {
+    let id_column = ["photo_id", "id"].iter().find_map(|name| {
+      column_lst
+        .iter()
+        .find(|column| column.trim().eq_ignore_ascii_case(name))
+        .cloned()
+    });
+    let target_lst = column_lst
+      .iter()
+      .map(|column| {
+        let target = PhotoField::ALL
+          .iter()
+          .find(|field| column.trim().eq_ignore_ascii_case(field.name()))
+          .copied();
+        (column.clone(), target)
+      })
+      .collect();
+    FieldMapping {
+      id_column,
+      target_lst,
+    }
+  }
+
+  fn mapped_lst(&self) -> impl Iterator<Item = (&String, PhotoField)> {
+    self
+      .target_lst
+      .iter()
+      .filter(|(column, _)| Some(column) != self.id_column.as_ref())
+      .filter_map(|(column, target)| target.map(|field| (column, field)))
+  }
+}
+
+/// 取り込んだときに1枚の写真がどう変わるか
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviewRow {
+  pub photo_id: String,
+  /// 作業データにIDが存在するかどうか
+  pub is_known: bool,
+  /// 項目・今の値・取り込んだ後の値
+  pub change_lst: Vec<(PhotoField, String, String)>,
+}
+
+/// 先頭の`limit`件について、取り込んだ結果を作る
+pub fn preview(
+  table: &ExternalTable,
+  mapping: &FieldMapping,
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  limit: usize,
+) -> Vec<PreviewRow> {
+  let id_column = match &mapping.id_column {
+    Some(id_column) => id_column,
+    None => return Vec::new(),
+  };
+  table
+    .row_lst
+    .iter()
+    .take(limit)
+    .map(|row| {
+      let photo_id = row.get(id_column).cloned().unwrap_or_default();
+      let photo_data = gui_photo_data_lst.get(&photo_id);
+      let change_lst = mapping
+        .mapped_lst()
+        .filter_map(|(column, field)| {
+          let new_value = row.get(column)?.clone();
+          let old_value = photo_data
+            .map(|photo_data| field.get(photo_data).to_string())
+            .unwrap_or_default();
+          Some((field, old_value, new_value))
+        })
+        .collect();
+      PreviewRow {
+        is_known: photo_data.is_some(),
+        photo_id,
+        change_lst,
+      }
+    })
+    .collect()
+}
+
+/// 取り込む前に確認してほしい点を挙げる
+pub fn mapping_warning_lst(
+  table: &ExternalTable,
+  mapping: &FieldMapping,
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+) -> Vec<String> {
+  let mut warning_lst = Vec::new();
+  let id_column = match &mapping.id_column {
+    Some(id_column) => id_column,
+    None => {
+      warning_lst.push("IDの列が選ばれていません".to_string());
+      return warning_lst;
+    }
+  };
+  for (column, target) in mapping.target_lst.iter() {
+    if column != id_column && target.is_none() {
+      warning_lst.push(format!("列「{}」は取り込まれません", column));
+    }
+  }
+  for field in PhotoField::ALL.iter() {
+    let count = mapping.mapped_lst().filter(|(_, f)| f == field).count();
+    if count > 1 {
+      warning_lst.push(format!(
+        "{}に{}個の列が対応づけられています（後の列が優先されます）",
+        field.name(),
+        count
+      ));
+    }
+  }
+  let unknown_count = table
+    .row_lst
+    .iter()
+    .filter(|row| {
+      row
+        .get(id_column)
+        .map(|id| !gui_photo_data_lst.contains_key(id))
+        .unwrap_or(true)
+    })
+    .count();
+  if unknown_count > 0 {
+    warning_lst.push(format!(
+      "{}行は作業データに無いIDのため取り込まれません",
+      unknown_count
+    ));
+  }
+  warning_lst
+}
+
+/// 対応づけに従って写真データを書き換える
+/// 実際に値が変わった写真について、変更前のデータを返す
+pub fn apply_mapping(
+  table: &ExternalTable,
+  mapping: &FieldMapping,
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+) -> Vec<GUIPhotoData> {
+  let id_column = match &mapping.id_column {
+    Some(id_column) => id_column,
+    None => return Vec::new(),
+  };
+  let mut before_lst: Vec<GUIPhotoData> = Vec::new();
+  for row in table.row_lst.iter() {
+    let photo_data = match row
+      .get(id_column)
+      .and_then(|id| gui_photo_data_lst.get_mut(id))
+    {
+      Some(photo_data) => photo_data,
+      None => continue,
+    };
+    let before = photo_data.clone();
+    for (column, field) in mapping.mapped_lst() {
+      if let Some(value) = row.get(column) {
+        field.set(photo_data, value.clone());
+      }
+    }
+    // 同じIDの行が複数ある場合は最初の状態だけを残す
+    if *photo_data != before && before_lst.iter().all(|b| b.photo_id != before.photo_id) {
+      before_lst.push(before);
+    }
+  }
+  before_lst
+}
+
+/// CSVの変更を作業ディレクトリのデータに反映する
+/// `photo_id`の列で写真を対応づけ、ファイル名や画像のpathは書き換えない
+/// 変更した写真の数を返す
+pub fn import_csv(work_dir: &str, csv_path: &str, json_style: save::JsonStyle) -> Result<usize> {
+  let table = load_csv_table(csv_path).with_context(|| format!("{}を読み込めません", csv_path))?;
+  let mapping = FieldMapping::guess(&table.column_lst);
+  if mapping.id_column.is_none() {
+    bail!("{}にphoto_idの列がありません", csv_path);
+  }
+  let photo_data_lst = photodata::load_photo_data_from_work_directory(work_dir)?;
+  let group_data_lst = photodata::load_group_data_from_work_directory(work_dir)?;
+  let photo_id_lst = photo_data_lst
+    .iter()
+    .map(|data| data.photo_id.clone())
+    .collect::<Vec<String>>();
+  let mut gui_photo_data_lst = photo_data_lst
+    .into_iter()
+    .map(|data| {
+      (
+        data.photo_id.clone(),
+        photodata::photo_data_to_gui_photo_data(data),
+      )
+    })
+    .collect::<HashMap<String, GUIPhotoData>>();
+  let gui_group_data_lst = group_data_lst
+    .iter()
+    .cloned()
+    .map(|data| {
+      (
+        data.group_id.clone(),
+        photodata::group_data_to_gui_group_data(data),
+      )
+    })
+    .collect();
+  for warning in mapping_warning_lst(&table, &mapping, &gui_photo_data_lst) {
+    warn!("{}", warning);
+  }
+  let audit_snapshot = audit::AuditSnapshot::new(&gui_photo_data_lst, &gui_group_data_lst);
+  let before_lst = apply_mapping(&table, &mapping, &mut gui_photo_data_lst);
+  if before_lst.is_empty() {
+    return Ok(0);
+  }
+  let photo_data_lst = photo_id_lst
+    .iter()
+    .map(|id| photodata::gui_photo_data_to_photo_data(gui_photo_data_lst[id].clone()))
+    .collect::<Vec<_>>();
+  save::save_work_data(work_dir, &photo_data_lst, &group_data_lst, json_style)?;
+  let audit_entry_lst = audit::make_audit_entry_lst(
+    &audit_snapshot,
+    &gui_photo_data_lst,
+    &gui_group_data_lst,
+    save::get_now(),
+  );
+  audit::append_audit_log(work_dir, &audit_entry_lst)?;
+  Ok(before_lst.len())
+}
@@ -1,13 +1,19 @@
 //! 画像ファイル名と説明文と撮影場所を記録したJSONファイルを読み込み、データを生成する
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, NaiveDateTime, Timelike};
 use exif::{DateTime, In, Tag, Value};
+use log::debug;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::Path;
 use std::{fs::File, io::BufReader, str};
 
+use crate::image;
+use crate::save;
+
 /// 書きだすためのデータ
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct PhotoData {
   /// ファイル名（編集不可にしたい）
   pub file_name: String,
@@ -22,9 +28,9 @@ pub struct PhotoData {
   /// `images/lazy/`から始まる
   pub photo_lazy_src: String,
   /// 画像の説明
-  pub alt: String,
+  pub alt: LocalizedText,
   /// 画像タイトル
-  pub title: Option<String>,
+  pub title: Option<LocalizedText>,
   /// 撮影日時
   pub year: Option<String>,
   /// 撮影日時
@@ -48,8 +54,174 @@ pub struct PhotoData {
   pub f_value: Option<String>,
   /// ISO感度
   pub iso: Option<String>,
+  /// 露出モード
+  pub exposure_program: Option<String>,
+  /// 開放F値
+  pub max_f_value: Option<String>,
   /// 撮影場所
   pub location: String,
+  /// 幅の異なる画像の一覧（レスポンシブな`srcset`用）
+  #[serde(default)]
+  pub srcset: Vec<SrcsetImage>,
+  /// ピント位置（Exifに記録されている場合のみ）
+  #[serde(default)]
+  pub focus_area: Option<FocusArea>,
+  /// 評価（1から5まで、0は未評価）
+  #[serde(default)]
+  pub rating: u8,
+  /// 自由に付けられるキーワード
+  #[serde(default)]
+  pub keywords: Vec<String>,
+  /// 撮影日時のUTCからの時差（`+09:00`のような形式）
+  /// Exifの`OffsetTimeOriginal`に記録されている場合のみ
+  #[serde(default)]
+  pub offset_time: Option<String>,
+  /// 35mm判換算の焦点距離
+  /// Exifの`FocalLengthIn35mmFilm`に記録されている場合のみで、センサーの大きさからの推測はしない
+  #[serde(default)]
+  pub focal_length_35mm: Option<String>,
+  /// 撮影地点の標高（m）
+  /// Exifの`GPSAltitude`に記録されている場合のみで、海面下は負の値になる
+  #[serde(default)]
+  pub altitude: Option<f64>,
+  /// 誤って編集しないようにロックしているかどうか
+  /// ロック中は編集フォームが読み取り専用になり、一括編集や削除の対象にならない
+  #[serde(default)]
+  pub locked: bool,
+  /// 撮影セッション（フィルムのロールのように、一度に撮影した写真のまとまり）の名前
+  /// 取り込み時にファイルの親フォルダ名か撮影日から推定する
+  #[serde(default)]
+  pub session: Option<String>,
+  /// 選別のためのフラグ（`pick`が採用・`reject`が不採用で、`None`は保留）
+  /// 不採用の写真は書き出さない
+  #[serde(default)]
+  pub flag: Option<String>,
+  /// WebP形式の表示用画像のpath
+  /// `--dual-format`を指定して書き出したときだけ作られ、`images/normal/`から始まる
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub photo_webp_src: Option<String>,
+}
+
+/// 評価の最大値
+pub const MAX_RATING: u8 = 5;
+
+/// 言語を指定していない説明やタイトルの言語
+pub const DEFAULT_LANGUAGE: &str = "ja";
+
+/// 言語コードから文字列への対応
+/// 既定の言語の文字列しか無い場合は、以前と同じ単一の文字列として読み書きする
+/// 保存するたびに順番が変わらないよう、言語コード順に並べる
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(from = "LocalizedTextRepr", into = "LocalizedTextRepr")]
+pub struct LocalizedText(pub BTreeMap<String, String>);
+
+/// `LocalizedText`のJSONでの表現
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum LocalizedTextRepr {
+  Single(String),
+  Multi(BTreeMap<String, String>),
+}
+
+impl From<LocalizedTextRepr> for LocalizedText {
+  fn from(repr: LocalizedTextRepr) -> Self {
+    match repr {
+      LocalizedTextRepr::Single(text) => LocalizedText::from_parts(text, BTreeMap::new()),
+      LocalizedTextRepr::Multi(text_lst) => LocalizedText(text_lst),
+    }
+  }
+}
+
+impl From<LocalizedText> for LocalizedTextRepr {
+  fn from(text: LocalizedText) -> Self {
+    if text.0.keys().all(|language| language == DEFAULT_LANGUAGE) {
+      LocalizedTextRepr::Single(text.default_text().to_string())
+    } else {
+      LocalizedTextRepr::Multi(text.0)
+    }
+  }
+}
+
+impl LocalizedText {
+  /// 既定の言語の文字列と、それ以外の言語の文字列から作る
+  /// 空の文字列は記録しない
+  pub fn from_parts(default_text: String, translation_lst: BTreeMap<String, String>) -> Self {
+    let mut text_lst = translation_lst;
+    text_lst.insert(DEFAULT_LANGUAGE.to_string(), default_text);
+    text_lst.retain(|_, text| !text.is_empty());
+    LocalizedText(text_lst)
+  }
+
+  /// 既定の言語の文字列
+  pub fn default_text(&self) -> &str {
+    self
+      .0
+      .get(DEFAULT_LANGUAGE)
+      .map(String::as_str)
+      .unwrap_or_default()
+  }
+
+  /// 指定した言語の文字列
+  /// その言語の文字列が無い場合は既定の言語の文字列を使う
+  pub fn get(&self, language: &str) -> &str {
+    self
+      .0
+      .get(language)
+      .map(String::as_str)
+      .unwrap_or_else(|| self.default_text())
+  }
+
+  /// 既定の言語以外の文字列
+  pub fn translation_lst(&self) -> BTreeMap<String, String> {
+    let mut text_lst = self.0.clone();
+    text_lst.remove(DEFAULT_LANGUAGE);
+    text_lst
+  }
+
+  /// 既定の言語の文字列だけを差し替える
+  pub fn with_default_text(&self, default_text: String) -> Self {
+    LocalizedText::from_parts(default_text, self.translation_lst())
+  }
+}
+
+/// 写真の説明とタイトルに使われている、既定の言語以外の言語コードを並べる
+pub fn translation_language_lst<'a>(
+  photo_data_lst: impl Iterator<Item = &'a PhotoData>,
+) -> Vec<String> {
+  let mut language_lst = BTreeSet::new();
+  for photo_data in photo_data_lst {
+    let text_lst = std::iter::once(&photo_data.alt).chain(photo_data.title.iter());
+    for text in text_lst {
+      language_lst.extend(text.translation_lst().into_keys());
+    }
+  }
+  language_lst.into_iter().collect()
+}
+
+/// `srcset`に並べる画像
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SrcsetImage {
+  /// staticフォルダ内の画像を参照するため、
+  /// `images/srcset/`から始まる
+  pub src: String,
+  /// 画像の幅
+  pub width: u32,
+}
+
+/// Exifに記録されているピント位置
+/// 座標は元画像のピクセル単位で、大きさが0の場合は点で記録されている
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusArea {
+  /// 中心のx座標
+  pub x: u32,
+  /// 中心のy座標
+  pub y: u32,
+  pub width: u32,
+  pub height: u32,
+  /// 座標の基準になる画像の幅
+  pub image_width: u32,
+  /// 座標の基準になる画像の高さ
+  pub image_height: u32,
 }
 
 /// GUIで使う用のデータ
@@ -63,6 +235,10 @@ pub struct GUIPhotoData {
   pub photo_lazy_src: String,
   pub alt: String,
   pub title: String,
+  /// 既定の言語以外の説明（言語コードから文字列への対応）
+  pub alt_translation_lst: BTreeMap<String, String>,
+  /// 既定の言語以外のタイトル（言語コードから文字列への対応）
+  pub title_translation_lst: BTreeMap<String, String>,
   pub year: String,
   pub month: String,
   pub day: String,
@@ -74,7 +250,21 @@ pub struct GUIPhotoData {
   pub focal_length: String,
   pub f_value: String,
   pub iso: String,
+  pub exposure_program: String,
+  pub max_f_value: String,
   pub location: String,
+  pub srcset: Vec<SrcsetImage>,
+  pub focus_area: Option<FocusArea>,
+  pub rating: u8,
+  /// カンマ区切りのキーワード
+  pub keywords: String,
+  pub offset_time: String,
+  pub focal_length_35mm: String,
+  pub altitude: String,
+  pub locked: bool,
+  pub session: String,
+  pub flag: Option<String>,
+  pub photo_webp_src: Option<String>,
 }
 
 pub fn gui_photo_data_to_photo_data(gui_photo_data: GUIPhotoData) -> PhotoData {
@@ -83,12 +273,12 @@ pub fn gui_photo_data_to_photo_data(gui_photo_data: GUIPhotoData) -> PhotoData {
     photo_id: gui_photo_data.photo_id,
     photo_src: gui_photo_data.photo_src,
     photo_lazy_src: gui_photo_data.photo_lazy_src,
-    alt: gui_photo_data.alt,
-    title: if gui_photo_data.title.is_empty() {
-      None
-    } else {
-      Some(gui_photo_data.title)
-    },
+    alt: LocalizedText::from_parts(gui_photo_data.alt, gui_photo_data.alt_translation_lst),
+    title: Some(LocalizedText::from_parts(
+      gui_photo_data.title,
+      gui_photo_data.title_translation_lst,
+    ))
+    .filter(|title| !title.0.is_empty()),
     year: if gui_photo_data.year.is_empty() {
       None
     } else {
@@ -144,7 +334,40 @@ pub fn gui_photo_data_to_photo_data(gui_photo_data: GUIPhotoData) -> PhotoData {
     } else {
       Some(gui_photo_data.iso)
     },
+    exposure_program: if gui_photo_data.exposure_program.is_empty() {
+      None
+    } else {
+      Some(gui_photo_data.exposure_program)
+    },
+    max_f_value: if gui_photo_data.max_f_value.is_empty() {
+      None
+    } else {
+      Some(gui_photo_data.max_f_value)
+    },
     location: gui_photo_data.location,
+    srcset: gui_photo_data.srcset,
+    focus_area: gui_photo_data.focus_area,
+    rating: gui_photo_data.rating,
+    keywords: parse_keywords(&gui_photo_data.keywords),
+    offset_time: if gui_photo_data.offset_time.is_empty() {
+      None
+    } else {
+      Some(gui_photo_data.offset_time)
+    },
+    focal_length_35mm: if gui_photo_data.focal_length_35mm.is_empty() {
+      None
+    } else {
+      Some(gui_photo_data.focal_length_35mm)
+    },
+    altitude: parse_altitude(&gui_photo_data.altitude),
+    locked: gui_photo_data.locked,
+    session: if gui_photo_data.session.trim().is_empty() {
+      None
+    } else {
+      Some(gui_photo_data.session.trim().to_string())
+    },
+    flag: gui_photo_data.flag,
+    photo_webp_src: gui_photo_data.photo_webp_src,
   }
 }
 
@@ -154,8 +377,17 @@ pub fn photo_data_to_gui_photo_data(photo_data: PhotoData) -> GUIPhotoData {
     photo_id: photo_data.photo_id,
     photo_src: photo_data.photo_src,
     photo_lazy_src: photo_data.photo_lazy_src,
-    alt: photo_data.alt,
-    title: photo_data.title.unwrap_or_default(),
+    alt: photo_data.alt.default_text().to_string(),
+    title: photo_data
+      .title
+      .as_ref()
+      .map(|title| title.default_text().to_string())
+      .unwrap_or_default(),
+    alt_translation_lst: photo_data.alt.translation_lst(),
+    title_translation_lst: photo_data
+      .title
+      .map(|title| title.translation_lst())
+      .unwrap_or_default(),
     year: photo_data.year.unwrap_or_default(),
     month: photo_data.month.unwrap_or_default(),
     day: photo_data.day.unwrap_or_default(),
@@ -167,7 +399,20 @@ pub fn photo_data_to_gui_photo_data(photo_data: PhotoData) -> GUIPhotoData {
     focal_length: photo_data.focal_length.unwrap_or_default(),
     f_value: photo_data.f_value.unwrap_or_default(),
     iso: photo_data.iso.unwrap_or_default(),
+    exposure_program: photo_data.exposure_program.unwrap_or_default(),
+    max_f_value: photo_data.max_f_value.unwrap_or_default(),
     location: photo_data.location,
+    srcset: photo_data.srcset,
+    focus_area: photo_data.focus_area,
+    rating: photo_data.rating,
+    keywords: join_keywords(&photo_data.keywords),
+    offset_time: photo_data.offset_time.unwrap_or_default(),
+    focal_length_35mm: photo_data.focal_length_35mm.unwrap_or_default(),
+    altitude: photo_data.altitude.map(format_altitude).unwrap_or_default(),
+    locked: photo_data.locked,
+    session: photo_data.session.unwrap_or_default(),
+    flag: photo_data.flag,
+    photo_webp_src: photo_data.photo_webp_src,
   }
 }
 
@@ -203,14 +448,46 @@ pub fn load_import_json_file(file_path: &str) -> Result<Vec<ImportPhotoData>> {
   Ok(data)
 }
 
+/// 保存済みの写真データから入力のJSONファイルの内容を作り直す
+/// 入力のJSONファイルを読み込めない場合に、編集済みのデータを失わないように使う
+/// 並び順はIDの順にする
+pub fn import_photo_data_from_photo_data(
+  photo_data_lst: &HashMap<String, PhotoData>,
+) -> Vec<ImportPhotoData> {
+  let mut import_photo_data_lst = photo_data_lst
+    .values()
+    .map(|photo_data| ImportPhotoData {
+      file_name: photo_data.file_name.clone(),
+      id: photo_data.photo_id.clone(),
+      alt: photo_data.alt.default_text().to_string(),
+      location: photo_data.location.clone(),
+    })
+    .collect::<Vec<_>>();
+  import_photo_data_lst.sort_by(|a, b| a.id.cmp(&b.id));
+  import_photo_data_lst
+}
+
+/// 入力のJSONファイルを書き換えてよいか
+/// ファイルが有るのに読み込めない場合は、手で直すまで上書きしない
+pub fn can_overwrite_import_json_file(file_path: &str) -> bool {
+  !Path::new(file_path).exists() || load_import_json_file(file_path).is_ok()
+}
+
 /// photo_data.jsonが保存されているディレクトリのpathから中身を読み取る
 pub fn load_photo_data_opt(work_directory: &str) -> HashMap<String, PhotoData> {
   let mut hashmap = HashMap::new();
+  if save::is_split(work_directory) {
+    let (data_lst, _) = save::load_split(work_directory).unwrap();
+    for data in data_lst.into_iter() {
+      hashmap.insert(data.photo_id.clone(), data);
+    }
+    return hashmap;
+  }
   let file_path = format!("{}/photo_data.json", work_directory);
   match File::open(file_path) {
     Ok(file) => {
       let reader = BufReader::new(file);
-      let data_lst: Vec<PhotoData> = serde_json::from_reader(reader).unwrap();
+      let data_lst: Vec<PhotoData> = save::read_versioned_data(reader).unwrap();
       for data in data_lst.iter() {
         hashmap.insert(data.clone().photo_id, data.clone());
       }
@@ -220,18 +497,221 @@ pub fn load_photo_data_opt(work_directory: &str) -> HashMap<String, PhotoData> {
   }
 }
 
+/// photo_data.jsonが保存されているディレクトリのpathから、並び順を保ったまま中身を読み取る
+/// 分割保存されている場合は分割したファイルから読み取る
+pub fn load_photo_data_from_work_directory(work_directory: &str) -> Result<Vec<PhotoData>> {
+  if save::is_split(work_directory) {
+    return Ok(save::load_split(work_directory)?.0);
+  }
+  let file_path = format!("{}/photo_data.json", work_directory);
+  match File::open(file_path) {
+    Ok(file) => {
+      let reader = BufReader::new(file);
+      let data: Vec<PhotoData> = save::read_versioned_data(reader)?;
+      Ok(data)
+    }
+    Err(_) => Ok(Vec::new()),
+  }
+}
+
+/// 入力のJSONファイルに書かれている画像のうち、元画像のフォルダに見つからないものを並び順のまま返す
+pub fn find_missing_files(
+  import_photo_data_lst: &[ImportPhotoData],
+  original_path: &str,
+) -> Vec<ImportPhotoData> {
+  import_photo_data_lst
+    .iter()
+    .filter(|import_photo_data| {
+      !Path::new(original_path)
+        .join(&import_photo_data.file_name)
+        .is_file()
+    })
+    .cloned()
+    .collect()
+}
+
+/// 元画像のフォルダに見つからない画像がある場合は、その一覧をエラーにする
+pub fn ensure_files_exist(
+  import_photo_data_lst: &[ImportPhotoData],
+  original_path: &str,
+) -> Result<()> {
+  let missing_file_lst = find_missing_files(import_photo_data_lst, original_path);
+  if missing_file_lst.is_empty() {
+    return Ok(());
+  }
+  bail!(
+    "{}個の画像ファイルが見つかりません\n{}",
+    missing_file_lst.len(),
+    missing_file_lst
+      .iter()
+      .map(|import_photo_data| format!(
+        "  {}（{}）",
+        import_photo_data.file_name, import_photo_data.id
+      ))
+      .collect::<Vec<String>>()
+      .join("\n")
+  )
+}
+
+/// 入力のJSONファイルで複数の画像に付けられているID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateId {
+  pub id: String,
+  /// このIDが付いている画像を入力のJSONファイルの並び順のまま並べたもの
+  /// 読み込むのは先頭の画像だけ
+  pub import_photo_data_lst: Vec<ImportPhotoData>,
+}
+
+impl DuplicateId {
+  /// 読み込まなかった2つ目以降の画像
+  pub fn skipped(&self) -> &[ImportPhotoData] {
+    &self.import_photo_data_lst[1..]
+  }
+
+  /// このIDが付いている画像のファイル名を`、`で繋げたもの
+  pub fn file_names(&self) -> String {
+    self
+      .import_photo_data_lst
+      .iter()
+      .map(|import_photo_data| import_photo_data.file_name.as_str())
+      .collect::<Vec<&str>>()
+      .join("、")
+  }
+}
+
+/// 入力のJSONファイルで複数の画像に付けられているIDを、最初に現れた順に返す
+pub fn find_duplicate_ids(import_photo_data_lst: &[ImportPhotoData]) -> Vec<DuplicateId> {
+  let mut id_lst: Vec<&str> = Vec::new();
+  let mut same_id_lst: HashMap<&str, Vec<ImportPhotoData>> = HashMap::new();
+  for import_photo_data in import_photo_data_lst.iter() {
+    let id = import_photo_data.id.as_str();
+    if !same_id_lst.contains_key(id) {
+      id_lst.push(id);
+    }
+    same_id_lst
+      .entry(id)
+      .or_default()
+      .push(import_photo_data.clone());
+  }
+  id_lst
+    .into_iter()
+    .filter_map(|id| {
+      let lst = same_id_lst.remove(id)?;
+      (lst.len() > 1).then(|| DuplicateId {
+        id: id.to_string(),
+        import_photo_data_lst: lst,
+      })
+    })
+    .collect()
+}
+
+/// 同じIDの画像が複数ある場合は、先頭の画像だけを残す
+pub fn dedup_import_photo_data(import_photo_data_lst: &mut Vec<ImportPhotoData>) {
+  let mut id_lst = HashSet::new();
+  import_photo_data_lst.retain(|import_photo_data| id_lst.insert(import_photo_data.id.clone()));
+}
+
+/// 同じIDが複数の画像に付けられている場合は、その一覧をエラーにする
+pub fn ensure_unique_ids(import_photo_data_lst: &[ImportPhotoData]) -> Result<()> {
+  let duplicate_id_lst = find_duplicate_ids(import_photo_data_lst);
+  if duplicate_id_lst.is_empty() {
+    return Ok(());
+  }
+  bail!(
+    "{}個のIDが複数の画像に付けられています\n{}",
+    duplicate_id_lst.len(),
+    duplicate_id_lst
+      .iter()
+      .map(|duplicate_id| format!("  {}（{}）", duplicate_id.id, duplicate_id.file_names()))
+      .collect::<Vec<String>>()
+      .join("\n")
+  )
+}
+
+/// 入力のJSONファイルを読み直したときの、前回読み込んだ内容との差分
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportDiff {
+  /// 新しく追加された画像
+  pub added: Vec<ImportPhotoData>,
+  /// 無くなった画像のID
+  pub removed: Vec<String>,
+  /// ファイル名・説明・撮影場所のいずれかが変わった画像
+  pub changed: Vec<ImportPhotoData>,
+}
+
+impl ImportDiff {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+  }
+}
+
+/// 前回読み込んだ`ImportPhotoData`と新しく読み込んだものを比べて、
+/// 追加・削除・変更のあった画像だけを並び順のまま取り出す
+pub fn diff_import_data(old: &[ImportPhotoData], new: &[ImportPhotoData]) -> ImportDiff {
+  let old_lst = old
+    .iter()
+    .map(|import_photo_data| (import_photo_data.id.as_str(), import_photo_data))
+    .collect::<HashMap<&str, &ImportPhotoData>>();
+  let new_id_lst = new
+    .iter()
+    .map(|import_photo_data| import_photo_data.id.as_str())
+    .collect::<HashSet<&str>>();
+  let mut diff = ImportDiff::default();
+  for import_photo_data in new.iter() {
+    match old_lst.get(import_photo_data.id.as_str()) {
+      None => diff.added.push(import_photo_data.clone()),
+      Some(old_import_photo_data) if *old_import_photo_data != import_photo_data => {
+        diff.changed.push(import_photo_data.clone())
+      }
+      Some(_) => (),
+    }
+  }
+  diff.removed = old
+    .iter()
+    .filter(|import_photo_data| !new_id_lst.contains(import_photo_data.id.as_str()))
+    .map(|import_photo_data| import_photo_data.id.clone())
+    .collect();
+  diff
+}
+
 /// 事前に生成されていたphoto_data.jsonを元にした`PhotoData`と
 /// 現像時に手動で作成した元の画像ファイル名などが入る`ImportPhotoData`と
 /// 元画像が置かれたフォルダへのpathを受け取って、
 /// その中身をもとにJPEGファイルを検索してデータを取り出し、`PhotoData`に変換する
+/// まだデータが無い写真のExifの解析は`thread_count`個のスレッドで並列に行う
+/// 画像のpathは`url_prefix`から始める
+/// 同じIDの画像が複数ある場合は先頭の画像だけを使う
 pub fn merge_photo_data_based_and_import_photo_data(
   original_photo_data_lst: &HashMap<String, PhotoData>,
   import_photo_data_lst: &[ImportPhotoData],
   original_path: &str,
+  thread_count: usize,
+  url_prefix: &str,
 ) -> Result<(Vec<String>, Vec<PhotoData>)> {
+  // Exifの解析はファイルの読み込みに時間がかかるので、先にまとめて並列に行う
+  // 同じIDの画像は先頭の画像だけを解析する
+  let mut new_id_lst = HashSet::new();
+  let new_photo_data_lst = import_photo_data_lst
+    .iter()
+    .filter(|import_photo_data| !original_photo_data_lst.contains_key(&import_photo_data.id))
+    .filter(|import_photo_data| new_id_lst.insert(import_photo_data.id.as_str()))
+    .collect::<Vec<&ImportPhotoData>>();
+  let path_lst = new_photo_data_lst
+    .iter()
+    .map(|import_photo_data| format!("{}/{}", original_path, import_photo_data.file_name))
+    .collect::<Vec<String>>();
+  let mut exif_lst = new_photo_data_lst
+    .iter()
+    .map(|import_photo_data| import_photo_data.id.clone())
+    .zip(parse_exif_data_parallel(&path_lst, thread_count))
+    .collect::<HashMap<String, Result<MinimalExif>>>();
   let mut photo_id_lst = Vec::new();
   let mut photo_data_lst = Vec::new();
+  let mut pushed_id_lst = HashSet::new();
   for import_photo_data in import_photo_data_lst.iter() {
+    if !pushed_id_lst.insert(import_photo_data.id.as_str()) {
+      continue;
+    }
     photo_id_lst.push(import_photo_data.clone().id);
 
     photo_data_lst.push(match original_photo_data_lst.get(&import_photo_data.id) {
@@ -239,24 +719,33 @@ pub fn merge_photo_data_based_and_import_photo_data(
       Some(photo_data) => PhotoData {
         file_name: import_photo_data.file_name.clone(),
         photo_id: import_photo_data.id.clone(),
-        photo_src: format!("/images/normal/{}.JPG", import_photo_data.id),
-        photo_lazy_src: format!("/images/lazy/{}.JPG", import_photo_data.id),
-        alt: import_photo_data.alt.clone(),
+        photo_src: photo_src(url_prefix, &import_photo_data.id),
+        photo_lazy_src: photo_lazy_src(url_prefix, &import_photo_data.id),
+        alt: photo_data.alt.with_default_text(merge_alt(
+          &import_photo_data.alt,
+          photo_data.alt.default_text(),
+        )),
         location: import_photo_data.location.clone(),
         ..photo_data.clone()
       },
       // まだデータが無い場合はExifファイルの中身を元に構築する
-      None => match parse_exif_data(&format!(
-        "{}/{}",
-        original_path,
-        import_photo_data.clone().file_name
-      )) {
+      None => match exif_lst
+        .remove(&import_photo_data.id)
+        .unwrap_or_else(|| Err(anyhow::anyhow!("Exifを解析していません")))
+      {
         Ok(minimal_exif_data) => PhotoData {
+          // 撮影日は後の項目で取り出すので、先に推定しておく
+          session: estimate_session(
+            &import_photo_data.file_name,
+            minimal_exif_data.year.as_deref(),
+            minimal_exif_data.month.as_deref(),
+            minimal_exif_data.day.as_deref(),
+          ),
           file_name: import_photo_data.file_name.clone(),
           photo_id: import_photo_data.id.clone(),
-          photo_src: format!("/images/normal/{}.JPG", import_photo_data.id),
-          photo_lazy_src: format!("/images/lazy/{}.JPG", import_photo_data.id),
-          alt: import_photo_data.alt.clone(),
+          photo_src: photo_src(url_prefix, &import_photo_data.id),
+          photo_lazy_src: photo_lazy_src(url_prefix, &import_photo_data.id),
+          alt: LocalizedText::from_parts(import_photo_data.alt.clone(), BTreeMap::new()),
           title: None,
           year: minimal_exif_data.year,
           month: minimal_exif_data.month,
@@ -269,14 +758,26 @@ pub fn merge_photo_data_based_and_import_photo_data(
           focal_length: minimal_exif_data.focal_length,
           f_value: minimal_exif_data.f_value,
           iso: minimal_exif_data.iso,
+          exposure_program: minimal_exif_data.exposure_program,
+          max_f_value: minimal_exif_data.max_f_value,
           location: import_photo_data.location.clone(),
+          srcset: Vec::new(),
+          focus_area: minimal_exif_data.focus_area,
+          rating: minimal_exif_data.rating.unwrap_or_default(),
+          keywords: minimal_exif_data.keywords,
+          offset_time: minimal_exif_data.offset_time,
+          focal_length_35mm: minimal_exif_data.focal_length_35mm,
+          altitude: minimal_exif_data.altitude,
+          locked: false,
+          flag: None,
+          photo_webp_src: None,
         },
         Err(_) => PhotoData {
           file_name: import_photo_data.file_name.clone(),
-          photo_id: String::new(),
-          photo_src: format!("/images/normal/{}.JPG", import_photo_data.id),
-          photo_lazy_src: format!("/images/lazy/{}.JPG", import_photo_data.id),
-          alt: import_photo_data.alt.clone(),
+          photo_id: import_photo_data.id.clone(),
+          photo_src: photo_src(url_prefix, &import_photo_data.id),
+          photo_lazy_src: photo_lazy_src(url_prefix, &import_photo_data.id),
+          alt: LocalizedText::from_parts(import_photo_data.alt.clone(), BTreeMap::new()),
           title: None,
           year: None,
           month: None,
@@ -289,7 +790,20 @@ pub fn merge_photo_data_based_and_import_photo_data(
           focal_length: None,
           f_value: None,
           iso: None,
+          exposure_program: None,
+          max_f_value: None,
           location: import_photo_data.location.clone(),
+          srcset: Vec::new(),
+          focus_area: None,
+          rating: 0,
+          keywords: Vec::new(),
+          offset_time: None,
+          focal_length_35mm: None,
+          altitude: None,
+          locked: false,
+          session: estimate_session(&import_photo_data.file_name, None, None, None),
+          flag: None,
+          photo_webp_src: None,
         },
       },
     })
@@ -301,12 +815,16 @@ pub fn merge_photo_data_based_and_import_photo_data(
 /// 現像時に手動で作成した元の画像ファイル名などが入る`ImportPhotoData`と、
 /// 元画像が置かれたフォルダへのpathを受け取って、
 /// その中身を良い感じに合成して`ImportPhotoData`の中身を反映する
+/// `GUIPhotoData`はその場で更新し、存在しない画像を取り除いたグループのデータを返す
+/// `import_photo_data_lst`には追加・変更のあった画像だけを渡せば良い
+/// 画像のpathは`url_prefix`から始める
 pub fn merge_gui_photo_data_based_and_import_photo_data(
   gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
-  gui_group_data_lst: &mut HashMap<String, GUIGroupData>,
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
   import_photo_data_lst: &[ImportPhotoData],
   original_path: &str,
-) -> (HashMap<String, GUIPhotoData>, HashMap<String, GUIGroupData>) {
+  url_prefix: &str,
+) -> HashMap<String, GUIGroupData> {
   // photo_dataの更新
   for import_photo_data in import_photo_data_lst.iter() {
     let gui_photo_data_opt = gui_photo_data_lst.get(&import_photo_data.id);
@@ -316,9 +834,9 @@ pub fn merge_gui_photo_data_based_and_import_photo_data(
         GUIPhotoData {
           file_name: import_photo_data.file_name.clone(),
           photo_id: import_photo_data.id.clone(),
-          photo_src: format!("/images/normal/{}.JPG", import_photo_data.id),
-          photo_lazy_src: format!("/images/lazy/{}.JPG", import_photo_data.id),
-          alt: import_photo_data.alt.clone(),
+          photo_src: photo_src(url_prefix, &import_photo_data.id),
+          photo_lazy_src: photo_lazy_src(url_prefix, &import_photo_data.id),
+          alt: merge_alt(&import_photo_data.alt, &gui_photo_data.alt),
           location: import_photo_data.location.clone(),
           ..gui_photo_data.clone()
         }
@@ -331,12 +849,22 @@ pub fn merge_gui_photo_data_based_and_import_photo_data(
           import_photo_data.clone().file_name
         )) {
           Ok(minimal_exif_data) => GUIPhotoData {
+            // 撮影日は後の項目で取り出すので、先に推定しておく
+            session: estimate_session(
+              &import_photo_data.file_name,
+              minimal_exif_data.year.as_deref(),
+              minimal_exif_data.month.as_deref(),
+              minimal_exif_data.day.as_deref(),
+            )
+            .unwrap_or_default(),
             file_name: import_photo_data.file_name.clone(),
             photo_id: import_photo_data.id.clone(),
-            photo_src: format!("/images/normal/{}.JPG", import_photo_data.id),
-            photo_lazy_src: format!("/images/lazy/{}.JPG", import_photo_data.id),
+            photo_src: photo_src(url_prefix, &import_photo_data.id),
+            photo_lazy_src: photo_lazy_src(url_prefix, &import_photo_data.id),
             alt: import_photo_data.alt.clone(),
             title: String::default(),
+            alt_translation_lst: BTreeMap::new(),
+            title_translation_lst: BTreeMap::new(),
             year: minimal_exif_data.year.unwrap_or_default(),
             month: minimal_exif_data.month.unwrap_or_default(),
             day: minimal_exif_data.day.unwrap_or_default(),
@@ -348,15 +876,32 @@ pub fn merge_gui_photo_data_based_and_import_photo_data(
             focal_length: minimal_exif_data.focal_length.unwrap_or_default(),
             f_value: minimal_exif_data.f_value.unwrap_or_default(),
             iso: minimal_exif_data.iso.unwrap_or_default(),
+            exposure_program: minimal_exif_data.exposure_program.unwrap_or_default(),
+            max_f_value: minimal_exif_data.max_f_value.unwrap_or_default(),
             location: import_photo_data.location.clone(),
+            srcset: Vec::new(),
+            focus_area: minimal_exif_data.focus_area,
+            rating: minimal_exif_data.rating.unwrap_or_default(),
+            keywords: join_keywords(&minimal_exif_data.keywords),
+            offset_time: minimal_exif_data.offset_time.unwrap_or_default(),
+            focal_length_35mm: minimal_exif_data.focal_length_35mm.unwrap_or_default(),
+            altitude: minimal_exif_data
+              .altitude
+              .map(format_altitude)
+              .unwrap_or_default(),
+            locked: false,
+            flag: None,
+            photo_webp_src: None,
           },
           Err(_) => GUIPhotoData {
             file_name: import_photo_data.file_name.clone(),
-            photo_id: String::new(),
-            photo_src: format!("/images/normal/{}.JPG", import_photo_data.id),
-            photo_lazy_src: format!("/images/lazy/{}.JPG", import_photo_data.id),
+            photo_id: import_photo_data.id.clone(),
+            photo_src: photo_src(url_prefix, &import_photo_data.id),
+            photo_lazy_src: photo_lazy_src(url_prefix, &import_photo_data.id),
             alt: import_photo_data.alt.clone(),
             title: String::default(),
+            alt_translation_lst: BTreeMap::new(),
+            title_translation_lst: BTreeMap::new(),
             year: String::default(),
             month: String::default(),
             day: String::default(),
@@ -368,7 +913,21 @@ pub fn merge_gui_photo_data_based_and_import_photo_data(
             focal_length: String::default(),
             f_value: String::default(),
             iso: String::default(),
+            exposure_program: String::default(),
+            max_f_value: String::default(),
             location: import_photo_data.location.clone(),
+            srcset: Vec::new(),
+            focus_area: None,
+            rating: 0,
+            keywords: String::new(),
+            offset_time: String::new(),
+            focal_length_35mm: String::new(),
+            altitude: String::new(),
+            locked: false,
+            session: estimate_session(&import_photo_data.file_name, None, None, None)
+              .unwrap_or_default(),
+            flag: None,
+            photo_webp_src: None,
           },
         }
       }
@@ -379,26 +938,31 @@ pub fn merge_gui_photo_data_based_and_import_photo_data(
   // photo_id_listの中身を検索してIDが存在しているかを確認する
   // IDが無くなっていれば削除する
   let mut new_gui_group_data_lst = HashMap::new();
-  for (id, gui_group_data) in gui_group_data_lst.clone().iter() {
-    let photo_id_list = gui_group_data
+  for (id, gui_group_data) in gui_group_data_lst.iter() {
+    let (photo_id_list, removed_id_lst): (Vec<String>, Vec<String>) = gui_group_data
       .photo_id_list
       .iter()
-      .filter(|id| gui_photo_data_lst.get(*id).is_some())
       .cloned()
-      .collect::<Vec<String>>();
-    new_gui_group_data_lst.insert(
-      id.to_string(),
-      GUIGroupData {
-        photo_id_list,
-        ..gui_group_data.clone()
-      },
-    );
+      .partition(|id| gui_photo_data_lst.get(id).is_some());
+    if !removed_id_lst.is_empty() {
+      debug!(
+        "グループ「{}」から存在しない画像を取り除きました: {}",
+        id,
+        removed_id_lst.join(", ")
+      );
+    }
+    let mut new_gui_group_data = GUIGroupData {
+      photo_id_list,
+      ..gui_group_data.clone()
+    };
+    clear_invalid_cover(&mut new_gui_group_data);
+    new_gui_group_data_lst.insert(id.to_string(), new_gui_group_data);
   }
-  (gui_photo_data_lst.clone(), new_gui_group_data_lst)
+  new_gui_group_data_lst
 }
 
 /// Exifデータの中で必要なもの
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MinimalExif {
   year: Option<String>,
   month: Option<String>,
@@ -411,6 +975,104 @@ pub struct MinimalExif {
   focal_length: Option<String>,
   f_value: Option<String>,
   iso: Option<String>,
+  exposure_program: Option<String>,
+  max_f_value: Option<String>,
+  focus_area: Option<FocusArea>,
+  rating: Option<u8>,
+  keywords: Vec<String>,
+  offset_time: Option<String>,
+  focal_length_35mm: Option<String>,
+  altitude: Option<f64>,
+}
+
+/// Exifの評価（`Rating`）のタグ
+/// kamadak-exifに定義が無いので番号で指定する
+const RATING_TAG: Tag = Tag(exif::Context::Tiff, 0x4746);
+
+/// Windowsのエクスプローラーで付けたキーワード（`XPKeywords`）のタグ
+/// UTF-16LEでセミコロン区切りで記録されている
+const XP_KEYWORDS_TAG: Tag = Tag(exif::Context::Tiff, 0x9c9e);
+
+/// Exifからキーワードを取り出す
+fn parse_exif_keywords(exif: &exif::Exif) -> Vec<String> {
+  let bytes = match exif.get_field(XP_KEYWORDS_TAG, In::PRIMARY) {
+    Some(exif::Field {
+      value: Value::Byte(ref bytes),
+      ..
+    }) => bytes,
+    _ => return Vec::new(),
+  };
+  let utf16 = bytes
+    .chunks_exact(2)
+    .map(|c| u16::from_le_bytes([c[0], c[1]]))
+    .take_while(|c| *c != 0)
+    .collect::<Vec<u16>>();
+  parse_keywords(&String::from_utf16_lossy(&utf16).replace(';', ","))
+}
+
+/// カンマ区切りのキーワードを分割する
+/// 前後の空白を取り除き、空のものと重複しているものは除く
+pub fn parse_keywords(keywords: &str) -> Vec<String> {
+  let mut keyword_lst: Vec<String> = Vec::new();
+  for keyword in keywords.split([',', '、']) {
+    let keyword = keyword.trim();
+    if !keyword.is_empty() && !keyword_lst.iter().any(|k| k == keyword) {
+      keyword_lst.push(keyword.to_string());
+    }
+  }
+  keyword_lst
+}
+
+/// キーワードをカンマ区切りの文字列にする
+pub fn join_keywords(keyword_lst: &[String]) -> String {
+  keyword_lst.join(", ")
+}
+
+/// 評価として扱える値だけを取り出す
+/// 0や範囲外の値（不採用を表す-1など）は`None`にする
+fn valid_rating(value: i64) -> Option<u8> {
+  if 1 <= value && value <= MAX_RATING as i64 {
+    Some(value as u8)
+  } else {
+    None
+  }
+}
+
+/// Exifの解析に使うスレッド数の既定値
+/// CPUのコア数を取得できない場合は1になる
+pub fn default_exif_thread_count() -> usize {
+  std::thread::available_parallelism()
+    .map(|n| n.get())
+    .unwrap_or(1)
+}
+
+/// 複数のファイルのExifを`thread_count`個のスレッドで並列に解析する
+/// 結果は`path_lst`と同じ順番で返す
+pub fn parse_exif_data_parallel(
+  path_lst: &[String],
+  thread_count: usize,
+) -> Vec<Result<MinimalExif>> {
+  if path_lst.is_empty() {
+    return Vec::new();
+  }
+  let chunk_size = path_lst.len().div_ceil(thread_count.max(1));
+  std::thread::scope(|scope| {
+    let handle_lst = path_lst
+      .chunks(chunk_size)
+      .map(|chunk| {
+        scope.spawn(move || {
+          chunk
+            .iter()
+            .map(|path| parse_exif_data(path))
+            .collect::<Vec<Result<MinimalExif>>>()
+        })
+      })
+      .collect::<Vec<_>>();
+    handle_lst
+      .into_iter()
+      .flat_map(|handle| handle.join().unwrap())
+      .collect()
+  })
 }
 
 /// Exifファイルを解析して必要なデータを取り出す
@@ -428,7 +1090,7 @@ pub fn parse_exif_data(path: &str) -> Result<MinimalExif> {
         (
           Some(dt.year.to_string()),
           Some(dt.month.to_string()),
-          Some(dt.hour.to_string()),
+          Some(dt.day.to_string()),
           Some(dt.hour.to_string()),
           Some(dt.minute.to_string()),
         )
@@ -450,9 +1112,18 @@ pub fn parse_exif_data(path: &str) -> Result<MinimalExif> {
     _ => None,
   };
   // シャッタースピード
+  // 露光時間が記録されていればそれを使い、無ければAPEX値のシャッタースピードを使う
   let time = exif
-    .get_field(Tag::ShutterSpeedValue, In::PRIMARY)
-    .map(|field| field.value.display_as(Tag::ShutterSpeedValue).to_string());
+    .get_field(Tag::ExposureTime, In::PRIMARY)
+    .and_then(|field| match field.value {
+      Value::Rational(ref vec) if !vec.is_empty() => format_exposure_time(vec[0]),
+      _ => None,
+    })
+    .or_else(|| {
+      exif
+        .get_field(Tag::ShutterSpeedValue, In::PRIMARY)
+        .map(|field| field.value.display_as(Tag::ShutterSpeedValue).to_string())
+    });
   // 焦点距離
   let focal_length = exif
     .get_field(Tag::FocalLength, In::PRIMARY)
@@ -462,9 +1133,29 @@ pub fn parse_exif_data(path: &str) -> Result<MinimalExif> {
     .get_field(Tag::FNumber, In::PRIMARY)
     .map(|field| field.value.display_as(Tag::FNumber).to_string());
   // ISO感度
-  let iso = exif
-    .get_field(Tag::ISOSpeed, In::PRIMARY)
-    .map(|field| field.value.display_as(Tag::ISOSpeed).to_string());
+  // ISOSpeedが無いカメラが多いので、PhotographicSensitivity（旧ISOSpeedRatings）も見る
+  let iso = [Tag::ISOSpeed, Tag::PhotographicSensitivity]
+    .iter()
+    .find_map(|tag| {
+      exif
+        .get_field(*tag, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+    })
+    .map(|iso| iso.to_string());
+  // 露出モード
+  let exposure_program = exif
+    .get_field(Tag::ExposureProgram, In::PRIMARY)
+    .and_then(|field| field.value.get_uint(0))
+    .map(exposure_program_label);
+  // 開放F値
+  // APEX値で記録されているのでF値に変換する
+  let max_f_value = exif
+    .get_field(Tag::MaxApertureValue, In::PRIMARY)
+    .and_then(|field| match field.value {
+      Value::Rational(ref vec) if !vec.is_empty() => Some(vec[0].to_f64()),
+      _ => None,
+    })
+    .map(|apex| format!("{:.1}", 2f64.powf(apex / 2.0)));
   let v = MinimalExif {
     year,
     month,
@@ -477,118 +1168,3098 @@ pub fn parse_exif_data(path: &str) -> Result<MinimalExif> {
     focal_length,
     f_value,
     iso,
+    exposure_program,
+    max_f_value,
+    focus_area: parse_focus_area(&exif),
+    rating: exif
+      .get_field(RATING_TAG, In::PRIMARY)
+      .and_then(|field| field.value.get_uint(0))
+      .and_then(|value| valid_rating(value as i64)),
+    keywords: parse_exif_keywords(&exif),
+    offset_time: exif
+      .get_field(Tag::OffsetTimeOriginal, In::PRIMARY)
+      .and_then(|field| match field.value {
+        Value::Ascii(ref vec) if !vec.is_empty() => str::from_utf8(&vec[0]).ok(),
+        _ => None,
+      })
+      .and_then(parse_offset_time)
+      .map(|offset| offset.to_string()),
+    // 0は不明を表すので記録しない
+    focal_length_35mm: exif
+      .get_field(Tag::FocalLengthIn35mmFilm, In::PRIMARY)
+      .and_then(|field| field.value.get_uint(0))
+      .filter(|value| *value > 0)
+      .map(|value| value.to_string()),
+    altitude: parse_exif_altitude(&exif),
   };
   Ok(v)
 }
 
-/// 出力する`group_data.json`ファイルに書き出す内容
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct GroupData {
-  pub group_id: String,
-  pub photo_id_list: Vec<String>,
-  pub year: Option<String>,
-  pub month: Option<String>,
-  pub day: Option<String>,
-  pub hour: Option<String>,
-  pub minutes: Option<String>,
-  pub title: String,
-  pub description: String,
-  pub location: Option<String>,
+/// Exifから撮影地点の標高を取り出す
+/// `GPSAltitudeRef`が1の場合は海面下なので負の値にする
+fn parse_exif_altitude(exif: &exif::Exif) -> Option<f64> {
+  let altitude = exif
+    .get_field(Tag::GPSAltitude, In::PRIMARY)
+    .and_then(|field| match field.value {
+      Value::Rational(ref vec) if !vec.is_empty() && vec[0].denom != 0 => Some(vec[0].to_f64()),
+      _ => None,
+    })?;
+  let is_below_sea_level = exif
+    .get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+    .and_then(|field| field.value.get_uint(0))
+    == Some(1);
+  Some(if is_below_sea_level {
+    -altitude
+  } else {
+    altitude
+  })
 }
 
-/// `GroupData`をGUIで扱うためのデータ構造
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct GUIGroupData {
-  pub group_id: String,
-  pub photo_id_list: Vec<String>,
-  pub year: String,
-  pub month: String,
-  pub day: String,
-  pub hour: String,
-  pub minutes: String,
-  pub title: String,
-  pub description: String,
-  pub location: String,
+/// 画像ファイルのExifに記録されている全てのフィールドを、タグ名と表示用の値の組で取り出す
+/// サムネイル用のフィールドは除く
+pub fn dump_all_exif(path: &str) -> Result<Vec<(String, String)>> {
+  let file = File::open(path)?;
+  let mut bufreader = BufReader::new(&file);
+  let exif = exif::Reader::new().read_from_container(&mut bufreader)?;
+  Ok(
+    exif
+      .fields()
+      .filter(|field| field.ifd_num == In::PRIMARY)
+      .map(|field| {
+        (
+          field.tag.to_string(),
+          field.display_value().with_unit(&exif).to_string(),
+        )
+      })
+      .collect(),
+  )
 }
 
-pub fn make_dummy_gui_group_data() -> GUIGroupData {
-  GUIGroupData {
-    group_id: String::new(),
-    photo_id_list: Vec::new(),
-    year: String::new(),
-    month: String::new(),
-    day: String::new(),
-    hour: String::new(),
-    minutes: String::new(),
-    title: String::new(),
-    description: String::new(),
-    location: String::new(),
+/// Exifの被写体領域（`SubjectArea`・`SubjectLocation`）からピント位置を取り出す
+/// メーカーノートに記録されたAFポイントには対応していないので、
+/// 被写体領域が無い場合は`None`になる
+fn parse_focus_area(exif: &exif::Exif) -> Option<FocusArea> {
+  let get_uint = |tag| {
+    exif
+      .get_field(tag, In::PRIMARY)
+      .and_then(|field| field.value.get_uint(0))
+  };
+  let image_width = get_uint(Tag::PixelXDimension)?;
+  let image_height = get_uint(Tag::PixelYDimension)?;
+  let value = &exif
+    .get_field(Tag::SubjectArea, In::PRIMARY)
+    .or_else(|| exif.get_field(Tag::SubjectLocation, In::PRIMARY))?
+    .value;
+  let v = (0..4)
+    .map_while(|i| value.get_uint(i))
+    .collect::<Vec<u32>>();
+  // 値の数によって、点・円（直径）・長方形のどれで記録されているかが変わる
+  let (x, y, width, height) = match v.as_slice() {
+    [x, y] => (*x, *y, 0, 0),
+    [x, y, diameter] => (*x, *y, *diameter, *diameter),
+    [x, y, width, height] => (*x, *y, *width, *height),
+    _ => return None,
+  };
+  if image_width == 0 || image_height == 0 || image_width < x || image_height < y {
+    return None;
   }
+  Some(FocusArea {
+    x,
+    y,
+    width,
+    height,
+    image_width,
+    image_height,
+  })
 }
 
-pub fn gui_group_data_to_group_data(gui_group_data: GUIGroupData) -> GroupData {
-  GroupData {
-    group_id: gui_group_data.group_id,
-    photo_id_list: gui_group_data.photo_id_list,
-    year: if gui_group_data.year.is_empty() {
-      None
-    } else {
-      Some(gui_group_data.year)
-    },
-    month: if gui_group_data.month.is_empty() {
-      None
-    } else {
-      Some(gui_group_data.month)
-    },
-    day: if gui_group_data.day.is_empty() {
-      None
-    } else {
-      Some(gui_group_data.day)
-    },
-    hour: if gui_group_data.hour.is_empty() {
-      None
-    } else {
-      Some(gui_group_data.hour)
-    },
-    minutes: if gui_group_data.minutes.is_empty() {
-      None
-    } else {
-      Some(gui_group_data.minutes)
-    },
-    title: gui_group_data.title,
-    description: gui_group_data.description,
-    location: if gui_group_data.location.is_empty() {
-      None
-    } else {
-      Some(gui_group_data.location)
-    },
+/// 入力JSONの代替テキストが空欄の場合は、XMPサイドカーファイルなどから補完した既存の値を残す
+fn merge_alt(import_alt: &str, alt: &str) -> String {
+  if import_alt.is_empty() {
+    alt.to_string()
+  } else {
+    import_alt.to_string()
   }
 }
 
-pub fn group_data_to_gui_group_data(group_data: GroupData) -> GUIGroupData {
-  GUIGroupData {
-    group_id: group_data.group_id,
-    photo_id_list: group_data.photo_id_list,
-    year: group_data.year.unwrap_or_default(),
-    month: group_data.month.unwrap_or_default(),
-    day: group_data.day.unwrap_or_default(),
-    hour: group_data.hour.unwrap_or_default(),
-    minutes: group_data.minutes.unwrap_or_default(),
-    title: group_data.title,
-    description: group_data.description,
-    location: group_data.location.unwrap_or_default(),
+/// XMPサイドカーファイルから読み取ったデータ
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct XmpSidecar {
+  /// `dc:title`
+  pub title: Option<String>,
+  /// `dc:description`
+  pub description: Option<String>,
+  /// `xmp:Rating`
+  pub rating: Option<u8>,
+  /// `dc:subject`
+  pub keywords: Vec<String>,
+}
+
+/// XMPの文字列中の実体参照を元に戻す
+fn unescape_xml(s: &str) -> String {
+  s.replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&quot;", "\"")
+    .replace("&apos;", "'")
+    .replace("&amp;", "&")
+}
+
+/// XMPの要素の中身の文字列を取り出す
+/// `rdf:Alt`などで囲まれている場合は最初の`rdf:li`の中身を使う
+fn xmp_element_text(xml: &str, name: &str) -> Option<String> {
+  let start = xml.find(&format!("<{}", name))?;
+  let start = start + xml[start..].find('>')? + 1;
+  let end = start + xml[start..].find(&format!("</{}>", name))?;
+  let mut inner = &xml[start..end];
+  if let Some(li) = inner.find("<rdf:li") {
+    let li_start = li + inner[li..].find('>')? + 1;
+    let li_end = li_start + inner[li_start..].find("</rdf:li>")?;
+    inner = &inner[li_start..li_end];
+  }
+  let text = unescape_xml(inner.trim());
+  if text.is_empty() || text.contains('<') {
+    None
+  } else {
+    Some(text)
   }
 }
 
-/// jsonファイルのpathからデータを構築する
-pub fn load_group_data_from_work_directory(work_directory: &str) -> Result<Vec<GroupData>> {
-  let file_path = format!("{}/group_data.json", work_directory);
-  match File::open(file_path) {
-    Ok(file) => {
-      let reader = BufReader::new(file);
-      let data: Vec<GroupData> = serde_json::from_reader(reader)?;
+/// `rdf:Bag`などで並べられた要素の中身の文字列を全て取り出す
+fn xmp_element_list(xml: &str, name: &str) -> Vec<String> {
+  let inner = xml.find(&format!("<{}", name)).and_then(|start| {
+    let start = start + xml[start..].find('>')? + 1;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..end])
+  });
+  let mut rest = match inner {
+    Some(inner) => inner,
+    None => return Vec::new(),
+  };
+  let mut lst = Vec::new();
+  while let Some(li) = rest.find("<rdf:li") {
+    let li_start = match rest[li..].find('>') {
+      Some(i) => li + i + 1,
+      None => break,
+    };
+    let li_end = match rest[li_start..].find("</rdf:li>") {
+      Some(i) => li_start + i,
+      None => break,
+    };
+    lst.push(unescape_xml(rest[li_start..li_end].trim()));
+    rest = &rest[li_end..];
+  }
+  lst
+}
+
+/// XMPの属性の値を取り出す
+/// `xmp:Rating="3"`のような属性と`<xmp:Rating>3</xmp:Rating>`のような要素の両方に対応する
+fn xmp_attribute(xml: &str, name: &str) -> Option<String> {
+  let pattern = format!("{}=", name);
+  match xml.find(&pattern) {
+    Some(start) => {
+      let rest = &xml[start + pattern.len()..];
+      let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+      let rest = &rest[1..];
+      let end = rest.find(quote)?;
+      Some(unescape_xml(&rest[..end]))
+    }
+    None => xmp_element_text(xml, name),
+  }
+}
+
+/// 画像ファイルに対応するXMPサイドカーファイルを読み取る
+/// `IMG_0001.JPG.xmp`と`IMG_0001.xmp`の両方の名前を探す
+/// ファイルが無い・読み取れない場合は`None`になる
+pub fn parse_xmp_sidecar(image_path: &str) -> Option<XmpSidecar> {
+  let path = Path::new(image_path);
+  let xml = std::fs::read_to_string(format!("{}.xmp", image_path))
+    .or_else(|_| std::fs::read_to_string(path.with_extension("xmp")))
+    .ok()?;
+  if !xml.contains("x:xmpmeta") && !xml.contains("rdf:RDF") {
+    return None;
+  }
+  Some(XmpSidecar {
+    title: xmp_element_text(&xml, "dc:title"),
+    description: xmp_element_text(&xml, "dc:description"),
+    rating: xmp_attribute(&xml, "xmp:Rating")
+      .and_then(|s| s.trim().parse().ok())
+      .and_then(valid_rating),
+    keywords: parse_keywords(&xmp_element_list(&xml, "dc:subject").join(",")),
+  })
+}
+
+/// XMPサイドカーファイルから読み取ったデータで、写真データの空欄になっている項目だけを埋める
+/// 埋めた項目の数を返す
+pub fn fill_empty_fields_from_xmp(photo_data: &mut GUIPhotoData, xmp: XmpSidecar) -> usize {
+  let mut count = 0;
+  let mut fill = |field: &mut String, value: Option<String>| {
+    if let (true, Some(value)) = (field.is_empty(), value) {
+      *field = value;
+      count += 1;
+    }
+  };
+  fill(&mut photo_data.title, xmp.title);
+  fill(&mut photo_data.alt, xmp.description);
+  if let (0, Some(rating)) = (photo_data.rating, xmp.rating) {
+    photo_data.rating = rating;
+    count += 1;
+  }
+  if photo_data.keywords.is_empty() && !xmp.keywords.is_empty() {
+    photo_data.keywords = join_keywords(&xmp.keywords);
+    count += 1;
+  }
+  count
+}
+
+/// 新しく読み込んだ写真だけ、XMPサイドカーファイルがある場合に空欄の項目を補完する
+/// 保存済みの写真は、ユーザーが空欄に戻した項目を埋め直さないように対象外にする
+pub fn fill_new_photos_from_xmp(
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  saved_photo_data_lst: &HashMap<String, PhotoData>,
+  original_dir: &str,
+) {
+  for gui_photo_data in gui_photo_data_lst
+    .values_mut()
+    .filter(|photo_data| !saved_photo_data_lst.contains_key(&photo_data.photo_id))
+  {
+    let image_path = format!("{}/{}", original_dir, gui_photo_data.file_name);
+    if let Some(xmp) = parse_xmp_sidecar(&image_path) {
+      fill_empty_fields_from_xmp(gui_photo_data, xmp);
+    }
+  }
+}
+
+/// Exifから読み取ったデータで、写真データの空欄になっている項目だけを埋める
+/// 既に入力されている値は上書きしない
+/// 埋めた項目の数を返す
+pub fn fill_empty_fields_from_exif(photo_data: &mut GUIPhotoData, exif: MinimalExif) -> usize {
+  let mut count = 0;
+  let mut fill = |field: &mut String, value: Option<String>| {
+    if let (true, Some(value)) = (field.is_empty(), value) {
+      *field = value;
+      count += 1;
+    }
+  };
+  fill(&mut photo_data.year, exif.year);
+  fill(&mut photo_data.month, exif.month);
+  fill(&mut photo_data.day, exif.day);
+  fill(&mut photo_data.hour, exif.hour);
+  fill(&mut photo_data.minutes, exif.minutes);
+  fill(&mut photo_data.body, exif.body);
+  fill(&mut photo_data.lens, exif.lens);
+  fill(&mut photo_data.time, exif.time);
+  fill(&mut photo_data.focal_length, exif.focal_length);
+  fill(&mut photo_data.f_value, exif.f_value);
+  fill(&mut photo_data.iso, exif.iso);
+  fill(&mut photo_data.exposure_program, exif.exposure_program);
+  fill(&mut photo_data.max_f_value, exif.max_f_value);
+  fill(&mut photo_data.offset_time, exif.offset_time);
+  fill(&mut photo_data.focal_length_35mm, exif.focal_length_35mm);
+  fill(&mut photo_data.altitude, exif.altitude.map(format_altitude));
+  if let (None, Some(focus_area)) = (photo_data.focus_area, exif.focus_area) {
+    photo_data.focus_area = Some(focus_area);
+    count += 1;
+  }
+  if let (0, Some(rating)) = (photo_data.rating, exif.rating) {
+    photo_data.rating = rating;
+    count += 1;
+  }
+  if photo_data.keywords.is_empty() && !exif.keywords.is_empty() {
+    photo_data.keywords = join_keywords(&exif.keywords);
+    count += 1;
+  }
+  count
+}
+
+/// Exifの`ExposureProgram`の値を表示用の名前にする
+pub fn exposure_program_label(value: u32) -> String {
+  match value {
+    1 => "マニュアル",
+    2 => "プログラムAE",
+    3 => "絞り優先",
+    4 => "シャッター優先",
+    5 => "クリエイティブ",
+    6 => "アクション",
+    7 => "ポートレート",
+    8 => "風景",
+    _ => "不明",
+  }
+  .to_string()
+}
+
+/// シャッタースピードの文字列を秒数にする
+/// `1/250`・`2.5`・`2.5 s`のような形式に対応する
+pub fn parse_exposure_seconds(time: &str) -> Option<f64> {
+  let time = time
+    .trim()
+    .trim_end_matches('s')
+    .trim_end_matches('秒')
+    .trim();
+  match time.split_once('/') {
+    Some((numerator, denominator)) => {
+      let numerator: f64 = numerator.trim().parse().ok()?;
+      let denominator: f64 = denominator.trim().parse().ok()?;
+      if denominator == 0.0 {
+        None
+      } else {
+        Some(numerator / denominator)
+      }
+    }
+    None => time.parse().ok(),
+  }
+}
+
+/// F値の文字列を数値にする
+/// `f/2.8`・`F2.8`・`2.8`のような形式に対応する
+pub fn parse_f_value(f_value: &str) -> Option<f64> {
+  f_value
+    .trim()
+    .trim_start_matches("f/")
+    .trim_start_matches("F/")
+    .trim_start_matches(['f', 'F'])
+    .trim()
+    .parse()
+    .ok()
+}
+
+/// 標高の文字列を数値にする
+/// `1234.5m`のように単位が付いていても読み取る
+pub fn parse_altitude(altitude: &str) -> Option<f64> {
+  altitude
+    .trim()
+    .trim_end_matches('m')
+    .trim()
+    .parse()
+    .ok()
+    .filter(|value: &f64| value.is_finite())
+}
+
+/// 標高をGUIで表示する文字列にする
+pub fn format_altitude(altitude: f64) -> String {
+  format_number(altitude)
+}
+
+/// 数値を小数点以下1桁に丸め、不要な`.0`を付けない文字列にする
+fn format_number(value: f64) -> String {
+  format!("{}", (value * 10.0).round() / 10.0)
+}
+
+/// Exifの露光時間を`1/250 s`や`2.5 s`のような形式にする
+/// 1秒未満の場合は`1/N s`の形にし、1秒以上の場合は秒数をそのまま書く
+/// 分母が0の場合などは`None`になる
+pub fn format_exposure_time(exposure_time: exif::Rational) -> Option<String> {
+  let seconds = exposure_time.to_f64();
+  if !seconds.is_finite() || seconds <= 0.0 {
+    return None;
+  }
+  if seconds < 1.0 {
+    Some(format!("1/{} s", (1.0 / seconds).round()))
+  } else {
+    Some(format!("{} s", format_number(seconds)))
+  }
+}
+
+/// F値を`2.8`のような数値だけの形式にする
+/// 解釈できない場合は`None`になる
+pub fn normalize_f_value(f_value: &str) -> Option<String> {
+  parse_f_value(f_value)
+    .filter(|v| v.is_finite())
+    .map(format_number)
+}
+
+/// 焦点距離を`24`のような数値だけの形式にする
+/// `24mm`・`24 mm`のような形式に対応する
+pub fn normalize_focal_length(focal_length: &str) -> Option<String> {
+  focal_length
+    .trim()
+    .trim_end_matches("mm")
+    .trim()
+    .parse::<f64>()
+    .ok()
+    .filter(|v| v.is_finite())
+    .map(format_number)
+}
+
+/// シャッタースピードを`1/250`や`2`のような単位の無い形式にする
+/// `1/250s`・`1/250 秒`・`2s`のような形式に対応する
+pub fn normalize_time(time: &str) -> Option<String> {
+  let trimmed = time
+    .trim()
+    .trim_end_matches('s')
+    .trim_end_matches('秒')
+    .trim();
+  parse_exposure_seconds(trimmed)?;
+  match trimmed.split_once('/') {
+    Some((numerator, denominator)) => Some(format!(
+      "{}/{}",
+      format_number(numerator.trim().parse().ok()?),
+      format_number(denominator.trim().parse().ok()?)
+    )),
+    None => Some(format_number(trimmed.parse().ok()?)),
+  }
+}
+
+/// ISO感度を`100`のような整数だけの形式にする
+/// `ISO100`・`ISO 100`のような形式に対応する
+pub fn normalize_iso(iso: &str) -> Option<String> {
+  iso
+    .trim()
+    .trim_start_matches("ISO")
+    .trim_start_matches("iso")
+    .trim()
+    .parse::<u32>()
+    .ok()
+    .map(|v| v.to_string())
+}
+
+/// 写真の撮影データの表記を揃える
+/// 空欄や解釈できない値はそのままにする
+/// 書き換えた項目の数を返す
+pub fn normalize_photo_metadata(photo_data: &mut GUIPhotoData) -> usize {
+  let mut count = 0;
+  let mut normalize = |field: &mut String, f: fn(&str) -> Option<String>| {
+    if let Some(value) = f(field) {
+      if *field != value {
+        *field = value;
+        count += 1;
+      }
+    }
+  };
+  normalize(&mut photo_data.f_value, normalize_f_value);
+  normalize(&mut photo_data.max_f_value, normalize_f_value);
+  normalize(&mut photo_data.focal_length, normalize_focal_length);
+  normalize(&mut photo_data.time, normalize_time);
+  normalize(&mut photo_data.iso, normalize_iso);
+  count
+}
+
+/// 全ての写真の撮影データの表記を揃える
+/// ロックされている写真は変更しない
+/// 書き換えた項目の数と、実際に値が変わった写真の変更前のデータを返す
+pub fn normalize_all_photo_metadata(
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+) -> (usize, Vec<GUIPhotoData>) {
+  let mut count = 0;
+  let mut before_lst = Vec::new();
+  for photo_data in gui_photo_data_lst.values_mut() {
+    if photo_data.locked {
+      continue;
+    }
+    let before = photo_data.clone();
+    let changed = normalize_photo_metadata(photo_data);
+    if changed > 0 {
+      count += changed;
+      before_lst.push(before);
+    }
+  }
+  (count, before_lst)
+}
+
+/// 写真の撮影日時を取り出す
+/// 年月日と時分が全て入力されていて、日時として正しい場合だけ値を返す
+pub fn photo_datetime(photo_data: &GUIPhotoData) -> Option<NaiveDateTime> {
+  let year = photo_data.year.trim().parse().ok()?;
+  let month = photo_data.month.trim().parse().ok()?;
+  let day = photo_data.day.trim().parse().ok()?;
+  let hour = photo_data.hour.trim().parse().ok()?;
+  let minutes = photo_data.minutes.trim().parse().ok()?;
+  NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(hour, minutes, 0)
+}
+
+/// 複数の写真にまとめて適用する変更
+/// `None`の項目は変更しない
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PhotoDataPatch {
+  pub location: Option<String>,
+  pub body: Option<String>,
+  pub lens: Option<String>,
+}
+
+impl PhotoDataPatch {
+  pub fn is_empty(&self) -> bool {
+    self.location.is_none() && self.body.is_none() && self.lens.is_none()
+  }
+}
+
+/// 指定したIDの写真に変更をまとめて適用する
+/// 実際に値が変わった写真について、変更前のデータを返す
+pub fn apply_photo_data_patch(
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  photo_id_lst: &[String],
+  patch: &PhotoDataPatch,
+) -> Vec<GUIPhotoData> {
+  let mut before_lst = Vec::new();
+  for photo_id in photo_id_lst.iter() {
+    // ロックされている写真は変更しない
+    let photo_data = gui_photo_data_lst
+      .get_mut(photo_id)
+      .filter(|photo_data| !photo_data.locked);
+    if let Some(photo_data) = photo_data {
+      let before = photo_data.clone();
+      if let Some(location) = &patch.location {
+        photo_data.location = location.clone();
+      }
+      if let Some(body) = &patch.body {
+        photo_data.body = body.clone();
+      }
+      if let Some(lens) = &patch.lens {
+        photo_data.lens = lens.clone();
+      }
+      if *photo_data != before {
+        before_lst.push(before);
+      }
+    }
+  }
+  before_lst
+}
+
+/// 取り込んだ写真の撮影セッションを推定する
+/// ファイルが元画像のフォルダの中のフォルダに置かれている場合はその親フォルダ名を、
+/// そうでない場合は撮影日（`2022-08-01`の形式）をセッション名にする
+/// どちらも分からない場合は`None`を返す
+pub fn estimate_session(
+  file_name: &str,
+  year: Option<&str>,
+  month: Option<&str>,
+  day: Option<&str>,
+) -> Option<String> {
+  let folder_name = Path::new(file_name)
+    .parent()
+    .and_then(|parent| parent.file_name())
+    .map(|name| name.to_string_lossy().to_string())
+    .filter(|name| !name.is_empty());
+  if folder_name.is_some() {
+    return folder_name;
+  }
+  let parse = |value: Option<&str>| value.and_then(|v| v.trim().parse::<u32>().ok());
+  match (parse(year), parse(month), parse(day)) {
+    (Some(year), Some(month), Some(day)) => Some(format!("{:04}-{:02}-{:02}", year, month, day)),
+    _ => None,
+  }
+}
+
+/// 写真に設定されている撮影セッションの名前を重複なく名前順に返す
+/// セッションが設定されていない写真は含めない
+pub fn session_lst<'a>(photo_data_lst: impl Iterator<Item = &'a GUIPhotoData>) -> Vec<String> {
+  photo_data_lst
+    .map(|photo_data| photo_data.session.trim())
+    .filter(|session| !session.is_empty())
+    .map(|session| session.to_string())
+    .collect::<BTreeSet<String>>()
+    .into_iter()
+    .collect()
+}
+
+/// 撮影セッションの名前が`from`の写真のセッション名を`to`にまとめて変更する
+/// ロックされている写真は変更しない
+/// 実際に値が変わった写真について、変更前のデータを返す
+pub fn rename_session(
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  from: &str,
+  to: &str,
+) -> Vec<GUIPhotoData> {
+  let mut before_lst = Vec::new();
+  for photo_data in gui_photo_data_lst.values_mut() {
+    if !photo_data.locked && photo_data.session.trim() == from && from != to.trim() {
+      before_lst.push(photo_data.clone());
+      photo_data.session = to.trim().to_string();
+    }
+  }
+  before_lst
+}
+
+/// ロックされていない写真のIDだけを並び順のまま返す
+pub fn unlocked_photo_id_lst(
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+) -> Vec<String> {
+  photo_id_lst
+    .iter()
+    .filter(|id| {
+      gui_photo_data_lst
+        .get(*id)
+        .map(|photo_data| !photo_data.locked)
+        .unwrap_or(false)
+    })
+    .cloned()
+    .collect()
+}
+
+/// 採用のフラグ
+pub const FLAG_PICK: &str = "pick";
+/// 不採用のフラグ
+pub const FLAG_REJECT: &str = "reject";
+
+/// フラグの表示名
+/// 知らない値は保留として扱う
+pub fn flag_label(flag: Option<&str>) -> &'static str {
+  match flag {
+    Some(FLAG_PICK) => "採用",
+    Some(FLAG_REJECT) => "不採用",
+    _ => "保留",
+  }
+}
+
+/// 不採用のフラグが付いているかどうか
+pub fn is_rejected(flag: Option<&str>) -> bool {
+  flag == Some(FLAG_REJECT)
+}
+
+/// フラグごとの写真の枚数を、採用・不採用・保留の順に返す
+pub fn count_by_flag<'a>(
+  photo_data_lst: impl Iterator<Item = &'a GUIPhotoData>,
+) -> (usize, usize, usize) {
+  photo_data_lst.fold(
+    (0, 0, 0),
+    |(pick, reject, unflagged), photo_data| match photo_data.flag.as_deref() {
+      Some(FLAG_PICK) => (pick + 1, reject, unflagged),
+      Some(FLAG_REJECT) => (pick, reject + 1, unflagged),
+      _ => (pick, reject, unflagged + 1),
+    },
+  )
+}
+
+/// 指定したIDの写真にフラグを付ける（`None`の場合は保留に戻す）
+/// 実際に値が変わった写真について、変更前のデータを返す
+pub fn set_flag(
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  photo_id_lst: &[String],
+  flag: Option<&str>,
+) -> Vec<GUIPhotoData> {
+  let mut before_lst = Vec::new();
+  for photo_id in photo_id_lst.iter() {
+    // ロックされている写真は変更しない
+    let photo_data = gui_photo_data_lst
+      .get_mut(photo_id)
+      .filter(|photo_data| !photo_data.locked);
+    if let Some(photo_data) = photo_data {
+      if photo_data.flag.as_deref() != flag {
+        before_lst.push(photo_data.clone());
+        photo_data.flag = flag.map(str::to_string);
+      }
+    }
+  }
+  before_lst
+}
+
+/// 指定したIDの写真のキーワードに、カンマ区切りの`keywords`を追加する
+/// 既にあるキーワードは重ねない
+/// 実際に値が変わった写真について、変更前のデータを返す
+pub fn add_keywords(
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  photo_id_lst: &[String],
+  keywords: &str,
+) -> Vec<GUIPhotoData> {
+  let new_keyword_lst = parse_keywords(keywords);
+  let mut before_lst = Vec::new();
+  for photo_id in photo_id_lst.iter() {
+    // ロックされている写真は変更しない
+    let photo_data = gui_photo_data_lst
+      .get_mut(photo_id)
+      .filter(|photo_data| !photo_data.locked);
+    if let Some(photo_data) = photo_data {
+      let mut keyword_lst = parse_keywords(&photo_data.keywords);
+      let len = keyword_lst.len();
+      for keyword in new_keyword_lst.iter() {
+        if !keyword_lst.contains(keyword) {
+          keyword_lst.push(keyword.clone());
+        }
+      }
+      if keyword_lst.len() != len {
+        before_lst.push(photo_data.clone());
+        photo_data.keywords = join_keywords(&keyword_lst);
+      }
+    }
+  }
+  before_lst
+}
+
+/// 画像の特徴と撮影データから付けると良さそうなタグを提案する
+/// どれも説明できる単純な規則によるもので、次の通り
+/// - 縦構図・横構図・正方形：アスペクト比
+/// - パノラマ：幅が高さの2倍以上
+/// - 夜景：暗い画像で、夜の時間帯か1/15秒以上の露光時間で撮影したもの
+/// - 空：明るく、平均色の青が赤と緑よりはっきり強い
+/// - 夕景：平均色の赤が緑と青よりはっきり強く、夕方の時間帯に撮影したもの
+/// - 緑：平均色の緑が赤と青よりはっきり強い
+/// - 長秒露光：1秒以上の露光時間
+/// - 広角・望遠：35mm判換算の焦点距離が24mm以下・135mm以上
+///
+/// 既にキーワードにあるタグは提案しない
+pub fn suggest_auto_tags(
+  gui_photo_data: &GUIPhotoData,
+  image_features: &image::ImageFeatures,
+) -> Vec<String> {
+  let mut tag_lst = Vec::new();
+  let aspect_ratio = image_features.aspect_ratio;
+  if aspect_ratio >= 2.0 {
+    tag_lst.push("パノラマ");
+  } else if aspect_ratio > 1.05 {
+    tag_lst.push("横構図");
+  } else if aspect_ratio < 0.95 {
+    tag_lst.push("縦構図");
+  } else {
+    tag_lst.push("正方形");
+  }
+  let hour = gui_photo_data.hour.trim().parse::<u32>().ok();
+  let exposure = parse_exposure_seconds(&gui_photo_data.time);
+  let is_night_hour = hour.map(|h| !(5..19).contains(&h)).unwrap_or(false);
+  let is_evening_hour = hour.map(|h| (16..20).contains(&h)).unwrap_or(false);
+  if image_features.brightness < 0.25
+    && (is_night_hour || exposure.map(|e| e >= 1.0 / 15.0).unwrap_or(false))
+  {
+    tag_lst.push("夜景");
+  }
+  let [r, g, b] = image_features.average_color.map(|c| c as i32);
+  if image_features.brightness > 0.4 && b > r + 20 && b > g + 10 {
+    tag_lst.push("空");
+  }
+  if is_evening_hour && r > g + 30 && r > b + 40 {
+    tag_lst.push("夕景");
+  }
+  if g > r + 15 && g > b + 15 {
+    tag_lst.push("緑");
+  }
+  if exposure.map(|e| e >= 1.0).unwrap_or(false) {
+    tag_lst.push("長秒露光");
+  }
+  if let Ok(focal_length) = gui_photo_data.focal_length_35mm.trim().parse::<f64>() {
+    if focal_length <= 24.0 {
+      tag_lst.push("広角");
+    } else if focal_length >= 135.0 {
+      tag_lst.push("望遠");
+    }
+  }
+  let keyword_lst = parse_keywords(&gui_photo_data.keywords);
+  tag_lst
+    .into_iter()
+    .filter(|tag| !keyword_lst.iter().any(|k| k == tag))
+    .map(|tag| tag.to_string())
+    .collect()
+}
+
+/// 撮影場所・撮影日・機材から代替テキストの雛形を作る
+/// 例：`東京駅で2022年8月1日にX-T4で撮影した写真`
+/// 使える情報が何も無い場合は空になる
+pub fn suggest_alt(photo_data: &GUIPhotoData) -> String {
+  let location = photo_data.location.trim();
+  let date = match (
+    photo_data.year.trim(),
+    photo_data.month.trim(),
+    photo_data.day.trim(),
+  ) {
+    ("", _, _) => String::new(),
+    (year, "", _) => format!("{}年", year),
+    (year, month, "") => format!("{}年{}月", year, month),
+    (year, month, day) => format!("{}年{}月{}日", year, month, day),
+  };
+  let body = photo_data.body.trim();
+  if location.is_empty() && date.is_empty() && body.is_empty() {
+    return String::new();
+  }
+  let mut alt = String::new();
+  if !location.is_empty() {
+    alt.push_str(&format!("{}で", location));
+  }
+  if !date.is_empty() {
+    alt.push_str(&format!("{}に", date));
+  }
+  if !body.is_empty() {
+    alt.push_str(&format!("{}で", body));
+  }
+  alt.push_str("撮影した写真");
+  alt
+}
+
+/// 写真の撮影日を取り出す
+/// 年月日が全て入力されていて、日付として正しい場合だけ値を返す
+pub fn photo_date(photo_data: &GUIPhotoData) -> Option<NaiveDate> {
+  let year = photo_data.year.trim().parse().ok()?;
+  let month = photo_data.month.trim().parse().ok()?;
+  let day = photo_data.day.trim().parse().ok()?;
+  NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// 撮影日が期間（両端を含む）に入っているかどうか
+/// 期間の始まりと終わりのどちらも無い場合は全て対象になり、
+/// 期間が指定されている場合は撮影日の無い写真を含めない
+pub fn is_in_date_range(
+  date: Option<NaiveDate>,
+  from: Option<NaiveDate>,
+  to: Option<NaiveDate>,
+) -> bool {
+  if from.is_none() && to.is_none() {
+    return true;
+  }
+  match date {
+    Some(date) => from.iter().all(|from| *from <= date) && to.iter().all(|to| date <= *to),
+    None => false,
+  }
+}
+
+/// `+09:00`のような時差の文字列を読み取る
+/// Exifの仕様で時差が不明な場合に使われる`   :  `などは`None`になる
+pub fn parse_offset_time(offset_time: &str) -> Option<FixedOffset> {
+  let offset_time = offset_time.trim();
+  let (sign, rest) = match offset_time.chars().next()? {
+    '+' => (1, &offset_time[1..]),
+    '-' => (-1, &offset_time[1..]),
+    _ => return None,
+  };
+  let (hour, minutes) = rest.split_once(':')?;
+  let hour: i32 = hour.parse().ok()?;
+  let minutes: i32 = minutes.parse().ok()?;
+  if minutes >= 60 {
+    return None;
+  }
+  FixedOffset::east_opt(sign * (hour * 3600 + minutes * 60))
+}
+
+/// 時差が分かっている写真の撮影日時を、時差付きの日時にする
+pub fn photo_datetime_with_offset(
+  photo_data: &GUIPhotoData,
+) -> Option<chrono::DateTime<FixedOffset>> {
+  let offset = parse_offset_time(&photo_data.offset_time)?;
+  photo_datetime(photo_data)?
+    .and_local_timezone(offset)
+    .single()
+}
+
+/// タイムラインで写真をまとめる時間の単位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineScale {
+  Year,
+  Month,
+  Day,
+}
+
+/// タイムラインの1区間
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineBucket {
+  /// `2022年8月`のような表示用の名前
+  pub label: String,
+  /// 区間に含まれる写真のID（撮影日時順）
+  pub photo_id_lst: Vec<String>,
+}
+
+/// 写真を撮影日時の区間ごとにまとめ、古い順に並べる
+/// 区間を決めるのに必要な日付が無い写真は2つ目の返り値にまとめる
+pub fn group_by_timeline(
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  scale: TimelineScale,
+) -> (Vec<TimelineBucket>, Vec<String>) {
+  let mut bucket_lst = BTreeMap::new();
+  let mut undated_id_lst = Vec::new();
+  for photo_id in photo_id_lst.iter() {
+    let photo_data = match gui_photo_data_lst.get(photo_id) {
+      Some(photo_data) => photo_data,
+      None => continue,
+    };
+    let year = photo_data.year.trim().parse::<i32>().ok();
+    let month = photo_data.month.trim().parse::<u32>().ok();
+    let day = photo_data.day.trim().parse::<u32>().ok();
+    let key = match (scale, year, month, day) {
+      (TimelineScale::Year, Some(year), _, _) => Some((year, 0, 0)),
+      (TimelineScale::Month, Some(year), Some(month), _) => Some((year, month, 0)),
+      (TimelineScale::Day, Some(year), Some(month), Some(day)) => Some((year, month, day)),
+      _ => None,
+    };
+    match key {
+      Some(key) => bucket_lst
+        .entry(key)
+        .or_insert_with(Vec::new)
+        .push(photo_id),
+      None => undated_id_lst.push(photo_id.clone()),
+    }
+  }
+  let bucket_lst = bucket_lst
+    .into_iter()
+    .map(|((year, month, day), mut id_lst)| {
+      // 同じ区間の中では撮影時刻順にし、時刻が無いものは後ろに回す
+      id_lst.sort_by_key(|id| {
+        let datetime = gui_photo_data_lst.get(*id).and_then(photo_datetime);
+        (datetime.is_none(), datetime)
+      });
+      let label = match scale {
+        TimelineScale::Year => format!("{}年", year),
+        TimelineScale::Month => format!("{}年{}月", year, month),
+        TimelineScale::Day => format!("{}年{}月{}日", year, month, day),
+      };
+      TimelineBucket {
+        label,
+        photo_id_lst: id_lst.into_iter().cloned().collect(),
+      }
+    })
+    .collect();
+  (bucket_lst, undated_id_lst)
+}
+
+/// 写真の撮影日時を書き換える
+pub fn set_photo_datetime(photo_data: &mut GUIPhotoData, datetime: NaiveDateTime) {
+  photo_data.year = datetime.year().to_string();
+  photo_data.month = datetime.month().to_string();
+  photo_data.day = datetime.day().to_string();
+  photo_data.hour = datetime.hour().to_string();
+  photo_data.minutes = datetime.minute().to_string();
+}
+
+/// 撮影日時とファイルの日時のずれを乖離とみなす既定の閾値（時間）
+pub const TIMESTAMP_DISCREPANCY_HOURS: i64 = 24;
+
+/// 撮影日時とファイルの日時が大きくずれている写真
+/// ファイルの作成日時は書き換えられないので、ファイルの日時には最終更新日時を使う
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampDiscrepancy {
+  pub photo_id: String,
+  pub exif_datetime: NaiveDateTime,
+  pub file_datetime: NaiveDateTime,
+}
+
+impl TimestampDiscrepancy {
+  /// ファイルの日時から撮影日時を引いた差
+  pub fn difference(&self) -> Duration {
+    self.file_datetime - self.exif_datetime
+  }
+
+  /// ずれの向きと大きさ
+  pub fn label(&self) -> String {
+    let difference = self.difference();
+    let (direction, difference) = if difference < Duration::zero() {
+      ("ファイルの日時が前", -difference)
+    } else {
+      ("ファイルの日時が後", difference)
+    };
+    format!(
+      "{}に{}日{}時間",
+      direction,
+      difference.num_days(),
+      difference.num_hours() % 24
+    )
+  }
+}
+
+/// 撮影日時とオリジナルの画像ファイルの最終更新日時を比べ、`threshold`以上ずれている写真を列挙する
+/// 撮影日時が無い写真やファイルの日時を読み取れない写真は飛ばす
+pub fn find_timestamp_discrepancies(
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  original_dir: &str,
+  threshold: Duration,
+) -> Vec<TimestampDiscrepancy> {
+  photo_id_lst
+    .iter()
+    .filter_map(|id| {
+      let photo_data = gui_photo_data_lst.get(id)?;
+      let exif_datetime = photo_datetime(photo_data)?;
+      let file_datetime =
+        save::get_file_modified_timestamp(&format!("{}/{}", original_dir, photo_data.file_name))?
+          .naive_local();
+      let discrepancy = TimestampDiscrepancy {
+        photo_id: id.clone(),
+        exif_datetime,
+        file_datetime,
+      };
+      let difference = discrepancy.difference();
+      if difference >= threshold || -difference >= threshold {
+        Some(discrepancy)
+      } else {
+        None
+      }
+    })
+    .collect()
+}
+
+/// 撮影日時をファイルの日時に合わせる
+/// 書き換えた写真の変更前のデータを返す
+pub fn align_datetime_to_file(
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  discrepancy_lst: &[TimestampDiscrepancy],
+) -> Vec<GUIPhotoData> {
+  let mut before_lst = Vec::new();
+  for discrepancy in discrepancy_lst.iter() {
+    // ロックされている写真は変更しない
+    let photo_data = gui_photo_data_lst
+      .get_mut(&discrepancy.photo_id)
+      .filter(|photo_data| !photo_data.locked);
+    if let Some(photo_data) = photo_data {
+      before_lst.push(photo_data.clone());
+      set_photo_datetime(photo_data, discrepancy.file_datetime);
+    }
+  }
+  before_lst
+}
+
+/// ファイルの最終更新日時を撮影日時に合わせる
+/// ロックされている写真のファイルは書き換えない
+/// 書き換えたファイルの数を返す
+pub fn align_file_to_datetime(
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  discrepancy_lst: &[TimestampDiscrepancy],
+  original_dir: &str,
+) -> Result<usize> {
+  let mut count = 0;
+  for discrepancy in discrepancy_lst.iter() {
+    let photo_data = gui_photo_data_lst
+      .get(&discrepancy.photo_id)
+      .filter(|photo_data| !photo_data.locked);
+    if let Some(photo_data) = photo_data {
+      save::set_file_modified_timestamp(
+        &format!("{}/{}", original_dir, photo_data.file_name),
+        discrepancy.exif_datetime,
+      )?;
+      count += 1;
+    }
+  }
+  Ok(count)
+}
+
+/// `photo_id_lst`の並び順で指定した写真より前にある写真を遡り、
+/// 最初に見つかった撮影日時を返す
+/// 撮影日時が無い写真は飛ばす
+pub fn previous_photo_datetime(
+  photo_id: &str,
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+) -> Option<NaiveDateTime> {
+  let index = photo_id_lst.iter().position(|id| id == photo_id)?;
+  photo_id_lst[..index]
+    .iter()
+    .rev()
+    .filter_map(|id| gui_photo_data_lst.get(id))
+    .find_map(photo_datetime)
+}
+
+/// 撮影日時に分を足す
+/// 日付や月・年の桁上がりも扱う
+pub fn add_minutes(datetime: NaiveDateTime, minutes: i64) -> NaiveDateTime {
+  datetime + Duration::minutes(minutes)
+}
+
+/// IDにそのまま使える文字かどうか
+fn is_safe_id_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'
+}
+
+/// IDがファイル名やURLにそのまま使えるかどうか
+/// ASCIIの英数字と`-`・`_`・`.`だけからなり、空でも`.`や`..`でもないものを認める
+pub fn is_valid_id(id: &str) -> bool {
+  !id.is_empty() && id != "." && id != ".." && id.chars().all(is_safe_id_char)
+}
+
+/// IDをファイル名やURLに使える形にする
+/// 使えない文字はUTF-8のバイトごとに`%E5`のようにパーセントエンコードする
+/// `is_valid_id`を満たすIDはそのまま返すため、これまでのpathは変わらない
+/// 作業ディレクトリや出力先に書き出すファイル名にはこれを使う
+pub fn sanitize_id(id: &str) -> String {
+  let mut s = String::new();
+  for c in id.chars() {
+    if is_safe_id_char(c) {
+      s.push(c)
+    } else {
+      let mut buf = [0; 4];
+      for b in c.encode_utf8(&mut buf).bytes() {
+        s.push_str(&format!("%{:02X}", b))
+      }
+    }
+  }
+  if s == "." || s == ".." {
+    s.replace('.', "%2E")
+  } else {
+    s
+  }
+}
+
+/// IDをURLのpathに埋め込む形にする
+/// `sanitize_id`で付けたファイル名の`%`を`%25`にして、ブラウザやサーバーがデコードしたときにファイル名と一致させる
+pub fn src_id(id: &str) -> String {
+  sanitize_id(id).replace('%', "%25")
+}
+
+/// 画像のURLの前に付けるpathの既定値
+pub const DEFAULT_URL_PREFIX: &str = "/";
+
+/// 画像のURLの前に付けるpathと、`images/`から始まるpathを繋ぐ
+/// 先頭にスラッシュを付け、繋ぎ目やpathの中で重なったスラッシュは1つにまとめる
+pub fn join_url_prefix(url_prefix: &str, path: &str) -> String {
+  let joined = format!("/{}/{}", url_prefix, path);
+  let mut src = String::new();
+  for c in joined.chars() {
+    if !(c == '/' && src.ends_with('/')) {
+      src.push(c);
+    }
+  }
+  src
+}
+
+/// URLの前に付けたpathを取り除き、出力先からの相対的な`images/`から始まるpathにする
+/// IDの`/`はエンコードしているので、最後の`/images/`から後ろを取り出せば良い
+pub fn strip_url_prefix(src: &str) -> &str {
+  match src.rfind("/images/") {
+    Some(i) => &src[i + 1..],
+    None => src.trim_start_matches('/'),
+  }
+}
+
+/// URLのpathを作業ディレクトリや出力先からの相対的なファイルのpathに戻す
+/// URLの前に付けたpathは取り除き、`src_id`でエンコードした`%25`を`%`に戻す
+pub fn src_to_path(src: &str) -> String {
+  strip_url_prefix(src).replace("%25", "%")
+}
+
+/// 写真の表示用画像のpath
+/// `url_prefix`の後ろに`images/normal/`を続ける
+pub fn photo_src(url_prefix: &str, photo_id: &str) -> String {
+  join_url_prefix(
+    url_prefix,
+    &format!("images/normal/{}.JPG", src_id(photo_id)),
+  )
+}
+
+/// 写真の遅延読み込み用の小さな画像のpath
+/// `url_prefix`の後ろに`images/lazy/`を続ける
+pub fn photo_lazy_src(url_prefix: &str, photo_id: &str) -> String {
+  join_url_prefix(url_prefix, &format!("images/lazy/{}.JPG", src_id(photo_id)))
+}
+
+/// `srcset`用の幅の異なる画像のpath
+/// `url_prefix`の後ろに`images/srcset/`を続ける
+pub fn srcset_src(url_prefix: &str, photo_id: &str, width: u32) -> String {
+  join_url_prefix(
+    url_prefix,
+    &format!("images/srcset/{}-{}.JPG", src_id(photo_id), width),
+  )
+}
+
+/// グループのカバー画像のpath
+/// `url_prefix`の後ろに`images/covers/`を続ける
+pub fn cover_src(url_prefix: &str, group_id: &str) -> String {
+  join_url_prefix(
+    url_prefix,
+    &format!("images/covers/{}.JPG", src_id(group_id)),
+  )
+}
+
+/// グループのページのpath
+/// HTMLのギャラリーを書き出したときのファイル名に対応し、`url_prefix`の後ろに続ける
+pub fn group_page_src(url_prefix: &str, group_id: &str) -> String {
+  join_url_prefix(url_prefix, &format!("{}.html", src_id(group_id)))
+}
+
+/// 公開先のURLと`photo_src`などのpathから完全なURLを組み立てる
+/// 繋ぎ目やpathの中で重なったスラッシュは1つにまとめる
+pub fn build_public_url(base_url: &str, photo_src: &str) -> String {
+  // `https://`のスラッシュはまとめない
+  let (scheme, rest) = match base_url.find("://") {
+    Some(i) => base_url.split_at(i + 3),
+    None => ("", base_url),
+  };
+  let joined = format!("{}/{}", rest, photo_src);
+  let mut path = String::new();
+  for c in joined.chars() {
+    if !(c == '/' && path.ends_with('/')) {
+      path.push(c);
+    }
+  }
+  format!("{}{}", scheme, path)
+}
+
+/// 出力する`group_data.json`ファイルに書き出す内容
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupData {
+  pub group_id: String,
+  pub photo_id_list: Vec<String>,
+  pub year: Option<String>,
+  pub month: Option<String>,
+  pub day: Option<String>,
+  pub hour: Option<String>,
+  pub minutes: Option<String>,
+  pub title: String,
+  pub description: String,
+  pub location: Option<String>,
+  /// グループを代表する写真のID（`photo_id_list`に含まれるもの）
+  #[serde(default)]
+  pub cover_photo_id: Option<String>,
+  /// 親グループのID
+  #[serde(default)]
+  pub parent_group_id: Option<String>,
+}
+
+/// `GroupData`をGUIで扱うためのデータ構造
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GUIGroupData {
+  pub group_id: String,
+  pub photo_id_list: Vec<String>,
+  pub year: String,
+  pub month: String,
+  pub day: String,
+  pub hour: String,
+  pub minutes: String,
+  pub title: String,
+  pub description: String,
+  pub location: String,
+  #[serde(default)]
+  pub cover_photo_id: Option<String>,
+  #[serde(default)]
+  pub parent_group_id: Option<String>,
+}
+
+/// どのグループの`photo_id_list`にも含まれていない写真のIDを、元の並び順のまま返す
+pub fn orphan_photo_id_lst(
+  photo_id_lst: &[String],
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> Vec<String> {
+  let grouped_id_lst = gui_group_data_lst
+    .values()
+    .flat_map(|group_data| group_data.photo_id_list.iter())
+    .collect::<std::collections::HashSet<&String>>();
+  photo_id_lst
+    .iter()
+    .filter(|id| !grouped_id_lst.contains(id))
+    .cloned()
+    .collect()
+}
+
+/// グループの`photo_id_list`に含まれている、存在しない写真のID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingPhotoId {
+  pub group_id: String,
+  pub photo_id_lst: Vec<String>,
+}
+
+/// 各グループの`photo_id_list`のうち、`photo_id_lst`に無いIDをグループごとに列挙する
+/// 存在しないIDが無いグループは含めない
+pub fn find_dangling_photo_ids(
+  group_data_lst: &[GroupData],
+  photo_id_lst: &[String],
+) -> Vec<DanglingPhotoId> {
+  group_data_lst
+    .iter()
+    .filter_map(|group_data| {
+      let dangling_id_lst = group_data
+        .photo_id_list
+        .iter()
+        .filter(|id| !photo_id_lst.contains(id))
+        .cloned()
+        .collect::<Vec<String>>();
+      if dangling_id_lst.is_empty() {
+        None
+      } else {
+        Some(DanglingPhotoId {
+          group_id: group_data.group_id.clone(),
+          photo_id_lst: dangling_id_lst,
+        })
+      }
+    })
+    .collect()
+}
+
+/// 各グループの`photo_id_list`から存在しない写真のIDを取り除く
+/// 取り除いたIDの数を返す
+pub fn prune_dangling_photo_ids(
+  gui_group_data_lst: &mut HashMap<String, GUIGroupData>,
+  photo_id_lst: &[String],
+) -> usize {
+  let mut count = 0;
+  for group_data in gui_group_data_lst.values_mut() {
+    let len = group_data.photo_id_list.len();
+    group_data
+      .photo_id_list
+      .retain(|id| photo_id_lst.contains(id));
+    if len != group_data.photo_id_list.len() {
+      debug!(
+        "グループ「{}」から{}件の存在しない画像を取り除きました",
+        group_data.group_id,
+        len - group_data.photo_id_list.len()
+      );
+    }
+    count += len - group_data.photo_id_list.len();
+    clear_invalid_cover(group_data);
+  }
+  count
+}
+
+/// グループの祖先のIDを、最上位のグループから直近の親の順に並べて返す
+/// 親子関係が循環している場合は、一度たどったグループに戻ったところで打ち切る
+pub fn group_ancestors(
+  group_id: &str,
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> Vec<String> {
+  let mut ancestor_lst = Vec::new();
+  let mut visited = HashSet::new();
+  visited.insert(group_id.to_string());
+  let mut parent_id = gui_group_data_lst
+    .get(group_id)
+    .and_then(|group_data| group_data.parent_group_id.clone());
+  while let Some(id) = parent_id {
+    if !visited.insert(id.clone()) {
+      break;
+    }
+    parent_id = gui_group_data_lst
+      .get(&id)
+      .and_then(|group_data| group_data.parent_group_id.clone());
+    ancestor_lst.push(id);
+  }
+  ancestor_lst.reverse();
+  ancestor_lst
+}
+
+/// 直接の子グループのIDを`group_id_lst`の並び順のまま返す
+pub fn child_group_id_lst(
+  group_id: &str,
+  group_id_lst: &[String],
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> Vec<String> {
+  group_id_lst
+    .iter()
+    .filter(|id| {
+      gui_group_data_lst
+        .get(*id)
+        .and_then(|group_data| group_data.parent_group_id.as_deref())
+        == Some(group_id)
+    })
+    .cloned()
+    .collect()
+}
+
+/// `parent_id`のグループを`group_id`のグループの親にできるかどうか
+/// 自分自身や子孫のグループを親にすると親子関係が循環するので認めない
+pub fn is_valid_parent(
+  group_id: &str,
+  parent_id: &str,
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> bool {
+  group_id != parent_id
+    && gui_group_data_lst.contains_key(parent_id)
+    && !group_ancestors(parent_id, gui_group_data_lst)
+      .iter()
+      .any(|id| id == group_id)
+}
+
+/// グループを親子関係の木の順に並べ、それぞれの深さ（最上位は0）と組にして返す
+/// 兄弟のグループは`group_id_lst`の並び順にする
+/// 親が見つからないグループは最上位に置き、循環していてたどれないグループは最後に最上位として並べる
+pub fn group_tree_order(
+  group_id_lst: &[String],
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> Vec<(String, usize)> {
+  fn visit(
+    group_id: &str,
+    depth: usize,
+    group_id_lst: &[String],
+    gui_group_data_lst: &HashMap<String, GUIGroupData>,
+    visited: &mut HashSet<String>,
+    order: &mut Vec<(String, usize)>,
+  ) {
+    if !visited.insert(group_id.to_string()) {
+      return;
+    }
+    order.push((group_id.to_string(), depth));
+    for child_id in child_group_id_lst(group_id, group_id_lst, gui_group_data_lst) {
+      visit(
+        &child_id,
+        depth + 1,
+        group_id_lst,
+        gui_group_data_lst,
+        visited,
+        order,
+      );
+    }
+  }
+  let mut visited = HashSet::new();
+  let mut order = Vec::new();
+  let root_id_lst = group_id_lst.iter().filter(|id| {
+    gui_group_data_lst
+      .get(*id)
+      .and_then(|group_data| group_data.parent_group_id.as_ref())
+      .map(|parent_id| !group_id_lst.contains(parent_id))
+      .unwrap_or(true)
+  });
+  for group_id in root_id_lst {
+    visit(
+      group_id,
+      0,
+      group_id_lst,
+      gui_group_data_lst,
+      &mut visited,
+      &mut order,
+    );
+  }
+  for group_id in group_id_lst.iter() {
+    visit(
+      group_id,
+      0,
+      group_id_lst,
+      gui_group_data_lst,
+      &mut visited,
+      &mut order,
+    );
+  }
+  order
+}
+
+/// 写真の空欄の撮影場所と撮影日時を、グループに設定されている値で補完したものを返す
+/// 撮影日（年月日）と時刻（時分）はそれぞれまとめて扱い、写真側が全て空欄の場合だけ補完する
+/// 元の写真のデータは変更しない
+pub fn inherit_from_group(photo: &GUIPhotoData, group: &GUIGroupData) -> GUIPhotoData {
+  let mut photo = photo.clone();
+  let is_blank = |lst: &[&String]| lst.iter().all(|value| value.trim().is_empty());
+  if is_blank(&[&photo.location]) && !is_blank(&[&group.location]) {
+    photo.location = group.location.clone();
+  }
+  if is_blank(&[&photo.year, &photo.month, &photo.day])
+    && !is_blank(&[&group.year, &group.month, &group.day])
+  {
+    photo.year = group.year.clone();
+    photo.month = group.month.clone();
+    photo.day = group.day.clone();
+  }
+  if is_blank(&[&photo.hour, &photo.minutes]) && !is_blank(&[&group.hour, &group.minutes]) {
+    photo.hour = group.hour.clone();
+    photo.minutes = group.minutes.clone();
+  }
+  photo
+}
+
+/// 写真を含むグループとその祖先のグループの値で、写真の空欄の撮影場所と撮影日時を補完したものを返す
+/// 写真を含むグループは`group_id_lst`の並び順に、それぞれ直近の親から順にたどり、先に見つかった値を使う
+pub fn inherit_from_groups(
+  photo: &GUIPhotoData,
+  group_id_lst: &[String],
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> GUIPhotoData {
+  let mut photo = photo.clone();
+  for group_id in group_id_lst.iter() {
+    let group_data = match gui_group_data_lst.get(group_id) {
+      Some(group_data) if group_data.photo_id_list.contains(&photo.photo_id) => group_data,
+      _ => continue,
+    };
+    photo = inherit_from_group(&photo, group_data);
+    for ancestor_id in group_ancestors(group_id, gui_group_data_lst).iter().rev() {
+      if let Some(ancestor) = gui_group_data_lst.get(ancestor_id) {
+        photo = inherit_from_group(&photo, ancestor);
+      }
+    }
+  }
+  photo
+}
+
+/// 指定した写真の空欄の撮影場所と撮影日時を、グループの値で補完して書き込む
+/// ロックされている写真は変更しない
+/// 実際に値が変わった写真について、変更前のデータを返す
+pub fn apply_group_inheritance(
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  group_id_lst: &[String],
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> Vec<GUIPhotoData> {
+  let mut before_lst = Vec::new();
+  for photo_id in photo_id_lst.iter() {
+    let photo_data = gui_photo_data_lst
+      .get_mut(photo_id)
+      .filter(|photo_data| !photo_data.locked);
+    if let Some(photo_data) = photo_data {
+      let inherited = inherit_from_groups(photo_data, group_id_lst, gui_group_data_lst);
+      if inherited != *photo_data {
+        before_lst.push(std::mem::replace(photo_data, inherited));
+      }
+    }
+  }
+  before_lst
+}
+
+/// グループ内で写真を動かす方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveDirection {
+  /// 前に動かす
+  Up,
+  /// 後ろに動かす
+  Down,
+}
+
+/// `index`番目の写真を一つ前または後ろに動かす
+/// 先頭を前に、末尾を後ろに動かそうとした場合や、範囲外の場合は何もしない
+/// 動かした場合は`true`を返す
+pub fn move_photo_id(photo_id_list: &mut [String], index: usize, direction: MoveDirection) -> bool {
+  let target = match direction {
+    MoveDirection::Up => index.checked_sub(1),
+    MoveDirection::Down => Some(index + 1),
+  };
+  match target {
+    Some(target) if index < photo_id_list.len() && target < photo_id_list.len() => {
+      photo_id_list.swap(index, target);
+      true
+    }
+    _ => false,
+  }
+}
+
+/// カバー写真がグループに含まれていない場合は解除する
+/// 解除した場合は`true`を返す
+pub fn clear_invalid_cover(group_data: &mut GUIGroupData) -> bool {
+  match &group_data.cover_photo_id {
+    Some(id) if !group_data.photo_id_list.contains(id) => {
+      group_data.cover_photo_id = None;
+      true
+    }
+    _ => false,
+  }
+}
+
+/// 写真を全てのグループの`photo_id_list`から取り除く
+/// 残った写真の並び順は変えず、カバー写真だった場合はカバーも解除する
+pub fn remove_photo_from_groups(
+  gui_group_data_lst: &mut HashMap<String, GUIGroupData>,
+  photo_id: &str,
+) {
+  for group_data in gui_group_data_lst.values_mut() {
+    group_data.photo_id_list.retain(|id| id != photo_id);
+    clear_invalid_cover(group_data);
+  }
+}
+
+/// 写真のデータを削除する
+/// IDの一覧・写真データ・サムネイルに加えて、各グループの`photo_id_list`からも取り除く
+pub fn delete_photo_data(
+  photo_id: &str,
+  photo_id_lst: &mut Vec<String>,
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+  thumbnail_lst: &mut HashMap<String, Vec<u8>>,
+  gui_group_data_lst: &mut HashMap<String, GUIGroupData>,
+) {
+  photo_id_lst.retain(|id| id != photo_id);
+  gui_photo_data_lst.remove(photo_id);
+  thumbnail_lst.remove(photo_id);
+  remove_photo_from_groups(gui_group_data_lst, photo_id);
+}
+
+pub fn make_dummy_gui_photo_data() -> GUIPhotoData {
+  GUIPhotoData {
+    file_name: String::new(),
+    photo_id: String::new(),
+    photo_src: String::new(),
+    photo_lazy_src: String::new(),
+    alt: String::new(),
+    title: String::new(),
+    alt_translation_lst: BTreeMap::new(),
+    title_translation_lst: BTreeMap::new(),
+    year: String::new(),
+    month: String::new(),
+    day: String::new(),
+    hour: String::new(),
+    minutes: String::new(),
+    body: String::new(),
+    lens: String::new(),
+    time: String::new(),
+    focal_length: String::new(),
+    f_value: String::new(),
+    iso: String::new(),
+    exposure_program: String::new(),
+    max_f_value: String::new(),
+    location: String::new(),
+    srcset: Vec::new(),
+    focus_area: None,
+    rating: 0,
+    keywords: String::new(),
+    offset_time: String::new(),
+    focal_length_35mm: String::new(),
+    altitude: String::new(),
+    locked: false,
+    session: String::new(),
+    flag: None,
+    photo_webp_src: None,
+  }
+}
+
+pub fn make_dummy_gui_group_data() -> GUIGroupData {
+  GUIGroupData {
+    group_id: String::new(),
+    photo_id_list: Vec::new(),
+    year: String::new(),
+    month: String::new(),
+    day: String::new(),
+    hour: String::new(),
+    minutes: String::new(),
+    title: String::new(),
+    description: String::new(),
+    location: String::new(),
+    cover_photo_id: None,
+    parent_group_id: None,
+  }
+}
+
+/// 写真のIDの一覧から、その写真を並び順のまま含む新しいグループのデータを作る
+/// グループIDやタイトルなどは空欄にしておき、作成する前に入力してもらう
+pub fn make_gui_group_data_from_photo_id_lst(photo_id_lst: &[String]) -> GUIGroupData {
+  GUIGroupData {
+    photo_id_list: photo_id_lst.to_vec(),
+    ..make_dummy_gui_group_data()
+  }
+}
+
+pub fn gui_group_data_to_group_data(gui_group_data: GUIGroupData) -> GroupData {
+  GroupData {
+    group_id: gui_group_data.group_id,
+    photo_id_list: gui_group_data.photo_id_list,
+    year: if gui_group_data.year.is_empty() {
+      None
+    } else {
+      Some(gui_group_data.year)
+    },
+    month: if gui_group_data.month.is_empty() {
+      None
+    } else {
+      Some(gui_group_data.month)
+    },
+    day: if gui_group_data.day.is_empty() {
+      None
+    } else {
+      Some(gui_group_data.day)
+    },
+    hour: if gui_group_data.hour.is_empty() {
+      None
+    } else {
+      Some(gui_group_data.hour)
+    },
+    minutes: if gui_group_data.minutes.is_empty() {
+      None
+    } else {
+      Some(gui_group_data.minutes)
+    },
+    title: gui_group_data.title,
+    description: gui_group_data.description,
+    location: if gui_group_data.location.is_empty() {
+      None
+    } else {
+      Some(gui_group_data.location)
+    },
+    cover_photo_id: gui_group_data.cover_photo_id,
+    parent_group_id: gui_group_data.parent_group_id,
+  }
+}
+
+pub fn group_data_to_gui_group_data(group_data: GroupData) -> GUIGroupData {
+  GUIGroupData {
+    group_id: group_data.group_id,
+    photo_id_list: group_data.photo_id_list,
+    year: group_data.year.unwrap_or_default(),
+    month: group_data.month.unwrap_or_default(),
+    day: group_data.day.unwrap_or_default(),
+    hour: group_data.hour.unwrap_or_default(),
+    minutes: group_data.minutes.unwrap_or_default(),
+    title: group_data.title,
+    description: group_data.description,
+    location: group_data.location.unwrap_or_default(),
+    cover_photo_id: group_data.cover_photo_id,
+    parent_group_id: group_data.parent_group_id,
+  }
+}
+
+/// jsonファイルのpathからデータを構築する
+/// 分割保存されている場合は分割したファイルから読み取る
+pub fn load_group_data_from_work_directory(work_directory: &str) -> Result<Vec<GroupData>> {
+  if save::is_split(work_directory) {
+    return Ok(save::load_split(work_directory)?.1);
+  }
+  let file_path = format!("{}/group_data.json", work_directory);
+  match File::open(file_path) {
+    Ok(file) => {
+      let reader = BufReader::new(file);
+      let data: Vec<GroupData> = save::read_versioned_data(reader)?;
       Ok(data)
     }
-    Err(_) => Ok(Vec::new()),
+    Err(_) => Ok(Vec::new()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  fn make_test_group_data(group_id: &str, photo_id_lst: &[&str]) -> GUIGroupData {
+    GUIGroupData {
+      group_id: group_id.to_string(),
+      photo_id_list: photo_id_lst.iter().map(|id| id.to_string()).collect(),
+      ..make_dummy_gui_group_data()
+    }
+  }
+
+  #[test]
+  fn remove_photo_from_groups_keeps_order_and_clears_cover() {
+    let mut gui_group_data_lst = HashMap::new();
+    gui_group_data_lst.insert(
+      "a".to_string(),
+      GUIGroupData {
+        cover_photo_id: Some("2".to_string()),
+        ..make_test_group_data("a", &["1", "2", "3", "4"])
+      },
+    );
+    gui_group_data_lst.insert(
+      "b".to_string(),
+      GUIGroupData {
+        cover_photo_id: Some("4".to_string()),
+        ..make_test_group_data("b", &["4", "2"])
+      },
+    );
+    remove_photo_from_groups(&mut gui_group_data_lst, "2");
+    let a = &gui_group_data_lst["a"];
+    assert_eq!(a.photo_id_list, vec!["1", "3", "4"]);
+    assert_eq!(a.cover_photo_id, None);
+    let b = &gui_group_data_lst["b"];
+    assert_eq!(b.photo_id_list, vec!["4"]);
+    assert_eq!(b.cover_photo_id, Some("4".to_string()));
+  }
+
+  #[test]
+  fn delete_photo_data_removes_every_reference() {
+    let mut photo_id_lst = ["1", "2", "3"].map(String::from).to_vec();
+    let mut gui_photo_data_lst = HashMap::new();
+    let mut thumbnail_lst = HashMap::new();
+    for id in photo_id_lst.iter() {
+      gui_photo_data_lst.insert(
+        id.clone(),
+        GUIPhotoData {
+          photo_id: id.clone(),
+          ..make_dummy_gui_photo_data()
+        },
+      );
+      thumbnail_lst.insert(id.clone(), id.as_bytes().to_vec());
+    }
+    let mut gui_group_data_lst = HashMap::new();
+    gui_group_data_lst.insert(
+      "a".to_string(),
+      GUIGroupData {
+        cover_photo_id: Some("2".to_string()),
+        ..make_test_group_data("a", &["2", "1"])
+      },
+    );
+    gui_group_data_lst.insert("b".to_string(), make_test_group_data("b", &["3"]));
+    delete_photo_data(
+      "2",
+      &mut photo_id_lst,
+      &mut gui_photo_data_lst,
+      &mut thumbnail_lst,
+      &mut gui_group_data_lst,
+    );
+    assert_eq!(photo_id_lst, vec!["1", "3"]);
+    assert!(!gui_photo_data_lst.contains_key("2"));
+    assert_eq!(gui_photo_data_lst.len(), 2);
+    assert!(!thumbnail_lst.contains_key("2"));
+    assert_eq!(thumbnail_lst.len(), 2);
+    assert_eq!(gui_group_data_lst["a"].photo_id_list, vec!["1"]);
+    assert_eq!(gui_group_data_lst["a"].cover_photo_id, None);
+    assert_eq!(gui_group_data_lst["b"].photo_id_list, vec!["3"]);
+  }
+
+  #[test]
+  fn remove_photo_from_groups_ignores_unknown_id() {
+    let mut gui_group_data_lst = HashMap::new();
+    gui_group_data_lst.insert("a".to_string(), make_test_group_data("a", &["1", "2"]));
+    remove_photo_from_groups(&mut gui_group_data_lst, "3");
+    assert_eq!(gui_group_data_lst["a"].photo_id_list, vec!["1", "2"]);
+  }
+
+  #[test]
+  fn import_photo_data_is_rebuilt_from_saved_photo_data() {
+    let mut photo_data_lst = HashMap::new();
+    for (id, file_name) in [("b", "b.jpg"), ("a", "a.jpg")] {
+      let photo_data = gui_photo_data_to_photo_data(GUIPhotoData {
+        photo_id: id.to_string(),
+        file_name: file_name.to_string(),
+        alt: format!("{}の説明", id),
+        location: "東京".to_string(),
+        ..make_dummy_gui_photo_data()
+      });
+      photo_data_lst.insert(id.to_string(), photo_data);
+    }
+    let import_photo_data_lst = import_photo_data_from_photo_data(&photo_data_lst);
+    assert_eq!(
+      import_photo_data_lst,
+      vec![
+        ImportPhotoData {
+          file_name: "a.jpg".to_string(),
+          id: "a".to_string(),
+          alt: "aの説明".to_string(),
+          location: "東京".to_string(),
+        },
+        ImportPhotoData {
+          file_name: "b.jpg".to_string(),
+          id: "b".to_string(),
+          alt: "bの説明".to_string(),
+          location: "東京".to_string(),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn broken_import_json_file_is_not_overwritten() {
+    let dir = test_util::temp_dir("import_json");
+    let path = format!("{}/photo.json", dir);
+    // まだ無いファイルは作ってよい
+    assert!(can_overwrite_import_json_file(&path));
+    std::fs::write(
+      &path,
+      r#"[{ "file_name": "a.jpg", "id": "a", "alt": "", "location": "" }]"#,
+    )
+    .unwrap();
+    assert!(can_overwrite_import_json_file(&path));
+    std::fs::write(&path, r#"[{ "file_name": "a.jpg", "id": "a""#).unwrap();
+    assert!(load_import_json_file(&path).is_err());
+    assert!(!can_overwrite_import_json_file(&path));
+  }
+
+  #[test]
+  fn srcset_src_includes_id_and_width() {
+    assert_eq!(srcset_src("/", "sea", 640), "/images/srcset/sea-640.JPG");
+    assert_eq!(
+      srcset_src("/photos/", "sea", 1280),
+      "/photos/images/srcset/sea-1280.JPG"
+    );
+    // IDの使えない文字はエンコードし、ファイルのpathに戻すと`sanitize_id`の形になる
+    let src = srcset_src("/", "海/1", 640);
+    assert_eq!(
+      src_to_path(&src),
+      format!("images/srcset/{}-640.JPG", sanitize_id("海/1"))
+    );
+  }
+
+  /// 評価などの項目が追加される前の形式のJSON
+  const LEGACY_PHOTO_DATA_JSON: &str = r#"{
+    "file_name": "a.jpg",
+    "photo_id": "a",
+    "photo_src": "/images/normal/a.JPG",
+    "photo_lazy_src": "/images/lazy/a.JPG",
+    "alt": "海",
+    "location": "東京"
+  }"#;
+
+  #[test]
+  fn rating_defaults_to_unrated_when_missing() {
+    let photo_data: PhotoData = serde_json::from_str(LEGACY_PHOTO_DATA_JSON).unwrap();
+    assert_eq!(photo_data.rating, 0);
+    assert_eq!(photo_data_to_gui_photo_data(photo_data).rating, 0);
+  }
+
+  #[test]
+  fn rating_round_trips_through_json() {
+    let photo_data = gui_photo_data_to_photo_data(GUIPhotoData {
+      rating: 4,
+      ..make_dummy_gui_photo_data()
+    });
+    let json_str = serde_json::to_string(&photo_data).unwrap();
+    let loaded: PhotoData = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(loaded.rating, 4);
+    assert_eq!(loaded, photo_data);
+  }
+
+  #[test]
+  fn only_one_to_five_are_valid_ratings() {
+    assert_eq!(valid_rating(0), None);
+    assert_eq!(valid_rating(-1), None);
+    assert_eq!(valid_rating(1), Some(1));
+    assert_eq!(valid_rating(5), Some(5));
+    assert_eq!(valid_rating(6), None);
+  }
+
+  #[test]
+  fn parse_keywords_trims_and_dedups() {
+    assert_eq!(
+      parse_keywords(" 海, 夕日、海 ,, 山 "),
+      vec!["海", "夕日", "山"]
+    );
+    assert!(parse_keywords(" , 、").is_empty());
+    assert_eq!(
+      join_keywords(&["海".to_string(), "夕日".to_string()]),
+      "海, 夕日"
+    );
+    // 分割して繋げ直したものは分割し直しても変わらない
+    let keyword_lst = parse_keywords("海、夕日, 山");
+    assert_eq!(parse_keywords(&join_keywords(&keyword_lst)), keyword_lst);
+  }
+
+  #[test]
+  fn keywords_round_trip_through_json() {
+    let photo_data: PhotoData = serde_json::from_str(LEGACY_PHOTO_DATA_JSON).unwrap();
+    assert!(photo_data.keywords.is_empty());
+    let photo_data = gui_photo_data_to_photo_data(GUIPhotoData {
+      keywords: "海, 夕日".to_string(),
+      ..make_dummy_gui_photo_data()
+    });
+    assert_eq!(photo_data.keywords, vec!["海", "夕日"]);
+    let json_str = serde_json::to_string(&photo_data).unwrap();
+    let loaded: PhotoData = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(loaded.keywords, photo_data.keywords);
+    assert_eq!(photo_data_to_gui_photo_data(loaded).keywords, "海, 夕日");
+  }
+
+  #[test]
+  fn clear_invalid_cover_only_clears_missing_cover() {
+    let mut group_data = GUIGroupData {
+      cover_photo_id: Some("2".to_string()),
+      ..make_test_group_data("a", &["1", "2"])
+    };
+    assert!(!clear_invalid_cover(&mut group_data));
+    assert_eq!(group_data.cover_photo_id, Some("2".to_string()));
+    group_data.photo_id_list.retain(|id| id != "2");
+    assert!(clear_invalid_cover(&mut group_data));
+    assert_eq!(group_data.cover_photo_id, None);
+    // カバーが無い場合は何もしない
+    assert!(!clear_invalid_cover(&mut group_data));
+  }
+
+  #[test]
+  fn move_photo_id_stops_at_both_ends() {
+    let mut photo_id_list = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+    assert!(!move_photo_id(&mut photo_id_list, 0, MoveDirection::Up));
+    assert!(!move_photo_id(&mut photo_id_list, 2, MoveDirection::Down));
+    assert!(!move_photo_id(&mut photo_id_list, 3, MoveDirection::Up));
+    assert!(!move_photo_id(&mut photo_id_list, 5, MoveDirection::Down));
+    assert_eq!(photo_id_list, vec!["1", "2", "3"]);
+    assert!(move_photo_id(&mut photo_id_list, 0, MoveDirection::Down));
+    assert_eq!(photo_id_list, vec!["2", "1", "3"]);
+    assert!(move_photo_id(&mut photo_id_list, 2, MoveDirection::Up));
+    assert_eq!(photo_id_list, vec!["2", "3", "1"]);
+    assert!(!move_photo_id(&mut [], 0, MoveDirection::Down));
+  }
+
+  #[test]
+  fn orphan_photo_id_lst_keeps_original_order() {
+    let photo_id_lst = ["4", "1", "3", "2", "5"].map(String::from);
+    let mut gui_group_data_lst = HashMap::new();
+    gui_group_data_lst.insert("a".to_string(), make_test_group_data("a", &["1"]));
+    gui_group_data_lst.insert("b".to_string(), make_test_group_data("b", &["5", "1"]));
+    assert_eq!(
+      orphan_photo_id_lst(&photo_id_lst, &gui_group_data_lst),
+      vec!["4", "3", "2"]
+    );
+    assert_eq!(
+      orphan_photo_id_lst(&photo_id_lst, &HashMap::new()),
+      photo_id_lst.to_vec()
+    );
+  }
+
+  #[test]
+  fn dangling_photo_ids_are_found_and_pruned() {
+    let photo_id_lst = ["1", "2"].map(String::from);
+    let group_data_lst = vec![
+      gui_group_data_to_group_data(make_test_group_data("a", &["1", "9", "2", "8"])),
+      gui_group_data_to_group_data(make_test_group_data("b", &["2"])),
+    ];
+    assert_eq!(
+      find_dangling_photo_ids(&group_data_lst, &photo_id_lst),
+      vec![DanglingPhotoId {
+        group_id: "a".to_string(),
+        photo_id_lst: vec!["9".to_string(), "8".to_string()],
+      }]
+    );
+
+    let mut gui_group_data_lst = HashMap::new();
+    gui_group_data_lst.insert(
+      "a".to_string(),
+      GUIGroupData {
+        cover_photo_id: Some("9".to_string()),
+        ..make_test_group_data("a", &["1", "9", "2", "8"])
+      },
+    );
+    gui_group_data_lst.insert("b".to_string(), make_test_group_data("b", &["2"]));
+    assert_eq!(
+      prune_dangling_photo_ids(&mut gui_group_data_lst, &photo_id_lst),
+      2
+    );
+    assert_eq!(gui_group_data_lst["a"].photo_id_list, vec!["1", "2"]);
+    assert_eq!(gui_group_data_lst["a"].cover_photo_id, None);
+    assert_eq!(gui_group_data_lst["b"].photo_id_list, vec!["2"]);
+    // 取り除いた後はもう見つからない
+    assert_eq!(
+      prune_dangling_photo_ids(&mut gui_group_data_lst, &photo_id_lst),
+      0
+    );
+  }
+
+  #[test]
+  fn format_exposure_time_uses_fraction_below_one_second() {
+    let rational = |num, denom| exif::Rational { num, denom };
+    assert_eq!(
+      format_exposure_time(rational(1, 250)),
+      Some("1/250 s".to_string())
+    );
+    assert_eq!(
+      format_exposure_time(rational(10, 2500)),
+      Some("1/250 s".to_string())
+    );
+    assert_eq!(
+      format_exposure_time(rational(5, 2)),
+      Some("2.5 s".to_string())
+    );
+    assert_eq!(
+      format_exposure_time(rational(1, 1)),
+      Some("1 s".to_string())
+    );
+    assert_eq!(format_exposure_time(rational(1, 0)), None);
+    assert_eq!(format_exposure_time(rational(0, 1)), None);
+  }
+
+  #[test]
+  fn iso_falls_back_to_photographic_sensitivity() {
+    let path = test_util::write_exif_jpeg(
+      "iso_fallback",
+      &[test_util::exif_field(
+        Tag::PhotographicSensitivity,
+        Value::Short(vec![200]),
+      )],
+    );
+    assert_eq!(parse_exif_data(&path).unwrap().iso, Some("200".to_string()));
+  }
+
+  #[test]
+  fn iso_speed_takes_priority() {
+    let path = test_util::write_exif_jpeg(
+      "iso_speed",
+      &[
+        test_util::exif_field(Tag::PhotographicSensitivity, Value::Short(vec![200])),
+        test_util::exif_field(Tag::ISOSpeed, Value::Long(vec![400])),
+      ],
+    );
+    assert_eq!(parse_exif_data(&path).unwrap().iso, Some("400".to_string()));
+  }
+
+  #[test]
+  fn parse_offset_time_reads_sign_and_minutes() {
+    assert_eq!(parse_offset_time("+09:00"), FixedOffset::east_opt(9 * 3600));
+    assert_eq!(
+      parse_offset_time("-05:30"),
+      FixedOffset::east_opt(-(5 * 3600 + 30 * 60))
+    );
+    assert_eq!(parse_offset_time("   :  "), None);
+    assert_eq!(parse_offset_time("09:00"), None);
+    assert_eq!(parse_offset_time("+09:60"), None);
+    assert_eq!(parse_offset_time(""), None);
+  }
+
+  #[test]
+  fn offset_time_is_read_from_exif() {
+    let path = test_util::write_exif_jpeg(
+      "offset_time",
+      &[test_util::exif_field(
+        Tag::OffsetTimeOriginal,
+        Value::Ascii(vec![b"+09:00".to_vec()]),
+      )],
+    );
+    assert_eq!(
+      parse_exif_data(&path).unwrap().offset_time,
+      Some("+09:00".to_string())
+    );
+    // 時差が不明な場合は記録しない
+    let path = test_util::write_exif_jpeg(
+      "offset_time_unknown",
+      &[test_util::exif_field(
+        Tag::OffsetTimeOriginal,
+        Value::Ascii(vec![b"   :  ".to_vec()]),
+      )],
+    );
+    assert_eq!(parse_exif_data(&path).unwrap().offset_time, None);
+  }
+
+  #[test]
+  fn focal_length_35mm_is_read_from_exif() {
+    let path = test_util::write_exif_jpeg(
+      "focal_length_35mm",
+      &[
+        test_util::exif_field(Tag::FocalLengthIn35mmFilm, Value::Short(vec![50])),
+        test_util::exif_field(
+          Tag::FocalLength,
+          Value::Rational(vec![exif::Rational { num: 33, denom: 1 }]),
+        ),
+      ],
+    );
+    assert_eq!(
+      parse_exif_data(&path).unwrap().focal_length_35mm,
+      Some("50".to_string())
+    );
+    // 0は不明を表すので記録しない
+    let path = test_util::write_exif_jpeg(
+      "focal_length_35mm_unknown",
+      &[test_util::exif_field(
+        Tag::FocalLengthIn35mmFilm,
+        Value::Short(vec![0]),
+      )],
+    );
+    assert_eq!(parse_exif_data(&path).unwrap().focal_length_35mm, None);
+  }
+
+  #[test]
+  fn build_public_url_normalizes_slashes() {
+    let photo_src = "/images/normal/sea.JPG";
+    for base_url in [
+      "https://example.com",
+      "https://example.com/",
+      "https://example.com//",
+    ] {
+      assert_eq!(
+        build_public_url(base_url, photo_src),
+        "https://example.com/images/normal/sea.JPG"
+      );
+    }
+    assert_eq!(
+      build_public_url("https://example.com/photos/", "images//normal/sea.JPG"),
+      "https://example.com/photos/images/normal/sea.JPG"
+    );
+    // スキームが無い場合もそのまま繋ぐ
+    assert_eq!(
+      build_public_url("example.com", photo_src),
+      "example.com/images/normal/sea.JPG"
+    );
+  }
+
+  #[test]
+  fn apply_photo_data_patch_skips_locked_and_unchanged_photos() {
+    let mut gui_photo_data_lst = HashMap::new();
+    for (id, location, locked) in [
+      ("1", "東京", false),
+      ("2", "大阪", false),
+      ("3", "京都", true),
+    ] {
+      gui_photo_data_lst.insert(
+        id.to_string(),
+        GUIPhotoData {
+          photo_id: id.to_string(),
+          location: location.to_string(),
+          body: "X-T4".to_string(),
+          locked,
+          ..make_dummy_gui_photo_data()
+        },
+      );
+    }
+    let patch = PhotoDataPatch {
+      location: Some("東京".to_string()),
+      ..PhotoDataPatch::default()
+    };
+    assert!(!patch.is_empty());
+    let photo_id_lst = ["1", "2", "3", "4"].map(String::from);
+    let before_lst = apply_photo_data_patch(&mut gui_photo_data_lst, &photo_id_lst, &patch);
+    // 値が変わった写真だけ変更前のデータを返す
+    assert_eq!(before_lst.len(), 1);
+    assert_eq!(before_lst[0].photo_id, "2");
+    assert_eq!(before_lst[0].location, "大阪");
+    assert_eq!(gui_photo_data_lst["2"].location, "東京");
+    // 指定していない項目は変えない
+    assert_eq!(gui_photo_data_lst["2"].body, "X-T4");
+    // ロックされている写真は変えない
+    assert_eq!(gui_photo_data_lst["3"].location, "京都");
+    assert!(PhotoDataPatch::default().is_empty());
+  }
+
+  fn make_import_photo_data(id: &str, file_name: &str) -> ImportPhotoData {
+    ImportPhotoData {
+      file_name: file_name.to_string(),
+      id: id.to_string(),
+      alt: String::new(),
+      location: String::new(),
+    }
+  }
+
+  #[test]
+  fn find_missing_files_keeps_input_order() {
+    let original_dir = test_util::temp_dir("missing_files");
+    std::fs::write(format!("{}/b.jpg", original_dir), b"").unwrap();
+    std::fs::create_dir(format!("{}/c.jpg", original_dir)).unwrap();
+    let import_photo_data_lst = vec![
+      make_import_photo_data("d", "d.jpg"),
+      make_import_photo_data("b", "b.jpg"),
+      make_import_photo_data("a", "a.jpg"),
+      make_import_photo_data("c", "c.jpg"),
+    ];
+    // フォルダはファイルとして扱わない
+    assert_eq!(
+      find_missing_files(&import_photo_data_lst, &original_dir),
+      vec![
+        make_import_photo_data("d", "d.jpg"),
+        make_import_photo_data("a", "a.jpg"),
+        make_import_photo_data("c", "c.jpg"),
+      ]
+    );
+  }
+
+  #[test]
+  fn is_in_date_range_includes_both_ends() {
+    let date = |day| NaiveDate::from_ymd_opt(2022, 8, day);
+    assert!(is_in_date_range(date(1), date(1), date(31)));
+    assert!(is_in_date_range(date(31), date(1), date(31)));
+    assert!(!is_in_date_range(
+      NaiveDate::from_ymd_opt(2022, 7, 31),
+      date(1),
+      date(31)
+    ));
+    assert!(!is_in_date_range(
+      NaiveDate::from_ymd_opt(2022, 9, 1),
+      date(1),
+      date(31)
+    ));
+    // 片方だけの指定
+    assert!(is_in_date_range(date(20), date(10), None));
+    assert!(!is_in_date_range(date(5), date(10), None));
+    assert!(is_in_date_range(date(5), None, date(10)));
+    // 撮影日の無い写真は期間を指定したときだけ除く
+    assert!(!is_in_date_range(None, date(1), date(31)));
+    assert!(!is_in_date_range(None, None, date(31)));
+    assert!(is_in_date_range(None, None, None));
+  }
+
+  #[test]
+  fn new_group_from_selection_keeps_photo_order() {
+    let photo_id_lst = ["3", "1", "2"].map(String::from);
+    let group_data = make_gui_group_data_from_photo_id_lst(&photo_id_lst);
+    assert_eq!(group_data.photo_id_list, vec!["3", "1", "2"]);
+    // IDやタイトルは作成する前に入力してもらう
+    assert_eq!(
+      group_data,
+      GUIGroupData {
+        photo_id_list: photo_id_lst.to_vec(),
+        ..make_dummy_gui_group_data()
+      }
+    );
+    assert!(make_gui_group_data_from_photo_id_lst(&[])
+      .photo_id_list
+      .is_empty());
+  }
+
+  fn make_group_tree(parent_lst: &[(&str, Option<&str>)]) -> HashMap<String, GUIGroupData> {
+    parent_lst
+      .iter()
+      .map(|(group_id, parent_id)| {
+        (
+          group_id.to_string(),
+          GUIGroupData {
+            parent_group_id: parent_id.map(str::to_string),
+            ..make_test_group_data(group_id, &[])
+          },
+        )
+      })
+      .collect()
+  }
+
+  #[test]
+  fn is_valid_parent_rejects_cycles() {
+    let gui_group_data_lst = make_group_tree(&[
+      ("japan", None),
+      ("tokyo", Some("japan")),
+      ("shibuya", Some("tokyo")),
+      ("osaka", Some("japan")),
+    ]);
+    assert!(is_valid_parent("osaka", "tokyo", &gui_group_data_lst));
+    assert!(is_valid_parent("shibuya", "osaka", &gui_group_data_lst));
+    assert!(!is_valid_parent("tokyo", "tokyo", &gui_group_data_lst));
+    // 子孫を親にすると循環する
+    assert!(!is_valid_parent("japan", "shibuya", &gui_group_data_lst));
+    assert!(!is_valid_parent("tokyo", "shibuya", &gui_group_data_lst));
+    assert!(!is_valid_parent("tokyo", "kyoto", &gui_group_data_lst));
+  }
+
+  #[test]
+  fn group_tree_order_follows_parents() {
+    let gui_group_data_lst = make_group_tree(&[
+      ("tokyo", Some("japan")),
+      ("shibuya", Some("tokyo")),
+      ("japan", None),
+      ("x", Some("y")),
+      ("y", Some("x")),
+      ("osaka", Some("japan")),
+      ("orphan", Some("missing")),
+    ]);
+    let group_id_lst = ["tokyo", "shibuya", "japan", "x", "y", "osaka", "orphan"].map(String::from);
+    assert_eq!(
+      group_tree_order(&group_id_lst, &gui_group_data_lst),
+      [
+        ("japan", 0),
+        ("tokyo", 1),
+        ("shibuya", 2),
+        ("osaka", 1),
+        ("orphan", 0),
+        // 循環しているグループは最後に最上位として並べる
+        ("x", 0),
+        ("y", 1),
+      ]
+      .map(|(id, depth)| (id.to_string(), depth))
+      .to_vec()
+    );
+  }
+
+  #[test]
+  fn valid_ids_are_safe_for_paths_and_urls() {
+    for id in ["IMG_0001.jpg", "a-b", "...", "2022.08.01"] {
+      assert!(is_valid_id(id), "{}", id);
+    }
+    for id in ["", ".", "..", "a/b", "a\\b", "a b", "a%b", "写真", "a?b#c"] {
+      assert!(!is_valid_id(id), "{}", id);
+    }
+  }
+
+  #[test]
+  fn sanitize_id_percent_encodes_unsafe_characters() {
+    assert_eq!(sanitize_id("IMG_0001"), "IMG_0001");
+    assert_eq!(sanitize_id("a/b"), "a%2Fb");
+    assert_eq!(sanitize_id("a%b"), "a%25b");
+    assert_eq!(sanitize_id("写"), "%E5%86%99");
+    assert_eq!(sanitize_id("."), "%2E");
+    assert_eq!(sanitize_id(".."), "%2E%2E");
+    assert_eq!(sanitize_id("../x"), "..%2Fx");
+    // エンコードした結果が元々のIDと重ならない
+    assert_ne!(sanitize_id("a/b"), sanitize_id("a%2Fb"));
+    assert_eq!(src_id("a/b"), "a%252Fb");
+  }
+
+  #[test]
+  fn src_round_trips_to_the_sanitized_file_path() {
+    for id in ["IMG_0001", "a/b", "a%b", "夏 休み", ".."] {
+      for url_prefix in ["/", "/gallery/"] {
+        assert_eq!(
+          src_to_path(&photo_src(url_prefix, id)),
+          format!("images/normal/{}.JPG", sanitize_id(id))
+        );
+        assert_eq!(
+          src_to_path(&photo_lazy_src(url_prefix, id)),
+          format!("images/lazy/{}.JPG", sanitize_id(id))
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn diff_import_data_reports_added_removed_and_changed() {
+    let old = vec![
+      make_import_photo_data("a", "a.jpg"),
+      make_import_photo_data("b", "b.jpg"),
+      make_import_photo_data("c", "c.jpg"),
+      make_import_photo_data("d", "d.jpg"),
+    ];
+    assert!(diff_import_data(&old, &old).is_empty());
+    let mut new = vec![
+      make_import_photo_data("e", "e.jpg"),
+      make_import_photo_data("d", "d2.jpg"),
+      make_import_photo_data("a", "a.jpg"),
+      make_import_photo_data("b", "b.jpg"),
+    ];
+    new[3].alt = "説明".to_string();
+    // 並び順だけが変わった画像は変更に含めない
+    assert_eq!(
+      diff_import_data(&old, &new),
+      ImportDiff {
+        added: vec![make_import_photo_data("e", "e.jpg")],
+        removed: vec!["c".to_string()],
+        changed: vec![new[1].clone(), new[3].clone()],
+      }
+    );
+    let diff = diff_import_data(&[], &old);
+    assert_eq!(diff.added, old);
+    assert!(diff.removed.is_empty() && diff.changed.is_empty());
+  }
+
+  #[test]
+  fn version_2_data_loads_without_flags() {
+    let json = format!(
+      r#"{{ "version": 2, "data": [{}] }}"#,
+      LEGACY_PHOTO_DATA_JSON
+    );
+    let photo_data_lst: Vec<PhotoData> = save::read_versioned_data(json.as_bytes()).unwrap();
+    assert_eq!(photo_data_lst[0].flag, None);
+    assert_eq!(
+      photo_data_to_gui_photo_data(photo_data_lst[0].clone()).flag,
+      None
+    );
+  }
+
+  #[test]
+  fn set_flag_skips_locked_and_unchanged_photos() {
+    let mut gui_photo_data_lst = ["a", "b", "c", "d"]
+      .iter()
+      .map(|id| {
+        (
+          id.to_string(),
+          GUIPhotoData {
+            photo_id: id.to_string(),
+            locked: *id == "d",
+            ..make_dummy_gui_photo_data()
+          },
+        )
+      })
+      .collect::<HashMap<_, _>>();
+    let id_lst = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+    let before_lst = set_flag(
+      &mut gui_photo_data_lst,
+      &id_lst(&["a", "b", "d"]),
+      Some(FLAG_PICK),
+    );
+    assert_eq!(before_lst.len(), 2);
+    assert!(before_lst
+      .iter()
+      .all(|photo_data| photo_data.flag.is_none()));
+    assert_eq!(gui_photo_data_lst["d"].flag, None);
+    // 既に同じフラグの写真は変更したことにしない
+    let before_lst = set_flag(
+      &mut gui_photo_data_lst,
+      &id_lst(&["b", "c"]),
+      Some(FLAG_REJECT),
+    );
+    assert_eq!(before_lst.len(), 2);
+    assert!(set_flag(&mut gui_photo_data_lst, &id_lst(&["c"]), Some(FLAG_REJECT)).is_empty());
+    assert_eq!(count_by_flag(gui_photo_data_lst.values()), (1, 2, 1));
+    // `None`で保留に戻す
+    let before_lst = set_flag(&mut gui_photo_data_lst, &id_lst(&["a", "missing"]), None);
+    assert_eq!(before_lst[0].flag.as_deref(), Some(FLAG_PICK));
+    assert_eq!(count_by_flag(gui_photo_data_lst.values()), (0, 2, 2));
+  }
+
+  #[test]
+  fn flag_labels_and_rejection() {
+    assert_eq!(flag_label(Some(FLAG_PICK)), "採用");
+    assert_eq!(flag_label(Some(FLAG_REJECT)), "不採用");
+    assert_eq!(flag_label(None), "保留");
+    // 知らない値は保留として扱う
+    assert_eq!(flag_label(Some("maybe")), "保留");
+    assert!(is_rejected(Some(FLAG_REJECT)));
+    assert!(!is_rejected(Some(FLAG_PICK)));
+    assert!(!is_rejected(None));
+  }
+
+  #[test]
+  fn dump_all_exif_lists_primary_fields_in_file_order() {
+    let ascii = |s: &str| Value::Ascii(vec![s.as_bytes().to_vec()]);
+    let rational = |num, denom| Value::Rational(vec![exif::Rational { num, denom }]);
+    let path = test_util::write_exif_jpeg(
+      "dump_all_exif",
+      &[
+        test_util::exif_field(Tag::PhotographicSensitivity, Value::Short(vec![200])),
+        test_util::exif_field(Tag::ExposureTime, rational(1, 250)),
+        test_util::exif_field(Tag::Model, ascii("EOS R6")),
+        test_util::exif_field(Tag::FNumber, rational(28, 10)),
+        test_util::exif_field(Tag::Make, ascii("Canon")),
+        // サムネイル用のフィールドは含めない
+        exif::Field {
+          tag: Tag::Software,
+          ifd_num: exif::In::THUMBNAIL,
+          value: ascii("thumbnail"),
+        },
+      ],
+    );
+    let expected = [
+      ("Make", "\"Canon\""),
+      ("Model", "\"EOS R6\""),
+      ("ExposureTime", "1/250 s"),
+      ("FNumber", "f/2.8"),
+      ("PhotographicSensitivity", "200"),
+    ]
+    .map(|(tag, value)| (tag.to_string(), value.to_string()));
+    assert_eq!(dump_all_exif(&path).unwrap(), expected);
+  }
+
+  #[test]
+  fn dump_all_exif_fails_without_exif() {
+    let path = format!("{}/plain.jpg", test_util::temp_dir("dump_no_exif"));
+    std::fs::write(&path, test_util::make_test_jpeg(8, 8)).unwrap();
+    assert!(dump_all_exif(&path).is_err());
+  }
+
+  #[test]
+  fn join_url_prefix_normalizes_slashes() {
+    let path = "images/normal/a.JPG";
+    assert_eq!(join_url_prefix("", path), "/images/normal/a.JPG");
+    assert_eq!(join_url_prefix("/", path), "/images/normal/a.JPG");
+    for url_prefix in ["/gallery", "/gallery/", "gallery", "//gallery//"] {
+      assert_eq!(
+        join_url_prefix(url_prefix, path),
+        "/gallery/images/normal/a.JPG"
+      );
+    }
+    assert_eq!(
+      join_url_prefix("/photos/2022/", path),
+      "/photos/2022/images/normal/a.JPG"
+    );
+  }
+
+  #[test]
+  fn strip_url_prefix_round_trips() {
+    assert_eq!(
+      strip_url_prefix("/gallery/images/normal/a.JPG"),
+      "images/normal/a.JPG"
+    );
+    assert_eq!(strip_url_prefix("images/lazy/a.JPG"), "images/lazy/a.JPG");
+    // 前に付けたpathに`images`が含まれていても最後の`/images/`から取り出す
+    assert_eq!(
+      strip_url_prefix("/images/gallery/images/normal/a.JPG"),
+      "images/normal/a.JPG"
+    );
+    for url_prefix in ["", "/", "/gallery/", "/images/"] {
+      let src = srcset_src(url_prefix, "a/b", 480);
+      assert_eq!(src_to_path(&src), "images/srcset/a%2Fb-480.JPG");
+    }
+  }
+
+  #[test]
+  fn group_srcs_respect_url_prefix() {
+    assert_eq!(cover_src("/", "sea"), "/images/covers/sea.JPG");
+    assert_eq!(group_page_src("/", "sea"), "/sea.html");
+    for url_prefix in ["/gallery", "gallery/", "//gallery//"] {
+      assert_eq!(
+        cover_src(url_prefix, "sea"),
+        "/gallery/images/covers/sea.JPG"
+      );
+      assert_eq!(group_page_src(url_prefix, "sea"), "/gallery/sea.html");
+    }
+    // 作業ディレクトリの中のpathは前に付けたpathによらない
+    assert_eq!(
+      src_to_path(&cover_src("/gallery/", "a/b")),
+      "images/covers/a%2Fb.JPG"
+    );
+  }
+
+  fn make_duplicate_import_photo_data_lst() -> Vec<ImportPhotoData> {
+    [
+      ("x", "a.jpg"),
+      ("y", "b.jpg"),
+      ("x", "c.jpg"),
+      ("z", "d.jpg"),
+      ("y", "e.jpg"),
+      ("x", "f.jpg"),
+    ]
+    .iter()
+    .map(|(id, file_name)| make_import_photo_data(id, file_name))
+    .collect()
+  }
+
+  #[test]
+  fn duplicate_ids_are_listed_in_order_of_appearance() {
+    let import_photo_data_lst = make_duplicate_import_photo_data_lst();
+    let duplicate_id_lst = find_duplicate_ids(&import_photo_data_lst);
+    assert_eq!(
+      duplicate_id_lst
+        .iter()
+        .map(|duplicate_id| (duplicate_id.id.as_str(), duplicate_id.file_names()))
+        .collect::<Vec<_>>(),
+      vec![
+        ("x", "a.jpg、c.jpg、f.jpg".to_string()),
+        ("y", "b.jpg、e.jpg".to_string())
+      ]
+    );
+    assert_eq!(
+      duplicate_id_lst[0].skipped(),
+      [
+        make_import_photo_data("x", "c.jpg"),
+        make_import_photo_data("x", "f.jpg")
+      ]
+    );
+    assert_eq!(
+      ensure_unique_ids(&import_photo_data_lst)
+        .unwrap_err()
+        .to_string(),
+      "2個のIDが複数の画像に付けられています\n  x（a.jpg、c.jpg、f.jpg）\n  y（b.jpg、e.jpg）"
+    );
+  }
+
+  #[test]
+  fn first_photo_wins_for_duplicate_ids() {
+    let mut import_photo_data_lst = make_duplicate_import_photo_data_lst();
+    // 並び順の日を撮影日にした画像を用意し、どの画像のExifを使ったかを見分ける
+    let original_path = test_util::temp_dir("duplicate_ids");
+    for (i, import_photo_data) in import_photo_data_lst.iter().enumerate() {
+      let datetime = format!("2022:08:{:02} 12:00:00", i + 1);
+      std::fs::write(
+        format!("{}/{}", original_path, import_photo_data.file_name),
+        test_util::make_exif_jpeg(&[test_util::exif_field(
+          Tag::DateTimeOriginal,
+          Value::Ascii(vec![datetime.into_bytes()]),
+        )]),
+      )
+      .unwrap();
+    }
+    let (photo_id_lst, photo_data_lst) = merge_photo_data_based_and_import_photo_data(
+      &HashMap::new(),
+      &import_photo_data_lst,
+      &original_path,
+      2,
+      DEFAULT_URL_PREFIX,
+    )
+    .unwrap();
+    assert_eq!(photo_id_lst, vec!["x", "y", "z"]);
+    assert_eq!(
+      photo_data_lst
+        .iter()
+        .map(|photo_data| (photo_data.file_name.as_str(), photo_data.day.as_deref()))
+        .collect::<Vec<_>>(),
+      vec![
+        ("a.jpg", Some("1")),
+        ("b.jpg", Some("2")),
+        ("d.jpg", Some("4"))
+      ]
+    );
+    dedup_import_photo_data(&mut import_photo_data_lst);
+    assert_eq!(
+      import_photo_data_lst,
+      vec![
+        make_import_photo_data("x", "a.jpg"),
+        make_import_photo_data("y", "b.jpg"),
+        make_import_photo_data("z", "d.jpg")
+      ]
+    );
+    assert!(find_duplicate_ids(&import_photo_data_lst).is_empty());
+    assert!(ensure_unique_ids(&import_photo_data_lst).is_ok());
+  }
+
+  #[test]
+  fn capture_datetime_is_read_from_exif() {
+    let path = test_util::write_exif_jpeg(
+      "capture_datetime",
+      &[test_util::exif_field(
+        Tag::DateTimeOriginal,
+        Value::Ascii(vec![b"2022:08:01 14:05:09".to_vec()]),
+      )],
+    );
+    let minimal_exif = parse_exif_data(&path).unwrap();
+    assert_eq!(minimal_exif.year.as_deref(), Some("2022"));
+    assert_eq!(minimal_exif.month.as_deref(), Some("8"));
+    assert_eq!(minimal_exif.day.as_deref(), Some("1"));
+    assert_eq!(minimal_exif.hour.as_deref(), Some("14"));
+    assert_eq!(minimal_exif.minutes.as_deref(), Some("5"));
+  }
+
+  #[test]
+  fn aligning_timestamps_skips_locked_photos() {
+    let original_dir = test_util::temp_dir("align_locked");
+    let file_datetime = NaiveDate::from_ymd_opt(2022, 8, 1)
+      .unwrap()
+      .and_hms_opt(9, 0, 0)
+      .unwrap();
+    let exif_datetime = NaiveDate::from_ymd_opt(2021, 5, 3)
+      .unwrap()
+      .and_hms_opt(18, 30, 0)
+      .unwrap();
+    let mut gui_photo_data_lst = HashMap::new();
+    let mut discrepancy_lst = Vec::new();
+    for (id, locked) in [("a", false), ("b", true)] {
+      let file_name = format!("{}.jpg", id);
+      let path = format!("{}/{}", original_dir, file_name);
+      std::fs::write(&path, b"image").unwrap();
+      save::set_file_modified_timestamp(&path, file_datetime).unwrap();
+      let mut photo_data = GUIPhotoData {
+        photo_id: id.to_string(),
+        file_name,
+        locked,
+        ..make_dummy_gui_photo_data()
+      };
+      set_photo_datetime(&mut photo_data, exif_datetime);
+      gui_photo_data_lst.insert(id.to_string(), photo_data);
+      discrepancy_lst.push(TimestampDiscrepancy {
+        photo_id: id.to_string(),
+        exif_datetime,
+        file_datetime,
+      });
+    }
+    let file_datetime_of = |id: &str| {
+      save::get_file_modified_timestamp(&format!("{}/{}.jpg", original_dir, id))
+        .unwrap()
+        .naive_local()
+    };
+
+    assert_eq!(
+      align_file_to_datetime(&gui_photo_data_lst, &discrepancy_lst, &original_dir).unwrap(),
+      1
+    );
+    assert_eq!(file_datetime_of("a"), exif_datetime);
+    assert_eq!(file_datetime_of("b"), file_datetime);
+
+    let before_lst = align_datetime_to_file(&mut gui_photo_data_lst, &discrepancy_lst);
+    assert_eq!(before_lst.len(), 1);
+    assert_eq!(before_lst[0].photo_id, "a");
+    assert_eq!(
+      photo_datetime(&gui_photo_data_lst["a"]),
+      Some(file_datetime)
+    );
+    assert_eq!(
+      photo_datetime(&gui_photo_data_lst["b"]),
+      Some(exif_datetime)
+    );
+  }
+
+  #[test]
+  fn shooting_data_notations_are_normalized() {
+    for (value, expected) in [
+      ("f/2.8", Some("2.8")),
+      ("F/4", Some("4")),
+      ("F2.8", Some("2.8")),
+      (" 2.80 ", Some("2.8")),
+      ("inf", None),
+      ("", None),
+      ("開放", None),
+    ] {
+      assert_eq!(normalize_f_value(value).as_deref(), expected, "{}", value);
+    }
+    for (value, expected) in [
+      ("24mm", Some("24")),
+      ("24 mm", Some("24")),
+      ("35.5 mm", Some("35.5")),
+      ("50.0", Some("50")),
+      ("mm", None),
+    ] {
+      assert_eq!(
+        normalize_focal_length(value).as_deref(),
+        expected,
+        "{}",
+        value
+      );
+    }
+    for (value, expected) in [
+      ("1/250s", Some("1/250")),
+      ("1/250 s", Some("1/250")),
+      ("1/250 秒", Some("1/250")),
+      ("2s", Some("2")),
+      ("0.5", Some("0.5")),
+      ("1/0", None),
+      ("速い", None),
+    ] {
+      assert_eq!(normalize_time(value).as_deref(), expected, "{}", value);
+    }
+    for (value, expected) in [
+      ("ISO100", Some("100")),
+      ("ISO 100", Some("100")),
+      ("iso 3200", Some("3200")),
+      ("100", Some("100")),
+      ("100.5", None),
+      ("AUTO", None),
+    ] {
+      assert_eq!(normalize_iso(value).as_deref(), expected, "{}", value);
+    }
+  }
+
+  #[test]
+  fn normalizing_all_photos_returns_previous_data() {
+    let make_photo_data = |id: &str, locked: bool| GUIPhotoData {
+      photo_id: id.to_string(),
+      f_value: "f/2.8".to_string(),
+      max_f_value: "2.8".to_string(),
+      focal_length: "24 mm".to_string(),
+      time: "不明".to_string(),
+      iso: "ISO 100".to_string(),
+      locked,
+      ..make_dummy_gui_photo_data()
+    };
+    let mut gui_photo_data_lst = HashMap::new();
+    gui_photo_data_lst.insert("a".to_string(), make_photo_data("a", false));
+    gui_photo_data_lst.insert("b".to_string(), make_photo_data("b", true));
+    gui_photo_data_lst.insert(
+      "c".to_string(),
+      GUIPhotoData {
+        photo_id: "c".to_string(),
+        ..make_dummy_gui_photo_data()
+      },
+    );
+    let (count, before_lst) = normalize_all_photo_metadata(&mut gui_photo_data_lst);
+    // F値・焦点距離・ISO感度の3項目だけが変わり、解釈できない値はそのまま残す
+    assert_eq!(count, 3);
+    assert_eq!(before_lst, vec![make_photo_data("a", false)]);
+    let photo_data = &gui_photo_data_lst["a"];
+    assert_eq!(
+      [
+        photo_data.f_value.as_str(),
+        photo_data.max_f_value.as_str(),
+        photo_data.focal_length.as_str(),
+        photo_data.time.as_str(),
+        photo_data.iso.as_str()
+      ],
+      ["2.8", "2.8", "24", "不明", "100"]
+    );
+    // ロックされている写真は変更しない
+    assert_eq!(gui_photo_data_lst["b"], make_photo_data("b", true));
+    assert_eq!(
+      normalize_all_photo_metadata(&mut gui_photo_data_lst),
+      (0, Vec::new())
+    );
+  }
+
+  #[test]
+  fn only_empty_fields_are_filled_from_exif() {
+    let path = test_util::write_exif_jpeg(
+      "fill_empty_fields",
+      &[
+        test_util::exif_field(
+          Tag::DateTimeOriginal,
+          Value::Ascii(vec![b"2022:08:01 14:05:09".to_vec()]),
+        ),
+        test_util::exif_field(Tag::LensModel, Value::Ascii(vec![b"50mm F1.8".to_vec()])),
+        test_util::exif_field(Tag::PhotographicSensitivity, Value::Short(vec![400])),
+      ],
+    );
+    let mut photo_data = GUIPhotoData {
+      year: "2020".to_string(),
+      lens: "手入力のレンズ".to_string(),
+      ..make_dummy_gui_photo_data()
+    };
+    let count = fill_empty_fields_from_exif(&mut photo_data, parse_exif_data(&path).unwrap());
+    // 入力済みの撮影年とレンズはそのまま
+    assert_eq!(photo_data.year, "2020");
+    assert_eq!(photo_data.lens, "手入力のレンズ");
+    // 空欄だった月・日・時・分・ISO感度だけを埋める
+    assert_eq!(
+      [
+        photo_data.month.as_str(),
+        photo_data.day.as_str(),
+        photo_data.hour.as_str(),
+        photo_data.minutes.as_str(),
+        photo_data.iso.as_str(),
+      ],
+      ["8", "1", "14", "5", "400"]
+    );
+    assert_eq!(count, 5);
+    // Exifに無い項目は空欄のまま
+    assert!(photo_data.body.is_empty());
+    assert_eq!(photo_data.rating, 0);
+    // もう一度埋めても何も変わらない
+    let before = photo_data.clone();
+    let count = fill_empty_fields_from_exif(&mut photo_data, parse_exif_data(&path).unwrap());
+    assert_eq!(count, 0);
+    assert_eq!(photo_data, before);
+  }
+
+  /// 評価を属性で記録したXMP
+  const XMP_WITH_RATING_ATTRIBUTE: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/" xmp:Rating="4">
+   <dc:title><rdf:Alt><rdf:li xml:lang="x-default">夕焼けの海</rdf:li></rdf:Alt></dc:title>
+   <dc:description><rdf:Alt><rdf:li xml:lang="x-default">砂浜と&amp;波</rdf:li></rdf:Alt></dc:description>
+   <dc:subject><rdf:Bag><rdf:li>海</rdf:li><rdf:li>夕日</rdf:li></rdf:Bag></dc:subject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+  /// 評価を要素で記録したXMP
+  const XMP_WITH_RATING_ELEMENT: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+   <xmp:Rating>2</xmp:Rating>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+  #[test]
+  fn xmp_sidecar_is_read_from_either_file_name() {
+    let original_dir = test_util::temp_dir("xmp_sidecar");
+    // `IMG_0001.JPG.xmp`の形の名前
+    std::fs::write(
+      format!("{}/IMG_0001.JPG.xmp", original_dir),
+      XMP_WITH_RATING_ATTRIBUTE,
+    )
+    .unwrap();
+    // 拡張子を置き換えた`IMG_0002.xmp`の形の名前
+    std::fs::write(
+      format!("{}/IMG_0002.xmp", original_dir),
+      XMP_WITH_RATING_ELEMENT,
+    )
+    .unwrap();
+    assert_eq!(
+      parse_xmp_sidecar(&format!("{}/IMG_0001.JPG", original_dir)),
+      Some(XmpSidecar {
+        title: Some("夕焼けの海".to_string()),
+        description: Some("砂浜と&波".to_string()),
+        rating: Some(4),
+        keywords: vec!["海".to_string(), "夕日".to_string()],
+      })
+    );
+    assert_eq!(
+      parse_xmp_sidecar(&format!("{}/IMG_0002.JPG", original_dir)),
+      Some(XmpSidecar {
+        rating: Some(2),
+        ..XmpSidecar::default()
+      })
+    );
+    assert_eq!(
+      parse_xmp_sidecar(&format!("{}/IMG_0003.JPG", original_dir)),
+      None
+    );
+  }
+
+  #[test]
+  fn malformed_xmp_sidecar_is_ignored() {
+    let original_dir = test_util::temp_dir("malformed_xmp");
+    // XMPではないファイル
+    std::fs::write(format!("{}/a.xmp", original_dir), "not xmp").unwrap();
+    assert_eq!(parse_xmp_sidecar(&format!("{}/a.jpg", original_dir)), None);
+    // 途中で切れていて要素が閉じていないファイル
+    std::fs::write(
+      format!("{}/b.xmp", original_dir),
+      r#"<x:xmpmeta><dc:title><rdf:Alt><rdf:li>途中で"#,
+    )
+    .unwrap();
+    let xmp = parse_xmp_sidecar(&format!("{}/b.jpg", original_dir)).unwrap();
+    assert_eq!(xmp, XmpSidecar::default());
+    let mut photo_data = make_dummy_gui_photo_data();
+    assert_eq!(fill_empty_fields_from_xmp(&mut photo_data, xmp), 0);
+    assert_eq!(photo_data, make_dummy_gui_photo_data());
+  }
+
+  #[test]
+  fn xmp_fills_only_newly_imported_photos() {
+    let original_dir = test_util::temp_dir("xmp_new_photos");
+    for file_name in ["new.jpg", "saved.jpg"] {
+      std::fs::write(
+        format!("{}/{}.xmp", original_dir, file_name),
+        XMP_WITH_RATING_ATTRIBUTE,
+      )
+      .unwrap();
+    }
+    let make_gui_photo_data = |photo_id: &str| GUIPhotoData {
+      photo_id: photo_id.to_string(),
+      file_name: format!("{}.jpg", photo_id),
+      keywords: "手入力".to_string(),
+      ..make_dummy_gui_photo_data()
+    };
+    let saved = make_gui_photo_data("saved");
+    let mut saved_photo_data_lst = HashMap::new();
+    saved_photo_data_lst.insert(
+      "saved".to_string(),
+      gui_photo_data_to_photo_data(saved.clone()),
+    );
+    let mut gui_photo_data_lst = HashMap::new();
+    gui_photo_data_lst.insert("new".to_string(), make_gui_photo_data("new"));
+    gui_photo_data_lst.insert("saved".to_string(), saved.clone());
+    fill_new_photos_from_xmp(
+      &mut gui_photo_data_lst,
+      &saved_photo_data_lst,
+      &original_dir,
+    );
+    let new = &gui_photo_data_lst["new"];
+    assert_eq!(new.title, "夕焼けの海");
+    assert_eq!(new.alt, "砂浜と&波");
+    assert_eq!(new.rating, 4);
+    // 入力済みのキーワードは上書きしない
+    assert_eq!(new.keywords, "手入力");
+    // 保存済みの写真の空欄は埋め直さない
+    assert_eq!(gui_photo_data_lst["saved"], saved);
+  }
+
+  #[test]
+  fn previous_photo_datetime_skips_undated_photos() {
+    // 日と時だけを変え、日が空欄の写真は撮影日時が無いものとする
+    let make_photo_data = |day: &str, hour: &str| GUIPhotoData {
+      year: "2022".to_string(),
+      month: "8".to_string(),
+      day: day.to_string(),
+      hour: hour.to_string(),
+      minutes: "30".to_string(),
+      ..make_dummy_gui_photo_data()
+    };
+    let photo_id_lst = ["1", "2", "3", "4"].map(String::from);
+    let mut gui_photo_data_lst = HashMap::new();
+    gui_photo_data_lst.insert("1".to_string(), make_photo_data("1", "9"));
+    gui_photo_data_lst.insert("2".to_string(), make_photo_data("2", "10"));
+    gui_photo_data_lst.insert("3".to_string(), make_photo_data("", ""));
+    gui_photo_data_lst.insert("4".to_string(), make_photo_data("", ""));
+    let datetime = |day, hour| {
+      NaiveDate::from_ymd_opt(2022, 8, day)
+        .unwrap()
+        .and_hms_opt(hour, 30, 0)
+    };
+    // 撮影日時の無い直前の写真を飛ばして遡る
+    assert_eq!(
+      previous_photo_datetime("4", &photo_id_lst, &gui_photo_data_lst),
+      datetime(2, 10)
+    );
+    assert_eq!(
+      previous_photo_datetime("2", &photo_id_lst, &gui_photo_data_lst),
+      datetime(1, 9)
+    );
+    // 先頭の写真・一覧に無い写真には前が無い
+    assert_eq!(
+      previous_photo_datetime("1", &photo_id_lst, &gui_photo_data_lst),
+      None
+    );
+    assert_eq!(
+      previous_photo_datetime("5", &photo_id_lst, &gui_photo_data_lst),
+      None
+    );
+  }
+
+  #[test]
+  fn add_minutes_carries_over_into_day_month_and_year() {
+    let datetime = |year, month, day, hour, minutes| {
+      NaiveDate::from_ymd_opt(year, month, day)
+        .unwrap()
+        .and_hms_opt(hour, minutes, 0)
+        .unwrap()
+    };
+    assert_eq!(
+      add_minutes(datetime(2022, 8, 1, 23, 50), 15),
+      datetime(2022, 8, 2, 0, 5)
+    );
+    assert_eq!(
+      add_minutes(datetime(2022, 8, 31, 23, 59), 1),
+      datetime(2022, 9, 1, 0, 0)
+    );
+    // うるう年の2月末
+    assert_eq!(
+      add_minutes(datetime(2024, 2, 28, 23, 30), 60),
+      datetime(2024, 2, 29, 0, 30)
+    );
+    assert_eq!(
+      add_minutes(datetime(2022, 12, 31, 23, 45), 30),
+      datetime(2023, 1, 1, 0, 15)
+    );
+    // 負の値は遡る
+    assert_eq!(
+      add_minutes(datetime(2023, 1, 1, 0, 10), -20),
+      datetime(2022, 12, 31, 23, 50)
+    );
+  }
+
+  #[test]
+  fn session_is_estimated_from_exif_capture_date() {
+    let original_path = test_util::temp_dir("session_from_exif");
+    std::fs::create_dir(format!("{}/trip", original_path)).unwrap();
+    let capture_date = |datetime: &str| {
+      test_util::make_exif_jpeg(&[test_util::exif_field(
+        Tag::DateTimeOriginal,
+        Value::Ascii(vec![datetime.as_bytes().to_vec()]),
+      )])
+    };
+    // 日と時を取り違えると`2022-08-23`になる
+    std::fs::write(
+      format!("{}/a.jpg", original_path),
+      capture_date("2022:08:01 23:59:00"),
+    )
+    .unwrap();
+    std::fs::write(
+      format!("{}/trip/b.jpg", original_path),
+      capture_date("2022:08:02 10:00:00"),
+    )
+    .unwrap();
+    std::fs::write(
+      format!("{}/c.jpg", original_path),
+      test_util::make_exif_jpeg(&[test_util::exif_field(
+        Tag::PhotographicSensitivity,
+        Value::Short(vec![400]),
+      )]),
+    )
+    .unwrap();
+    let import_photo_data_lst = vec![
+      make_import_photo_data("a", "a.jpg"),
+      make_import_photo_data("b", "trip/b.jpg"),
+      make_import_photo_data("c", "c.jpg"),
+    ];
+    let (_, photo_data_lst) = merge_photo_data_based_and_import_photo_data(
+      &HashMap::new(),
+      &import_photo_data_lst,
+      &original_path,
+      2,
+      DEFAULT_URL_PREFIX,
+    )
+    .unwrap();
+    // フォルダに置かれている写真はフォルダ名に、撮影日の無い写真は未設定にする
+    assert_eq!(
+      photo_data_lst
+        .iter()
+        .map(|photo_data| photo_data.session.as_deref())
+        .collect::<Vec<_>>(),
+      vec![Some("2022-08-01"), Some("trip"), None]
+    );
   }
 }
@@ -1,17 +1,22 @@
 //! ファイルの保存に関する制御をする
 //! データファイルの書き出し・画像ファイルの書き出しの他、適度なタイミングでのデータの読み込みとそれの反映も制御する
 
-use anyhow::Result;
-use chrono::{DateTime, FixedOffset, Local};
-use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone};
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::{fs::File, io::BufReader, io::Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::{fs::File, io::BufReader, io::Read, io::Write};
+
+use crate::photodata::{self, GUIGroupData, GUIPhotoData, GroupData, PhotoData, SrcsetImage};
 
 const MINUTES: i32 = 60;
-/// 画像を保存する間隔
+/// 画像を保存する間隔の既定値
 pub const SAVE_IMAGE_DIFF_TIME: i32 = MINUTES * 7;
-/// JSONファイルを保存する間隔
+/// JSONファイルを保存する間隔の既定値
 pub const SAVE_JSON_DIFF_TIME: i32 = MINUTES;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,6 +62,233 @@ pub fn get_time_info_lst(work_dir: &str) -> HashMap<String, DateTime<FixedOffset
   }
 }
 
+/// 起動時に使う各画像の前回の変換時刻
+/// 全ての画像を作り直す場合は読み込まず、全ての画像を未圧縮として扱う
+pub fn startup_time_info_lst(
+  work_dir: &str,
+  force_regenerate: bool,
+) -> HashMap<String, DateTime<FixedOffset>> {
+  if force_regenerate {
+    HashMap::new()
+  } else {
+    get_time_info_lst(work_dir)
+  }
+}
+
+/// 画像を圧縮し直す必要があるかどうかと、その理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageStaleness {
+  /// 圧縮した画像は最新
+  UpToDate,
+  /// まだ一度も圧縮していない
+  New,
+  /// 前回圧縮した後にオリジナルの画像が更新された
+  Modified,
+  /// オリジナルの画像の作成時刻を読み取れない
+  UnknownTimestamp,
+  /// 圧縮した画像が作業ディレクトリに無い
+  MissingOutput,
+}
+
+impl ImageStaleness {
+  pub fn is_stale(&self) -> bool {
+    *self != ImageStaleness::UpToDate
+  }
+
+  pub fn label(&self) -> &'static str {
+    match self {
+      ImageStaleness::UpToDate => "最新",
+      ImageStaleness::New => "未圧縮",
+      ImageStaleness::Modified => "オリジナルが更新されている",
+      ImageStaleness::UnknownTimestamp => "オリジナルの作成時刻が不明",
+      ImageStaleness::MissingOutput => "圧縮した画像が無い",
+    }
+  }
+}
+
+/// 画像ファイルのpathから最終更新時刻を取得する
+pub fn get_file_modified_timestamp(path: &str) -> Option<DateTime<FixedOffset>> {
+  let time = fs::metadata(path).ok()?.modified().ok()?;
+  let datetime_local = DateTime::<Local>::from(time);
+  Some(datetime_local.with_timezone(datetime_local.offset()))
+}
+
+/// 画像ファイルの最終更新時刻を書き換える
+/// 日時はこのコンピュータのタイムゾーンでの日時として扱う
+pub fn set_file_modified_timestamp(path: &str, datetime: NaiveDateTime) -> Result<()> {
+  let datetime = Local
+    .from_local_datetime(&datetime)
+    .earliest()
+    .ok_or_else(|| anyhow!("{}は存在しない日時です", datetime))?;
+  File::options()
+    .write(true)
+    .open(path)
+    .with_context(|| format!("{}を開けません", path))?
+    .set_modified(datetime.into())?;
+  Ok(())
+}
+
+/// 前回圧縮した時刻・オリジナルの画像の作成時刻・圧縮した画像があるかどうかから、圧縮し直す必要があるかを決める
+pub fn image_staleness(
+  save_time: Option<&DateTime<FixedOffset>>,
+  original_time: Option<DateTime<FixedOffset>>,
+  is_output_exist: bool,
+) -> ImageStaleness {
+  match (save_time, original_time) {
+    (None, _) => ImageStaleness::New,
+    (Some(_), None) => ImageStaleness::UnknownTimestamp,
+    (Some(save_time), Some(original_time)) if *save_time < original_time => {
+      ImageStaleness::Modified
+    }
+    _ if !is_output_exist => ImageStaleness::MissingOutput,
+    _ => ImageStaleness::UpToDate,
+  }
+}
+
+/// 作業ディレクトリの中で画像を書き出すフォルダ
+const WORK_SUB_DIRECTORY_LST: [&str; 3] = ["images/lazy", "images/normal", "thumbnails"];
+
+/// 作業ディレクトリと、画像を書き出すフォルダが無い場合は作る
+/// 既にある場合は何もしない
+pub fn ensure_work_directories(work_dir: &str) -> Result<()> {
+  for sub_directory in WORK_SUB_DIRECTORY_LST.iter() {
+    let path = Path::new(work_dir).join(sub_directory);
+    fs::create_dir_all(&path).with_context(|| format!("{}を作れません", path.display()))?;
+  }
+  Ok(())
+}
+
+/// 圧縮した表示用の画像のpath
+pub fn normal_image_path(work_dir: &str, id: &str) -> String {
+  format!(
+    "{}/images/normal/{}.JPG",
+    work_dir,
+    photodata::sanitize_id(id)
+  )
+}
+
+/// 圧縮した遅延読み込み用の画像のpath
+pub fn lazy_image_path(work_dir: &str, id: &str) -> String {
+  format!(
+    "{}/images/lazy/{}.JPG",
+    work_dir,
+    photodata::sanitize_id(id)
+  )
+}
+
+/// 同じ内容のオリジナルの画像から圧縮した`from_id`の画像を、`to_id`の画像としてコピーする
+/// 遅延読み込み用・表示用・`srcset`用の画像をコピーし、`to_id`の`srcset`用の画像の一覧を返す
+/// `srcset`用の画像のpathは`url_prefix`から始める
+pub fn copy_compressed_images(
+  work_dir: &str,
+  from_id: &str,
+  to_id: &str,
+  srcset: &[SrcsetImage],
+  url_prefix: &str,
+) -> Result<Vec<SrcsetImage>> {
+  fs::copy(
+    lazy_image_path(work_dir, from_id),
+    lazy_image_path(work_dir, to_id),
+  )?;
+  fs::copy(
+    normal_image_path(work_dir, from_id),
+    normal_image_path(work_dir, to_id),
+  )?;
+  let mut v = Vec::new();
+  for image in srcset.iter() {
+    let src = photodata::srcset_src(url_prefix, to_id, image.width);
+    fs::copy(
+      Path::new(work_dir).join(photodata::src_to_path(&image.src)),
+      Path::new(work_dir).join(photodata::src_to_path(&src)),
+    )?;
+    v.push(SrcsetImage {
+      src,
+      width: image.width,
+    });
+  }
+  debug!("{}の圧縮した画像を{}にコピーしました", from_id, to_id);
+  Ok(v)
+}
+
+/// ファイルの時刻を調べて、画像を圧縮し直す必要があるかを決める
+pub fn check_image_staleness(
+  time_info_lst: &HashMap<String, DateTime<FixedOffset>>,
+  work_dir: &str,
+  id: &str,
+  image_path: &str,
+) -> ImageStaleness {
+  let save_time = time_info_lst.get(id);
+  let original_time = get_file_timestamp(image_path);
+  let staleness = image_staleness(
+    save_time,
+    original_time,
+    Path::new(&normal_image_path(work_dir, id)).exists(),
+  );
+  debug!(
+    "{}: 前回の圧縮 {:?}、オリジナルの画像 {:?} → {}",
+    id,
+    save_time,
+    original_time,
+    staleness.label()
+  );
+  staleness
+}
+
+/// 保存してあるサムネイルのpath
+pub fn thumbnail_cache_path(work_dir: &str, id: &str) -> String {
+  format!("{}/thumbnails/{}.JPG", work_dir, photodata::sanitize_id(id))
+}
+
+/// 保存してあるサムネイルがオリジナルの画像より新しい場合だけ読み込む
+/// どちらかの時刻が読み取れない場合は作り直すことにして`None`を返す
+pub fn load_thumbnail_cache(work_dir: &str, id: &str, image_path: &str) -> Option<Vec<u8>> {
+  let cache_path = thumbnail_cache_path(work_dir, id);
+  let staleness = image_staleness(
+    get_file_timestamp(&cache_path).as_ref(),
+    get_file_timestamp(image_path),
+    true,
+  );
+  debug!("{}: 保存してあるサムネイル → {}", id, staleness.label());
+  if staleness.is_stale() {
+    return None;
+  }
+  fs::read(cache_path).ok()
+}
+
+/// サムネイルを次回の起動時に使えるように保存する
+/// 時刻は作成時刻で比べるので、上書きせずに一度削除してから書き出す
+pub fn save_thumbnail_cache(work_dir: &str, id: &str, thumbnail: &[u8]) -> Result<()> {
+  fs::create_dir_all(format!("{}/thumbnails", work_dir))?;
+  let cache_path = thumbnail_cache_path(work_dir, id);
+  let _ = fs::remove_file(&cache_path);
+  fs::write(cache_path, thumbnail)?;
+  Ok(())
+}
+
+/// 起動したときに圧縮し直すことになる画像を、何も書き出さずに調べる
+/// 入力のJSONファイルの順に、IDと判定結果を返す
+pub fn dry_run(
+  input_json_path: &str,
+  original_dir: &str,
+  work_dir: &str,
+) -> Result<Vec<(String, ImageStaleness)>> {
+  let import_photo_data_lst = photodata::load_import_json_file(input_json_path)?;
+  photodata::ensure_unique_ids(&import_photo_data_lst)?;
+  photodata::ensure_files_exist(&import_photo_data_lst, original_dir)?;
+  let time_info_lst = get_time_info_lst(work_dir);
+  Ok(
+    import_photo_data_lst
+      .iter()
+      .map(|import_photo_data| {
+        let image_path = format!("{}/{}", original_dir, import_photo_data.file_name);
+        let staleness =
+          check_image_staleness(&time_info_lst, work_dir, &import_photo_data.id, &image_path);
+        (import_photo_data.id.clone(), staleness)
+      })
+      .collect(),
+  )
+}
+
 pub fn save_time_info_lst(
   work_dir: &str,
   time_info_lst: &HashMap<String, DateTime<FixedOffset>>,
@@ -77,6 +309,65 @@ pub fn save_time_info_lst(
   Ok(())
 }
 
+/// 公開したときの各データの時刻と内容のハッシュ値
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishInfo {
+  pub id: String,
+  /// 最後に内容が変わって書き出した時刻
+  pub time: DateTime<FixedOffset>,
+  pub hash: String,
+}
+
+/// 前回公開したときの写真とグループの情報
+/// 差分だけを書き出すときに比較元にする
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishManifest {
+  pub photo: Vec<PublishInfo>,
+  pub group: Vec<PublishInfo>,
+}
+
+/// 出力先のフォルダに保存した、前回公開したときの情報を取得する
+/// まだ公開したことが無い場合は空にする
+pub fn get_publish_manifest(output_dir: &str) -> Result<PublishManifest> {
+  let file_path = format!("{}/publish.json", output_dir);
+  match File::open(&file_path) {
+    Ok(file) => {
+      let reader = BufReader::new(file);
+      let manifest = serde_json::from_reader(reader)
+        .with_context(|| format!("{}を読み込めません", file_path))?;
+      Ok(manifest)
+    }
+    Err(_) => Ok(PublishManifest::default()),
+  }
+}
+
+pub fn save_publish_manifest(output_dir: &str, manifest: &PublishManifest) -> Result<()> {
+  let path = format!("{}/publish.json", output_dir);
+  fs::write(path, serde_json::to_string_pretty(manifest)?)?;
+  Ok(())
+}
+
+/// 内容が変わったかどうかを比べるためのハッシュ値（FNV-1a）
+/// Rustのバージョンによって値が変わらないように自前で計算する
+pub fn content_hash(bytes: &[u8]) -> String {
+  let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+    (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+  });
+  format!("{:016x}", hash)
+}
+
+/// `path`のファイルの内容が`bytes`と同じかどうか
+/// ハッシュ値が一致しても内容が同じとは限らないので、重複している画像として扱う前に確かめる
+/// 大きさが違う場合はファイルを読み込まずに`false`を返す
+pub fn is_same_content(path: &str, bytes: &[u8]) -> bool {
+  match fs::metadata(path) {
+    Ok(metadata) if metadata.len() == bytes.len() as u64 => {
+      fs::read(path).map_or(false, |content| content == bytes)
+    }
+    _ => false,
+  }
+}
+
 pub fn get_now() -> DateTime<FixedOffset> {
   let now = Local::now();
   now.with_timezone(now.offset())
@@ -86,3 +377,711 @@ pub fn time_add_sec(time: DateTime<FixedOffset>, sec: i32) -> DateTime<FixedOffs
   let datetime = FixedOffset::east(sec);
   time + datetime
 }
+
+/// 連番を振るときの並び順
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenumberOrder {
+  /// 撮影日時順
+  Date,
+  /// グループ順（グループに含まれない写真は最後に並べる）
+  Group,
+  /// 標高の高い順（標高が無い写真は最後に並べる）
+  Altitude,
+}
+
+/// 撮影日時を比較できる形にする
+/// 年が無いものは`None`になる
+fn photo_date_key(photo_data: &GUIPhotoData) -> Option<(u32, u32, u32, u32, u32)> {
+  let year = photo_data.year.trim().parse().ok()?;
+  let parse = |s: &str| s.trim().parse().unwrap_or_default();
+  Some((
+    year,
+    parse(&photo_data.month),
+    parse(&photo_data.day),
+    parse(&photo_data.hour),
+    parse(&photo_data.minutes),
+  ))
+}
+
+/// 写真のIDを指定した順番に並び替える
+/// 撮影日時が無い写真は最後に並べる
+pub fn sort_photo_id_lst(
+  order: RenumberOrder,
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  group_id_lst: &[String],
+  gui_group_data_lst: &HashMap<String, GUIGroupData>,
+) -> Vec<String> {
+  match order {
+    RenumberOrder::Date => {
+      let mut v = photo_id_lst.to_vec();
+      v.sort_by_key(|id| {
+        let key = gui_photo_data_lst.get(id).and_then(photo_date_key);
+        (key.is_none(), key)
+      });
+      v
+    }
+    RenumberOrder::Altitude => {
+      let altitude = |id: &String| {
+        gui_photo_data_lst
+          .get(id)
+          .and_then(|photo_data| photodata::parse_altitude(&photo_data.altitude))
+      };
+      let mut v = photo_id_lst.to_vec();
+      v.sort_by(|a, b| match (altitude(a), altitude(b)) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+      });
+      v
+    }
+    RenumberOrder::Group => {
+      let mut v: Vec<String> = Vec::new();
+      for group_id in group_id_lst.iter() {
+        if let Some(group_data) = gui_group_data_lst.get(group_id) {
+          for id in group_data.photo_id_list.iter() {
+            if photo_id_lst.contains(id) && !v.contains(id) {
+              v.push(id.clone())
+            }
+          }
+        }
+      }
+      for id in photo_id_lst.iter() {
+        if !v.contains(id) {
+          v.push(id.clone())
+        }
+      }
+      v
+    }
+  }
+}
+
+/// 並び順に従って`001.jpg`のような連番のファイル名を割り当てる
+/// 画像IDと新しいファイル名の組を返す
+/// 拡張子は元のファイル名のものを小文字にして使う
+pub fn renumber_files(
+  photo_id_lst: &[String],
+  gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+  start: usize,
+  padding: usize,
+) -> HashMap<String, String> {
+  let mut map = HashMap::new();
+  for (i, id) in photo_id_lst.iter().enumerate() {
+    if let Some(photo_data) = gui_photo_data_lst.get(id) {
+      let extension = Path::new(&photo_data.file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "jpg".to_string());
+      let file_name = format!("{:0width$}.{}", start + i, extension, width = padding);
+      map.insert(id.clone(), file_name);
+    }
+  }
+  map
+}
+
+/// 連番を振る前後のファイル名の対応
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RenumberInfo {
+  id: String,
+  original_file_name: String,
+  file_name: String,
+}
+
+/// オリジナル画像のファイル名を実際に変更し、`GUIPhotoData`のファイル名も書き換える
+/// 対応表は`renumber.json`に書き出す
+/// 既に同じ名前のファイルが入れ替わる場合を考え、一度仮の名前を経由して変更する
+pub fn apply_renumber_files(
+  original_path: &str,
+  work_dir: &str,
+  renumber_map: &HashMap<String, String>,
+  gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+) -> Result<()> {
+  let mut info_lst = Vec::new();
+  for (id, file_name) in renumber_map.iter() {
+    if let Some(photo_data) = gui_photo_data_lst.get(id) {
+      if photo_data.file_name != *file_name {
+        info_lst.push(RenumberInfo {
+          id: id.clone(),
+          original_file_name: photo_data.file_name.clone(),
+          file_name: file_name.clone(),
+        })
+      }
+    }
+  }
+  // 対象外のファイルを上書きしてしまわないかを確認する
+  for info in info_lst.iter() {
+    let is_renamed = info_lst
+      .iter()
+      .any(|other| other.original_file_name == info.file_name);
+    if !is_renamed && Path::new(&format!("{}/{}", original_path, info.file_name)).exists() {
+      return Err(anyhow!("{}は既に存在します", info.file_name));
+    }
+  }
+  for info in info_lst.iter() {
+    fs::rename(
+      format!("{}/{}", original_path, info.original_file_name),
+      format!("{}/{}.renumber", original_path, info.file_name),
+    )?;
+  }
+  for info in info_lst.iter() {
+    fs::rename(
+      format!("{}/{}.renumber", original_path, info.file_name),
+      format!("{}/{}", original_path, info.file_name),
+    )?;
+    if let Some(photo_data) = gui_photo_data_lst.get_mut(&info.id) {
+      photo_data.file_name = info.file_name.clone();
+    }
+  }
+  info_lst.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+  let mut file = File::create(format!("{}/renumber.json", work_dir))?;
+  let json_str = serde_json::to_string_pretty(&info_lst)?;
+  let buf = json_str.into_bytes();
+  file.write_all(&buf)?;
+  file.flush()?;
+  Ok(())
+}
+
+/// 分割保存したデータを置くフォルダ
+const SPLIT_DIRECTORY: &str = "split";
+
+/// 分割保存したときの写真とグループの並び順
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct SplitIndex {
+  photo_id_lst: Vec<String>,
+  group_id_lst: Vec<String>,
+}
+
+/// 作業ディレクトリのデータが分割保存されているかどうか
+pub fn is_split(work_dir: &str) -> bool {
+  Path::new(work_dir)
+    .join(SPLIT_DIRECTORY)
+    .join("index.json")
+    .exists()
+}
+
+/// JSONファイルを書き出すときの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JsonStyle {
+  /// 改行と字下げを入れた読みやすい形式
+  #[default]
+  Pretty,
+  /// 空白を入れずにサイズを小さくした形式
+  Minify,
+}
+
+impl JsonStyle {
+  /// 形式に従ってJSON文字列に変換する
+  pub fn to_json_string<T: Serialize + ?Sized>(self, value: &T) -> serde_json::Result<String> {
+    match self {
+      JsonStyle::Pretty => serde_json::to_string_pretty(value),
+      JsonStyle::Minify => serde_json::to_string(value),
+    }
+  }
+
+  /// `{ "version": DATA_VERSION, "data": [...] }`の形に包んでJSON文字列に変換する
+  pub fn to_versioned_json_string<T: Serialize>(self, data: &[T]) -> serde_json::Result<String> {
+    self.to_json_string(&VersionedData {
+      version: DATA_VERSION,
+      data,
+    })
+  }
+}
+
+/// `photo_data.json`と`group_data.json`の形式のバージョン
+/// 項目を追加・変更したときは1つ上げる
+/// 1は`{ "version": N, "data": [...] }`で包む前の、配列だけを書き出していた形式
+/// 3で写真に選別のフラグ（`flag`）を追加した
+pub const DATA_VERSION: u64 = 3;
+
+/// バージョンを付けて包んだ`photo_data.json`と`group_data.json`の中身
+#[derive(Serialize)]
+struct VersionedData<'a, T> {
+  version: u64,
+  data: &'a [T],
+}
+
+/// バージョンを付けて包んだJSONの値から中身の配列を取り出す
+/// 包む前の配列だけの形式はそのまま返す
+/// 対応しているものより新しいバージョンの場合はエラーにする
+pub fn unwrap_versioned_value(value: serde_json::Value) -> Result<serde_json::Value> {
+  match value {
+    serde_json::Value::Array(_) => Ok(value),
+    serde_json::Value::Object(mut map) => {
+      let version = map
+        .get("version")
+        .and_then(|version| version.as_u64())
+        .ok_or_else(|| anyhow!("バージョンが書かれていません"))?;
+      if version > DATA_VERSION {
+        return Err(anyhow!(
+          "新しいバージョン（{}）のファイルには対応していません（対応しているのは{}まで）",
+          version,
+          DATA_VERSION
+        ));
+      }
+      map
+        .remove("data")
+        .ok_or_else(|| anyhow!("`data`が書かれていません"))
+    }
+    _ => Err(anyhow!("配列かバージョン付きのオブジェクトではありません")),
+  }
+}
+
+/// `photo_data.json`や`group_data.json`を読み込む
+/// バージョンを付けて包んだ形式と、包む前の配列だけの形式のどちらも読み込める
+pub fn read_versioned_data<T: DeserializeOwned, R: Read>(reader: R) -> Result<Vec<T>> {
+  let value = serde_json::from_reader(reader)?;
+  Ok(serde_json::from_value(unwrap_versioned_value(value)?)?)
+}
+
+impl FromStr for JsonStyle {
+  type Err = String;
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    match s {
+      "pretty" => Ok(JsonStyle::Pretty),
+      "minify" => Ok(JsonStyle::Minify),
+      _ => Err(format!(
+        "{}は使えません（prettyかminifyを指定してください）",
+        s
+      )),
+    }
+  }
+}
+
+/// 内容が変わっている場合だけファイルを書き換える
+/// 書き換えた場合は`true`を返す
+fn write_if_changed(path: &Path, content: &str) -> Result<bool> {
+  if fs::read_to_string(path).ok().as_deref() == Some(content) {
+    return Ok(false);
+  }
+  fs::write(path, content)?;
+  Ok(true)
+}
+
+/// IDごとのJSONファイルを書き出し、一覧に無くなったIDのファイルは削除する
+/// 書き換えたファイルの数を返す
+fn save_split_directory<T: Serialize>(directory: &Path, data_lst: &[(&str, &T)]) -> Result<usize> {
+  fs::create_dir_all(directory)?;
+  let mut count = 0;
+  for (id, data) in data_lst.iter() {
+    let path = directory.join(format!("{}.json", id));
+    if write_if_changed(&path, &serde_json::to_string_pretty(data)?)? {
+      count += 1;
+    }
+  }
+  for entry in fs::read_dir(directory)? {
+    let path = entry?.path();
+    let is_removed = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .map(|stem| data_lst.iter().all(|(id, _)| *id != stem))
+      .unwrap_or(false);
+    if is_removed {
+      fs::remove_file(path)?;
+      count += 1;
+    }
+  }
+  Ok(count)
+}
+
+/// 写真とグループのデータを作業ディレクトリに保存する
+/// 分割保存の形式の場合は、変更のあったデータのファイルだけを書き換える
+/// 単一ファイルの形式の場合は`json_style`の形式で書き出す
+pub fn save_work_data(
+  work_dir: &str,
+  photo_data_lst: &[PhotoData],
+  group_data_lst: &[GroupData],
+  json_style: JsonStyle,
+) -> Result<()> {
+  if is_split(work_dir) {
+    save_split(work_dir, photo_data_lst, group_data_lst)?;
+  } else {
+    fs::write(
+      format!("{}/photo_data.json", work_dir),
+      json_style.to_versioned_json_string(photo_data_lst)?,
+    )?;
+    fs::write(
+      format!("{}/group_data.json", work_dir),
+      json_style.to_versioned_json_string(group_data_lst)?,
+    )?;
+  }
+  debug!("写真とグループのデータを保存しました: {}", work_dir);
+  Ok(())
+}
+
+/// 写真とグループのデータを1件ずつ別のJSONファイルに分けて保存する
+/// クラウドの同期フォルダに置いたときの転送量を減らすため、変更の無いファイルは書き換えない
+/// 書き換えたファイルの数を返す
+pub fn save_split(
+  work_dir: &str,
+  photo_data_lst: &[PhotoData],
+  group_data_lst: &[GroupData],
+) -> Result<usize> {
+  let directory = Path::new(work_dir).join(SPLIT_DIRECTORY);
+  let photo_lst = photo_data_lst
+    .iter()
+    .map(|data| (data.photo_id.as_str(), data))
+    .collect::<Vec<(&str, &PhotoData)>>();
+  let group_lst = group_data_lst
+    .iter()
+    .map(|data| (data.group_id.as_str(), data))
+    .collect::<Vec<(&str, &GroupData)>>();
+  let mut count = save_split_directory(&directory.join("photos"), &photo_lst)?;
+  count += save_split_directory(&directory.join("groups"), &group_lst)?;
+  let index = SplitIndex {
+    photo_id_lst: photo_lst.iter().map(|(id, _)| id.to_string()).collect(),
+    group_id_lst: group_lst.iter().map(|(id, _)| id.to_string()).collect(),
+  };
+  if write_if_changed(
+    &directory.join("index.json"),
+    &serde_json::to_string_pretty(&index)?,
+  )? {
+    count += 1;
+  }
+  Ok(count)
+}
+
+/// 分割保存したJSONファイルを並び順に従って読み込む
+/// ファイルが見つからないIDは飛ばす
+fn load_split_directory<T: for<'de> Deserialize<'de>>(
+  directory: &Path,
+  id_lst: &[String],
+) -> Result<Vec<T>> {
+  let mut v = Vec::new();
+  for id in id_lst.iter() {
+    let path = directory.join(format!("{}.json", id));
+    match File::open(&path) {
+      Ok(file) => v.push(serde_json::from_reader(BufReader::new(file))?),
+      Err(_) => warn!("分割保存したファイルが見つかりません: {}", path.display()),
+    }
+  }
+  Ok(v)
+}
+
+/// 分割保存した写真とグループのデータを読み込んでまとめる
+pub fn load_split(work_dir: &str) -> Result<(Vec<PhotoData>, Vec<GroupData>)> {
+  let directory = Path::new(work_dir).join(SPLIT_DIRECTORY);
+  let index: SplitIndex =
+    serde_json::from_reader(BufReader::new(File::open(directory.join("index.json"))?))?;
+  let photo_data_lst = load_split_directory(&directory.join("photos"), &index.photo_id_lst)?;
+  let group_data_lst = load_split_directory(&directory.join("groups"), &index.group_id_lst)?;
+  Ok((photo_data_lst, group_data_lst))
+}
+
+/// `photo_data.json`と`group_data.json`の単一ファイル形式から分割保存の形式に変換する
+/// 以降の保存は分割保存の形式で行われる
+pub fn convert_to_split(work_dir: &str) -> Result<usize> {
+  let photo_data_lst = photodata::load_photo_data_from_work_directory(work_dir)?;
+  let group_data_lst = photodata::load_group_data_from_work_directory(work_dir)?;
+  save_split(work_dir, &photo_data_lst, &group_data_lst)
+}
+
+/// 分割保存の形式から`photo_data.json`と`group_data.json`の単一ファイル形式に変換する
+/// 変換後は分割保存したファイルを削除する
+pub fn convert_to_single(work_dir: &str) -> Result<()> {
+  let (photo_data_lst, group_data_lst) = load_split(work_dir)?;
+  fs::write(
+    format!("{}/photo_data.json", work_dir),
+    JsonStyle::Pretty.to_versioned_json_string(&photo_data_lst)?,
+  )?;
+  fs::write(
+    format!("{}/group_data.json", work_dir),
+    JsonStyle::Pretty.to_versioned_json_string(&group_data_lst)?,
+  )?;
+  fs::remove_dir_all(Path::new(work_dir).join(SPLIT_DIRECTORY))?;
+  Ok(())
+}
+
+/// 2つの`photo_data.json`を比べたときの1枚の写真の差分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonDiffKind {
+  /// 比較先にだけある写真
+  Added,
+  /// 比較元にだけある写真
+  Removed,
+  /// 両方にあり、値の異なるフィールドがある写真
+  /// フィールド名・比較元の値・比較先の値の組を並べる
+  Changed(Vec<(String, String, String)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonDiff {
+  pub photo_id: String,
+  pub kind: JsonDiffKind,
+}
+
+/// 差分として表示するための値の文字列
+/// 文字列はそのまま、無い値は空文字列、それ以外はJSONとして表す
+fn json_value_label(value: Option<&serde_json::Value>) -> String {
+  match value {
+    None | Some(serde_json::Value::Null) => String::new(),
+    Some(serde_json::Value::String(s)) => s.clone(),
+    Some(value) => value.to_string(),
+  }
+}
+
+/// `photo_data.json`を写真のIDとフィールドの一覧の組として読み込む
+/// 古い形式のファイルとも比べられるように、`PhotoData`としては読み込まない
+fn load_photo_data_json_fields(
+  path: &str,
+) -> Result<Vec<(String, serde_json::Map<String, serde_json::Value>)>> {
+  let value_lst: Vec<serde_json::Value> = read_versioned_data(BufReader::new(
+    File::open(path).with_context(|| format!("{}を開けません", path))?,
+  ))
+  .with_context(|| format!("{}を読み込めません", path))?;
+  value_lst
+    .into_iter()
+    .map(|value| match value {
+      serde_json::Value::Object(map) => {
+        let photo_id = json_value_label(map.get("photo_id"));
+        Ok((photo_id, map))
+      }
+      _ => Err(anyhow!("{}に写真データではない値が含まれています", path)),
+    })
+    .collect()
+}
+
+/// 2つの`photo_data.json`を読み込み、追加・削除・変更された写真とフィールドを列挙する
+/// 比較先の並び順に並べ、比較元にだけある写真は最後に並べる
+pub fn diff_json_files(path_a: &str, path_b: &str) -> Result<Vec<JsonDiff>> {
+  let lst_a = load_photo_data_json_fields(path_a)?;
+  let lst_b = load_photo_data_json_fields(path_b)?;
+  let map_a = lst_a
+    .iter()
+    .map(|(id, fields)| (id.as_str(), fields))
+    .collect::<HashMap<&str, &serde_json::Map<String, serde_json::Value>>>();
+  let mut v = Vec::new();
+  for (photo_id, fields_b) in lst_b.iter() {
+    let kind = match map_a.get(photo_id.as_str()) {
+      None => JsonDiffKind::Added,
+      Some(fields_a) => {
+        let mut key_lst = fields_a.keys().chain(fields_b.keys()).collect::<Vec<_>>();
+        key_lst.sort();
+        key_lst.dedup();
+        let changed_lst = key_lst
+          .into_iter()
+          .filter(|key| fields_a.get(*key) != fields_b.get(*key))
+          .map(|key| {
+            (
+              key.clone(),
+              json_value_label(fields_a.get(key)),
+              json_value_label(fields_b.get(key)),
+            )
+          })
+          .collect::<Vec<(String, String, String)>>();
+        if changed_lst.is_empty() {
+          continue;
+        }
+        JsonDiffKind::Changed(changed_lst)
+      }
+    };
+    v.push(JsonDiff {
+      photo_id: photo_id.clone(),
+      kind,
+    });
+  }
+  for (photo_id, _) in lst_a.iter() {
+    if !lst_b.iter().any(|(id, _)| id == photo_id) {
+      v.push(JsonDiff {
+        photo_id: photo_id.clone(),
+        kind: JsonDiffKind::Removed,
+      });
+    }
+  }
+  Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse_time(time: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(time).unwrap()
+  }
+
+  #[test]
+  fn image_staleness_reports_the_reason() {
+    let saved = parse_time("2022-08-01T12:00:00+09:00");
+    let older = parse_time("2022-08-01T11:00:00+09:00");
+    let newer = parse_time("2022-08-01T13:00:00+09:00");
+    assert_eq!(
+      image_staleness(None, Some(older), true),
+      ImageStaleness::New
+    );
+    assert_eq!(
+      image_staleness(Some(&saved), None, true),
+      ImageStaleness::UnknownTimestamp
+    );
+    assert_eq!(
+      image_staleness(Some(&saved), Some(newer), true),
+      ImageStaleness::Modified
+    );
+    // オリジナルが更新されている場合は圧縮した画像が無いことより優先する
+    assert_eq!(
+      image_staleness(Some(&saved), Some(newer), false),
+      ImageStaleness::Modified
+    );
+    assert_eq!(
+      image_staleness(Some(&saved), Some(older), false),
+      ImageStaleness::MissingOutput
+    );
+    assert_eq!(
+      image_staleness(Some(&saved), Some(older), true),
+      ImageStaleness::UpToDate
+    );
+    // 同じ時刻の場合は最新とみなす
+    assert_eq!(
+      image_staleness(Some(&saved), Some(saved), true),
+      ImageStaleness::UpToDate
+    );
+    assert!(!ImageStaleness::UpToDate.is_stale());
+    assert!(ImageStaleness::MissingOutput.is_stale());
+  }
+
+  #[test]
+  fn thumbnail_cache_is_reused_until_the_original_changes() {
+    let work_dir = crate::test_util::temp_dir("thumbnail_cache");
+    let image_path = format!("{}/original.jpg", work_dir);
+    fs::write(&image_path, b"original").unwrap();
+    if fs::metadata(&image_path).unwrap().created().is_err() {
+      // 作成時刻を記録しないファイルシステムでは常に作り直すので確かめられない
+      return;
+    }
+    assert_eq!(load_thumbnail_cache(&work_dir, "a", &image_path), None);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    save_thumbnail_cache(&work_dir, "a", b"thumbnail").unwrap();
+    assert_eq!(
+      load_thumbnail_cache(&work_dir, "a", &image_path),
+      Some(b"thumbnail".to_vec())
+    );
+    // 別のIDのサムネイルは使わない
+    assert_eq!(load_thumbnail_cache(&work_dir, "b", &image_path), None);
+    // オリジナルを差し替えた後は作り直す
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    fs::remove_file(&image_path).unwrap();
+    fs::write(&image_path, b"replaced").unwrap();
+    assert_eq!(load_thumbnail_cache(&work_dir, "a", &image_path), None);
+  }
+
+  #[test]
+  fn force_regenerate_ignores_saved_times() {
+    let work_dir = crate::test_util::temp_dir("force_regenerate");
+    let original_time = parse_time("2022-08-01T11:00:00+09:00");
+    let saved_time = parse_time("2022-08-01T12:00:00+09:00");
+    let mut time_info_lst = HashMap::new();
+    time_info_lst.insert("a".to_string(), saved_time);
+    save_time_info_lst(&work_dir, &time_info_lst).unwrap();
+
+    let time_info_lst = startup_time_info_lst(&work_dir, false);
+    assert_eq!(
+      image_staleness(time_info_lst.get("a"), Some(original_time), true),
+      ImageStaleness::UpToDate
+    );
+    let mut time_info_lst = startup_time_info_lst(&work_dir, true);
+    assert_eq!(
+      image_staleness(time_info_lst.get("a"), Some(original_time), true),
+      ImageStaleness::New
+    );
+    // 作り直した時刻を保存すると、次回からはその時刻と比べる
+    let now = get_now();
+    time_info_lst.insert("a".to_string(), now);
+    save_time_info_lst(&work_dir, &time_info_lst).unwrap();
+    let loaded = startup_time_info_lst(&work_dir, false);
+    assert_eq!(loaded.get("a"), Some(&now));
+    assert!(saved_time < loaded["a"]);
+  }
+
+  #[derive(Debug, PartialEq, Serialize, Deserialize)]
+  struct TestEntry {
+    id: String,
+  }
+
+  fn make_test_entry_lst() -> Vec<TestEntry> {
+    ["a", "b"]
+      .iter()
+      .map(|id| TestEntry { id: id.to_string() })
+      .collect()
+  }
+
+  #[test]
+  fn read_versioned_data_accepts_old_and_current_formats() {
+    let entry_lst = make_test_entry_lst();
+    // 包む前の配列だけの形式
+    let legacy = r#"[{ "id": "a" }, { "id": "b" }]"#;
+    assert_eq!(
+      read_versioned_data::<TestEntry, _>(legacy.as_bytes()).unwrap(),
+      entry_lst
+    );
+    for version in 1..=DATA_VERSION {
+      let json = format!(
+        r#"{{ "version": {}, "data": [{{ "id": "a" }}, {{ "id": "b" }}] }}"#,
+        version
+      );
+      assert_eq!(
+        read_versioned_data::<TestEntry, _>(json.as_bytes()).unwrap(),
+        entry_lst
+      );
+    }
+  }
+
+  #[test]
+  fn read_versioned_data_rejects_newer_and_broken_files() {
+    let json = format!(r#"{{ "version": {}, "data": [] }}"#, DATA_VERSION + 1);
+    let err = read_versioned_data::<TestEntry, _>(json.as_bytes()).unwrap_err();
+    assert!(err.to_string().contains("新しいバージョン"));
+    assert!(read_versioned_data::<TestEntry, _>(r#"{ "data": [] }"#.as_bytes()).is_err());
+    assert!(read_versioned_data::<TestEntry, _>(r#"{ "version": 1 }"#.as_bytes()).is_err());
+    assert!(read_versioned_data::<TestEntry, _>(r#""a""#.as_bytes()).is_err());
+  }
+
+  #[test]
+  fn versioned_json_is_written_in_the_current_format() {
+    let entry_lst = make_test_entry_lst();
+    for json_style in [JsonStyle::Pretty, JsonStyle::Minify] {
+      let json = json_style.to_versioned_json_string(&entry_lst).unwrap();
+      let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+      assert_eq!(value["version"], DATA_VERSION);
+      assert!(value["data"].is_array());
+      assert_eq!(
+        read_versioned_data::<TestEntry, _>(json.as_bytes()).unwrap(),
+        entry_lst
+      );
+    }
+  }
+
+  #[test]
+  fn content_hash_is_fnv1a() {
+    // FNV-1a（64bit）の公開されているテストベクタ
+    assert_eq!(content_hash(b""), "cbf29ce484222325");
+    assert_eq!(content_hash(b"a"), "af63dc4c8601ec8c");
+    assert_eq!(content_hash(b"foobar"), "85944171f73967e8");
+    assert_ne!(content_hash(b"ab"), content_hash(b"ba"));
+  }
+
+  #[test]
+  fn ensure_work_directories_is_idempotent() {
+    let work_dir = format!("{}/work", crate::test_util::temp_dir("work_directories"));
+    ensure_work_directories(&work_dir).unwrap();
+    for sub_directory in WORK_SUB_DIRECTORY_LST.iter() {
+      assert!(Path::new(&work_dir).join(sub_directory).is_dir());
+    }
+    // 既にあるフォルダの中身は消さない
+    let image_path = normal_image_path(&work_dir, "a");
+    fs::write(&image_path, b"image").unwrap();
+    ensure_work_directories(&work_dir).unwrap();
+    assert_eq!(fs::read(&image_path).unwrap(), b"image");
+    // 同じ名前のファイルがある場合はエラーにする
+    let file_path = format!("{}/file", work_dir);
+    fs::write(&file_path, b"").unwrap();
+    assert!(ensure_work_directories(&file_path).is_err());
+  }
+
+  #[test]
+  fn is_same_content_compares_length_and_bytes() {
+    let work_dir = crate::test_util::temp_dir("same_content");
+    let path = format!("{}/original.jpg", work_dir);
+    fs::write(&path, b"abcd").unwrap();
+    assert!(is_same_content(&path, b"abcd"));
+    assert!(!is_same_content(&path, b"abce"));
+    assert!(!is_same_content(&path, b"abc"));
+    assert!(!is_same_content(&format!("{}/missing.jpg", work_dir), b""));
+  }
+}
@@ -0,0 +1,137 @@
+//! 前回終了時の作業状態を保存し、次回起動時に復元する
+//! 作業状態は作業ディレクトリの`.session.json`に保存する
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader, io::Write};
+
+use crate::gui::{Mode, PhotoFilter, Theme};
+use crate::save::RenumberOrder;
+
+/// 復元する作業状態
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+  /// 開いていた写真またはグループのID
+  pub now_id: String,
+  pub mode: Mode,
+  /// 画像ID一覧の絞り込み条件
+  pub photo_filter: PhotoFilter,
+  /// 並び替えの基準
+  pub sort_order: RenumberOrder,
+  /// 画像ID一覧のスクロール位置
+  pub scroll_offset: f32,
+  /// 画面の配色
+  #[serde(default)]
+  pub theme: Theme,
+}
+
+impl Session {
+  /// 開いていた写真やグループが無くなっている場合は、何も開いていない状態にする
+  /// 一覧表示のモードでは写真やグループを開いていないので、常に空にする
+  pub fn clear_stale_now_id(&mut self, photo_id_lst: &[String], group_id_lst: &[String]) {
+    let is_exist = match self.mode {
+      Mode::EditPhotoData => photo_id_lst.contains(&self.now_id),
+      Mode::EditGroupData => group_id_lst.contains(&self.now_id),
+      Mode::Gallery | Mode::Timeline => false,
+    };
+    if !is_exist {
+      self.now_id.clear();
+    }
+  }
+}
+
+/// 保存されている作業状態を読み込む
+/// ファイルが無い場合や読み取れない場合は`None`になる
+pub fn load_session(work_dir: &str) -> Option<Session> {
+  let file_path = format!("{}/.session.json", work_dir);
+  let reader = BufReader::new(File::open(file_path).ok()?);
+  serde_json::from_reader(reader).ok()
+}
+
+pub fn save_session(work_dir: &str, session: &Session) -> Result<()> {
+  let path = format!("{}/.session.json", work_dir);
+  let mut file = File::create(path)?;
+  let json_str = serde_json::to_string_pretty(session)?;
+  let buf = json_str.into_bytes();
+  file.write_all(&buf)?;
+  file.flush()?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::bin_test_util;
+  use crate::gui::FilterCombination;
+
+  fn make_test_session(mode: Mode, now_id: &str) -> Session {
+    Session {
+      now_id: now_id.to_string(),
+      mode,
+      photo_filter: PhotoFilter {
+        combination: FilterCombination::Or,
+        date_from: "2022-01-01".to_string(),
+        ..PhotoFilter::default()
+      },
+      sort_order: RenumberOrder::Date,
+      scroll_offset: 120.5,
+      theme: Theme::Dark,
+    }
+  }
+
+  #[test]
+  fn session_round_trips_through_file() {
+    let work_dir = bin_test_util::temp_dir("session");
+    assert_eq!(load_session(&work_dir), None);
+    let session = make_test_session(Mode::EditGroupData, "sea");
+    save_session(&work_dir, &session).unwrap();
+    assert_eq!(load_session(&work_dir), Some(session));
+    std::fs::remove_dir_all(work_dir).unwrap();
+  }
+
+  #[test]
+  fn stale_now_id_is_cleared() {
+    let photo_id_lst = vec!["1".to_string()];
+    let group_id_lst = vec!["sea".to_string()];
+
+    let mut session = make_test_session(Mode::EditPhotoData, "1");
+    session.clear_stale_now_id(&photo_id_lst, &group_id_lst);
+    assert_eq!(session.now_id, "1");
+    let mut session = make_test_session(Mode::EditPhotoData, "2");
+    session.clear_stale_now_id(&photo_id_lst, &group_id_lst);
+    assert_eq!(session.now_id, "");
+
+    // 写真とグループのIDは区別する
+    let mut session = make_test_session(Mode::EditGroupData, "1");
+    session.clear_stale_now_id(&photo_id_lst, &group_id_lst);
+    assert_eq!(session.now_id, "");
+    let mut session = make_test_session(Mode::EditGroupData, "sea");
+    session.clear_stale_now_id(&photo_id_lst, &group_id_lst);
+    assert_eq!(session.now_id, "sea");
+
+    let mut session = make_test_session(Mode::Gallery, "1");
+    session.clear_stale_now_id(&photo_id_lst, &group_id_lst);
+    assert_eq!(session.now_id, "");
+    // 他の状態はそのまま残す
+    assert_eq!(session.theme, Theme::Dark);
+    assert_eq!(session.scroll_offset, 120.5);
+  }
+
+  #[test]
+  fn theme_round_trips_and_defaults_to_light() {
+    for theme in [Theme::Light, Theme::Dark] {
+      let session = Session {
+        theme,
+        ..make_test_session(Mode::Gallery, "")
+      };
+      let json_str = serde_json::to_string(&session).unwrap();
+      let loaded: Session = serde_json::from_str(&json_str).unwrap();
+      assert_eq!(loaded.theme, theme);
+    }
+    // 配色を保存する前に作られたファイルは明るい配色で開く
+    let mut value = serde_json::to_value(make_test_session(Mode::Gallery, "")).unwrap();
+    value.as_object_mut().unwrap().remove("theme");
+    let loaded: Session = serde_json::from_value(value).unwrap();
+    assert_eq!(loaded.theme, Theme::Light);
+  }
+}
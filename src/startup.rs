@@ -0,0 +1,165 @@
+//! 起動時の画像の圧縮をバックグラウンドで行うための進捗の共有
+//! 圧縮を行うスレッドが処理結果を積み、画面の更新時にまとめて取り出す
+
+use chrono::{DateTime, FixedOffset};
+use std::sync::{Arc, Mutex};
+
+use crate::gui::InvalidImage;
+use crate::photodata::SrcsetImage;
+
+/// 起動時の画像処理の進み具合
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StartupProgress {
+  /// 処理が終わった画像の数
+  pub done: usize,
+  /// 処理する画像の総数
+  pub total: usize,
+}
+
+impl StartupProgress {
+  pub fn new(total: usize) -> Self {
+    StartupProgress { done: 0, total }
+  }
+
+  /// 画像を1枚処理し終えたことを記録する
+  /// 総数を超えて数えることは無い
+  pub fn record(&mut self) {
+    if self.done < self.total {
+      self.done += 1
+    }
+  }
+
+  pub fn is_complete(&self) -> bool {
+    self.done >= self.total
+  }
+
+  /// 進捗バーに表示する割合（0.0〜1.0）
+  pub fn fraction(&self) -> f32 {
+    if self.total == 0 {
+      1.0
+    } else {
+      self.done as f32 / self.total as f32
+    }
+  }
+
+  pub fn label(&self) -> String {
+    format!("{}/{} 画像を処理中", self.done, self.total)
+  }
+}
+
+/// 1枚の画像の処理結果
+#[derive(Clone, Debug)]
+pub struct ProcessedImage {
+  pub photo_id: String,
+  /// サムネイル用に圧縮したデータ
+  /// 処理対象から外した画像では`None`になる
+  pub thumbnail: Option<Vec<u8>>,
+  /// 検証で問題が見つかった場合の内容
+  pub invalid_image: Option<InvalidImage>,
+  /// 破損しているため処理対象から外したかどうか
+  pub excluded: bool,
+  /// 画像を圧縮し直した場合の`srcset`と保存した時刻
+  pub compressed: Option<(Vec<SrcsetImage>, DateTime<FixedOffset>)>,
+  /// 同じ内容の画像を圧縮した結果をコピーした場合の`srcset`と保存した時刻
+  pub copied: Option<(Vec<SrcsetImage>, DateTime<FixedOffset>)>,
+}
+
+#[derive(Debug, Default)]
+struct StartupState {
+  progress: StartupProgress,
+  processed_image_lst: Vec<ProcessedImage>,
+}
+
+/// 圧縮を行うスレッドと画面の間で共有する処理状況
+#[derive(Clone, Debug, Default)]
+pub struct StartupLoader {
+  state: Arc<Mutex<StartupState>>,
+}
+
+impl StartupLoader {
+  pub fn new(total: usize) -> Self {
+    StartupLoader {
+      state: Arc::new(Mutex::new(StartupState {
+        progress: StartupProgress::new(total),
+        processed_image_lst: Vec::new(),
+      })),
+    }
+  }
+
+  /// 処理結果を積んで進捗を進める
+  pub fn push(&self, processed_image: ProcessedImage) {
+    let mut state = self.state.lock().unwrap();
+    state.processed_image_lst.push(processed_image);
+    state.progress.record();
+  }
+
+  /// これまでに積まれた処理結果を取り出し、現在の進捗と一緒に返す
+  pub fn take(&self) -> (Vec<ProcessedImage>, StartupProgress) {
+    let mut state = self.state.lock().unwrap();
+    let processed_image_lst = std::mem::take(&mut state.processed_image_lst);
+    (processed_image_lst, state.progress)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn make_processed_image(photo_id: &str) -> ProcessedImage {
+    ProcessedImage {
+      photo_id: photo_id.to_string(),
+      thumbnail: None,
+      invalid_image: None,
+      excluded: false,
+      compressed: None,
+      copied: None,
+    }
+  }
+
+  #[test]
+  fn progress_counts_up_to_total() {
+    let mut progress = StartupProgress::new(2);
+    assert!(!progress.is_complete());
+    assert_eq!(progress.fraction(), 0.0);
+    progress.record();
+    assert_eq!(progress.fraction(), 0.5);
+    assert_eq!(progress.label(), "1/2 画像を処理中");
+    progress.record();
+    progress.record();
+    assert_eq!(progress.done, 2);
+    assert!(progress.is_complete());
+    assert_eq!(progress.fraction(), 1.0);
+    // 処理する画像が無い場合は最初から終わっている
+    let progress = StartupProgress::new(0);
+    assert!(progress.is_complete());
+    assert_eq!(progress.fraction(), 1.0);
+  }
+
+  #[test]
+  fn loader_hands_over_results_from_other_threads() {
+    let loader = StartupLoader::new(3);
+    let handle_lst = ["1", "2", "3"]
+      .map(|photo_id| {
+        let loader = loader.clone();
+        std::thread::spawn(move || loader.push(make_processed_image(photo_id)))
+      })
+      .into_iter()
+      .collect::<Vec<_>>();
+    for handle in handle_lst {
+      handle.join().unwrap();
+    }
+    let (processed_image_lst, progress) = loader.take();
+    let mut photo_id_lst = processed_image_lst
+      .into_iter()
+      .map(|processed_image| processed_image.photo_id)
+      .collect::<Vec<_>>();
+    photo_id_lst.sort();
+    assert_eq!(photo_id_lst, vec!["1", "2", "3"]);
+    assert_eq!(progress, StartupProgress { done: 3, total: 3 });
+    assert!(progress.is_complete());
+    // 取り出した結果は二度は返さない
+    let (processed_image_lst, progress) = loader.take();
+    assert!(processed_image_lst.is_empty());
+    assert!(progress.is_complete());
+  }
+}
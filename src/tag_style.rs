@@ -0,0 +1,245 @@
+//! タグ（写真のキーワード）ごとの表示の色とアイコンを管理する
+//! 設定は作業ディレクトリの`tag_styles.json`に保存し、GUIのタグの表示とHTMLの書き出しで使う
+//! 設定の無いタグは既定のスタイルで表示する
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use crate::photodata::{self, GUIPhotoData};
+
+/// 設定の無いタグの背景色
+pub const DEFAULT_TAG_COLOR: [u8; 3] = [0xE0, 0xE0, 0xE0];
+
+/// 明るい背景色の上に置く文字色
+const DARK_TEXT_COLOR: [u8; 3] = [0x22, 0x22, 0x22];
+
+/// 暗い背景色の上に置く文字色
+const LIGHT_TEXT_COLOR: [u8; 3] = [0xFF, 0xFF, 0xFF];
+
+/// タグの表示の設定
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagStyle {
+  /// 背景色（`#RRGGBB`の形式）
+  pub color: String,
+  /// タグ名の前に表示するアイコン（絵文字）
+  #[serde(default)]
+  pub icon: String,
+}
+
+impl Default for TagStyle {
+  fn default() -> Self {
+    TagStyle {
+      color: format_color(DEFAULT_TAG_COLOR),
+      icon: String::new(),
+    }
+  }
+}
+
+impl TagStyle {
+  /// 背景色
+  /// 正しい形式で書かれていない場合は既定の色にする
+  pub fn rgb(&self) -> [u8; 3] {
+    parse_color(&self.color).unwrap_or(DEFAULT_TAG_COLOR)
+  }
+
+  /// 背景色の上で読みやすい文字色
+  pub fn text_rgb(&self) -> [u8; 3] {
+    let [r, g, b] = self.rgb();
+    let luminance = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    if luminance > 150.0 {
+      DARK_TEXT_COLOR
+    } else {
+      LIGHT_TEXT_COLOR
+    }
+  }
+
+  /// アイコンを付けたタグの表示名
+  pub fn label(&self, tag: &str) -> String {
+    let icon = self.icon.trim();
+    if icon.is_empty() {
+      tag.to_string()
+    } else {
+      format!("{} {}", icon, tag)
+    }
+  }
+}
+
+/// `#RRGGBB`の形式の色を読み取る
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+  let hex = color.trim().strip_prefix('#')?;
+  if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+    return None;
+  }
+  let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+  Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// 色を`#RRGGBB`の形式にする
+pub fn format_color([r, g, b]: [u8; 3]) -> String {
+  format!("#{:02X}{:02X}{:02X}", r, g, b)
+}
+
+/// タグのスタイルを取り出す
+/// 設定が無い場合は既定のスタイルにする
+pub fn find_tag_style(tag_style_lst: &BTreeMap<String, TagStyle>, tag: &str) -> TagStyle {
+  tag_style_lst.get(tag).cloned().unwrap_or_default()
+}
+
+/// 写真に付けられているタグを重複を除いて名前順に並べる
+pub fn used_tag_lst<'a>(photo_data_lst: impl Iterator<Item = &'a GUIPhotoData>) -> Vec<String> {
+  let mut tag_lst = BTreeSet::new();
+  for photo_data in photo_data_lst {
+    tag_lst.extend(photodata::parse_keywords(&photo_data.keywords));
+  }
+  tag_lst.into_iter().collect()
+}
+
+fn tag_styles_path(work_dir: &str) -> String {
+  format!("{}/tag_styles.json", work_dir)
+}
+
+/// 保存されているタグのスタイルを読み込む
+/// ファイルが無い場合は空になる
+pub fn load_tag_styles(work_dir: &str) -> Result<BTreeMap<String, TagStyle>> {
+  let path = tag_styles_path(work_dir);
+  match fs::read_to_string(&path) {
+    Ok(json_str) => {
+      serde_json::from_str(&json_str).with_context(|| format!("{}を読み込めません", path))
+    }
+    Err(_) => Ok(BTreeMap::new()),
+  }
+}
+
+pub fn save_tag_styles(work_dir: &str, tag_style_lst: &BTreeMap<String, TagStyle>) -> Result<()> {
+  fs::write(
+    tag_styles_path(work_dir),
+    serde_json::to_string_pretty(tag_style_lst)?,
+  )?;
+  Ok(())
+}
+
+/// HTMLでタグに付けるclass
+/// 設定のあるタグには`tag tag-0`のように設定の並び順の番号を付ける
+pub fn tag_class(tag_style_lst: &BTreeMap<String, TagStyle>, tag: &str) -> String {
+  match tag_style_lst.keys().position(|key| key == tag) {
+    Some(i) => format!("tag tag-{}", i),
+    None => "tag".to_string(),
+  }
+}
+
+fn color_declaration(tag_style: &TagStyle) -> String {
+  format!(
+    "background-color: {}; color: {};",
+    format_color(tag_style.rgb()),
+    format_color(tag_style.text_rgb())
+  )
+}
+
+/// タグの色を反映させるCSS
+/// 色は読み取った値から書き直すので、設定ファイルの文字列はそのまま入らない
+pub fn make_tag_css(tag_style_lst: &BTreeMap<String, TagStyle>) -> String {
+  let mut css = format!(
+    ".tag {{ display: inline-block; padding: 0 0.5em; margin: 0.3em 0.3em 0 0; border-radius: 0.8em; {} }}\n",
+    color_declaration(&TagStyle::default())
+  );
+  for (i, tag_style) in tag_style_lst.values().enumerate() {
+    css.push_str(&format!(
+      ".tag-{} {{ {} }}\n",
+      i,
+      color_declaration(tag_style)
+    ));
+  }
+  css
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  fn make_tag_style(color: &str, icon: &str) -> TagStyle {
+    TagStyle {
+      color: color.to_string(),
+      icon: icon.to_string(),
+    }
+  }
+
+  #[test]
+  fn parse_and_format_color() {
+    assert_eq!(parse_color("#FF8000"), Some([0xFF, 0x80, 0x00]));
+    assert_eq!(parse_color(" #ff8000 "), Some([0xFF, 0x80, 0x00]));
+    assert_eq!(parse_color("FF8000"), None);
+    assert_eq!(parse_color("#FF80"), None);
+    assert_eq!(parse_color("#GG8000"), None);
+    assert_eq!(parse_color("#ＦＦ80"), None);
+    assert_eq!(format_color([0xFF, 0x80, 0x00]), "#FF8000");
+  }
+
+  #[test]
+  fn unset_tag_uses_default_style() {
+    let mut tag_style_lst = BTreeMap::new();
+    tag_style_lst.insert("桜".to_string(), make_tag_style("#FFB7C5", "🌸"));
+    assert_eq!(find_tag_style(&tag_style_lst, "桜").label("桜"), "🌸 桜");
+    let default_style = find_tag_style(&tag_style_lst, "海");
+    assert_eq!(default_style, TagStyle::default());
+    assert_eq!(default_style.rgb(), DEFAULT_TAG_COLOR);
+    assert_eq!(default_style.label("海"), "海");
+    // 壊れた色は既定の色として扱う
+    assert_eq!(make_tag_style("red", "").rgb(), DEFAULT_TAG_COLOR);
+  }
+
+  #[test]
+  fn text_color_depends_on_background() {
+    assert_eq!(make_tag_style("#FFFFFF", "").text_rgb(), DARK_TEXT_COLOR);
+    assert_eq!(make_tag_style("#000080", "").text_rgb(), LIGHT_TEXT_COLOR);
+  }
+
+  #[test]
+  fn css_has_a_rule_for_each_configured_tag() {
+    let mut tag_style_lst = BTreeMap::new();
+    tag_style_lst.insert("山".to_string(), make_tag_style("#336633", ""));
+    tag_style_lst.insert("海".to_string(), make_tag_style("#3366ff", "🌊"));
+    let css = make_tag_css(&tag_style_lst);
+    assert!(css.contains(".tag {"));
+    // 設定はタグの名前順に並ぶ
+    assert!(css.contains(".tag-0 { background-color: #336633; color: #FFFFFF; }"));
+    assert!(css.contains(".tag-1 { background-color: #3366FF; color: #FFFFFF; }"));
+    assert_eq!(tag_class(&tag_style_lst, "山"), "tag tag-0");
+    assert_eq!(tag_class(&tag_style_lst, "海"), "tag tag-1");
+    assert_eq!(tag_class(&tag_style_lst, "街"), "tag");
+  }
+
+  #[test]
+  fn used_tags_are_sorted_and_unique() {
+    let photo_data_lst = ["海, 夕日", "山、海"].map(|keywords| GUIPhotoData {
+      keywords: keywords.to_string(),
+      ..photodata::make_dummy_gui_photo_data()
+    });
+    assert_eq!(
+      used_tag_lst(photo_data_lst.iter()),
+      vec!["夕日", "山", "海"]
+    );
+  }
+
+  #[test]
+  fn save_and_load_tag_styles() {
+    let work_dir = test_util::temp_dir("tag_styles");
+    assert!(load_tag_styles(&work_dir).unwrap().is_empty());
+    let mut tag_style_lst = BTreeMap::new();
+    tag_style_lst.insert("桜".to_string(), make_tag_style("#FFB7C5", "🌸"));
+    save_tag_styles(&work_dir, &tag_style_lst).unwrap();
+    assert_eq!(load_tag_styles(&work_dir).unwrap(), tag_style_lst);
+    // アイコンは省略できる
+    fs::write(
+      tag_styles_path(&work_dir),
+      r##"{ "海": { "color": "#3366FF" } }"##,
+    )
+    .unwrap();
+    assert_eq!(
+      load_tag_styles(&work_dir).unwrap()["海"],
+      make_tag_style("#3366FF", "")
+    );
+  }
+}
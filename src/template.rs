@@ -0,0 +1,86 @@
+//! グループの説明文のテンプレートを管理する
+//! テンプレートは`description_templates.json`に保存し、撮影地点や日付のプレースホルダを含めることができる
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufReader, io::Write};
+
+use crate::photodata::GUIGroupData;
+
+/// テンプレート中で使えるプレースホルダとその説明
+pub const PLACEHOLDER_LST: [(&str, &str); 5] = [
+  ("{location}", "撮影地点"),
+  ("{date}", "撮影年月日"),
+  ("{year}", "撮影年"),
+  ("{month}", "撮影月"),
+  ("{day}", "撮影日"),
+];
+
+/// 説明文のテンプレート
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DescriptionTemplate {
+  /// 選択するときに表示する名前
+  pub name: String,
+  /// テンプレート本文
+  pub body: String,
+}
+
+/// 保存されているテンプレートを読み込む
+/// ファイルが無い場合は空になる
+pub fn load_description_templates(work_dir: &str) -> Result<Vec<DescriptionTemplate>> {
+  let file_path = format!("{}/description_templates.json", work_dir);
+  match File::open(&file_path) {
+    Ok(file) => {
+      let reader = BufReader::new(file);
+      let data: Vec<DescriptionTemplate> = serde_json::from_reader(reader)
+        .with_context(|| format!("{}を読み込めません", file_path))?;
+      Ok(data)
+    }
+    Err(_) => Ok(Vec::new()),
+  }
+}
+
+pub fn save_description_templates(
+  work_dir: &str,
+  template_lst: &[DescriptionTemplate],
+) -> Result<()> {
+  let path = format!("{}/description_templates.json", work_dir);
+  let mut file = File::create(&path).with_context(|| format!("{}を作成できません", path))?;
+  let json_str = serde_json::to_string_pretty(template_lst)?;
+  let buf = json_str.into_bytes();
+  file.write_all(&buf)?;
+  file.flush()?;
+  Ok(())
+}
+
+/// グループの撮影年月日を`2022/8/1`のような形にする
+/// 入力されている部分だけを繋げる
+fn group_date_str(group_data: &GUIGroupData) -> String {
+  [&group_data.year, &group_data.month, &group_data.day]
+    .iter()
+    .filter(|s| !s.is_empty())
+    .map(|s| s.to_string())
+    .collect::<Vec<String>>()
+    .join("/")
+}
+
+/// テンプレートのプレースホルダをグループのデータで置き換える
+pub fn apply_description_template(template_body: &str, group_data: &GUIGroupData) -> String {
+  template_body
+    .replace("{location}", &group_data.location)
+    .replace("{date}", &group_date_str(group_data))
+    .replace("{year}", &group_data.year)
+    .replace("{month}", &group_data.month)
+    .replace("{day}", &group_data.day)
+}
+
+/// 説明文にテンプレートを挿入する
+/// 説明文が空でなければ末尾に追記する
+pub fn insert_description_template(template_body: &str, group_data: &mut GUIGroupData) {
+  let text = apply_description_template(template_body, group_data);
+  if group_data.description.is_empty() {
+    group_data.description = text;
+  } else {
+    group_data.description.push_str(&text);
+  }
+}
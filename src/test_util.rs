@@ -0,0 +1,85 @@
+//! テストで使う一時ディレクトリや画像などを用意する
+
+use exif::experimental::Writer;
+use mozjpeg::Marker;
+use std::fs;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::image::{self, JpegScan};
+
+static TEMP_DIR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// テストごとに別の空の一時ディレクトリを作り、そのpathを返す
+pub fn temp_dir(name: &str) -> String {
+  let path = std::env::temp_dir().join(format!(
+    "photag-test-{}-{}-{}",
+    name,
+    std::process::id(),
+    TEMP_DIR_COUNT.fetch_add(1, Ordering::SeqCst)
+  ));
+  let _ = fs::remove_dir_all(&path);
+  fs::create_dir_all(&path).unwrap();
+  path.to_string_lossy().into_owned()
+}
+
+fn make_test_rgb_image(width: u32, height: u32) -> ::image::RgbImage {
+  ::image::RgbImage::from_fn(width, height, |x, y| {
+    ::image::Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+  })
+}
+
+/// 色が横方向と縦方向に変わるテスト用のJPEG
+pub fn make_test_jpeg(width: u32, height: u32) -> Vec<u8> {
+  image::encode(
+    &make_test_rgb_image(width, height),
+    &[],
+    90.0,
+    JpegScan::Baseline,
+  )
+  .unwrap()
+}
+
+/// `make_test_jpeg`と同じ画像のPNG
+pub fn make_test_png(width: u32, height: u32) -> Vec<u8> {
+  let mut buf = Cursor::new(Vec::new());
+  ::image::DynamicImage::ImageRgb8(make_test_rgb_image(width, height))
+    .write_to(&mut buf, ::image::ImageOutputFormat::Png)
+    .unwrap();
+  buf.into_inner()
+}
+
+/// `field_lst`のExifをAPP1マーカーとして書き込んだテスト用のJPEG
+pub fn make_exif_jpeg(field_lst: &[exif::Field]) -> Vec<u8> {
+  let mut writer = Writer::new();
+  for field in field_lst.iter() {
+    writer.push_field(field);
+  }
+  let mut tiff = Cursor::new(Vec::new());
+  writer.write(&mut tiff, false).unwrap();
+  let mut app1 = b"Exif\0\0".to_vec();
+  app1.extend_from_slice(tiff.get_ref());
+  image::encode(
+    &make_test_rgb_image(16, 16),
+    &[(Marker::APP(1), app1)],
+    90.0,
+    JpegScan::Baseline,
+  )
+  .unwrap()
+}
+
+/// 主画像のExifのフィールド
+pub fn exif_field(tag: exif::Tag, value: exif::Value) -> exif::Field {
+  exif::Field {
+    tag,
+    ifd_num: exif::In::PRIMARY,
+    value,
+  }
+}
+
+/// `field_lst`のExifを書き込んだJPEGを一時ディレクトリに書き出し、そのpathを返す
+pub fn write_exif_jpeg(name: &str, field_lst: &[exif::Field]) -> String {
+  let path = format!("{}/{}.jpg", temp_dir(name), name);
+  fs::write(&path, make_exif_jpeg(field_lst)).unwrap();
+  path
+}
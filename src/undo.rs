@@ -0,0 +1,588 @@
+//! 編集や削除を取り消すための履歴と、取り消した操作をやり直すための履歴を管理する
+//! 全体を複製すると重いので、操作で変化するデータだけを保持する
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::photodata::{self, GUIGroupData, GUIPhotoData};
+
+/// 取り消し履歴を何件まで保持するか
+pub const UNDO_STACK_SIZE: usize = 50;
+
+/// 操作する前の状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoEntry {
+  /// 写真を削除する前の状態
+  DeletePhoto {
+    /// `photo_id_lst`の中での位置
+    index: usize,
+    photo_data: Box<GUIPhotoData>,
+    thumbnail: Option<Vec<u8>>,
+    /// 所属していたグループのIDと、そのグループの`photo_id_list`の中での位置
+    group_membership: Vec<(String, usize)>,
+  },
+  /// グループを削除する前の状態
+  DeleteGroup {
+    /// `group_id_lst`の中での位置
+    index: usize,
+    group_data: GUIGroupData,
+  },
+  /// 写真データを編集する前の状態（複数枚の一括編集も含む）
+  EditPhoto(Vec<GUIPhotoData>),
+  /// グループデータを編集する前の状態
+  EditGroup(GUIGroupData),
+  /// 削除を取り消して復元した写真（やり直すと再び削除する）
+  RestoredPhoto(String),
+  /// 削除を取り消して復元したグループ（やり直すと再び削除する）
+  RestoredGroup(String),
+}
+
+/// 取り消した結果、どのデータが復元されたか
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UndoTarget {
+  Photo(String),
+  Group(String),
+}
+
+impl UndoEntry {
+  /// 写真を削除する前の状態を作る
+  pub fn delete_photo(
+    photo_id: &str,
+    photo_id_lst: &[String],
+    gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+    thumbnail_lst: &HashMap<String, Vec<u8>>,
+    gui_group_data_lst: &HashMap<String, GUIGroupData>,
+  ) -> Option<Self> {
+    let index = photo_id_lst.iter().position(|id| id == photo_id)?;
+    let photo_data = Box::new(gui_photo_data_lst.get(photo_id)?.clone());
+    let group_membership = gui_group_data_lst
+      .iter()
+      .filter_map(|(group_id, group_data)| {
+        group_data
+          .photo_id_list
+          .iter()
+          .position(|id| id == photo_id)
+          .map(|i| (group_id.clone(), i))
+      })
+      .collect();
+    Some(UndoEntry::DeletePhoto {
+      index,
+      photo_data,
+      thumbnail: thumbnail_lst.get(photo_id).cloned(),
+      group_membership,
+    })
+  }
+
+  /// グループを削除する前の状態を作る
+  pub fn delete_group(
+    group_id: &str,
+    group_id_lst: &[String],
+    gui_group_data_lst: &HashMap<String, GUIGroupData>,
+  ) -> Option<Self> {
+    let index = group_id_lst.iter().position(|id| id == group_id)?;
+    let group_data = gui_group_data_lst.get(group_id)?.clone();
+    Some(UndoEntry::DeleteGroup { index, group_data })
+  }
+
+  /// この状態に戻す直前の、現在の状態を作る
+  /// 取り消した操作をやり直すときに使う
+  pub fn current_state(
+    &self,
+    photo_id_lst: &[String],
+    gui_photo_data_lst: &HashMap<String, GUIPhotoData>,
+    thumbnail_lst: &HashMap<String, Vec<u8>>,
+    group_id_lst: &[String],
+    gui_group_data_lst: &HashMap<String, GUIGroupData>,
+  ) -> Option<Self> {
+    match self {
+      UndoEntry::DeletePhoto { photo_data, .. } => {
+        Some(UndoEntry::RestoredPhoto(photo_data.photo_id.clone()))
+      }
+      UndoEntry::DeleteGroup { group_data, .. } => {
+        // 同じIDのグループが作り直されている場合は復元しないので、やり直す操作も無い
+        if group_id_lst.contains(&group_data.group_id) {
+          None
+        } else {
+          Some(UndoEntry::RestoredGroup(group_data.group_id.clone()))
+        }
+      }
+      UndoEntry::EditPhoto(photo_data_lst) => {
+        let current_lst = photo_data_lst
+          .iter()
+          .filter_map(|photo_data| gui_photo_data_lst.get(&photo_data.photo_id).cloned())
+          .collect::<Vec<GUIPhotoData>>();
+        if current_lst.is_empty() {
+          None
+        } else {
+          Some(UndoEntry::EditPhoto(current_lst))
+        }
+      }
+      UndoEntry::EditGroup(group_data) => gui_group_data_lst
+        .get(&group_data.group_id)
+        .cloned()
+        .map(UndoEntry::EditGroup),
+      UndoEntry::RestoredPhoto(photo_id) => UndoEntry::delete_photo(
+        photo_id,
+        photo_id_lst,
+        gui_photo_data_lst,
+        thumbnail_lst,
+        gui_group_data_lst,
+      ),
+      UndoEntry::RestoredGroup(group_id) => {
+        UndoEntry::delete_group(group_id, group_id_lst, gui_group_data_lst)
+      }
+    }
+  }
+
+  /// 操作前の状態に戻す
+  /// 復元した写真やグループを削除し直した場合は`None`を返す
+  /// 削除したグループと同じIDのグループが作り直されている場合は、何もせずに`None`を返す
+  pub fn restore(
+    self,
+    photo_id_lst: &mut Vec<String>,
+    gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+    thumbnail_lst: &mut HashMap<String, Vec<u8>>,
+    group_id_lst: &mut Vec<String>,
+    gui_group_data_lst: &mut HashMap<String, GUIGroupData>,
+  ) -> Option<UndoTarget> {
+    match self {
+      UndoEntry::DeletePhoto {
+        index,
+        photo_data,
+        thumbnail,
+        group_membership,
+      } => {
+        let photo_id = photo_data.photo_id.clone();
+        if !photo_id_lst.contains(&photo_id) {
+          photo_id_lst.insert(index.min(photo_id_lst.len()), photo_id.clone());
+        }
+        gui_photo_data_lst.insert(photo_id.clone(), *photo_data);
+        if let Some(thumbnail) = thumbnail {
+          thumbnail_lst.insert(photo_id.clone(), thumbnail);
+        }
+        for (group_id, i) in group_membership.into_iter() {
+          if let Some(group_data) = gui_group_data_lst.get_mut(&group_id) {
+            if !group_data.photo_id_list.contains(&photo_id) {
+              let i = i.min(group_data.photo_id_list.len());
+              group_data.photo_id_list.insert(i, photo_id.clone());
+            }
+          }
+        }
+        Some(UndoTarget::Photo(photo_id))
+      }
+      UndoEntry::DeleteGroup { index, group_data } => {
+        let group_id = group_data.group_id.clone();
+        // 削除した後に同じIDで作られたグループを上書きしない
+        if group_id_lst.contains(&group_id) {
+          return None;
+        }
+        group_id_lst.insert(index.min(group_id_lst.len()), group_id.clone());
+        gui_group_data_lst.insert(group_id.clone(), group_data);
+        Some(UndoTarget::Group(group_id))
+      }
+      UndoEntry::EditPhoto(photo_data_lst) => {
+        let mut target = None;
+        for photo_data in photo_data_lst.into_iter() {
+          // 編集した後に削除された写真は復元しない
+          if gui_photo_data_lst.contains_key(&photo_data.photo_id) {
+            target = Some(UndoTarget::Photo(photo_data.photo_id.clone()));
+            gui_photo_data_lst.insert(photo_data.photo_id.clone(), photo_data);
+          }
+        }
+        target
+      }
+      UndoEntry::EditGroup(group_data) => {
+        if gui_group_data_lst.contains_key(&group_data.group_id) {
+          let group_id = group_data.group_id.clone();
+          gui_group_data_lst.insert(group_id.clone(), group_data);
+          Some(UndoTarget::Group(group_id))
+        } else {
+          None
+        }
+      }
+      UndoEntry::RestoredPhoto(photo_id) => {
+        photodata::delete_photo_data(
+          &photo_id,
+          photo_id_lst,
+          gui_photo_data_lst,
+          thumbnail_lst,
+          gui_group_data_lst,
+        );
+        None
+      }
+      UndoEntry::RestoredGroup(group_id) => {
+        // GUIでグループを削除したときと同じく、一覧からだけ取り除く
+        group_id_lst.retain(|id| *id != group_id);
+        None
+      }
+    }
+  }
+}
+
+/// 上限付きの取り消し履歴
+#[derive(Debug, Clone)]
+pub struct UndoStack {
+  entries: VecDeque<UndoEntry>,
+  /// 取り消した操作をやり直すための状態
+  /// 新しく操作をしたときに空にする
+  redo_entries: Vec<UndoEntry>,
+  max_size: usize,
+  /// 同じ写真・グループへの連続した編集を1回分にまとめるための編集対象
+  editing_target: Option<UndoTarget>,
+}
+
+impl UndoStack {
+  pub fn new(max_size: usize) -> Self {
+    UndoStack {
+      entries: VecDeque::new(),
+      redo_entries: Vec::new(),
+      max_size,
+      editing_target: None,
+    }
+  }
+
+  /// 操作前の状態を積む
+  /// 上限を超えた場合は古いものから捨てる
+  pub fn push(&mut self, entry: UndoEntry) {
+    self.editing_target = None;
+    self.push_inner(entry);
+  }
+
+  fn push_inner(&mut self, entry: UndoEntry) {
+    self.redo_entries.clear();
+    self.push_bounded(entry);
+  }
+
+  fn push_bounded(&mut self, entry: UndoEntry) {
+    if self.max_size == 0 {
+      return;
+    }
+    while self.entries.len() >= self.max_size {
+      self.entries.pop_front();
+    }
+    self.entries.push_back(entry);
+  }
+
+  /// 写真の編集前の状態を積む
+  /// 同じ写真を続けて編集している間は最初の1回だけ積む
+  pub fn push_photo_edit(&mut self, photo_data: GUIPhotoData) {
+    let target = UndoTarget::Photo(photo_data.photo_id.clone());
+    if self.editing_target.as_ref() != Some(&target) {
+      self.push_inner(UndoEntry::EditPhoto(vec![photo_data]));
+      self.editing_target = Some(target);
+    }
+  }
+
+  /// グループの編集前の状態を積む
+  /// 同じグループを続けて編集している間は最初の1回だけ積む
+  pub fn push_group_edit(&mut self, group_data: GUIGroupData) {
+    let target = UndoTarget::Group(group_data.group_id.clone());
+    if self.editing_target.as_ref() != Some(&target) {
+      self.push_inner(UndoEntry::EditGroup(group_data));
+      self.editing_target = Some(target);
+    }
+  }
+
+  /// 編集対象が切り替わったときに呼び、連続した編集のまとまりを区切る
+  pub fn seal(&mut self) {
+    self.editing_target = None;
+  }
+
+  /// 直前の操作を取り消し、やり直せるように現在の状態を残す
+  /// 取り消す操作が無い場合や、復元したデータが無い場合は`None`を返す
+  pub fn undo(
+    &mut self,
+    photo_id_lst: &mut Vec<String>,
+    gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+    thumbnail_lst: &mut HashMap<String, Vec<u8>>,
+    group_id_lst: &mut Vec<String>,
+    gui_group_data_lst: &mut HashMap<String, GUIGroupData>,
+  ) -> Option<UndoTarget> {
+    self.editing_target = None;
+    let entry = self.entries.pop_back()?;
+    if let Some(redo_entry) = entry.current_state(
+      photo_id_lst,
+      gui_photo_data_lst,
+      thumbnail_lst,
+      group_id_lst,
+      gui_group_data_lst,
+    ) {
+      self.redo_entries.push(redo_entry);
+    }
+    entry.restore(
+      photo_id_lst,
+      gui_photo_data_lst,
+      thumbnail_lst,
+      group_id_lst,
+      gui_group_data_lst,
+    )
+  }
+
+  /// 直前に取り消した操作をやり直し、もう一度取り消せるように現在の状態を残す
+  pub fn redo(
+    &mut self,
+    photo_id_lst: &mut Vec<String>,
+    gui_photo_data_lst: &mut HashMap<String, GUIPhotoData>,
+    thumbnail_lst: &mut HashMap<String, Vec<u8>>,
+    group_id_lst: &mut Vec<String>,
+    gui_group_data_lst: &mut HashMap<String, GUIGroupData>,
+  ) -> Option<UndoTarget> {
+    self.editing_target = None;
+    let entry = self.redo_entries.pop()?;
+    if let Some(undo_entry) = entry.current_state(
+      photo_id_lst,
+      gui_photo_data_lst,
+      thumbnail_lst,
+      group_id_lst,
+      gui_group_data_lst,
+    ) {
+      self.push_bounded(undo_entry);
+    }
+    entry.restore(
+      photo_id_lst,
+      gui_photo_data_lst,
+      thumbnail_lst,
+      group_id_lst,
+      gui_group_data_lst,
+    )
+  }
+}
+
+impl Default for UndoStack {
+  fn default() -> Self {
+    UndoStack::new(UNDO_STACK_SIZE)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// テストで使う写真とグループの一覧
+  struct State {
+    photo_id_lst: Vec<String>,
+    gui_photo_data_lst: HashMap<String, GUIPhotoData>,
+    thumbnail_lst: HashMap<String, Vec<u8>>,
+    group_id_lst: Vec<String>,
+    gui_group_data_lst: HashMap<String, GUIGroupData>,
+  }
+
+  impl State {
+    fn new() -> Self {
+      let mut gui_photo_data_lst = HashMap::new();
+      for id in ["a", "b"] {
+        gui_photo_data_lst.insert(
+          id.to_string(),
+          GUIPhotoData {
+            photo_id: id.to_string(),
+            ..photodata::make_dummy_gui_photo_data()
+          },
+        );
+      }
+      let mut gui_group_data_lst = HashMap::new();
+      gui_group_data_lst.insert(
+        "g".to_string(),
+        GUIGroupData {
+          group_id: "g".to_string(),
+          photo_id_list: vec!["a".to_string(), "b".to_string()],
+          ..photodata::make_dummy_gui_group_data()
+        },
+      );
+      State {
+        photo_id_lst: vec!["a".to_string(), "b".to_string()],
+        gui_photo_data_lst,
+        thumbnail_lst: HashMap::new(),
+        group_id_lst: vec!["g".to_string()],
+        gui_group_data_lst,
+      }
+    }
+
+    fn undo(&mut self, undo_stack: &mut UndoStack) -> Option<UndoTarget> {
+      undo_stack.undo(
+        &mut self.photo_id_lst,
+        &mut self.gui_photo_data_lst,
+        &mut self.thumbnail_lst,
+        &mut self.group_id_lst,
+        &mut self.gui_group_data_lst,
+      )
+    }
+
+    fn redo(&mut self, undo_stack: &mut UndoStack) -> Option<UndoTarget> {
+      undo_stack.redo(
+        &mut self.photo_id_lst,
+        &mut self.gui_photo_data_lst,
+        &mut self.thumbnail_lst,
+        &mut self.group_id_lst,
+        &mut self.gui_group_data_lst,
+      )
+    }
+
+    /// 写真の説明を書き換え、書き換える前の状態を積む
+    fn edit_alt(&mut self, undo_stack: &mut UndoStack, id: &str, alt: &str) {
+      let photo_data = self.gui_photo_data_lst.get_mut(id).unwrap();
+      undo_stack.push(UndoEntry::EditPhoto(vec![photo_data.clone()]));
+      photo_data.alt = alt.to_string();
+    }
+
+    fn alt(&self, id: &str) -> &str {
+      &self.gui_photo_data_lst[id].alt
+    }
+
+    /// グループを追加する
+    fn add_group(&mut self, id: &str, photo_id_lst: &[&str]) {
+      self.group_id_lst.push(id.to_string());
+      self.gui_group_data_lst.insert(
+        id.to_string(),
+        GUIGroupData {
+          group_id: id.to_string(),
+          photo_id_list: photo_id_lst
+            .iter()
+            .map(|photo_id| photo_id.to_string())
+            .collect(),
+          ..photodata::make_dummy_gui_group_data()
+        },
+      );
+    }
+
+    /// GUIと同じくグループを一覧から取り除き、取り除く前の状態を積む
+    fn delete_group(&mut self, undo_stack: &mut UndoStack, id: &str) {
+      let entry =
+        UndoEntry::delete_group(id, &self.group_id_lst, &self.gui_group_data_lst).unwrap();
+      undo_stack.push(entry);
+      self.group_id_lst.retain(|group_id| group_id != id);
+    }
+  }
+
+  #[test]
+  fn undo_stack_keeps_only_the_latest_entries() {
+    let mut state = State::new();
+    let mut undo_stack = UndoStack::default();
+    for i in 0..UNDO_STACK_SIZE + 10 {
+      state.edit_alt(&mut undo_stack, "a", &i.to_string());
+    }
+    let mut count = 0;
+    while state.undo(&mut undo_stack).is_some() {
+      count += 1;
+    }
+    assert_eq!(count, UNDO_STACK_SIZE);
+    // 古い10件は捨てられているので、最初の状態までは戻らない
+    assert_eq!(state.alt("a"), "9");
+  }
+
+  #[test]
+  fn undo_and_redo_in_order() {
+    let mut state = State::new();
+    let mut undo_stack = UndoStack::default();
+    state.edit_alt(&mut undo_stack, "a", "1");
+    state.edit_alt(&mut undo_stack, "a", "2");
+    state.edit_alt(&mut undo_stack, "b", "3");
+
+    assert_eq!(
+      state.undo(&mut undo_stack),
+      Some(UndoTarget::Photo("b".to_string()))
+    );
+    assert_eq!(state.alt("b"), "");
+    state.undo(&mut undo_stack);
+    assert_eq!(state.alt("a"), "1");
+
+    assert_eq!(
+      state.redo(&mut undo_stack),
+      Some(UndoTarget::Photo("a".to_string()))
+    );
+    assert_eq!(state.alt("a"), "2");
+    state.redo(&mut undo_stack);
+    assert_eq!(state.alt("b"), "3");
+    assert_eq!(state.redo(&mut undo_stack), None);
+
+    // やり直した操作はもう一度取り消せる
+    state.undo(&mut undo_stack);
+    assert_eq!(state.alt("b"), "");
+  }
+
+  #[test]
+  fn new_edit_clears_redo() {
+    let mut state = State::new();
+    let mut undo_stack = UndoStack::default();
+    state.edit_alt(&mut undo_stack, "a", "1");
+    state.undo(&mut undo_stack);
+    state.edit_alt(&mut undo_stack, "b", "2");
+    assert_eq!(state.redo(&mut undo_stack), None);
+    assert_eq!(state.alt("a"), "");
+
+    // 続けて編集している間も、新しい編集でやり直しの履歴は消える
+    undo_stack.push_photo_edit(state.gui_photo_data_lst["a"].clone());
+    state.undo(&mut undo_stack);
+    undo_stack.push_photo_edit(state.gui_photo_data_lst["a"].clone());
+    assert_eq!(state.redo(&mut undo_stack), None);
+  }
+
+  #[test]
+  fn redo_deletes_restored_photo_again() {
+    let mut state = State::new();
+    let mut undo_stack = UndoStack::default();
+    let entry = UndoEntry::delete_photo(
+      "a",
+      &state.photo_id_lst,
+      &state.gui_photo_data_lst,
+      &state.thumbnail_lst,
+      &state.gui_group_data_lst,
+    )
+    .unwrap();
+    undo_stack.push(entry);
+    state.photo_id_lst.retain(|id| id != "a");
+    state.gui_photo_data_lst.remove("a");
+    photodata::remove_photo_from_groups(&mut state.gui_group_data_lst, "a");
+
+    assert_eq!(
+      state.undo(&mut undo_stack),
+      Some(UndoTarget::Photo("a".to_string()))
+    );
+    assert_eq!(state.photo_id_lst, vec!["a", "b"]);
+    assert_eq!(state.gui_group_data_lst["g"].photo_id_list, vec!["a", "b"]);
+
+    assert_eq!(state.redo(&mut undo_stack), None);
+    assert_eq!(state.photo_id_lst, vec!["b"]);
+    assert!(!state.gui_photo_data_lst.contains_key("a"));
+    assert_eq!(state.gui_group_data_lst["g"].photo_id_list, vec!["b"]);
+
+    state.undo(&mut undo_stack);
+    assert_eq!(state.photo_id_lst, vec!["a", "b"]);
+  }
+
+  #[test]
+  fn deleted_group_is_restored_at_its_position() {
+    let mut state = State::new();
+    let mut undo_stack = UndoStack::default();
+    state.add_group("h", &["b"]);
+    state.group_id_lst.insert(0, "f".to_string());
+    state.delete_group(&mut undo_stack, "g");
+    assert_eq!(state.group_id_lst, vec!["f", "h"]);
+
+    assert_eq!(
+      state.undo(&mut undo_stack),
+      Some(UndoTarget::Group("g".to_string()))
+    );
+    assert_eq!(state.group_id_lst, vec!["f", "g", "h"]);
+    assert_eq!(state.gui_group_data_lst["g"].photo_id_list, vec!["a", "b"]);
+
+    assert_eq!(state.redo(&mut undo_stack), None);
+    assert_eq!(state.group_id_lst, vec!["f", "h"]);
+    state.undo(&mut undo_stack);
+    assert_eq!(state.group_id_lst, vec!["f", "g", "h"]);
+  }
+
+  #[test]
+  fn undo_keeps_group_recreated_with_the_deleted_id() {
+    let mut state = State::new();
+    let mut undo_stack = UndoStack::default();
+    state.delete_group(&mut undo_stack, "g");
+    // 削除したグループと同じIDで新しいグループを作る
+    state.add_group("g", &["b"]);
+
+    assert_eq!(state.undo(&mut undo_stack), None);
+    assert_eq!(state.group_id_lst, vec!["g"]);
+    assert_eq!(state.gui_group_data_lst["g"].photo_id_list, vec!["b"]);
+    // 復元していないので、やり直しで新しいグループを取り除くこともない
+    assert_eq!(state.redo(&mut undo_stack), None);
+    assert_eq!(state.group_id_lst, vec!["g"]);
+  }
+}
@@ -0,0 +1,205 @@
+//! 写真データの数値の入力欄が正しい形式かを検証する
+//! 空欄は未入力として扱い、問題なしとする
+
+use crate::photodata;
+
+/// 整数で、かつ指定した範囲に収まっているかを検証する
+fn validate_integer_range(value: &str, min: u32, max: u32) -> Result<(), String> {
+  let value = value.trim();
+  if value.is_empty() {
+    return Ok(());
+  }
+  match value.parse::<u32>() {
+    Ok(n) if min <= n && n <= max => Ok(()),
+    Ok(_) => Err(format!("{}から{}の範囲で入力してください", min, max)),
+    Err(_) => Err("整数で入力してください".to_string()),
+  }
+}
+
+/// 正の数かを検証する
+fn validate_positive_number(value: Option<f64>) -> Result<(), String> {
+  match value {
+    Some(n) if n > 0.0 && n.is_finite() => Ok(()),
+    Some(_) => Err("正の数で入力してください".to_string()),
+    None => Err("数値で入力してください".to_string()),
+  }
+}
+
+/// ISO感度を検証する
+pub fn validate_iso(iso: &str) -> Result<(), String> {
+  validate_integer_range(iso, 1, u32::MAX)
+}
+
+/// F値を検証する
+/// `f/2.8`のような表記も受け付ける
+pub fn validate_f_value(f_value: &str) -> Result<(), String> {
+  if f_value.trim().is_empty() {
+    return Ok(());
+  }
+  validate_positive_number(photodata::parse_f_value(f_value))
+}
+
+/// 焦点距離を検証する
+pub fn validate_focal_length(focal_length: &str) -> Result<(), String> {
+  let focal_length = focal_length.trim();
+  if focal_length.is_empty() {
+    return Ok(());
+  }
+  validate_positive_number(focal_length.parse().ok())
+}
+
+/// 標高を検証する
+/// 海面下の負の値も受け付ける
+pub fn validate_altitude(altitude: &str) -> Result<(), String> {
+  if altitude.trim().is_empty() {
+    return Ok(());
+  }
+  match photodata::parse_altitude(altitude) {
+    Some(_) => Ok(()),
+    None => Err("数値で入力してください".to_string()),
+  }
+}
+
+/// 撮影月を検証する
+pub fn validate_month(month: &str) -> Result<(), String> {
+  validate_integer_range(month, 1, 12)
+}
+
+/// 撮影日を検証する
+pub fn validate_day(day: &str) -> Result<(), String> {
+  validate_integer_range(day, 1, 31)
+}
+
+/// 撮影時刻の時を検証する
+pub fn validate_hour(hour: &str) -> Result<(), String> {
+  validate_integer_range(hour, 0, 23)
+}
+
+/// 撮影時刻の分を検証する
+pub fn validate_minutes(minutes: &str) -> Result<(), String> {
+  validate_integer_range(minutes, 0, 59)
+}
+
+/// グループIDがファイル名やURLに使える形で、他のグループと重複していないかを検証する
+/// `current_id`には編集中のグループの元のIDを渡し、新規作成の場合は`None`にする
+pub fn validate_group_id(
+  group_id: &str,
+  group_id_lst: &[String],
+  current_id: Option<&str>,
+) -> Result<(), String> {
+  if group_id.is_empty() || current_id == Some(group_id) {
+    return Ok(());
+  }
+  if !photodata::is_valid_id(group_id) {
+    return Err("グループIDには半角英数字と「-」「_」「.」だけを使ってください".to_string());
+  }
+  if group_id_lst.iter().any(|id| id == group_id) {
+    Err(format!("グループID「{}」は既に使われています", group_id))
+  } else {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn empty_fields_are_valid() {
+    for validate in [
+      validate_iso,
+      validate_f_value,
+      validate_focal_length,
+      validate_month,
+      validate_day,
+      validate_hour,
+      validate_minutes,
+    ] {
+      assert!(validate("").is_ok());
+      assert!(validate("  ").is_ok());
+    }
+  }
+
+  #[test]
+  fn iso_must_be_a_positive_integer() {
+    assert!(validate_iso("100").is_ok());
+    assert!(validate_iso(" 3200 ").is_ok());
+    assert!(validate_iso("0").is_err());
+    assert!(validate_iso("-100").is_err());
+    assert!(validate_iso("1.5").is_err());
+    assert!(validate_iso("ISO100").is_err());
+  }
+
+  #[test]
+  fn f_value_accepts_prefixed_notation() {
+    assert!(validate_f_value("2.8").is_ok());
+    assert!(validate_f_value("f/2.8").is_ok());
+    assert!(validate_f_value("F4").is_ok());
+    assert!(validate_f_value("f2..8").is_err());
+    assert!(validate_f_value("0").is_err());
+    assert!(validate_f_value("-1.4").is_err());
+  }
+
+  #[test]
+  fn focal_length_must_be_positive() {
+    assert!(validate_focal_length("35").is_ok());
+    assert!(validate_focal_length("18.5").is_ok());
+    assert!(validate_focal_length("0").is_err());
+    assert!(validate_focal_length("35mm").is_err());
+    assert!(validate_focal_length("inf").is_err());
+  }
+
+  #[test]
+  fn date_and_time_parts_are_range_checked() {
+    assert!(validate_month("1").is_ok());
+    assert!(validate_month("12").is_ok());
+    assert!(validate_month("0").is_err());
+    assert!(validate_month("13").is_err());
+    assert!(validate_day("31").is_ok());
+    assert!(validate_day("32").is_err());
+    assert!(validate_hour("0").is_ok());
+    assert!(validate_hour("23").is_ok());
+    assert!(validate_hour("24").is_err());
+    assert!(validate_minutes("59").is_ok());
+    assert!(validate_minutes("60").is_err());
+    assert_eq!(
+      validate_minutes("60"),
+      Err("0から59の範囲で入力してください".to_string())
+    );
+    assert_eq!(
+      validate_minutes("half"),
+      Err("整数で入力してください".to_string())
+    );
+  }
+
+  #[test]
+  fn group_id_must_be_unique() {
+    let group_id_lst = ["sea".to_string(), "mountain".to_string()];
+    assert_eq!(
+      validate_group_id("sea", &group_id_lst, None),
+      Err("グループID「sea」は既に使われています".to_string())
+    );
+    // 他のグループのIDに変えることはできない
+    assert!(validate_group_id("sea", &group_id_lst, Some("mountain")).is_err());
+    // 編集中のグループの元のIDはそのまま使える
+    assert_eq!(validate_group_id("sea", &group_id_lst, Some("sea")), Ok(()));
+    assert_eq!(validate_group_id("river", &group_id_lst, None), Ok(()));
+  }
+
+  #[test]
+  fn group_id_must_be_usable_in_paths() {
+    let group_id_lst = ["sea".to_string()];
+    for group_id in ["海", "a/b", "..", "a b"] {
+      assert_eq!(
+        validate_group_id(group_id, &group_id_lst, None),
+        Err("グループIDには半角英数字と「-」「_」「.」だけを使ってください".to_string()),
+        "{}",
+        group_id
+      );
+    }
+    assert_eq!(
+      validate_group_id("sea-2022.08", &group_id_lst, None),
+      Ok(())
+    );
+  }
+}
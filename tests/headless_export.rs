@@ -0,0 +1,106 @@
+//! ライブラリの公開APIだけを使って、読み込み・保存・書き出しを通して行う
+
+use photag::{export, image, photodata, save};
+use std::fs;
+use std::path::PathBuf;
+
+/// テストごとに空の一時フォルダを作る
+fn temp_dir(name: &str) -> PathBuf {
+  let path = std::env::temp_dir().join(format!("photag-test-{}-{}", name, std::process::id()));
+  let _ = fs::remove_dir_all(&path);
+  fs::create_dir_all(&path).unwrap();
+  path
+}
+
+fn make_test_image(width: u32, height: u32) -> ::image::DynamicImage {
+  ::image::DynamicImage::ImageRgb8(::image::RgbImage::from_fn(width, height, |x, y| {
+    ::image::Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 128])
+  }))
+}
+
+#[test]
+fn load_save_and_export_without_gui() {
+  let root = temp_dir("headless_export");
+  let original_dir = root.join("original");
+  let work_dir = root.join("work");
+  let output_dir = root.join("output");
+  fs::create_dir_all(&original_dir).unwrap();
+  make_test_image(64, 48)
+    .save(original_dir.join("sea.jpg"))
+    .unwrap();
+  make_test_image(40, 60)
+    .save(original_dir.join("mountain.png"))
+    .unwrap();
+  let import_json_path = root.join("original_photo_data.json");
+  fs::write(
+    &import_json_path,
+    r#"[
+  { "file_name": "sea.jpg", "id": "sea", "alt": "海", "location": "鎌倉" },
+  { "file_name": "mountain.png", "id": "mountain", "alt": "山", "location": "" }
+]"#,
+  )
+  .unwrap();
+
+  // 読み込み
+  let work_dir = work_dir.to_str().unwrap();
+  let import_photo_data_lst =
+    photodata::load_import_json_file(import_json_path.to_str().unwrap()).unwrap();
+  let (photo_id_lst, photo_data_lst) = photodata::merge_photo_data_based_and_import_photo_data(
+    &photodata::load_photo_data_opt(work_dir),
+    &import_photo_data_lst,
+    original_dir.to_str().unwrap(),
+    photodata::default_exif_thread_count(),
+    photodata::DEFAULT_URL_PREFIX,
+  )
+  .unwrap();
+  assert_eq!(photo_id_lst, vec!["sea", "mountain"]);
+  assert_eq!(
+    photo_data_lst
+      .iter()
+      .map(|photo_data| photo_data.photo_id.as_str())
+      .collect::<Vec<_>>(),
+    vec!["sea", "mountain"]
+  );
+
+  // 圧縮と保存
+  save::ensure_work_directories(work_dir).unwrap();
+  for photo_data in photo_data_lst.iter() {
+    let raw_data =
+      image::open_file(original_dir.join(&photo_data.file_name).to_str().unwrap()).unwrap();
+    let normal = image::compression(&raw_data, 85.0, 32, false, image::JpegScan::Baseline).unwrap();
+    fs::write(
+      save::normal_image_path(work_dir, &photo_data.photo_id),
+      normal,
+    )
+    .unwrap();
+    let lazy = image::compression(&raw_data, 50.0, 8, false, image::JpegScan::Baseline).unwrap();
+    fs::write(save::lazy_image_path(work_dir, &photo_data.photo_id), lazy).unwrap();
+  }
+  save::save_work_data(work_dir, &photo_data_lst, &[], save::JsonStyle::Pretty).unwrap();
+  assert_eq!(
+    photodata::load_photo_data_from_work_directory(work_dir).unwrap(),
+    photo_data_lst
+  );
+
+  // 書き出し
+  export::export_json(
+    work_dir,
+    output_dir.to_str().unwrap(),
+    None,
+    &export::RatingFilter::default(),
+    false,
+    save::JsonStyle::Minify,
+    false,
+  )
+  .unwrap();
+  let exported =
+    photodata::load_photo_data_from_work_directory(output_dir.to_str().unwrap()).unwrap();
+  assert_eq!(exported, photo_data_lst);
+  for photo_data in exported.iter() {
+    for src in [&photo_data.photo_src, &photo_data.photo_lazy_src] {
+      let path = output_dir.join(photodata::src_to_path(src));
+      let img = ::image::open(&path).unwrap();
+      assert!(img.width().max(img.height()) <= 32, "{}", path.display());
+    }
+  }
+}